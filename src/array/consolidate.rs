@@ -0,0 +1,507 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType, Layout, COMMITS_DIR, VACUUM_FILE_SUFFIX};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::query::{
+    integral_bounds, DenseReader, DenseTiling, DenseWriter, SparseReader,
+    SparseWriter, Subarray,
+};
+use crate::Result;
+
+// Merges the fragments committed within a timestamp range into a single
+// fragment covering the same range. The merged fragments are listed in a
+// vacuum file named after the new fragment which hides them from readers
+// until they are vacuumed.
+pub struct Consolidator {
+    array_uri: uri::URI,
+    timestamp_start: u64,
+    timestamp_end: u64,
+    with_timestamps: bool,
+}
+
+impl Consolidator {
+    pub fn new(array_uri: &uri::URI) -> Self {
+        Consolidator {
+            array_uri: array_uri.clone(),
+            timestamp_start: 0,
+            timestamp_end: u64::MAX,
+            with_timestamps: false,
+        }
+    }
+
+    // Only fragments written entirely within the inclusive range are
+    // consolidated. Defaults to every fragment.
+    pub fn set_timestamp_range(mut self, start: u64, end: u64) -> Self {
+        self.timestamp_start = start;
+        self.timestamp_end = end;
+        self
+    }
+
+    // Store the timestamp of every cell so that the array can still be
+    // opened at timestamps inside the consolidated range. Every version of
+    // a cell is kept rather than only the newest. Only sparse arrays
+    // support this.
+    pub fn set_with_timestamps(mut self, with_timestamps: bool) -> Self {
+        self.with_timestamps = with_timestamps;
+        self
+    }
+
+    // Returns the URI of the new fragment, or None when there are fewer
+    // than two fragments or no cells left to consolidate.
+    pub fn consolidate(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<Option<uri::URI>> {
+        let array = array::Array::open_at(
+            vfs,
+            &self.array_uri,
+            self.timestamp_start,
+            self.timestamp_end,
+        )?;
+
+        let fragments = array.fragments();
+        if let Some(fragment) = fragments.iter().find(|f| {
+            let (start, end) = f.timestamp_range();
+            start < self.timestamp_start || end > self.timestamp_end
+        }) {
+            return Err(anyhow!(
+                "Fragment only partially overlaps the consolidation range"
+            )
+            .context(format!("Fragment: {}", fragment.uri())));
+        }
+
+        if fragments.len() < 2 {
+            return Ok(None);
+        }
+
+        let timestamp_range = (
+            fragments
+                .iter()
+                .map(|f| f.timestamp_range().0)
+                .min()
+                .unwrap_or_default(),
+            fragments
+                .iter()
+                .map(|f| f.timestamp_range().1)
+                .max()
+                .unwrap_or_default(),
+        );
+
+        let fragment_uri = match array.schema().array_type() {
+            ArrayType::Dense if self.with_timestamps => {
+                return Err(anyhow!(
+                    "Consolidation with timestamps requires a sparse array"
+                )
+                .context(format!("URI: {}", self.array_uri)));
+            }
+            ArrayType::Dense => {
+                Some(consolidate_dense(vfs, &array, timestamp_range)?)
+            }
+            ArrayType::Sparse => consolidate_sparse(
+                vfs,
+                &array,
+                timestamp_range,
+                self.with_timestamps,
+            )?,
+            array_type => {
+                return Err(anyhow!("Invalid array type {:?}", array_type)
+                    .context(format!("URI: {}", self.array_uri)));
+            }
+        };
+
+        let Some(fragment_uri) = fragment_uri else {
+            return Ok(None);
+        };
+
+        let mut vac = String::new();
+        for fragment in fragments {
+            vac += &fragment.uri().remove_trailing_slash().to_string();
+            vac += "\n";
+        }
+        let name = fragment_uri.remove_trailing_slash().last_path_part();
+        let vac_uri = self
+            .array_uri
+            .join(COMMITS_DIR)
+            .join(&(name + VACUUM_FILE_SUFFIX));
+        vfs.file_create(&vac_uri)?;
+        vfs.file_write(&vac_uri, 0, vac.as_bytes())?;
+
+        Ok(Some(fragment_uri))
+    }
+}
+
+// Dense fragments are rewritten over the space tiles covering the union of
+// their non-empty domains, which becomes the non-empty domain of the new
+// fragment. Cells that no fragment wrote are rewritten as fill values, so
+// the union must not overlap older fragments outside of the range which
+// those cells would hide.
+fn consolidate_dense(
+    vfs: &dyn VFSService,
+    array: &array::Array,
+    timestamp_range: (u64, u64),
+) -> Result<uri::URI> {
+    let schema = array.schema();
+    let mut bounds: Vec<(i128, i128)> = Vec::new();
+    for fragment in array.fragments() {
+        let ned = integral_bounds(schema, fragment.non_empty_domain())?;
+        if bounds.is_empty() {
+            bounds = ned;
+            continue;
+        }
+        for (b, (lo, hi)) in bounds.iter_mut().zip(ned) {
+            *b = (b.0.min(lo), b.1.max(hi));
+        }
+    }
+
+    let names: HashSet<&str> =
+        array.fragments().iter().map(|f| f.name()).collect();
+    let all = array::Array::open(vfs, array.uri())?;
+    for fragment in all.fragments() {
+        if names.contains(fragment.name())
+            || fragment.timestamp_range() >= timestamp_range
+            || fragment.non_empty_domain().is_empty()
+        {
+            continue;
+        }
+        let ned = integral_bounds(schema, fragment.non_empty_domain())?;
+        if bounds
+            .iter()
+            .zip(ned)
+            .all(|((lo, hi), (olo, ohi))| *lo <= ohi && olo <= *hi)
+        {
+            return Err(anyhow!(
+                "Consolidated fragments overlap an older fragment outside \
+                of the timestamp range"
+            )
+            .context(format!("Fragment: {}", fragment.uri())));
+        }
+    }
+
+    let dims = schema.domain().dimensions();
+    let mut non_empty_domain = Vec::new();
+    for (dim, (lo, hi)) in dims.iter().zip(bounds.iter()) {
+        let dtype = dim.data_type();
+        let value = |v: i128| {
+            dtype.from_i128(v).ok_or_else(|| {
+                anyhow!("Dense dimension has non-integral type {:?}", dtype)
+            })
+        };
+        non_empty_domain.push(array::Range::new(value(*lo)?, value(*hi)?));
+    }
+
+    let subarray = tile_aligned_subarray(schema, &bounds)?;
+    let buffers = DenseReader::new(array)
+        .set_subarray(subarray.clone())
+        .read(vfs)?;
+
+    let mut writer = DenseWriter::new(array.uri(), array.schema_name(), schema)
+        .set_subarray(subarray)
+        .set_layout(Layout::RowMajor)
+        .set_non_empty_domain(non_empty_domain)
+        .set_timestamp_range(timestamp_range.0, timestamp_range.1);
+    for (name, buffer) in buffers {
+        writer = writer.set_buffer(&name, buffer);
+    }
+    writer.write(vfs)
+}
+
+// The subarray covering every space tile intersecting the bounds, clipped
+// to the end of the domain.
+pub(crate) fn tile_aligned_subarray(
+    schema: &array::Schema,
+    bounds: &[(i128, i128)],
+) -> Result<Subarray> {
+    let tiling = DenseTiling::new(schema, bounds)?;
+    let tiles = tiling.tile_range(bounds);
+    let first: Vec<i128> = tiles.iter().map(|(lo, _)| *lo).collect();
+    let last: Vec<i128> = tiles.iter().map(|(_, hi)| *hi).collect();
+    let region = tiling
+        .tile_bounds(&first)
+        .into_iter()
+        .zip(tiling.tile_bounds(&last).into_iter().map(|(_, hi)| hi));
+
+    let mut subarray = Subarray::new();
+    for (d, ((lo, _), hi)) in region.enumerate() {
+        let dim = &schema.domain().dimensions()[d];
+        let dtype = dim.data_type();
+        let domain_hi = dim
+            .range()
+            .and_then(|(_, hi)| dtype.to_i128(hi))
+            .unwrap_or(hi);
+        let value = |v: i128| {
+            dtype.from_i128(v).ok_or_else(|| {
+                anyhow!("Dense dimension has non-integral type {:?}", dtype)
+            })
+        };
+        subarray = subarray.set_range(
+            d,
+            array::Range::new(value(lo)?, value(hi.min(domain_hi))?),
+        );
+    }
+
+    Ok(subarray)
+}
+
+// Sparse cells are merged in global order. Duplicates are kept when the
+// schema allows them and otherwise only the newest cell is kept, unless
+// timestamps are kept in which case every version is kept. Cells removed
+// by delete conditions are dropped, and the conditions are recorded as
+// processed so that they are not applied to the merged cells written after
+// them.
+fn consolidate_sparse(
+    vfs: &dyn VFSService,
+    array: &array::Array,
+    timestamp_range: (u64, u64),
+    with_timestamps: bool,
+) -> Result<Option<uri::URI>> {
+    let buffers = SparseReader::new(array)
+        .set_layout(Layout::GlobalOrder)
+        .set_timestamps(with_timestamps)
+        .read(vfs)?;
+
+    let schema = array.schema();
+    let cell_num = schema
+        .domain()
+        .dimensions()
+        .first()
+        .and_then(|d| buffers.get(d.name()))
+        .map_or(0, |b| b.cell_num());
+    if cell_num == 0 {
+        return Ok(None);
+    }
+
+    let processed: Vec<&str> =
+        array.delete_conditions().iter().map(|c| c.name()).collect();
+    let mut writer =
+        SparseWriter::new(array.uri(), array.schema_name(), schema)
+            .set_timestamp_range(timestamp_range.0, timestamp_range.1)
+            .set_processed_conditions(&processed);
+    for (name, buffer) in buffers {
+        writer = writer.set_buffer(&name, buffer);
+    }
+    Ok(Some(writer.write(vfs)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::WRITE_FILE_SUFFIX;
+    use crate::datatype::DataType;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{QueryBuffer, QueryCondition};
+    use crate::storage::{self, ConditionOp};
+
+    fn write_sparse(
+        vfs: &dyn VFSService,
+        array: &array::Array,
+        timestamp: u64,
+        x: &[i64],
+        a: &[i32],
+    ) -> Result<()> {
+        SparseWriter::new(array.uri(), array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(x))
+            .set_buffer("a", QueryBuffer::from_values(a))
+            .set_timestamp(timestamp)
+            .write(vfs)?;
+        Ok(())
+    }
+
+    fn read_sparse(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        timestamp_end: u64,
+    ) -> Result<(Vec<i64>, Vec<i32>)> {
+        let array = array::Array::open_at(vfs, uri, 0, timestamp_end)?;
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .read(vfs)?;
+        Ok((results["x"].values(), results["a"].values()))
+    }
+
+    #[test]
+    fn sparse_with_timestamps() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) = fixtures::temp_array(
+            &vfs,
+            "consolidate",
+            &fixtures::sparse_schema(),
+        )?;
+        let uri = array.uri().clone();
+        // Fragments are written after the schema.
+        let t = storage::current_timestamp();
+        write_sparse(&vfs, &array, t + 1, &[1, 2], &[10, 20])?;
+        write_sparse(&vfs, &array, t + 2, &[2, 3], &[21, 30])?;
+        write_sparse(&vfs, &array, t + 3, &[1], &[11])?;
+
+        let consolidator = Consolidator::new(&uri).set_with_timestamps(true);
+        let fragment_uri = consolidator.consolidate(&vfs)?.unwrap();
+        let name = fragment_uri.last_path_part();
+        assert_eq!(storage::timestamp_range(&name)?, (t + 1, t + 3));
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.fragments().len(), 1);
+        assert!(array.fragments()[0].has_timestamps());
+        assert_eq!(
+            read_sparse(&vfs, &uri, t + 3)?,
+            (vec![1, 2, 3], vec![11, 21, 30])
+        );
+        assert!(consolidator.consolidate(&vfs)?.is_none());
+
+        // The replaced fragments serve earlier timestamps until they are
+        // vacuumed, after which the consolidated fragment's timestamps do.
+        assert_eq!(
+            read_sparse(&vfs, &uri, t + 2)?,
+            (vec![1, 2, 3], vec![10, 21, 30])
+        );
+        let commits = uri.join(COMMITS_DIR);
+        for entry in vfs.ls(&commits)? {
+            let file = entry.uri().last_path_part();
+            if file.ends_with(WRITE_FILE_SUFFIX) && !file.starts_with(&name) {
+                vfs.file_remove(&entry.uri())?;
+            }
+        }
+        vfs.file_remove(&commits.join(&(name.clone() + VACUUM_FILE_SUFFIX)))?;
+        assert_eq!(
+            read_sparse(&vfs, &uri, t + 2)?,
+            (vec![1, 2, 3], vec![10, 21, 30])
+        );
+        assert_eq!(read_sparse(&vfs, &uri, t + 1)?, (vec![1, 2], vec![10, 20]));
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_with_deletes() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) = fixtures::temp_array(
+            &vfs,
+            "consolidate",
+            &fixtures::sparse_schema(),
+        )?;
+        let uri = array.uri().clone();
+        let t = storage::current_timestamp();
+        write_sparse(&vfs, &array, t + 1, &[1, 2], &[10, 20])?;
+        let condition = QueryCondition::compare("a", ConditionOp::Ge, 20i32);
+        array::DeleteCondition::create(
+            &vfs,
+            &uri,
+            &condition.to_node()?,
+            t + 2,
+        )?;
+        write_sparse(&vfs, &array, t + 3, &[3], &[30])?;
+        let expected = (vec![1, 3], vec![10, 30]);
+        assert_eq!(read_sparse(&vfs, &uri, t + 3)?, expected);
+
+        // The merged fragment is older than the delete but already had it
+        // applied, so the cell written after the delete survives.
+        Consolidator::new(&uri).consolidate(&vfs)?.unwrap();
+        assert_eq!(array::Array::open(&vfs, &uri)?.fragments().len(), 1);
+        assert_eq!(read_sparse(&vfs, &uri, t + 3)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn dense() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let domain = array::Range::from_values(1i32, 8);
+        let schema = storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "x",
+                DataType::Int32,
+                &domain,
+            )
+            .set_extent(2i32.to_le_bytes().to_vec())]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        );
+        let (_dir, array) = fixtures::temp_array(&vfs, "consolidate", &schema)?;
+        let uri = array.uri().clone();
+        let t = storage::current_timestamp();
+        // The second fragment only covers the first cell of its tile.
+        let writes = [(t + 1, 1, [1, 2], 2), (t + 2, 5, [5, 6], 5)];
+        for (ts, lo, values, hi) in writes {
+            DenseWriter::new(&uri, array.schema_name(), array.schema())
+                .set_subarray(Subarray::new().set_typed_range(0, lo, lo + 1))
+                .set_non_empty_domain(vec![array::Range::from_values(lo, hi)])
+                .set_buffer("a", QueryBuffer::from_values(&values))
+                .set_timestamp(ts)
+                .write(&vfs)?;
+        }
+
+        assert!(Consolidator::new(&uri)
+            .set_with_timestamps(true)
+            .consolidate(&vfs)
+            .is_err());
+        Consolidator::new(&uri).consolidate(&vfs)?.unwrap();
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.fragments().len(), 1);
+        assert_eq!(
+            array.fragments()[0].non_empty_domain(),
+            &[array::Range::from_values(1i32, 5)]
+        );
+        let results = DenseReader::new(&array)
+            .set_subarray(Subarray::new().set_typed_range(0, 1i32, 6))
+            .read(&vfs)?;
+        let fill = i32::MIN;
+        assert_eq!(
+            results["a"].values::<i32>(),
+            vec![1, 2, fill, fill, 5, fill]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dense_over_older_fragments() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "x",
+                DataType::Int32,
+                &array::Range::from_values(1i32, 4),
+            )
+            .set_extent(1i32.to_le_bytes().to_vec())]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        );
+        let (_dir, array) = fixtures::temp_array(&vfs, "consolidate", &schema)?;
+        let uri = array.uri().clone();
+        let t = storage::current_timestamp();
+        let writes = [
+            (t + 1, (1, 4), vec![1, 2, 3, 4]),
+            (t + 2, (1, 1), vec![10]),
+            (t + 3, (4, 4), vec![40]),
+        ];
+        for (ts, (lo, hi), values) in writes {
+            DenseWriter::new(&uri, array.schema_name(), array.schema())
+                .set_subarray(Subarray::new().set_typed_range(0, lo, hi))
+                .set_buffer("a", QueryBuffer::from_values(&values))
+                .set_timestamp(ts)
+                .write(&vfs)?;
+        }
+        let read = || -> Result<Vec<i32>> {
+            let array = array::Array::open(&vfs, &uri)?;
+            Ok(DenseReader::new(&array).read(&vfs)?["a"].values())
+        };
+        assert_eq!(read()?, vec![10, 2, 3, 40]);
+
+        // Merging the last two fragments would write fill values over the
+        // cells of the first one between them.
+        assert!(Consolidator::new(&uri)
+            .set_timestamp_range(t + 2, t + 3)
+            .consolidate(&vfs)
+            .is_err());
+        assert_eq!(read()?, vec![10, 2, 3, 40]);
+
+        Consolidator::new(&uri).consolidate(&vfs)?.unwrap();
+        assert_eq!(array::Array::open(&vfs, &uri)?.fragments().len(), 1);
+        assert_eq!(read()?, vec![10, 2, 3, 40]);
+        Ok(())
+    }
+}
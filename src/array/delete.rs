@@ -0,0 +1,74 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use crate::array::{DeleteCommit, COMMITS_DIR};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+// A delete commit. Every cell written at or before the timestamp that
+// matches the condition is deleted, unless the fragment holding the cell
+// already processed the condition.
+#[derive(Clone, Debug)]
+pub struct DeleteCondition {
+    name: String,
+    timestamp: u64,
+    condition: storage::ConditionNode,
+}
+
+impl DeleteCondition {
+    pub fn load(vfs: &dyn VFSService, commit: &DeleteCommit) -> Result<Self> {
+        let timestamp = delete_timestamp(commit.name())?;
+        let condition =
+            storage::ConditionNode::load(vfs, commit.uri(), commit.offset())?;
+        Ok(DeleteCondition {
+            name: commit.name().to_string(),
+            timestamp,
+            condition,
+        })
+    }
+
+    // Commit a condition deleting every cell written at or before the
+    // timestamp that matches it.
+    pub fn create(
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        condition: &storage::ConditionNode,
+        timestamp: u64,
+    ) -> Result<uri::URI> {
+        let commits_uri = array_uri.join(COMMITS_DIR);
+        if !vfs.dir_exists(&commits_uri)? {
+            vfs.dir_create(&commits_uri)?;
+        }
+
+        let name = storage::timestamped_name(timestamp, timestamp)
+            + storage::DELETE_FILE_SUFFIX;
+        let uri = commits_uri.join(&name);
+        condition
+            .store(vfs, &uri)
+            .map_err(|err| err.context(format!("URI: {}", uri)))?;
+        Ok(uri)
+    }
+
+    // The commit file name which fragments use to record that the
+    // condition has been processed.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn condition(&self) -> &storage::ConditionNode {
+        &self.condition
+    }
+}
+
+fn delete_timestamp(name: &str) -> Result<u64> {
+    let stem = name
+        .strip_suffix(storage::DELETE_FILE_SUFFIX)
+        .unwrap_or(name);
+    Ok(storage::timestamp_range(stem)?.0)
+}
@@ -1,39 +1,102 @@
 // This file is part of tdbtk released under the MIT license.
 // Copyright (c) 2023 TileDB, Inc.
 
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
 use crate::io::service::WalkOptions;
-use crate::io::{service, uri, FSEntry};
+use crate::io::{service, uri, FSEntry, FSEntryType};
+use crate::storage::{
+    self, DELETE_FILE_SUFFIX, ENUMERATIONS_DIR, META_FILE_SUFFIX,
+};
 use crate::Result;
 
-const SCHEMA_DIR: &str = "__schema";
-const COMMITS_DIR: &str = "__commits";
-const FRAGMENTS_DIR: &str = "__fragments";
+pub(crate) const SCHEMA_DIR: &str = "__schema";
+pub(crate) const COMMITS_DIR: &str = "__commits";
+pub(crate) const FRAGMENTS_DIR: &str = "__fragments";
+pub(crate) const FRAGMENT_META_DIR: &str = "__fragment_meta";
+pub(crate) const METADATA_DIR: &str = "__meta";
 //const DIMENSION_LABELS_DIR: &str = "__dimension_labels";
-const ENUMERATIONS_DIR: &str = "__enumerations";
 
 //const FILE_SUFFIX: &str = ".tdb";
-const OK_FILE_SUFFIX: &str = ".ok";
-const WRITE_FILE_SUFFIX: &str = ".wrt";
+pub(crate) const OK_FILE_SUFFIX: &str = ".ok";
+pub(crate) const WRITE_FILE_SUFFIX: &str = ".wrt";
+const CONSOLIDATED_COMMITS_FILE_SUFFIX: &str = ".con";
+pub(crate) const VACUUM_FILE_SUFFIX: &str = ".vac";
+pub(crate) const IGNORE_FILE_SUFFIX: &str = ".ign";
+
+pub(crate) const OLD_SCHEMA_NAME: &str = "__array_schema.tdb";
+
+// The location of a delete commit. Delete commits merged by commit
+// consolidation are stored inside the consolidated commits file rather than
+// in their own file.
+#[derive(Clone)]
+pub struct DeleteCommit {
+    name: String,
+    uri: uri::URI,
+    offset: u64,
+}
 
-const OLD_SCHEMA_NAME: &str = "__array_schema.tdb";
+impl DeleteCommit {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The file holding the serialized condition.
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
 
 pub struct Directory {
     array_uri: uri::URI,
+    timestamp_start: u64,
+    timestamp_end: u64,
     commit_entries: Vec<FSEntry>,
+    fragment_meta_entries: Vec<FSEntry>,
+    metadata_entries: Vec<FSEntry>,
     root_entries: Vec<FSEntry>,
     schema_entries: Vec<FSEntry>,
+    old_fragments: Vec<String>,
+    consolidated_fragments: Vec<String>,
+    consolidated_deletes: Vec<DeleteCommit>,
+    ignored: HashSet<String>,
+    vacuumed: Vec<String>,
+    vacuumed_metadata: Vec<String>,
 }
 
 impl Directory {
     pub fn new(array_uri: &uri::URI) -> Self {
         Directory {
             array_uri: array_uri.clone(),
+            timestamp_start: 0,
+            timestamp_end: u64::MAX,
             commit_entries: Vec::new(),
+            fragment_meta_entries: Vec::new(),
+            metadata_entries: Vec::new(),
             root_entries: Vec::new(),
             schema_entries: Vec::new(),
+            old_fragments: Vec::new(),
+            consolidated_fragments: Vec::new(),
+            consolidated_deletes: Vec::new(),
+            ignored: HashSet::new(),
+            vacuumed: Vec::new(),
+            vacuumed_metadata: Vec::new(),
         }
     }
 
+    // Only commits written entirely within the inclusive range are listed.
+    pub fn set_timestamp_range(mut self, start: u64, end: u64) -> Self {
+        self.timestamp_start = start;
+        self.timestamp_end = end;
+        self
+    }
+
     pub fn load_all(&mut self, vfs: &dyn service::VFSService) -> Result<()> {
         let wopts = WalkOptions::default().set_min_depth(1).set_max_depth(1);
 
@@ -42,21 +105,135 @@ impl Directory {
             Ok(true)
         })?;
 
+        // Fragments before version 5 are committed by a file within their
+        // directory rather than by an ok file.
+        for entry in self.root_entries.iter() {
+            let uri = entry.uri().remove_trailing_slash();
+            let name = uri.last_path_part();
+            if matches!(entry.entry_type(), FSEntryType::Dir)
+                && storage::timestamp_range(&name).is_ok()
+                && storage::get_fragment_version(&name).is_ok_and(|v| v < 5)
+                && vfs.file_exists(&uri.join(storage::FRAGMENT_FILENAME))?
+            {
+                self.old_fragments.push(name);
+            }
+        }
+
         let commits_uri = self.array_uri.join(COMMITS_DIR);
         vfs.walk_with_options(&commits_uri, &wopts, &mut |entry| {
             self.commit_entries.push(entry.clone());
             Ok(true)
         })?;
 
+        let fragment_meta_uri = self.array_uri.join(FRAGMENT_META_DIR);
+        vfs.walk_with_options(&fragment_meta_uri, &wopts, &mut |entry| {
+            self.fragment_meta_entries.push(entry.clone());
+            Ok(true)
+        })?;
+
+        let metadata_uri = self.array_uri.join(METADATA_DIR);
+        vfs.walk_with_options(&metadata_uri, &wopts, &mut |entry| {
+            self.metadata_entries.push(entry.clone());
+            Ok(true)
+        })?;
+
         let schema_uri = self.array_uri.join(SCHEMA_DIR);
         vfs.walk_with_options(&schema_uri, &wopts, &mut |entry| {
             self.schema_entries.push(entry.clone());
             Ok(true)
         })?;
 
+        // Sort entries so that listings are deterministic.
+        for entries in [
+            &mut self.root_entries,
+            &mut self.commit_entries,
+            &mut self.fragment_meta_entries,
+            &mut self.metadata_entries,
+            &mut self.schema_entries,
+        ] {
+            entries.sort_by_key(|e| e.uri().last_path_part());
+        }
+
+        for con_uri in self.consolidated_commit_uris() {
+            self.load_consolidated_commits(vfs, &con_uri)?;
+        }
+
+        for ign_uri in self.ignore_file_uris() {
+            for line in read_lines(vfs, &ign_uri)? {
+                self.ignored.insert(commit_name(&line));
+            }
+        }
+
+        for vac_uri in self.vacuum_file_uris()? {
+            self.vacuumed.extend(read_lines(vfs, &vac_uri)?);
+        }
+
+        for vac_uri in self.metadata_vacuum_file_uris()? {
+            self.vacuumed_metadata.extend(read_lines(vfs, &vac_uri)?);
+        }
+
+        Ok(())
+    }
+
+    // Each line of a consolidated commits file names a commit relative to
+    // the array. Delete commits are followed by the size of the serialized
+    // condition and the condition itself.
+    fn load_consolidated_commits(
+        &mut self,
+        vfs: &dyn service::VFSService,
+        con_uri: &uri::URI,
+    ) -> Result<()> {
+        let data = read_file(vfs, con_uri)?;
+        let mut pos = 0;
+        while pos < data.len() {
+            let end = data[pos..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map_or(data.len(), |p| pos + p);
+            let line = String::from_utf8_lossy(&data[pos..end]).to_string();
+            pos = end + 1;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.ends_with(DELETE_FILE_SUFFIX) {
+                let size = data
+                    .get(pos..pos + 8)
+                    .and_then(|s| s.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or_else(|| {
+                        anyhow!("Truncated delete commit '{}'", line)
+                            .context(format!("URI: {}", con_uri))
+                    })?;
+                self.consolidated_deletes.push(DeleteCommit {
+                    name: commit_name(&line),
+                    uri: con_uri.clone(),
+                    offset: (pos + 8) as u64,
+                });
+                pos += 8 + size as usize;
+            } else {
+                self.consolidated_fragments.push(commit_name(&line));
+            }
+        }
+
         Ok(())
     }
 
+    fn in_range(&self, name: &str) -> Result<bool> {
+        let (start, end) = storage::timestamp_range(name)?;
+        Ok(start >= self.timestamp_start && end <= self.timestamp_end)
+    }
+
+    fn overlaps(&self, name: &str) -> Result<bool> {
+        let (start, end) = storage::timestamp_range(name)?;
+        Ok(start <= self.timestamp_end && end >= self.timestamp_start)
+    }
+
+    pub fn array_uri(&self) -> &uri::URI {
+        &self.array_uri
+    }
+
     pub fn schema_uris(&self) -> Vec<uri::URI> {
         let mut ret: Vec<uri::URI> = Vec::new();
 
@@ -76,39 +253,450 @@ impl Directory {
         ret
     }
 
-    // PJD: This one feels like it could be greatly simplified by using the
-    // more functional approach with filters and maps and chained iterator
-    // collection or w/e. However, I failed my first attempt so I'll come back
-    // to it later.
-    pub fn fragment_uris(&self) -> Vec<uri::URI> {
-        let mut fragments: Vec<uri::URI> = Vec::new();
+    // Every fragment directory on disk whether or not it is committed.
+    // Fragments written before format version 12 are stored in the array
+    // directory itself and later ones under __fragments.
+    pub fn fragment_dir_uris(
+        &self,
+        vfs: &dyn service::VFSService,
+    ) -> Result<Vec<uri::URI>> {
+        let mut ret: Vec<uri::URI> = self
+            .root_entries
+            .iter()
+            .filter(|e| matches!(e.entry_type(), FSEntryType::Dir))
+            .map(|e| e.uri())
+            .filter(|u| storage::timestamp_range(&u.last_path_part()).is_ok())
+            .collect();
+
+        let wopts = WalkOptions::default().set_min_depth(1).set_max_depth(1);
+        let fragments_uri = self.array_uri.join(FRAGMENTS_DIR);
+        vfs.walk_with_options(&fragments_uri, &wopts, &mut |entry| {
+            if matches!(entry.entry_type(), FSEntryType::Dir) {
+                ret.push(entry.uri());
+            }
+            Ok(true)
+        })?;
+
+        ret.sort_by_key(|u| u.remove_trailing_slash().last_path_part());
+        Ok(ret)
+    }
+
+    // Whether a fragment is hidden by an ignore file.
+    pub fn is_ignored(&self, name: &str) -> bool {
+        self.ignored.contains(name)
+    }
+
+    // Every committed fragment within the timestamp range, excluding those
+    // that were replaced by consolidation or explicitly ignored. Fragments
+    // are ordered from oldest to newest.
+    pub fn fragment_uris(&self) -> Result<Vec<uri::URI>> {
+        self.list_fragments(false)
+    }
+
+    // Like fragment_uris but also lists consolidated fragments that only
+    // partially overlap the timestamp range. These are only visible if
+    // they store the timestamp of each of their cells. Until the fragments
+    // they replaced are vacuumed those are used instead.
+    pub fn overlapping_fragment_uris(&self) -> Result<Vec<uri::URI>> {
+        self.list_fragments(true)
+    }
+
+    fn list_fragments(&self, overlapping: bool) -> Result<Vec<uri::URI>> {
+        let mut fragments: Vec<(String, uri::URI)> = Vec::new();
 
         for entry in self.root_entries.iter() {
-            if !entry.uri().path().ends_with(OK_FILE_SUFFIX) {
+            let name = entry.uri().last_path_part();
+            if let Some(name) = name.strip_suffix(OK_FILE_SUFFIX) {
+                fragments.push((name.to_string(), self.array_uri.join(name)));
+            }
+        }
+        for name in self.old_fragments.iter() {
+            fragments.push((name.clone(), self.array_uri.join(name)));
+        }
+
+        let committed = self
+            .commit_entries
+            .iter()
+            .filter_map(|e| {
+                let name = e.uri().last_path_part();
+                name.strip_suffix(WRITE_FILE_SUFFIX).map(|n| n.to_string())
+            })
+            .chain(self.consolidated_fragments.iter().cloned());
+        for name in committed {
+            let uri = self.array_uri.join(FRAGMENTS_DIR).join(&name);
+            fragments.push((name, uri));
+        }
+
+        let vacuumed: HashSet<String> =
+            self.vacuumed.iter().map(|v| commit_name(v)).collect();
+
+        let unvacuumed: HashSet<String> =
+            suffixed_uris(&self.commit_entries, VACUUM_FILE_SUFFIX)
+                .iter()
+                .map(|u| {
+                    let name = u.last_path_part();
+                    name.trim_end_matches(VACUUM_FILE_SUFFIX).to_string()
+                })
+                .collect();
+
+        let mut seen = HashSet::new();
+        let mut ret = Vec::new();
+        for (name, uri) in fragments {
+            let visible = self.in_range(&name)?
+                || (overlapping
+                    && self.overlaps(&name)?
+                    && !unvacuumed.contains(&name));
+            if !visible
+                || vacuumed.contains(&name)
+                || self.ignored.contains(&name)
+                || !seen.insert(name.clone())
+            {
+                continue;
+            }
+            ret.push((storage::timestamp_range(&name)?, name, uri));
+        }
+
+        ret.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        Ok(ret.into_iter().map(|(_, _, uri)| uri).collect())
+    }
+
+    // Every delete commit within the timestamp range ordered from oldest
+    // to newest.
+    pub fn delete_commits(&self) -> Result<Vec<DeleteCommit>> {
+        let files = self.commit_entries.iter().filter_map(|e| {
+            let name = e.uri().last_path_part();
+            name.ends_with(DELETE_FILE_SUFFIX).then(|| DeleteCommit {
+                name,
+                uri: e.uri(),
+                offset: 0,
+            })
+        });
+
+        let mut seen = HashSet::new();
+        let mut ret = Vec::new();
+        for commit in files.chain(self.consolidated_deletes.iter().cloned()) {
+            let stem = commit.name.trim_end_matches(DELETE_FILE_SUFFIX);
+            if !self.in_range(stem)?
+                || self.ignored.contains(&commit.name)
+                || !seen.insert(commit.name.clone())
+            {
                 continue;
             }
+            ret.push((storage::timestamp_range(stem)?, commit));
+        }
+
+        ret.sort_by(|a, b| (a.0, &a.1.name).cmp(&(b.0, &b.1.name)));
+        Ok(ret.into_iter().map(|(_, commit)| commit).collect())
+    }
+
+    // The fragments committed by consolidated commits files rather than
+    // their own commit files.
+    pub fn consolidated_fragment_names(&self) -> &[String] {
+        &self.consolidated_fragments
+    }
+
+    pub fn consolidated_commit_uris(&self) -> Vec<uri::URI> {
+        suffixed_uris(&self.commit_entries, CONSOLIDATED_COMMITS_FILE_SUFFIX)
+    }
+
+    pub fn ignore_file_uris(&self) -> Vec<uri::URI> {
+        suffixed_uris(&self.commit_entries, IGNORE_FILE_SUFFIX)
+    }
+
+    // Vacuum files are written by fragment consolidation and list the
+    // fragments that the consolidated fragment replaced. Older arrays store
+    // them in the array root.
+    pub fn vacuum_file_uris(&self) -> Result<Vec<uri::URI>> {
+        let mut ret = Vec::new();
+        let uris = suffixed_uris(&self.root_entries, VACUUM_FILE_SUFFIX)
+            .into_iter()
+            .chain(suffixed_uris(&self.commit_entries, VACUUM_FILE_SUFFIX));
+        for uri in uris {
+            let name = uri.last_path_part();
+            if self.in_range(name.trim_end_matches(VACUUM_FILE_SUFFIX))? {
+                ret.push(uri);
+            }
+        }
+        Ok(ret)
+    }
 
-            let path = entry.uri().path();
-            path.strip_suffix(OK_FILE_SUFFIX)
-                .expect("Error removing suffix from string with suffix");
-            let mut new_uri = entry.uri().clone();
-            new_uri.set_path(&path);
-            fragments.push(new_uri);
+    // The fragments listed by vacuum files that may be removed.
+    pub fn vacuumed_fragment_uris(&self) -> Result<Vec<uri::URI>> {
+        self.resolve_uris(&self.vacuumed)
+    }
+
+    fn resolve_uris(&self, lines: &[String]) -> Result<Vec<uri::URI>> {
+        let mut ret = Vec::new();
+        for line in lines {
+            if line.contains("://") {
+                ret.push(uri::URI::from_string(line)?);
+            } else {
+                ret.push(self.array_uri.join(line.trim_start_matches('/')));
+            }
         }
+        Ok(ret)
+    }
+
+    // Array metadata files within the timestamp range that were not
+    // replaced by metadata consolidation, ordered from oldest to newest.
+    pub fn metadata_uris(&self) -> Result<Vec<uri::URI>> {
+        let vacuumed: HashSet<String> = self
+            .vacuumed_metadata
+            .iter()
+            .map(|v| commit_name(v))
+            .collect();
 
-        for entry in self.commit_entries.iter() {
-            if !entry.uri().path().ends_with(WRITE_FILE_SUFFIX) {
+        let mut ret = Vec::new();
+        for entry in self.metadata_entries.iter() {
+            let name = entry.uri().last_path_part();
+            if name.ends_with(VACUUM_FILE_SUFFIX)
+                || vacuumed.contains(&name)
+                || !self.in_range(&name)?
+            {
                 continue;
             }
+            ret.push((storage::timestamp_range(&name)?, name, entry.uri()));
+        }
+
+        ret.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        Ok(ret.into_iter().map(|(_, _, uri)| uri).collect())
+    }
+
+    pub fn metadata_vacuum_file_uris(&self) -> Result<Vec<uri::URI>> {
+        let mut ret = Vec::new();
+        for uri in suffixed_uris(&self.metadata_entries, VACUUM_FILE_SUFFIX) {
+            let name = uri.last_path_part();
+            if self.in_range(name.trim_end_matches(VACUUM_FILE_SUFFIX))? {
+                ret.push(uri);
+            }
+        }
+        Ok(ret)
+    }
+
+    // The metadata files listed by vacuum files that may be removed.
+    pub fn vacuumed_metadata_uris(&self) -> Result<Vec<uri::URI>> {
+        self.resolve_uris(&self.vacuumed_metadata)
+    }
+
+    // Consolidated fragment metadata files within the timestamp range
+    // ordered from oldest to newest.
+    pub fn fragment_meta_uris(&self) -> Result<Vec<uri::URI>> {
+        let mut ret = Vec::new();
+        for uri in suffixed_uris(&self.fragment_meta_entries, META_FILE_SUFFIX)
+        {
+            let name = uri.last_path_part();
+            let stem = name.trim_end_matches(META_FILE_SUFFIX);
+            let (start, end) = storage::timestamp_range(stem)?;
+            if end <= self.timestamp_end {
+                ret.push(((start, end), uri));
+            }
+        }
+        ret.sort_by_key(|(range, _)| *range);
+        Ok(ret.into_iter().map(|(_, uri)| uri).collect())
+    }
+}
+
+fn suffixed_uris(entries: &[FSEntry], suffix: &str) -> Vec<uri::URI> {
+    entries
+        .iter()
+        .map(|e| e.uri())
+        .filter(|u| u.last_path_part().ends_with(suffix))
+        .collect()
+}
+
+fn read_file(vfs: &dyn service::VFSService, uri: &uri::URI) -> Result<Vec<u8>> {
+    let size = vfs.file_size(uri)?;
+    vfs.file_read_vec(uri, size, 0)
+}
+
+fn read_lines(
+    vfs: &dyn service::VFSService,
+    uri: &uri::URI,
+) -> Result<Vec<String>> {
+    let data = read_file(vfs, uri)?;
+    Ok(String::from_utf8_lossy(&data)
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect())
+}
+
+// Commits are referenced by relative or absolute URIs depending on the
+// writer. Fragments are identified by name without their commit suffix.
+fn commit_name(path: &str) -> String {
+    let name = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    name.strip_suffix(WRITE_FILE_SUFFIX)
+        .or_else(|| name.strip_suffix(OK_FILE_SUFFIX))
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    const UUID: &str = "0123456789abcdef0123456789abcdef";
+
+    fn name(start: u64, end: u64) -> String {
+        format!("__{}_{}_{}_21", start, end, UUID)
+    }
+
+    // Commit files are written by hand in an array directory that is
+    // removed with the guard.
+    struct TempArray {
+        _dir: fixtures::TempDir,
+        path: PathBuf,
+    }
+
+    impl TempArray {
+        fn new() -> Self {
+            let dir = fixtures::TempDir::new("directory").unwrap();
+            let path = PathBuf::from(dir.uri().path());
+            for dir in [COMMITS_DIR, FRAGMENTS_DIR, SCHEMA_DIR] {
+                fs::create_dir_all(path.join(dir)).unwrap();
+            }
+            TempArray { _dir: dir, path }
+        }
 
-            let path = entry.uri().path();
-            path.strip_suffix(WRITE_FILE_SUFFIX)
-                .expect("Error removing suffix from string with suffix");
+        fn write(&self, path: &str, data: &[u8]) {
+            let path = self.path.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+
+        fn fragment(&self, start: u64, end: u64) -> String {
+            let name = name(start, end);
+            fs::create_dir_all(self.path.join(FRAGMENTS_DIR).join(&name))
+                .unwrap();
+            name
+        }
 
-            let new_uri = self.array_uri.join(FRAGMENTS_DIR).join(&path);
-            fragments.push(new_uri);
+        fn directory(&self, start: u64, end: u64) -> Directory {
+            let uri = uri::URI::from_string(&path_str(&self.path)).unwrap();
+            let mut dir = Directory::new(&uri).set_timestamp_range(start, end);
+            dir.load_all(&PosixVFSService::default()).unwrap();
+            dir
         }
+    }
+
+    fn path_str(path: &Path) -> String {
+        path.to_string_lossy().to_string()
+    }
+
+    fn names(uris: Vec<uri::URI>) -> Vec<String> {
+        uris.iter().map(|u| u.last_path_part()).collect()
+    }
+
+    #[test]
+    fn write_commits() {
+        let arr = TempArray::new();
+        let first = arr.fragment(1, 1);
+        let second = arr.fragment(2, 2);
+        arr.write(&format!("__commits/{}.wrt", second), b"");
+        arr.write(&format!("__commits/{}.wrt", first), b"");
+
+        // Fragments without a commit file are not listed.
+        arr.fragment(3, 3);
+
+        let dir = arr.directory(0, u64::MAX);
+        let fragments = dir.fragment_uris().unwrap();
+        assert_eq!(names(fragments), vec![first.clone(), second]);
+
+        let dir = arr.directory(0, 1);
+        assert_eq!(names(dir.fragment_uris().unwrap()), vec![first]);
+    }
+
+    #[test]
+    fn ok_files() {
+        let arr = TempArray::new();
+        let name = name(5, 5);
+        fs::create_dir_all(arr.path.join(&name)).unwrap();
+        arr.write(&format!("{}.ok", name), b"");
+
+        let dir = arr.directory(0, u64::MAX);
+        let fragments = dir.fragment_uris().unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].path(), arr.path.join(&name).to_string_lossy());
+    }
+
+    #[test]
+    fn consolidated_commits() {
+        let arr = TempArray::new();
+        let first = arr.fragment(1, 1);
+        let second = arr.fragment(2, 2);
+        let delete = format!("{}.del", name(3, 3));
+
+        let mut con = Vec::new();
+        con.extend_from_slice(format!("__fragments/{}\n", first).as_bytes());
+        con.extend_from_slice(format!("__commits/{}\n", delete).as_bytes());
+        con.extend_from_slice(&4u64.to_le_bytes());
+        con.extend_from_slice(b"\nabc");
+        con.extend_from_slice(format!("__fragments/{}\n", second).as_bytes());
+        arr.write(&format!("__commits/{}.con", name(1, 3)), &con);
+
+        // Commits already present in a consolidated file are listed once.
+        arr.write(&format!("__commits/{}.wrt", first), b"");
+
+        let dir = arr.directory(0, u64::MAX);
+        assert_eq!(
+            names(dir.fragment_uris().unwrap()),
+            vec![first.clone(), second.clone()]
+        );
+
+        let deletes = dir.delete_commits().unwrap();
+        assert_eq!(deletes.len(), 1);
+        assert_eq!(deletes[0].name(), delete);
+        assert!(deletes[0].uri().last_path_part().ends_with(".con"));
+        let offset = (first.len() + 13 + delete.len() + 11 + 8) as u64;
+        assert_eq!(deletes[0].offset(), offset);
+
+        let dir = arr.directory(2, 2);
+        assert_eq!(names(dir.fragment_uris().unwrap()), vec![second]);
+        assert!(dir.delete_commits().unwrap().is_empty());
+    }
+
+    #[test]
+    fn vacuum_and_ignore() {
+        let arr = TempArray::new();
+        let first = arr.fragment(1, 1);
+        let second = arr.fragment(2, 2);
+        let merged = arr.fragment(1, 2);
+        let third = arr.fragment(3, 3);
+        for name in [&first, &second, &merged, &third] {
+            arr.write(&format!("__commits/{}.wrt", name), b"");
+        }
+
+        let vac = format!(
+            "file://{}/__fragments/{}\nfile://{}/__fragments/{}\n",
+            path_str(&arr.path),
+            first,
+            path_str(&arr.path),
+            second
+        );
+        arr.write(&format!("__commits/{}.vac", name(1, 2)), vac.as_bytes());
+        arr.write(
+            &format!("__commits/{}.ign", name(4, 4)),
+            format!("__commits/{}.wrt\n", third).as_bytes(),
+        );
+
+        let dir = arr.directory(0, u64::MAX);
+        assert_eq!(names(dir.fragment_uris().unwrap()), vec![merged]);
+        assert_eq!(
+            names(dir.vacuumed_fragment_uris().unwrap()),
+            vec![first.clone(), second.clone()]
+        );
 
-        fragments
+        // Opening before consolidation shows the original fragments.
+        let dir = arr.directory(0, 1);
+        assert_eq!(names(dir.fragment_uris().unwrap()), vec![first]);
+        assert!(dir.vacuum_file_uris().unwrap().is_empty());
     }
 }
@@ -0,0 +1,278 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+
+use crate::array::directory::SCHEMA_DIR;
+use crate::datatype::DataType;
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage::{self, CELL_VAR_SIZE};
+use crate::Result;
+
+// The labels of an enumerated attribute. Attribute values are indexes into
+// the list of values.
+#[derive(Clone, Debug)]
+pub struct Enumeration {
+    name: String,
+    path: String,
+    data_type: DataType,
+    cell_val_num: u32,
+    ordered: bool,
+    values: Vec<Vec<u8>>,
+}
+
+impl Enumeration {
+    // Fixed size values must hold cell_val_num values of the datatype and
+    // every value must be unique.
+    pub fn new(
+        name: &str,
+        data_type: DataType,
+        cell_val_num: u32,
+        ordered: bool,
+        values: Vec<Vec<u8>>,
+    ) -> Result<Enumeration> {
+        Enumeration {
+            name: name.to_string(),
+            path: String::new(),
+            data_type,
+            cell_val_num,
+            ordered,
+            values: Vec::new(),
+        }
+        .extend(values)
+    }
+
+    // Returns a copy of the enumeration with values appended.
+    pub fn extend(&self, values: Vec<Vec<u8>>) -> Result<Enumeration> {
+        let size = self.data_type.size() * self.cell_val_num as usize;
+        let mut ret = self.clone();
+        for value in values {
+            if !self.is_var_sized() && value.len() != size {
+                return Err(anyhow!(
+                    "Invalid value size {} for enumeration '{}'",
+                    value.len(),
+                    self.name
+                ));
+            }
+            if ret.index_of(&value).is_some() {
+                return Err(anyhow!(
+                    "Duplicate value in enumeration '{}'",
+                    self.name
+                ));
+            }
+            ret.values.push(value);
+        }
+        Ok(ret)
+    }
+
+    pub fn load(
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        path: &str,
+    ) -> Result<Enumeration> {
+        let schema_dir = array_uri.join(SCHEMA_DIR);
+        let storage = storage::Enumeration::load(vfs, &schema_dir, path)?;
+        Enumeration::try_from(storage)
+    }
+
+    // Write the enumeration under the array's schema directory at a new
+    // path.
+    pub fn store(
+        &self,
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        path: &str,
+    ) -> Result<()> {
+        let dir = array_uri.join(SCHEMA_DIR).join(storage::ENUMERATIONS_DIR);
+        if !vfs.dir_exists(&dir)? {
+            vfs.dir_create(&dir)?;
+        }
+
+        let mut data = Vec::new();
+        let mut offsets = Vec::new();
+        for value in self.values.iter() {
+            offsets.push(data.len() as u64);
+            data.extend_from_slice(value);
+        }
+        if !self.is_var_sized() {
+            offsets.clear();
+        }
+
+        let storage = storage::Enumeration {
+            version: storage::ENUMERATION_VERSION,
+            name_size: self.name.len() as u32,
+            name: self.name.as_bytes().to_vec(),
+            path_size: path.len() as u32,
+            path: path.as_bytes().to_vec(),
+            datatype: self.data_type as u8,
+            cell_val_num: self.cell_val_num,
+            ordered: self.ordered as u8,
+            data_size: data.len() as u64,
+            data,
+            offsets_size: offsets.len() as u64 * 8,
+            offsets,
+        };
+        storage::write_generic_tile(vfs, &dir.join(path), &storage.to_bytes()?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn cell_val_num(&self) -> u32 {
+        self.cell_val_num
+    }
+
+    pub fn is_var_sized(&self) -> bool {
+        self.cell_val_num == CELL_VAR_SIZE
+    }
+
+    pub fn ordered(&self) -> bool {
+        self.ordered
+    }
+
+    pub fn values(&self) -> &[Vec<u8>] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn value(&self, idx: usize) -> Option<&[u8]> {
+        self.values.get(idx).map(|v| v.as_slice())
+    }
+
+    pub fn index_of(&self, value: &[u8]) -> Option<usize> {
+        self.values.iter().position(|v| v == value)
+    }
+}
+
+impl TryFrom<storage::Enumeration> for Enumeration {
+    type Error = anyhow::Error;
+
+    fn try_from(storage: storage::Enumeration) -> Result<Self> {
+        let name = String::from_utf8(storage.name)?;
+        let data_type = DataType::from(storage.datatype);
+        if data_type == DataType::Invalid {
+            return Err(anyhow!("Invalid datatype for enumeration '{}'", name));
+        }
+
+        let values = if storage.cell_val_num == CELL_VAR_SIZE {
+            let offsets = &storage.offsets;
+            offsets
+                .iter()
+                .enumerate()
+                .map(|(i, start)| {
+                    let end = offsets
+                        .get(i + 1)
+                        .map_or(storage.data.len() as u64, |o| *o);
+                    storage
+                        .data
+                        .get(*start as usize..end as usize)
+                        .map(|v| v.to_vec())
+                        .ok_or_else(|| {
+                            anyhow!("Invalid offset for enumeration '{}'", name)
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let size = data_type.size() * storage.cell_val_num as usize;
+            if size == 0 || !storage.data.len().is_multiple_of(size) {
+                return Err(anyhow!(
+                    "Invalid data size for enumeration '{}'",
+                    name
+                ));
+            }
+            storage.data.chunks(size).map(|v| v.to_vec()).collect()
+        };
+
+        Ok(Enumeration {
+            name,
+            path: String::from_utf8(storage.path)?,
+            data_type,
+            cell_val_num: storage.cell_val_num,
+            ordered: storage.ordered != 0,
+            values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage_enumeration(
+        data_type: DataType,
+        cell_val_num: u32,
+        data: &[u8],
+        offsets: &[u64],
+    ) -> storage::Enumeration {
+        storage::Enumeration {
+            version: 0,
+            name_size: 6,
+            name: b"colors".to_vec(),
+            path_size: 4,
+            path: b"path".to_vec(),
+            datatype: data_type as u8,
+            cell_val_num,
+            ordered: 1,
+            data_size: data.len() as u64,
+            data: data.to_vec(),
+            offsets_size: offsets.len() as u64 * 8,
+            offsets: offsets.to_vec(),
+        }
+    }
+
+    #[test]
+    fn var_sized_values() -> Result<()> {
+        let storage = storage_enumeration(
+            DataType::StringUtf8,
+            CELL_VAR_SIZE,
+            b"redgreenblue",
+            &[0, 3, 8],
+        );
+        let data = storage.to_bytes()?;
+        let parsed = <storage::Enumeration as binrw::BinRead>::read(
+            &mut binrw::io::Cursor::new(data),
+        )?;
+
+        let enmr = Enumeration::try_from(parsed)?;
+        assert_eq!(enmr.name(), "colors");
+        assert!(enmr.ordered());
+        assert_eq!(enmr.len(), 3);
+        assert_eq!(enmr.value(1), Some(&b"green"[..]));
+        assert_eq!(enmr.index_of(b"blue"), Some(2));
+        assert_eq!(enmr.value(3), None);
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_sized_values() -> Result<()> {
+        let data: Vec<u8> = [10i32, 20, 30]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let storage = storage_enumeration(DataType::Int32, 1, &data, &[]);
+        let enmr = Enumeration::try_from(storage)?;
+        assert_eq!(enmr.len(), 3);
+        assert_eq!(enmr.value(2), Some(&30i32.to_le_bytes()[..]));
+
+        let storage = storage_enumeration(DataType::Int32, 1, &data[..5], &[]);
+        assert!(Enumeration::try_from(storage).is_err());
+        Ok(())
+    }
+}
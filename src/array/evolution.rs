@@ -0,0 +1,298 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+
+use crate::array::{self, Enumeration, OLD_SCHEMA_NAME, SCHEMA_DIR};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+// Changes to the latest schema of an array. Evolving an array writes a new
+// timestamped schema and leaves existing fragments untouched. Readers fill
+// attributes that a fragment's schema lacks with their fill value.
+// Attributes are dropped before others are added so that an attribute may
+// be replaced in a single evolution.
+#[derive(Default)]
+pub struct ArraySchemaEvolution {
+    attributes_to_add: Vec<storage::Attribute>,
+    attributes_to_drop: Vec<String>,
+    enumerations_to_add: Vec<Enumeration>,
+    enumerations_to_extend: Vec<(String, Vec<Vec<u8>>)>,
+    enumerations_to_drop: Vec<String>,
+    timestamp: Option<u64>,
+}
+
+impl ArraySchemaEvolution {
+    pub fn new() -> Self {
+        ArraySchemaEvolution::default()
+    }
+
+    pub fn add_attribute(mut self, attribute: storage::Attribute) -> Self {
+        self.attributes_to_add.push(attribute);
+        self
+    }
+
+    pub fn drop_attribute(mut self, name: &str) -> Self {
+        self.attributes_to_drop.push(name.to_string());
+        self
+    }
+
+    pub fn add_enumeration(mut self, enumeration: Enumeration) -> Self {
+        self.enumerations_to_add.push(enumeration);
+        self
+    }
+
+    // Append values to an existing enumeration.
+    pub fn extend_enumeration(
+        mut self,
+        name: &str,
+        values: Vec<Vec<u8>>,
+    ) -> Self {
+        self.enumerations_to_extend.push((name.to_string(), values));
+        self
+    }
+
+    // Only enumerations that no attribute uses may be dropped.
+    pub fn drop_enumeration(mut self, name: &str) -> Self {
+        self.enumerations_to_drop.push(name.to_string());
+        self
+    }
+
+    // Defaults to the current time, or just after the latest schema if
+    // that is newer.
+    pub fn set_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    // Write the evolved schema and return its name.
+    pub fn evolve(
+        &self,
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+    ) -> Result<String> {
+        let array = array::Array::open(vfs, array_uri)?;
+        let latest = array.schema_name();
+        let latest_uri = if latest == OLD_SCHEMA_NAME {
+            array_uri.join(OLD_SCHEMA_NAME)
+        } else {
+            array_uri.join(SCHEMA_DIR).join(latest)
+        };
+        let mut schema = storage::ArraySchema::load(vfs, &latest_uri)?;
+        if schema.version != storage::CURRENT_FORMAT_VERSION {
+            return Err(anyhow!(
+                "Schema version {} must be upgraded to version {} before it \
+                can be evolved",
+                schema.version,
+                storage::CURRENT_FORMAT_VERSION
+            )
+            .context(format!("URI: {}", latest_uri)));
+        }
+
+        let timestamp = match self.timestamp {
+            Some(timestamp) => timestamp,
+            None => {
+                let latest_end = match latest {
+                    OLD_SCHEMA_NAME => 0,
+                    name => storage::timestamp_range(name)?.1,
+                };
+                storage::current_timestamp().max(latest_end + 1)
+            }
+        };
+
+        let enumerations =
+            self.apply(vfs, array_uri, &mut schema, timestamp)
+                .map_err(|err| err.context(format!("URI: {}", array_uri)))?;
+        for (enumeration, path) in enumerations {
+            enumeration.store(vfs, array_uri, &path)?;
+        }
+
+        let name = storage::timestamped_name(timestamp, timestamp);
+        schema.store(vfs, &array_uri.join(SCHEMA_DIR).join(&name))?;
+        Ok(name)
+    }
+
+    // Apply the changes to a schema, returning the enumerations that need
+    // to be written along with their paths.
+    fn apply(
+        &self,
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        schema: &mut storage::ArraySchema,
+        timestamp: u64,
+    ) -> Result<Vec<(Enumeration, String)>> {
+        for name in self.attributes_to_drop.iter() {
+            let idx = schema
+                .attributes
+                .iter()
+                .position(|a| &a.name == name)
+                .ok_or_else(|| anyhow!("Unknown attribute '{}'", name))?;
+            schema.attributes.remove(idx);
+        }
+
+        // New and extended enumerations are written to new paths so that
+        // older schemas still refer to their original values.
+        let mut enumerations = Vec::new();
+        let path = |idx: usize| {
+            format!(
+                "{}_{}",
+                storage::timestamped_name(timestamp, timestamp),
+                idx
+            )
+        };
+
+        for enumeration in self.enumerations_to_add.iter() {
+            if schema.enumeration_map.contains_key(enumeration.name()) {
+                return Err(anyhow!(
+                    "Enumeration '{}' already exists",
+                    enumeration.name()
+                ));
+            }
+            let path = path(enumerations.len());
+            schema
+                .enumeration_map
+                .insert(enumeration.name().to_string(), path.clone());
+            enumerations.push((enumeration.clone(), path));
+        }
+
+        for (name, values) in self.enumerations_to_extend.iter() {
+            let current = schema
+                .enumeration_map
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown enumeration '{}'", name))?;
+            let enumeration = Enumeration::load(vfs, array_uri, current)?
+                .extend(values.clone())?;
+            let path = path(enumerations.len());
+            schema.enumeration_map.insert(name.clone(), path.clone());
+            enumerations.push((enumeration, path));
+        }
+
+        for attr in self.attributes_to_add.iter() {
+            let exists = schema.attributes.iter().any(|a| a.name == attr.name)
+                || schema
+                    .domain
+                    .dimensions
+                    .iter()
+                    .any(|d| d.name == attr.name.as_bytes());
+            if exists {
+                return Err(anyhow!(
+                    "Attribute '{}' conflicts with an existing field",
+                    attr.name
+                ));
+            }
+            schema.attributes.push(attr.clone());
+        }
+
+        for name in self.enumerations_to_drop.iter() {
+            if schema.enumeration_map.remove(name).is_none() {
+                return Err(anyhow!("Unknown enumeration '{}'", name));
+            }
+        }
+
+        for attr in schema.attributes.iter() {
+            let Ok(name) = std::str::from_utf8(&attr.enumeration_name) else {
+                continue;
+            };
+            if !name.is_empty() && !schema.enumeration_map.contains_key(name) {
+                return Err(anyhow!(
+                    "Attribute '{}' uses unknown enumeration '{}'",
+                    attr.name,
+                    name
+                ));
+            }
+        }
+
+        if schema.attributes.is_empty() {
+            return Err(anyhow!("Schemas require at least one attribute"));
+        }
+        schema.num_attributes = schema.attributes.len() as u32;
+
+        Ok(enumerations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::Layout;
+    use crate::datatype::DataType;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{QueryBuffer, SparseReader, SparseWriter};
+
+    #[test]
+    fn add_and_drop() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) = fixtures::temp_array(
+            &vfs,
+            "evolution",
+            &fixtures::sparse_schema(),
+        )?;
+        let uri = array.uri().clone();
+        SparseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(&[1i64]))
+            .set_buffer("a", QueryBuffer::from_values(&[10i32]))
+            .write(&vfs)?;
+
+        let colors = Enumeration::new(
+            "colors",
+            DataType::StringUtf8,
+            storage::CELL_VAR_SIZE,
+            false,
+            vec![b"red".to_vec(), b"green".to_vec()],
+        )?;
+        ArraySchemaEvolution::new()
+            .add_enumeration(colors)
+            .add_attribute(
+                storage::Attribute::new("b", DataType::Float64)
+                    .set_fill_value(1.5f64.to_le_bytes().to_vec(), false),
+            )
+            .add_attribute(
+                storage::Attribute::new("c", DataType::Uint8)
+                    .set_fill_value(vec![0], false)
+                    .set_enumeration_name("colors"),
+            )
+            .evolve(&vfs, &uri)?;
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.schemas().len(), 2);
+        SparseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(&[2i64]))
+            .set_buffer("a", QueryBuffer::from_values(&[20i32]))
+            .set_buffer("b", QueryBuffer::from_values(&[2.5f64]))
+            .set_buffer("c", QueryBuffer::from_values(&[1u8]))
+            .write(&vfs)?;
+
+        ArraySchemaEvolution::new()
+            .drop_attribute("a")
+            .extend_enumeration("colors", vec![b"blue".to_vec()])
+            .evolve(&vfs, &uri)?;
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.load_enumeration(&vfs, "colors")?.len(), 3);
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .set_decode_enumerations(true)
+            .read(&vfs)?;
+        assert!(!results.contains_key("a"));
+        assert_eq!(results["b"].values::<f64>(), vec![1.5, 2.5]);
+        assert_eq!(results["c"].value(0), Some(&b"red"[..]));
+        assert_eq!(results["c"].value(1), Some(&b"green"[..]));
+
+        assert!(ArraySchemaEvolution::new()
+            .drop_attribute("a")
+            .evolve(&vfs, &uri)
+            .is_err());
+        assert!(ArraySchemaEvolution::new()
+            .drop_enumeration("colors")
+            .evolve(&vfs, &uri)
+            .is_err());
+        assert!(ArraySchemaEvolution::new()
+            .add_attribute(storage::Attribute::new("x", DataType::Int32))
+            .evolve(&vfs, &uri)
+            .is_err());
+        Ok(())
+    }
+}
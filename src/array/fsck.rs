@@ -0,0 +1,457 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::array::{self, Directory};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    // Data that readers will fail on or silently miss.
+    Error,
+    // Something unexpected that does not affect reads, or that could not
+    // be checked.
+    Warning,
+}
+
+pub struct IntegrityIssue {
+    severity: Severity,
+    uri: uri::URI,
+    message: String,
+}
+
+impl IntegrityIssue {
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}: {}", self.severity, self.uri, self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct IntegrityReport {
+    issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn issues(&self) -> &[IntegrityIssue] {
+        &self.issues
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &IntegrityIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &IntegrityIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    fn error(&mut self, uri: &uri::URI, message: String) {
+        self.push(Severity::Error, uri, message);
+    }
+
+    fn warning(&mut self, uri: &uri::URI, message: String) {
+        self.push(Severity::Warning, uri, message);
+    }
+
+    fn push(&mut self, severity: Severity, uri: &uri::URI, message: String) {
+        self.issues.push(IntegrityIssue {
+            severity,
+            uri: uri.clone(),
+            message,
+        });
+    }
+}
+
+// Checks that every schema parses and that every fragment is committed,
+// references an existing schema and has all of its files with the sizes
+// recorded in its footer. Every tile is read which verifies chunk headers
+// and any checksum filters. Only failures to list the array are returned
+// as errors, everything else is recorded in the report.
+pub struct IntegrityChecker {
+    array_uri: uri::URI,
+}
+
+impl IntegrityChecker {
+    pub fn new(array_uri: &uri::URI) -> Self {
+        IntegrityChecker {
+            array_uri: array_uri.clone(),
+        }
+    }
+
+    pub fn check(&self, vfs: &dyn VFSService) -> Result<IntegrityReport> {
+        let mut dir = Directory::new(&self.array_uri);
+        dir.load_all(vfs)?;
+
+        let mut report = IntegrityReport::default();
+        let mut schemas = HashMap::new();
+        for schema_uri in dir.schema_uris() {
+            let schema = storage::ArraySchema::load(vfs, &schema_uri)
+                .and_then(array::Schema::try_from);
+            match schema {
+                Ok(schema) => {
+                    schemas.insert(schema_uri.last_path_part(), schema);
+                }
+                Err(err) => report.error(
+                    &schema_uri,
+                    format!("Invalid array schema: {:#}", err),
+                ),
+            }
+        }
+
+        let committed = dir.fragment_uris()?;
+        let committed_names: HashSet<String> = committed
+            .iter()
+            .map(|u| u.remove_trailing_slash().last_path_part())
+            .collect();
+
+        // Vacuumed and ignored fragments are no longer listed as committed
+        // but may legitimately remain until they are removed.
+        let vacuumed: HashSet<String> = dir
+            .vacuumed_fragment_uris()?
+            .iter()
+            .map(|u| u.remove_trailing_slash().last_path_part())
+            .collect();
+        for fragment_uri in dir.fragment_dir_uris(vfs)? {
+            let name = fragment_uri.remove_trailing_slash().last_path_part();
+            if !committed_names.contains(&name)
+                && !vacuumed.contains(&name)
+                && !dir.is_ignored(&name)
+            {
+                report.warning(
+                    &fragment_uri,
+                    "Fragment has no commit marker".to_string(),
+                );
+            }
+        }
+
+        for fragment_uri in committed {
+            let fragment_uri = fragment_uri.remove_trailing_slash();
+            if !vfs.dir_exists(&fragment_uri)? {
+                report.error(
+                    &fragment_uri,
+                    "Committed fragment does not exist".to_string(),
+                );
+                continue;
+            }
+            self.check_fragment(vfs, &fragment_uri, &schemas, &mut report)?;
+        }
+
+        Ok(report)
+    }
+
+    fn check_fragment(
+        &self,
+        vfs: &dyn VFSService,
+        fragment_uri: &uri::URI,
+        schemas: &HashMap<String, array::Schema>,
+        report: &mut IntegrityReport,
+    ) -> Result<()> {
+        let footer =
+            storage::FragmentMetadata::read_footer(vfs, fragment_uri, schemas)
+                .and_then(|data| {
+                    let name =
+                        storage::FragmentMetadata::footer_schema_name(&data)?;
+                    Ok((data, name))
+                });
+        let (data, schema_name) = match footer {
+            Ok(footer) => footer,
+            Err(err) => {
+                report.error(
+                    fragment_uri,
+                    format!("Invalid fragment metadata: {:#}", err),
+                );
+                return Ok(());
+            }
+        };
+
+        let Some(schema) = schemas.get(&schema_name) else {
+            report.error(
+                fragment_uri,
+                format!("Array schema '{}' does not exist", schema_name),
+            );
+            return Ok(());
+        };
+
+        let fragment = match storage::FragmentMetadata::from_footer(
+            fragment_uri,
+            &data,
+            schemas,
+        ) {
+            Ok(fragment) => fragment,
+            Err(err) => {
+                report.error(
+                    fragment_uri,
+                    format!("Invalid fragment footer: {:#}", err),
+                );
+                return Ok(());
+            }
+        };
+
+        for field in fragment_fields(&fragment, schema).iter() {
+            if self.check_files(vfs, &fragment, field, report)? {
+                check_tiles(vfs, &fragment, field, report);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Check the size of every file storing a field, returning whether all
+    // of them are as expected.
+    fn check_files(
+        &self,
+        vfs: &dyn VFSService,
+        fragment: &storage::FragmentMetadata,
+        field: &storage::Field,
+        report: &mut IntegrityReport,
+    ) -> Result<bool> {
+        let mut ok = true;
+        for (file_uri, expected) in field_files(fragment, field) {
+            let actual = if vfs.file_exists(&file_uri)? {
+                vfs.file_size(&file_uri)?
+            } else if expected > 0 {
+                report.error(
+                    &file_uri,
+                    format!("Missing file for field '{}'", field.name()),
+                );
+                ok = false;
+                continue;
+            } else {
+                0
+            };
+
+            if actual != expected {
+                report.error(
+                    &file_uri,
+                    format!(
+                        "File for field '{}' has {} bytes, expected {}",
+                        field.name(),
+                        actual,
+                        expected
+                    ),
+                );
+                ok = false;
+            }
+        }
+
+        Ok(ok)
+    }
+}
+
+// Every field stored by a fragment. Dense fragments do not store their
+// coordinates.
+pub(crate) fn fragment_fields<'a>(
+    fragment: &storage::FragmentMetadata,
+    schema: &'a array::Schema,
+) -> Vec<storage::Field<'a>> {
+    let mut fields: Vec<storage::Field> = (0..schema.attributes().len())
+        .map(|idx| storage::Field::attribute(schema, idx))
+        .collect();
+    if !fragment.is_dense() {
+        fields.extend(
+            (0..schema.domain().ndim())
+                .map(|idx| fragment.dimension_field(schema, idx)),
+        );
+    }
+    fields.extend(fragment.timestamps_field(schema));
+    fields.extend(fragment.delete_timestamps_field(schema));
+    fields.extend(fragment.delete_condition_index_field(schema));
+    fields
+}
+
+// The files storing a field along with the sizes recorded in the footer.
+pub(crate) fn field_files(
+    fragment: &storage::FragmentMetadata,
+    field: &storage::Field,
+) -> Vec<(uri::URI, u64)> {
+    let offsets = &fragment.footer().file_offsets;
+    let idx = field.idx();
+    let mut files = vec![(fragment.field_uri(field), offsets.fixed_sizes[idx])];
+    if field.is_var_sized() {
+        files.push((fragment.field_var_uri(field), offsets.var_sizes[idx]));
+    }
+    if field.nullable() {
+        let size = offsets.validity_sizes.get(idx).copied().unwrap_or(0);
+        files.push((fragment.field_validity_uri(field), size));
+    }
+    files
+}
+
+// Read every tile of a field. Tiles are fully unfiltered when possible so
+// that checksums are verified, otherwise only their chunk headers are
+// parsed.
+fn check_tiles(
+    vfs: &dyn VFSService,
+    fragment: &storage::FragmentMetadata,
+    field: &storage::Field,
+    report: &mut IntegrityReport,
+) {
+    let index = match fragment.load_tile_index(vfs, field) {
+        Ok(index) => index,
+        Err(err) => {
+            report.error(
+                &fragment.metadata_uri(),
+                format!(
+                    "Invalid tile offsets for field '{}': {:#}",
+                    field.name(),
+                    err
+                ),
+            );
+            return;
+        }
+    };
+
+    let supported = field.filters().is_supported()
+        && (!field.is_var_sized() || field.offsets_filters().is_supported())
+        && (!field.nullable() || field.validity_filters().is_supported());
+    if !supported {
+        report.warning(
+            &fragment.field_uri(field),
+            format!(
+                "Unsupported filters, tile data for field '{}' was not \
+                verified",
+                field.name()
+            ),
+        );
+    }
+
+    for tile in 0..index.tile_num() {
+        let result = if supported {
+            fragment.read_tile(vfs, field, &index, tile).map(|_| ())
+        } else {
+            fragment.read_tile_chunks(vfs, field, &index, tile)
+        };
+        if let Err(err) = result {
+            report.error(
+                &fragment.field_uri(field),
+                format!(
+                    "Invalid tile {} for field '{}': {:#}",
+                    tile,
+                    field.name(),
+                    err
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::{
+        ArrayType, COMMITS_DIR, FRAGMENTS_DIR, IGNORE_FILE_SUFFIX,
+    };
+    use crate::datatype::DataType;
+    use crate::filters::FilterType;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{QueryBuffer, SparseWriter};
+
+    #[test]
+    fn detects_damage() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "x",
+                DataType::Int64,
+                &array::Range::from_values(0i64, 99),
+            )]),
+            vec![storage::Attribute::new("a", DataType::Int32).set_filters(
+                storage::FilterList::new(
+                    65536,
+                    vec![storage::Filter::new(
+                        FilterType::ChecksumSHA256,
+                        storage::FilterConfig::None,
+                    )],
+                ),
+            )],
+        )
+        .set_capacity(2);
+        let (_dir, array) = fixtures::temp_array(&vfs, "fsck", &schema)?;
+        let uri = array.uri().clone();
+        let mut fragments = Vec::new();
+        let t = storage::current_timestamp();
+        for (x, ts) in [(1i64, t + 1), (2, t + 2)] {
+            let writer =
+                SparseWriter::new(&uri, array.schema_name(), array.schema())
+                    .set_buffer("x", QueryBuffer::from_values(&[x, x + 10]))
+                    .set_buffer("a", QueryBuffer::from_values(&[1i32, 2]))
+                    .set_timestamp(ts);
+            fragments.push(writer.write(&vfs)?);
+        }
+
+        let report = IntegrityChecker::new(&uri).check(&vfs)?;
+        assert!(report.issues().is_empty());
+
+        // Flip a byte of attribute data which only the checksum catches.
+        let a0 = fragments[0].join("a0.tdb");
+        let mut data = vfs.file_read_vec(&a0, vfs.file_size(&a0)?, 0)?;
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        vfs.file_write(&a0, 0, &data)?;
+
+        // Truncate a dimension file.
+        let d0 = fragments[1].join("d0.tdb");
+        let data = vfs.file_read_vec(&d0, 4, 0)?;
+        vfs.file_remove(&d0)?;
+        vfs.file_create(&d0)?;
+        vfs.file_write(&d0, 0, &data)?;
+
+        // And leave uncommitted fragments behind in both layouts. Before
+        // format version 12 fragments were committed by an .ok file next to
+        // them in the array directory.
+        let orphan = uri.join(FRAGMENTS_DIR).join("__1_1_orphan_22");
+        vfs.dir_create(&orphan)?;
+        let old_orphan = uri.join("__2_2_orphan_11");
+        vfs.dir_create(&old_orphan)?;
+
+        // Ignored fragments may remain until they are vacuumed.
+        let ignored = "__3_3_ignored_22";
+        vfs.dir_create(&uri.join(FRAGMENTS_DIR).join(ignored))?;
+        let ign_uri = uri.join(COMMITS_DIR).join(&format!(
+            "{}{}",
+            storage::timestamped_name(t + 3, t + 3),
+            IGNORE_FILE_SUFFIX
+        ));
+        vfs.file_create(&ign_uri)?;
+        vfs.file_write(&ign_uri, 0, format!("{}\n", ignored).as_bytes())?;
+
+        let report = IntegrityChecker::new(&uri).check(&vfs)?;
+        assert!(!report.is_ok());
+        let errors: Vec<_> = report.errors().collect();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].uri() == &a0);
+        assert!(errors[0].message().contains("ChecksumSHA256 mismatch"));
+        assert!(errors[1].uri() == &d0);
+        let warnings: Vec<_> = report.warnings().collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].uri() == &orphan);
+        assert!(warnings[1].uri() == &old_orphan);
+        Ok(())
+    }
+}
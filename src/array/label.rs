@@ -0,0 +1,474 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType, DataOrder};
+use crate::datatype::DataType;
+use crate::io::service::VFSService;
+use crate::query::{self, DenseReader, DenseTiling, QueryBuffer, Subarray};
+use crate::Result;
+
+// An opened dimension label. The label array is a one dimensional dense
+// array indexed by the labelled dimension with a single attribute holding
+// the label of each index. Ordered labels are sorted along the index so a
+// range of labels maps to a contiguous range of indexes.
+pub struct LabelArray {
+    name: String,
+    dimension_idx: usize,
+    attribute_name: String,
+    data_order: DataOrder,
+    data_type: DataType,
+    array: array::Array,
+}
+
+impl LabelArray {
+    // Open a label of the array at the array's timestamp range.
+    pub fn open(
+        vfs: &dyn VFSService,
+        parent: &array::Array,
+        name: &str,
+    ) -> Result<Self> {
+        let label = parent.schema().dimension_label(name).ok_or_else(|| {
+            anyhow!("Unknown dimension label '{}'", name)
+                .context(format!("URI: {}", parent.uri()))
+        })?;
+
+        let uri = label.resolve_uri(parent.uri())?;
+        let array = array::Array::open_at(
+            vfs,
+            &uri,
+            parent.timestamp_start(),
+            parent.timestamp_end(),
+        )?;
+
+        let schema = array.schema();
+        if schema.array_type() != ArrayType::Dense
+            || schema.domain().ndim() != 1
+            || schema.attribute(label.attribute_name()).is_none()
+        {
+            return Err(anyhow!("Invalid label array for '{}'", name)
+                .context(format!("URI: {}", uri)));
+        }
+
+        Ok(LabelArray {
+            name: name.to_string(),
+            dimension_idx: label.dimension_idx() as usize,
+            attribute_name: label.attribute_name().to_string(),
+            data_order: label.data_order(),
+            data_type: label.data_type(),
+            array,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The index of the labelled dimension in the parent array.
+    pub fn dimension_idx(&self) -> usize {
+        self.dimension_idx
+    }
+
+    pub fn data_order(&self) -> DataOrder {
+        self.data_order
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn array(&self) -> &array::Array {
+        &self.array
+    }
+
+    // The range of indexes whose labels fall within the inclusive range of
+    // label values, or None if no label matches. Labels written by a single
+    // fragment are searched tile by tile using the min and max label of
+    // each tile, so that only the tiles holding the ends of the range are
+    // read. Otherwise every written label is read.
+    pub fn index_range(
+        &self,
+        vfs: &dyn VFSService,
+        range: &array::Range,
+    ) -> Result<Option<array::Range>> {
+        let dim = &self.array.schema().domain().dimensions()[0];
+        let dtype = dim.data_type();
+
+        // The written indexes are the union of every fragment's domain.
+        let mut written: Option<(i128, i128)> = None;
+        for fragment in self.array.fragments() {
+            let Some(ned) = fragment.non_empty_domain().first() else {
+                continue;
+            };
+            let (lo, hi) = ned.to_i128(dtype).ok_or_else(|| {
+                anyhow!("Label index has non-integral type {:?}", dtype)
+            })?;
+            written = Some(match written {
+                Some((wlo, whi)) => (wlo.min(lo), whi.max(hi)),
+                None => (lo, hi),
+            });
+        }
+
+        let Some(written) = written else {
+            return Ok(None);
+        };
+
+        let positions = match self.tile_labels(vfs)? {
+            Some(tiles) => self.search_tiles(vfs, &tiles, range)?,
+            None => {
+                let labels = self.read_labels(vfs, written)?;
+                label_positions(
+                    self.data_order,
+                    self.data_type,
+                    &labels,
+                    range,
+                )?
+                .map(|(first, last)| {
+                    (written.0 + first as i128, written.0 + last as i128)
+                })
+            }
+        };
+
+        let index = |v: i128| {
+            dtype.from_i128(v).ok_or_else(|| {
+                anyhow!("Label index has non-integral type {:?}", dtype)
+            })
+        };
+        match positions {
+            Some((first, last)) => {
+                Ok(Some(array::Range::new(index(first)?, index(last)?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // The labels of an inclusive range of indexes.
+    fn read_labels(
+        &self,
+        vfs: &dyn VFSService,
+        (lo, hi): (i128, i128),
+    ) -> Result<QueryBuffer> {
+        let dtype = self.array.schema().domain().dimensions()[0].data_type();
+        let index = |v: i128| {
+            dtype.from_i128(v).ok_or_else(|| {
+                anyhow!("Label index has non-integral type {:?}", dtype)
+            })
+        };
+        let subarray = Subarray::new()
+            .set_range(0, array::Range::new(index(lo)?, index(hi)?));
+        let mut results = DenseReader::new(&self.array)
+            .set_subarray(subarray)
+            .set_attributes(&[&self.attribute_name])
+            .read(vfs)?;
+        results.remove(&self.attribute_name).ok_or_else(|| {
+            anyhow!("Missing label attribute '{}'", self.attribute_name)
+        })
+    }
+
+    // The indexes and min and max label of every tile, in index order, when
+    // a single fragment wrote whole tiles of labels with statistics.
+    fn tile_labels(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<Option<Vec<TileLabels>>> {
+        let [fragment] = self.array.fragments() else {
+            return Ok(None);
+        };
+        let schema = self.array.fragment_schema(fragment)?;
+        let Some(field) =
+            fragment.attribute_field(schema, &self.attribute_name)
+        else {
+            return Ok(None);
+        };
+        let stats = fragment.load_tile_stats(vfs, &field)?;
+
+        let bounds =
+            query::integral_bounds(schema, fragment.non_empty_domain())?;
+        let tiling = DenseTiling::new(schema, &bounds)?;
+        if !tiling.is_aligned(&bounds)
+            || stats.len() as u64 != tiling.tile_num()
+        {
+            return Ok(None);
+        }
+
+        let first_tile = tiling.tile_range(&bounds)[0].0;
+        let mut ret = Vec::with_capacity(stats.len());
+        for (idx, tile) in stats.iter().enumerate() {
+            let (Some(min), Some(max)) = (tile.min_bytes(), tile.max_bytes())
+            else {
+                return Ok(None);
+            };
+            ret.push(TileLabels {
+                indexes: tiling.tile_bounds(&[first_tile + idx as i128])[0],
+                min: min.to_vec(),
+                max: max.to_vec(),
+            });
+        }
+        Ok(Some(ret))
+    }
+
+    // Find the tiles holding the first and last label within the range and
+    // search only those.
+    fn search_tiles(
+        &self,
+        vfs: &dyn VFSService,
+        tiles: &[TileLabels],
+        range: &array::Range,
+    ) -> Result<Option<(i128, i128)>> {
+        let dtype = self.data_type;
+        let below_start = |v: &[u8]| dtype.compare(v, range.start()).is_lt();
+        let above_end = |v: &[u8]| dtype.compare(v, range.end()).is_gt();
+        let (first_tile, last_tile) = match self.data_order {
+            DataOrder::Increasing => (
+                tiles.iter().position(|t| !below_start(&t.max)),
+                tiles.iter().rposition(|t| !above_end(&t.min)),
+            ),
+            DataOrder::Decreasing => (
+                tiles.iter().position(|t| !above_end(&t.min)),
+                tiles.iter().rposition(|t| !below_start(&t.max)),
+            ),
+            order => {
+                return Err(anyhow!(
+                    "Label ranges require ordered labels, not {:?}",
+                    order
+                ))
+            }
+        };
+        let (Some(first_tile), Some(last_tile)) = (first_tile, last_tile)
+        else {
+            return Ok(None);
+        };
+        if first_tile > last_tile {
+            return Ok(None);
+        }
+
+        let search = |tile: &TileLabels| -> Result<Option<(i128, i128)>> {
+            let labels = self.read_labels(vfs, tile.indexes)?;
+            let positions =
+                label_positions(self.data_order, dtype, &labels, range)?;
+            Ok(positions.map(|(first, last)| {
+                (
+                    tile.indexes.0 + first as i128,
+                    tile.indexes.0 + last as i128,
+                )
+            }))
+        };
+        let Some((first, _)) = search(&tiles[first_tile])? else {
+            return Ok(None);
+        };
+        let Some((_, last)) = search(&tiles[last_tile])? else {
+            return Ok(None);
+        };
+        Ok((first <= last).then_some((first, last)))
+    }
+}
+
+struct TileLabels {
+    indexes: (i128, i128),
+    min: Vec<u8>,
+    max: Vec<u8>,
+}
+
+// Binary search sorted labels for the inclusive positions of the labels
+// within a range.
+fn label_positions(
+    order: DataOrder,
+    dtype: DataType,
+    labels: &QueryBuffer,
+    range: &array::Range,
+) -> Result<Option<(usize, usize)>> {
+    let label = |idx: usize| labels.value(idx).unwrap_or_default();
+    let cmp = |idx: usize, value: &[u8]| dtype.compare(label(idx), value);
+    let (first, end) = match order {
+        DataOrder::Increasing => (
+            partition_point(labels.cell_num(), |i| {
+                cmp(i, range.start()) == Ordering::Less
+            }),
+            partition_point(labels.cell_num(), |i| {
+                cmp(i, range.end()) != Ordering::Greater
+            }),
+        ),
+        DataOrder::Decreasing => (
+            partition_point(labels.cell_num(), |i| {
+                cmp(i, range.end()) == Ordering::Greater
+            }),
+            partition_point(labels.cell_num(), |i| {
+                cmp(i, range.start()) != Ordering::Less
+            }),
+        ),
+        order => {
+            return Err(anyhow!(
+                "Label ranges require ordered labels, not {:?}",
+                order
+            ))
+        }
+    };
+
+    if first < end {
+        Ok(Some((first, end - 1)))
+    } else {
+        Ok(None)
+    }
+}
+
+// The first position in 0..len for which pred is false, given that pred is
+// true for a prefix of the positions.
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::DenseWriter;
+    use crate::storage;
+
+    fn dense_schema(
+        name: &str,
+        attribute: &str,
+        dtype: DataType,
+        extent: i32,
+    ) -> storage::ArraySchema {
+        storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![storage::Dimension::new(
+                name,
+                DataType::Int32,
+                &array::Range::from_values(1i32, 8),
+            )
+            .set_extent(extent.to_le_bytes().to_vec())]),
+            vec![storage::Attribute::new(attribute, dtype)],
+        )
+    }
+
+    fn write<T: crate::datatype::Primitive>(
+        vfs: &dyn VFSService,
+        array: &array::Array,
+        (lo, hi): (i32, i32),
+        attribute: &str,
+        values: &[T],
+    ) -> Result<()> {
+        DenseWriter::new(array.uri(), array.schema_name(), array.schema())
+            .set_subarray(Subarray::new().set_typed_range(0, lo, hi))
+            .set_buffer(attribute, QueryBuffer::from_values(values))
+            .write(vfs)?;
+        Ok(())
+    }
+
+    #[test]
+    fn query_by_label() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = dense_schema("rows", "a", DataType::Int32, 4)
+            .add_dimension_label(storage::DimensionLabel::new(
+                0,
+                "time",
+                "__labels/l0",
+                "label",
+                DataOrder::Increasing,
+                DataType::Float64,
+            ));
+        let (_dir, parent) = fixtures::temp_array(&vfs, "label", &schema)?;
+        let values: Vec<i32> = (1..=8).map(|v| v * 10).collect();
+        write(&vfs, &parent, (1, 8), "a", &values)?;
+
+        // Four tiles of two labels each.
+        let labels_uri = parent.uri().join("__labels");
+        vfs.dir_create(&labels_uri)?;
+        let schema = dense_schema("index", "label", DataType::Float64, 2);
+        let labels =
+            array::Array::create(&vfs, &labels_uri.join("l0"), &schema)?;
+        let values: Vec<f64> = (1..=8).map(|v| v as f64 * 0.5).collect();
+        write(&vfs, &labels, (1, 8), "label", &values)?;
+
+        let read = |parent: &array::Array, start: f64, end: f64| {
+            let label = LabelArray::open(&vfs, parent, "time")?;
+            let range = array::Range::from_values(start, end);
+            let subarray =
+                Subarray::new().set_label_range(&vfs, &label, &range)?;
+            let results =
+                DenseReader::new(parent).set_subarray(subarray).read(&vfs)?;
+            Ok::<_, anyhow::Error>(results["a"].values::<i32>())
+        };
+
+        // Labels 1.5 to 2.5 are at indexes 3 to 5, across three tiles.
+        let parent = array::Array::open(&vfs, parent.uri())?;
+        assert_eq!(read(&parent, 1.2, 2.6)?, vec![30, 40, 50]);
+        assert_eq!(read(&parent, 0.0, 0.5)?, vec![10]);
+        assert!(read(&parent, 1.1, 1.2).is_err());
+
+        // Once a second fragment overwrites labels every label is read.
+        let labels = array::Array::open(&vfs, labels.uri())?;
+        write(&vfs, &labels, (7, 8), "label", &[5.0f64, 6.0])?;
+        let parent = array::Array::open(&vfs, parent.uri())?;
+        assert_eq!(read(&parent, 4.5, 5.5)?, vec![70]);
+        assert_eq!(read(&parent, 2.6, 6.0)?, vec![60, 70, 80]);
+        Ok(())
+    }
+
+    fn labels(values: &[f64]) -> QueryBuffer {
+        let mut buffer = QueryBuffer::new(false, false);
+        for value in values {
+            buffer.push(&value.to_le_bytes(), true);
+        }
+        buffer
+    }
+
+    #[test]
+    fn ordered_positions() -> Result<()> {
+        let increasing = labels(&[0.5, 1.0, 1.5, 2.0, 2.5]);
+        let decreasing = labels(&[2.5, 2.0, 1.5, 1.0, 0.5]);
+        let cases = [
+            ((1.0, 2.0), Some((1, 3)), Some((1, 3))),
+            ((1.2, 1.8), Some((2, 2)), Some((2, 2))),
+            ((0.0, 0.5), Some((0, 0)), Some((4, 4))),
+            ((2.6, 3.0), None, None),
+            ((1.1, 1.2), None, None),
+        ];
+        for ((start, end), inc, dec) in cases {
+            let range = array::Range::from_values(start, end);
+            let dtype = DataType::Float64;
+            assert_eq!(
+                label_positions(
+                    DataOrder::Increasing,
+                    dtype,
+                    &increasing,
+                    &range
+                )?,
+                inc
+            );
+            assert_eq!(
+                label_positions(
+                    DataOrder::Decreasing,
+                    dtype,
+                    &decreasing,
+                    &range
+                )?,
+                dec
+            );
+        }
+
+        let range = array::Range::from_values(0.0, 1.0);
+        assert!(label_positions(
+            DataOrder::Unordered,
+            DataType::Float64,
+            &increasing,
+            &range
+        )
+        .is_err());
+        Ok(())
+    }
+}
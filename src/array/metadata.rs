@@ -0,0 +1,354 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+
+use crate::array::directory::{METADATA_DIR, VACUUM_FILE_SUFFIX};
+use crate::array::Directory;
+use crate::datatype::{self, DataType, Primitive};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+// A typed metadata value holding zero or more values of its datatype.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataValue {
+    data_type: DataType,
+    value: Vec<u8>,
+}
+
+impl MetadataValue {
+    pub fn new(data_type: DataType, value: Vec<u8>) -> Self {
+        MetadataValue { data_type, value }
+    }
+
+    pub fn from_values<T: Primitive>(
+        data_type: DataType,
+        values: &[T],
+    ) -> Self {
+        MetadataValue {
+            data_type,
+            value: datatype::values_to_bytes(values),
+        }
+    }
+
+    pub fn from_string(data_type: DataType, value: &str) -> Self {
+        MetadataValue {
+            data_type,
+            value: value.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    pub fn value_num(&self) -> usize {
+        self.value.len() / self.data_type.size().max(1)
+    }
+
+    pub fn values<T: Primitive>(&self) -> Vec<T> {
+        datatype::values_from_bytes(&self.value)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.value).ok()
+    }
+}
+
+// The key-value metadata of an array. Every metadata file under __meta
+// holds the keys written or deleted at its timestamp and files are applied
+// from oldest to newest. Changes are buffered until stored.
+pub struct ArrayMetadata {
+    array_uri: uri::URI,
+    entries: BTreeMap<String, MetadataValue>,
+    pending: BTreeMap<String, Option<MetadataValue>>,
+}
+
+impl ArrayMetadata {
+    pub fn load(vfs: &dyn VFSService, array_uri: &uri::URI) -> Result<Self> {
+        ArrayMetadata::load_at(vfs, array_uri, 0, u64::MAX)
+    }
+
+    pub fn load_at(
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        timestamp_start: u64,
+        timestamp_end: u64,
+    ) -> Result<Self> {
+        let mut dir = Directory::new(array_uri)
+            .set_timestamp_range(timestamp_start, timestamp_end);
+        dir.load_all(vfs)?;
+
+        let mut entries = BTreeMap::new();
+        for meta_uri in dir.metadata_uris()? {
+            let data = storage::read_generic_tile(vfs, &meta_uri, 0)?;
+            let parsed = storage::MetadataEntries::parse(&data)
+                .map_err(|err| err.context(format!("URI: {}", meta_uri)))?;
+            for entry in parsed.entries() {
+                if entry.is_deleted() {
+                    entries.remove(entry.key());
+                } else {
+                    let value = MetadataValue::new(
+                        entry.data_type(),
+                        entry.value().to_vec(),
+                    );
+                    entries.insert(entry.key().to_string(), value);
+                }
+            }
+        }
+
+        Ok(ArrayMetadata {
+            array_uri: array_uri.clone(),
+            entries,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    // Pending changes are visible before they are stored.
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        match self.pending.get(key) {
+            Some(value) => value.as_ref(),
+            None => self.entries.get(key),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .entries
+            .keys()
+            .filter(|k| !self.pending.contains_key(*k))
+            .chain(
+                self.pending
+                    .iter()
+                    .filter(|(_, v)| v.is_some())
+                    .map(|(k, _)| k),
+            )
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn put(&mut self, key: &str, value: MetadataValue) -> Result<()> {
+        if value.data_type() == DataType::Invalid {
+            return Err(anyhow!("Invalid datatype for metadata key '{}'", key));
+        }
+        self.pending.insert(key.to_string(), Some(value));
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: &str) {
+        self.pending.insert(key.to_string(), None);
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Write pending changes to a new metadata file at the given timestamp
+    // and return its URI.
+    pub fn store(
+        &mut self,
+        vfs: &dyn VFSService,
+        timestamp: u64,
+    ) -> Result<Option<uri::URI>> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+
+        let entries = self
+            .pending
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => storage::MetadataEntry::new(
+                    key,
+                    value.data_type(),
+                    value.value(),
+                ),
+                None => storage::MetadataEntry::deleted(key),
+            })
+            .collect();
+        let name = storage::timestamped_name(timestamp, timestamp);
+        let uri = write_metadata(vfs, &self.array_uri, &name, entries)?;
+
+        for (key, value) in std::mem::take(&mut self.pending) {
+            match value {
+                Some(value) => self.entries.insert(key, value),
+                None => self.entries.remove(&key),
+            };
+        }
+
+        Ok(Some(uri))
+    }
+
+    // Merge every metadata file into a single file spanning their
+    // timestamps. A vacuum file lists the merged files so that readers
+    // ignore them until they are removed.
+    pub fn consolidate(
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+    ) -> Result<Option<uri::URI>> {
+        let mut dir = Directory::new(array_uri);
+        dir.load_all(vfs)?;
+        let uris = dir.metadata_uris()?;
+        if uris.len() < 2 {
+            return Ok(None);
+        }
+
+        let mut start = u64::MAX;
+        let mut end = 0;
+        for meta_uri in uris.iter() {
+            let range = storage::timestamp_range(&meta_uri.last_path_part())?;
+            start = start.min(range.0);
+            end = end.max(range.1);
+        }
+
+        let metadata = ArrayMetadata::load(vfs, array_uri)?;
+        let entries = metadata
+            .entries
+            .iter()
+            .map(|(key, value)| {
+                storage::MetadataEntry::new(
+                    key,
+                    value.data_type(),
+                    value.value(),
+                )
+            })
+            .collect();
+
+        let name = storage::timestamped_name(start, end);
+        let uri = write_metadata(vfs, array_uri, &name, entries)?;
+
+        let vac_uri = array_uri
+            .join(METADATA_DIR)
+            .join(&(name + VACUUM_FILE_SUFFIX));
+        let vac: String = uris.iter().map(|u| u.to_string() + "\n").collect();
+        vfs.file_create(&vac_uri)?;
+        vfs.file_write(&vac_uri, 0, vac.as_bytes())?;
+
+        Ok(Some(uri))
+    }
+}
+
+fn write_metadata(
+    vfs: &dyn VFSService,
+    array_uri: &uri::URI,
+    name: &str,
+    entries: Vec<storage::MetadataEntry>,
+) -> Result<uri::URI> {
+    let dir_uri = array_uri.join(METADATA_DIR);
+    if !vfs.dir_exists(&dir_uri)? {
+        vfs.dir_create(&dir_uri)?;
+    }
+
+    let uri = dir_uri.join(name);
+    let data = storage::MetadataEntries::new(entries).to_bytes()?;
+    storage::write_generic_tile(vfs, &uri, &data)?;
+    Ok(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+
+    fn temp_array(vfs: &dyn VFSService) -> Result<fixtures::TempDir> {
+        let dir = fixtures::TempDir::new("metadata")?;
+        vfs.dir_create(dir.uri())?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn put_get_delete() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = temp_array(&vfs)?;
+        let uri = dir.uri().clone();
+
+        let mut meta = ArrayMetadata::load(&vfs, &uri)?;
+        assert!(meta.is_empty());
+        meta.put("ints", MetadataValue::from_values(DataType::Int32, &[1, 2]))?;
+        meta.put(
+            "name",
+            MetadataValue::from_string(DataType::StringUtf8, "x"),
+        )?;
+        meta.store(&vfs, 10)?;
+
+        let mut meta = ArrayMetadata::load(&vfs, &uri)?;
+        assert_eq!(meta.keys(), vec!["ints", "name"]);
+        assert_eq!(meta.get("ints").unwrap().values::<i32>(), vec![1, 2]);
+        assert_eq!(meta.get("name").unwrap().as_str(), Some("x"));
+
+        meta.delete("ints");
+        meta.put(
+            "name",
+            MetadataValue::from_string(DataType::StringUtf8, "y"),
+        )?;
+        assert!(meta.get("ints").is_none());
+        meta.store(&vfs, 20)?;
+
+        let meta = ArrayMetadata::load(&vfs, &uri)?;
+        assert_eq!(meta.keys(), vec!["name"]);
+        assert_eq!(meta.get("name").unwrap().as_str(), Some("y"));
+
+        // Time travel to before the delete.
+        let meta = ArrayMetadata::load_at(&vfs, &uri, 0, 15)?;
+        assert_eq!(meta.get("ints").unwrap().value_num(), 2);
+        assert_eq!(meta.get("name").unwrap().as_str(), Some("x"));
+        Ok(())
+    }
+
+    #[test]
+    fn consolidation() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = temp_array(&vfs)?;
+        let uri = dir.uri().clone();
+
+        let mut meta = ArrayMetadata::load(&vfs, &uri)?;
+        assert!(ArrayMetadata::consolidate(&vfs, &uri)?.is_none());
+        for ts in 1..4u64 {
+            meta.put(
+                "ts",
+                MetadataValue::from_values(DataType::Uint64, &[ts]),
+            )?;
+            meta.put(
+                &ts.to_string(),
+                MetadataValue::new(DataType::Int8, vec![]),
+            )?;
+            meta.store(&vfs, ts)?;
+        }
+        meta.delete("2");
+        meta.store(&vfs, 4)?;
+
+        let merged = ArrayMetadata::consolidate(&vfs, &uri)?.unwrap();
+        assert_eq!(storage::timestamp_range(&merged.last_path_part())?, (1, 4));
+
+        let mut dir = Directory::new(&uri);
+        dir.load_all(&vfs)?;
+        let uris = dir.metadata_uris()?;
+        assert_eq!(uris.len(), 1);
+        assert_eq!(uris[0].last_path_part(), merged.last_path_part());
+        assert_eq!(dir.vacuumed_metadata_uris()?.len(), 4);
+
+        let meta = ArrayMetadata::load(&vfs, &uri)?;
+        assert_eq!(meta.keys(), vec!["1", "3", "ts"]);
+        assert_eq!(meta.get("ts").unwrap().values::<u64>(), vec![3]);
+        Ok(())
+    }
+}
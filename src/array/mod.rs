@@ -1,14 +1,45 @@
 // This file is part of tdbtk released under the MIT license.
 // Copyright (c) 2023 TileDB, Inc.
 
+pub mod consolidate;
+pub mod delete;
 pub mod directory;
+pub mod enumeration;
+pub mod evolution;
+pub mod fsck;
+pub mod label;
+pub mod metadata;
+pub mod range;
+pub mod repair;
 pub mod schema;
+pub mod upgrade;
+pub mod vacuum;
 
+pub use consolidate::*;
+pub use delete::*;
 pub use directory::*;
+pub use enumeration::*;
+pub use evolution::*;
+pub use fsck::*;
+pub use label::*;
+pub use metadata::*;
+pub use range::*;
+pub use repair::*;
 pub use schema::*;
+pub use upgrade::*;
+pub use vacuum::*;
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum ArrayType {
     #[default]
     Dense = 0,
@@ -27,7 +58,7 @@ impl From<u8> for ArrayType {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Layout {
     #[default]
     RowMajor = 0,
@@ -52,7 +83,7 @@ impl From<u8> for Layout {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum DataOrder {
     #[default]
     Unordered = 0,
@@ -71,3 +102,235 @@ impl From<u8> for DataOrder {
         }
     }
 }
+
+// An opened array: every schema along with the metadata of every committed
+// fragment, ordered from oldest to newest.
+pub struct Array {
+    uri: uri::URI,
+    timestamp_start: u64,
+    timestamp_end: u64,
+    schemas: HashMap<String, Schema>,
+    latest_schema: String,
+    fragments: Vec<storage::FragmentMetadata>,
+    delete_conditions: Vec<DeleteCondition>,
+}
+
+impl Array {
+    // Create an empty array whose first schema is written at the current
+    // time.
+    pub fn create(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        schema: &storage::ArraySchema,
+    ) -> Result<Array> {
+        if vfs.dir_exists(&uri.join(SCHEMA_DIR))? {
+            return Err(anyhow!("Array already exists")
+                .context(format!("URI: {}", uri)));
+        }
+
+        if !vfs.dir_exists(uri)? {
+            vfs.dir_create(uri)?;
+        }
+        for dir in [
+            SCHEMA_DIR,
+            COMMITS_DIR,
+            FRAGMENTS_DIR,
+            FRAGMENT_META_DIR,
+            METADATA_DIR,
+        ] {
+            vfs.dir_create(&uri.join(dir))?;
+        }
+
+        let ts = storage::current_timestamp();
+        let name = storage::timestamped_name(ts, ts);
+        schema.store(vfs, &uri.join(SCHEMA_DIR).join(&name))?;
+
+        Array::open(vfs, uri)
+    }
+
+    pub fn open(vfs: &dyn VFSService, uri: &uri::URI) -> Result<Array> {
+        Array::open_at(vfs, uri, 0, u64::MAX)
+    }
+
+    // Open the array as it existed for the inclusive timestamp range. Only
+    // fragments written entirely within the range are visible, unless they
+    // are sparse and store per-cell timestamps in which case readers skip
+    // the cells outside of the range. The latest schema created at or before the end
+    // of the range is used.
+    pub fn open_at(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        timestamp_start: u64,
+        timestamp_end: u64,
+    ) -> Result<Array> {
+        if timestamp_start > timestamp_end {
+            return Err(anyhow!(
+                "Invalid timestamp range [{}, {}]",
+                timestamp_start,
+                timestamp_end
+            )
+            .context(format!("URI: {}", uri)));
+        }
+
+        let mut dir = Directory::new(uri)
+            .set_timestamp_range(timestamp_start, timestamp_end);
+        dir.load_all(vfs)?;
+
+        // Every schema is loaded because fragments in the range may have
+        // been written with a schema that is no longer the latest.
+        let mut schemas = HashMap::new();
+        let mut candidates = Vec::new();
+        for schema_uri in dir.schema_uris() {
+            let storage_schema = storage::ArraySchema::load(vfs, &schema_uri)?;
+            let name = schema_uri.last_path_part();
+            let range = schema_timestamp_range(&name)?;
+            if range.1 <= timestamp_end {
+                candidates.push((range, name.clone()));
+            }
+            schemas.insert(name, Schema::try_from(storage_schema)?);
+        }
+
+        candidates.sort();
+        let latest_schema =
+            candidates.pop().map(|(_, n)| n).ok_or_else(|| {
+                anyhow!("No array schema found at timestamp {}", timestamp_end)
+                    .context(format!("URI: {}", uri))
+            })?;
+
+        let consolidated = load_consolidated_metadata(vfs, &dir)?;
+
+        let mut fragments = Vec::new();
+        for fragment_uri in dir.overlapping_fragment_uris()? {
+            let name = fragment_uri.remove_trailing_slash().last_path_part();
+            let fragment = match consolidated.footer(&name) {
+                Some(footer) => storage::FragmentMetadata::from_footer(
+                    &fragment_uri,
+                    footer,
+                    &schemas,
+                )?,
+                None => storage::FragmentMetadata::load(
+                    vfs,
+                    &fragment_uri,
+                    &schemas,
+                )?,
+            };
+            let (start, end) = fragment.timestamp_range();
+            if (fragment.has_timestamps() && !fragment.is_dense())
+                || (start >= timestamp_start && end <= timestamp_end)
+            {
+                fragments.push(fragment);
+            }
+        }
+
+        let mut delete_conditions = Vec::new();
+        for commit in dir.delete_commits()? {
+            delete_conditions.push(DeleteCondition::load(vfs, &commit)?);
+        }
+
+        Ok(Array {
+            uri: uri.clone(),
+            timestamp_start,
+            timestamp_end,
+            schemas,
+            latest_schema,
+            fragments,
+            delete_conditions,
+        })
+    }
+
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn timestamp_start(&self) -> u64 {
+        self.timestamp_start
+    }
+
+    pub fn timestamp_end(&self) -> u64 {
+        self.timestamp_end
+    }
+
+    // The latest schema which is used to interpret query results.
+    pub fn schema(&self) -> &Schema {
+        &self.schemas[&self.latest_schema]
+    }
+
+    pub fn schema_name(&self) -> &str {
+        &self.latest_schema
+    }
+
+    pub fn schemas(&self) -> &HashMap<String, Schema> {
+        &self.schemas
+    }
+
+    pub fn fragments(&self) -> &[storage::FragmentMetadata] {
+        &self.fragments
+    }
+
+    // Enumerations are only loaded when needed as they may be large.
+    pub fn load_enumeration(
+        &self,
+        vfs: &dyn VFSService,
+        name: &str,
+    ) -> Result<Enumeration> {
+        let path = self.schema().enumerations().get(name).ok_or_else(|| {
+            anyhow!("Unknown enumeration '{}'", name)
+                .context(format!("URI: {}", self.uri))
+        })?;
+        Enumeration::load(vfs, &self.uri, path)
+    }
+
+    pub fn delete_conditions(&self) -> &[DeleteCondition] {
+        &self.delete_conditions
+    }
+
+    // Restrict the array to a single fragment read with the schema it was
+    // written with and without applying delete conditions, so that the
+    // fragment can be rewritten on its own.
+    pub(crate) fn isolate_fragment(&mut self, name: &str) -> Result<()> {
+        self.fragments.retain(|f| f.name() == name);
+        let fragment = self.fragments.first().ok_or_else(|| {
+            anyhow!("Unknown fragment '{}'", name)
+                .context(format!("URI: {}", self.uri))
+        })?;
+        self.latest_schema = fragment.schema_name().to_string();
+        self.delete_conditions.clear();
+        Ok(())
+    }
+
+    // The schema a fragment was written with.
+    pub fn fragment_schema(
+        &self,
+        fragment: &storage::FragmentMetadata,
+    ) -> Result<&Schema> {
+        self.schemas.get(fragment.schema_name()).ok_or_else(|| {
+            anyhow!("Missing schema '{}'", fragment.schema_name())
+                .context(format!("Fragment: {}", fragment.uri()))
+        })
+    }
+}
+
+// The original schema predates timestamped schema names and is older than
+// all of them.
+fn schema_timestamp_range(name: &str) -> Result<(u64, u64)> {
+    if name == OLD_SCHEMA_NAME {
+        return Ok((0, 0));
+    }
+
+    storage::timestamp_range(name)
+        .map_err(|err| err.context(format!("Invalid schema name: {}", name)))
+}
+
+// Fragment metadata consolidation gathers fragment footers into files under
+// __fragment_meta. Newer files take precedence when a fragment appears in
+// more than one of them.
+fn load_consolidated_metadata(
+    vfs: &dyn VFSService,
+    dir: &Directory,
+) -> Result<storage::ConsolidatedFragmentMetadata> {
+    let mut ret = storage::ConsolidatedFragmentMetadata::default();
+    for meta_uri in dir.fragment_meta_uris()?.iter().rev() {
+        ret.merge(storage::ConsolidatedFragmentMetadata::load(vfs, meta_uri)?);
+    }
+    Ok(ret)
+}
@@ -0,0 +1,65 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use crate::datatype::{DataType, Primitive};
+
+// An inclusive range of values on a single dimension. Values are stored as
+// little endian bytes so that fixed and var sized dimensions can share the
+// same representation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Range {
+    start: Vec<u8>,
+    end: Vec<u8>,
+}
+
+impl Range {
+    pub fn new(start: Vec<u8>, end: Vec<u8>) -> Self {
+        Range { start, end }
+    }
+
+    pub fn from_values<T: Primitive>(start: T, end: T) -> Self {
+        Range {
+            start: start.to_bytes(),
+            end: end.to_bytes(),
+        }
+    }
+
+    pub fn start(&self) -> &[u8] {
+        &self.start
+    }
+
+    pub fn end(&self) -> &[u8] {
+        &self.end
+    }
+
+    pub fn start_as<T: Primitive>(&self) -> T {
+        T::from_bytes(&self.start)
+    }
+
+    pub fn end_as<T: Primitive>(&self) -> T {
+        T::from_bytes(&self.end)
+    }
+
+    pub fn contains(&self, dtype: DataType, value: &[u8]) -> bool {
+        dtype.compare(value, &self.start) != Ordering::Less
+            && dtype.compare(value, &self.end) != Ordering::Greater
+    }
+
+    pub fn intersects(&self, dtype: DataType, other: &Range) -> bool {
+        dtype.compare(&self.end, &other.start) != Ordering::Less
+            && dtype.compare(&other.end, &self.start) != Ordering::Less
+    }
+
+    // True if other lies completely within this range.
+    pub fn covers(&self, dtype: DataType, other: &Range) -> bool {
+        dtype.compare(&other.start, &self.start) != Ordering::Less
+            && dtype.compare(&other.end, &self.end) != Ordering::Greater
+    }
+
+    // The bounds of an integral range widened to i128.
+    pub fn to_i128(&self, dtype: DataType) -> Option<(i128, i128)> {
+        Some((dtype.to_i128(&self.start)?, dtype.to_i128(&self.end)?))
+    }
+}
@@ -0,0 +1,387 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::array::{
+    self, field_files, fragment_fields, Directory, COMMITS_DIR,
+    IGNORE_FILE_SUFFIX, OK_FILE_SUFFIX, WRITE_FILE_SUFFIX,
+};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+pub(crate) const QUARANTINE_DIR: &str = "__quarantine";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepairReason {
+    // A fragment directory that was never committed.
+    Uncommitted,
+    // A commit whose fragment directory does not exist.
+    MissingFragment,
+    // A committed fragment with a missing or empty file.
+    EmptyFile,
+    // A committed fragment with a file shorter than its footer records, or
+    // whose footer can't be read.
+    TruncatedFile,
+}
+
+// A file or directory removed or quarantined by a repair.
+pub struct RepairEntry {
+    uri: uri::URI,
+    is_dir: bool,
+    reason: RepairReason,
+}
+
+impl RepairEntry {
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn reason(&self) -> RepairReason {
+        self.reason
+    }
+}
+
+// Everything changed by a repair, or that would be changed by a dry run.
+#[derive(Default)]
+pub struct RepairReport {
+    entries: Vec<RepairEntry>,
+    ignored_commits: Vec<String>,
+}
+
+impl RepairReport {
+    pub fn entries(&self) -> &[RepairEntry] {
+        &self.entries
+    }
+
+    // Commits stored in consolidated commit files which can't be removed
+    // and are ignored instead.
+    pub fn ignored_commits(&self) -> &[String] {
+        &self.ignored_commits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.ignored_commits.is_empty()
+    }
+}
+
+// Cleans up after crashed writers: fragment directories without a commit,
+// commits of fragments that do not exist and committed fragments with empty
+// or truncated files. Damaged fragments are uncommitted before their data is
+// touched. By default everything is moved under the array's quarantine
+// directory rather than deleted. Repairs must not run concurrently with
+// writers as their fragments are not committed until they finish.
+pub struct Repairer {
+    array_uri: uri::URI,
+    dry_run: bool,
+    quarantine: bool,
+}
+
+impl Repairer {
+    pub fn new(array_uri: &uri::URI) -> Self {
+        Repairer {
+            array_uri: array_uri.clone(),
+            dry_run: false,
+            quarantine: true,
+        }
+    }
+
+    // Report what would be repaired without changing the array.
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Delete rather than quarantine damaged files.
+    pub fn set_quarantine(mut self, quarantine: bool) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    pub fn repair(&self, vfs: &dyn VFSService) -> Result<RepairReport> {
+        let mut dir = Directory::new(&self.array_uri);
+        dir.load_all(vfs)?;
+
+        // Invalid schemas are left for the integrity checker to report.
+        let mut schemas = HashMap::new();
+        for schema_uri in dir.schema_uris() {
+            let schema = storage::ArraySchema::load(vfs, &schema_uri)
+                .and_then(array::Schema::try_from);
+            if let Ok(schema) = schema {
+                schemas.insert(schema_uri.last_path_part(), schema);
+            }
+        }
+
+        let mut report = RepairReport::default();
+        let mut damaged = Vec::new();
+        let committed = dir.fragment_uris()?;
+        for fragment_uri in committed.iter() {
+            let fragment_uri = fragment_uri.remove_trailing_slash();
+            let reason = if vfs.dir_exists(&fragment_uri)? {
+                match damage(vfs, &fragment_uri, &schemas)? {
+                    Some(reason) => reason,
+                    None => continue,
+                }
+            } else {
+                RepairReason::MissingFragment
+            };
+            damaged.push((fragment_uri, reason));
+        }
+
+        let consolidated: HashSet<&String> =
+            dir.consolidated_fragment_names().iter().collect();
+        let commits_uri = self.array_uri.join(COMMITS_DIR);
+        for (fragment_uri, reason) in damaged.iter() {
+            let name = fragment_uri.last_path_part();
+            let commits = [
+                commits_uri.join(&(name.clone() + WRITE_FILE_SUFFIX)),
+                self.array_uri.join(&(name.clone() + OK_FILE_SUFFIX)),
+            ];
+            for commit_uri in commits {
+                if vfs.file_exists(&commit_uri)? {
+                    report.entries.push(RepairEntry {
+                        uri: commit_uri,
+                        is_dir: false,
+                        reason: *reason,
+                    });
+                }
+            }
+            if consolidated.contains(&name) {
+                report.ignored_commits.push(format!(
+                    "{}/{}{}",
+                    COMMITS_DIR, name, WRITE_FILE_SUFFIX
+                ));
+            }
+        }
+
+        for (fragment_uri, reason) in damaged {
+            if reason != RepairReason::MissingFragment {
+                report.entries.push(RepairEntry {
+                    uri: fragment_uri,
+                    is_dir: true,
+                    reason,
+                });
+            }
+        }
+
+        // Fragments listed in vacuum files are left for the vacuumer and
+        // ignored fragments are already hidden from readers.
+        let known: HashSet<String> = committed
+            .iter()
+            .chain(dir.vacuumed_fragment_uris()?.iter())
+            .map(|u| u.remove_trailing_slash().last_path_part())
+            .collect();
+        for fragment_uri in dir.fragment_dir_uris(vfs)? {
+            let name = fragment_uri.remove_trailing_slash().last_path_part();
+            if !known.contains(&name) && !dir.is_ignored(&name) {
+                report.entries.push(RepairEntry {
+                    uri: fragment_uri,
+                    is_dir: true,
+                    reason: RepairReason::Uncommitted,
+                });
+            }
+        }
+
+        if self.dry_run {
+            return Ok(report);
+        }
+
+        if !report.ignored_commits.is_empty() {
+            let ts = storage::current_timestamp();
+            let name = storage::timestamped_name(ts, ts) + IGNORE_FILE_SUFFIX;
+            let ign_uri = commits_uri.join(&name);
+            vfs.file_create(&ign_uri)?;
+            vfs.file_write(
+                &ign_uri,
+                0,
+                (report.ignored_commits.join("\n") + "\n").as_bytes(),
+            )?;
+        }
+
+        for entry in report.entries.iter() {
+            if self.quarantine {
+                self.quarantine(vfs, entry)?;
+            } else if entry.is_dir {
+                vfs.dir_remove(&entry.uri)?;
+            } else {
+                vfs.file_remove(&entry.uri)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Move an entry to the same path relative to the quarantine directory.
+    fn quarantine(
+        &self,
+        vfs: &dyn VFSService,
+        entry: &RepairEntry,
+    ) -> Result<()> {
+        let array_path = self.array_uri.remove_trailing_slash().path();
+        let path = entry.uri.remove_trailing_slash().path();
+        let relative = path
+            .strip_prefix(&array_path)
+            .unwrap_or(&path)
+            .trim_start_matches('/');
+        let (dirs, name) = relative.rsplit_once('/').unwrap_or(("", relative));
+
+        let mut parent = self.array_uri.join(QUARANTINE_DIR);
+        if !vfs.dir_exists(&parent)? {
+            vfs.dir_create(&parent)?;
+        }
+        for part in dirs.split('/').filter(|p| !p.is_empty()) {
+            parent = parent.join(part);
+            if !vfs.dir_exists(&parent)? {
+                vfs.dir_create(&parent)?;
+            }
+        }
+
+        let target = parent.join(name);
+        if entry.is_dir {
+            vfs.dir_move(&entry.uri, &target)
+        } else {
+            vfs.file_move(&entry.uri, &target)
+        }
+    }
+}
+
+// Check the files of a committed fragment against its footer. Fragments
+// whose schema is missing can't be checked and are left alone.
+fn damage(
+    vfs: &dyn VFSService,
+    fragment_uri: &uri::URI,
+    schemas: &HashMap<String, array::Schema>,
+) -> Result<Option<RepairReason>> {
+    let fmd_uri = fragment_uri.join(storage::FRAGMENT_METADATA_FILENAME);
+    if !vfs.file_exists(&fmd_uri)? || vfs.file_size(&fmd_uri)? == 0 {
+        return Ok(Some(RepairReason::EmptyFile));
+    }
+
+    let Ok(data) =
+        storage::FragmentMetadata::read_footer(vfs, fragment_uri, schemas)
+    else {
+        return Ok(Some(RepairReason::TruncatedFile));
+    };
+    let Ok(schema_name) = storage::FragmentMetadata::footer_schema_name(&data)
+    else {
+        return Ok(Some(RepairReason::TruncatedFile));
+    };
+    let Some(schema) = schemas.get(&schema_name) else {
+        return Ok(None);
+    };
+    let Ok(fragment) =
+        storage::FragmentMetadata::from_footer(fragment_uri, &data, schemas)
+    else {
+        return Ok(Some(RepairReason::TruncatedFile));
+    };
+
+    for field in fragment_fields(&fragment, schema).iter() {
+        for (file_uri, expected) in field_files(&fragment, field) {
+            let actual = if vfs.file_exists(&file_uri)? {
+                vfs.file_size(&file_uri)?
+            } else {
+                0
+            };
+            if expected > 0 && actual == 0 {
+                return Ok(Some(RepairReason::EmptyFile));
+            }
+            if actual < expected {
+                return Ok(Some(RepairReason::TruncatedFile));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::FRAGMENTS_DIR;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{QueryBuffer, SparseWriter};
+
+    #[test]
+    fn repair_crashed_writes() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) =
+            fixtures::temp_array(&vfs, "repair", &fixtures::sparse_schema())?;
+        let uri = array.uri().clone();
+        let mut fragments = Vec::new();
+        let t = storage::current_timestamp();
+        for (x, ts) in [(1i64, t + 1), (2, t + 2)] {
+            let writer =
+                SparseWriter::new(&uri, array.schema_name(), array.schema())
+                    .set_buffer("x", QueryBuffer::from_values(&[x]))
+                    .set_buffer("a", QueryBuffer::from_values(&[0i32]))
+                    .set_timestamp(ts);
+            fragments.push(writer.write(&vfs)?);
+        }
+
+        // Empty the attribute file of the second fragment, leave a fragment
+        // without a commit and a commit without a fragment.
+        let a0 = fragments[1].join("a0.tdb");
+        vfs.file_remove(&a0)?;
+        vfs.file_create(&a0)?;
+        let name = storage::timestamped_name(t + 3, t + 3);
+        vfs.dir_create(&uri.join(FRAGMENTS_DIR).join(&name))?;
+        let name = storage::timestamped_name(t + 4, t + 4);
+        let dangling = uri.join(COMMITS_DIR).join(&(name + WRITE_FILE_SUFFIX));
+        vfs.file_create(&dangling)?;
+
+        // Before format version 12 fragments were written to the array
+        // directory and committed by an .ok file.
+        let name = storage::versioned_name(t + 5, t + 5, 11);
+        let old_uncommitted = uri.join(&name);
+        vfs.dir_create(&old_uncommitted)?;
+
+        // Ignored fragments are already hidden and left for the vacuumer.
+        let name = storage::timestamped_name(t + 6, t + 6);
+        vfs.dir_create(&uri.join(FRAGMENTS_DIR).join(&name))?;
+        let ign_uri = uri.join(COMMITS_DIR).join(
+            &(storage::timestamped_name(t + 7, t + 7) + IGNORE_FILE_SUFFIX),
+        );
+        vfs.file_create(&ign_uri)?;
+        vfs.file_write(&ign_uri, 0, (name + "\n").as_bytes())?;
+
+        let report = Repairer::new(&uri).set_dry_run(true).repair(&vfs)?;
+        let reasons: Vec<_> =
+            report.entries().iter().map(|e| e.reason()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                RepairReason::EmptyFile,
+                RepairReason::MissingFragment,
+                RepairReason::EmptyFile,
+                RepairReason::Uncommitted,
+                RepairReason::Uncommitted,
+            ]
+        );
+        assert!(report.entries()[1].uri() == &dangling);
+        assert!(report.entries()[2].uri() == &fragments[1]);
+        assert!(report.entries()[4].uri() == &old_uncommitted);
+        assert!(vfs.file_exists(&dangling)?);
+
+        Repairer::new(&uri).repair(&vfs)?;
+        assert!(!vfs.dir_exists(&fragments[1])?);
+        let quarantined = uri
+            .join(QUARANTINE_DIR)
+            .join(FRAGMENTS_DIR)
+            .join(&fragments[1].last_path_part());
+        assert!(vfs.file_exists(&quarantined.join("a0.tdb"))?);
+        assert!(vfs.dir_exists(
+            &uri.join(QUARANTINE_DIR)
+                .join(&old_uncommitted.last_path_part())
+        )?);
+        assert_eq!(array::Array::open(&vfs, &uri)?.fragments().len(), 1);
+        assert!(Repairer::new(&uri).repair(&vfs)?.is_empty());
+        Ok(())
+    }
+}
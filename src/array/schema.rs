@@ -9,12 +9,14 @@ use crate::datatype::DataType;
 use crate::filters::FilterChain;
 use crate::io::uri;
 use crate::storage;
+use crate::storage::CELL_VAR_SIZE;
 
 pub struct Dimension {
     name: String,
     data_type: DataType,
     cell_val_num: u32,
     filters: Box<FilterChain>,
+    has_filters: bool,
     range: Vec<u8>,
     extent: Vec<u8>,
 }
@@ -29,16 +31,88 @@ impl TryFrom<&storage::schema::Dimension> for Dimension {
             data_type: storage.data_type,
             cell_val_num: storage.cell_val_num,
             filters: <_>::try_from(&storage.coords_filters)?,
+            has_filters: !storage.coords_filters.filters().is_empty(),
             range: storage.range.clone(),
             extent: storage.tile_extent.clone(),
         })
     }
 }
 
+impl Dimension {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn cell_val_num(&self) -> u32 {
+        self.cell_val_num
+    }
+
+    pub fn is_var_sized(&self) -> bool {
+        self.cell_val_num == CELL_VAR_SIZE
+    }
+
+    pub fn coord_size(&self) -> usize {
+        self.data_type.size()
+    }
+
+    // Dimensions without their own filters use the schema's coords filters.
+    pub fn has_filters(&self) -> bool {
+        self.has_filters
+    }
+
+    pub fn filters(&self) -> &FilterChain {
+        &self.filters
+    }
+
+    // The domain of the dimension as a (low, high) pair. Var sized dimensions
+    // have no domain and return None.
+    pub fn range(&self) -> Option<(&[u8], &[u8])> {
+        if self.range.is_empty() {
+            return None;
+        }
+        let size = self.coord_size();
+        Some((&self.range[..size], &self.range[size..2 * size]))
+    }
+
+    pub fn extent(&self) -> Option<&[u8]> {
+        if self.extent.is_empty() {
+            None
+        } else {
+            Some(&self.extent)
+        }
+    }
+}
+
 pub struct Domain {
     dimensions: Vec<Dimension>,
 }
 
+impl Domain {
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dimensions
+    }
+
+    pub fn dimension(&self, name: &str) -> Option<&Dimension> {
+        self.dimensions.iter().find(|d| d.name == name)
+    }
+
+    pub fn dimension_idx(&self, name: &str) -> Option<usize> {
+        self.dimensions.iter().position(|d| d.name == name)
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.dimensions.len()
+    }
+
+    pub fn all_dims_fixed(&self) -> bool {
+        self.dimensions.iter().all(|d| !d.is_var_sized())
+    }
+}
+
 impl TryFrom<&storage::schema::Domain> for Domain {
     type Error = anyhow::Error;
     fn try_from(
@@ -85,11 +159,77 @@ impl TryFrom<&storage::schema::Attribute> for Attribute {
     }
 }
 
+impl Attribute {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn cell_val_num(&self) -> u32 {
+        self.cell_val_num
+    }
+
+    pub fn is_var_sized(&self) -> bool {
+        self.cell_val_num == CELL_VAR_SIZE
+    }
+
+    // The size in bytes of a single fixed size cell.
+    pub fn cell_size(&self) -> usize {
+        if self.is_var_sized() {
+            self.data_type.size()
+        } else {
+            self.data_type.size() * self.cell_val_num as usize
+        }
+    }
+
+    pub fn filters(&self) -> &FilterChain {
+        &self.filters
+    }
+
+    // Schemas older than version 6 did not store fill values so we fall
+    // back to the per-datatype defaults.
+    pub fn fill_value(&self) -> Vec<u8> {
+        if !self.fill_value.is_empty() {
+            return self.fill_value.clone();
+        }
+
+        let value = self.data_type.default_fill_value();
+        if self.is_var_sized() {
+            value
+        } else {
+            value.repeat(self.cell_val_num as usize)
+        }
+    }
+
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+
+    pub fn fill_value_validity(&self) -> bool {
+        self.fill_value_validity
+    }
+
+    pub fn data_order(&self) -> DataOrder {
+        self.data_order
+    }
+
+    pub fn enumeration_name(&self) -> Option<&str> {
+        if self.enumeration_name.is_empty() {
+            None
+        } else {
+            Some(&self.enumeration_name)
+        }
+    }
+}
+
 pub struct DimensionLabel {
     dimension_idx: u32,
     name: String,
     relative_uri: bool,
-    uri: uri::URI,
+    uri: String,
     attribute_name: String,
     data_order: DataOrder,
     data_type: DataType,
@@ -106,9 +246,7 @@ impl TryFrom<&storage::schema::DimensionLabel> for DimensionLabel {
             dimension_idx: storage.dimension_id,
             name: String::from_utf8(storage.name.clone())?,
             relative_uri: storage.relative_uri != 0,
-            uri: uri::URI::from_string(&String::from_utf8(
-                storage.uri.clone(),
-            )?)?,
+            uri: String::from_utf8(storage.uri.clone())?,
             attribute_name: String::from_utf8(storage.attribute_name.clone())?,
             data_order: storage.data_order,
             data_type: storage.data_type,
@@ -118,6 +256,53 @@ impl TryFrom<&storage::schema::DimensionLabel> for DimensionLabel {
     }
 }
 
+impl DimensionLabel {
+    pub fn dimension_idx(&self) -> u32 {
+        self.dimension_idx
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_relative_uri(&self) -> bool {
+        self.relative_uri
+    }
+
+    // The label array's URI as stored, which may be relative to the array.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn resolve_uri(&self, array_uri: &uri::URI) -> crate::Result<uri::URI> {
+        if self.relative_uri {
+            Ok(array_uri.join(&self.uri))
+        } else {
+            uri::URI::from_string(&self.uri)
+        }
+    }
+
+    pub fn attribute_name(&self) -> &str {
+        &self.attribute_name
+    }
+
+    pub fn data_order(&self) -> DataOrder {
+        self.data_order
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn cell_val_num(&self) -> u32 {
+        self.cell_val_num
+    }
+
+    pub fn is_external(&self) -> bool {
+        self.is_external
+    }
+}
+
 pub struct Schema {
     version: u32,
     allows_dups: bool,
@@ -125,6 +310,7 @@ pub struct Schema {
     tile_order: Layout,
     cell_order: Layout,
     capacity: u64,
+    coords_filters: Box<FilterChain>,
     cell_var_filters: Box<FilterChain>,
     cell_validity_filters: Box<FilterChain>,
     domain: Domain,
@@ -154,6 +340,7 @@ impl TryFrom<storage::ArraySchema> for Schema {
             tile_order: storage.tile_order,
             cell_order: storage.cell_order,
             capacity: storage.capacity,
+            coords_filters: <_>::try_from(&storage.coords_filters)?,
             cell_var_filters: <_>::try_from(&storage.cell_var_filters)?,
             cell_validity_filters: <_>::try_from(
                 &storage.cell_validity_filters,
@@ -165,3 +352,81 @@ impl TryFrom<storage::ArraySchema> for Schema {
         })
     }
 }
+
+impl Schema {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn allows_dups(&self) -> bool {
+        self.allows_dups
+    }
+
+    pub fn array_type(&self) -> ArrayType {
+        self.array_type
+    }
+
+    pub fn tile_order(&self) -> Layout {
+        self.tile_order
+    }
+
+    pub fn cell_order(&self) -> Layout {
+        self.cell_order
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn coords_filters(&self) -> &FilterChain {
+        &self.coords_filters
+    }
+
+    pub fn cell_var_filters(&self) -> &FilterChain {
+        &self.cell_var_filters
+    }
+
+    pub fn cell_validity_filters(&self) -> &FilterChain {
+        &self.cell_validity_filters
+    }
+
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    pub fn attribute_idx(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|a| a.name == name)
+    }
+
+    pub fn dimension_labels(&self) -> &[DimensionLabel] {
+        &self.dimension_labels
+    }
+
+    pub fn dimension_label(&self, name: &str) -> Option<&DimensionLabel> {
+        self.dimension_labels.iter().find(|l| l.name == name)
+    }
+
+    pub fn enumerations(&self) -> &HashMap<String, String> {
+        &self.enumerations
+    }
+
+    // The filters used for a dimension's coordinate tiles.
+    pub fn dimension_filters<'a>(
+        &'a self,
+        dim: &'a Dimension,
+    ) -> &'a FilterChain {
+        if dim.has_filters() {
+            dim.filters()
+        } else {
+            self.coords_filters()
+        }
+    }
+}
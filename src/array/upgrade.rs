@@ -0,0 +1,478 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+
+use crate::array::{
+    self, tile_aligned_subarray, ArrayType, Directory, Layout, COMMITS_DIR,
+    FRAGMENTS_DIR, FRAGMENT_META_DIR, IGNORE_FILE_SUFFIX, METADATA_DIR,
+    OK_FILE_SUFFIX, OLD_SCHEMA_NAME, SCHEMA_DIR, WRITE_FILE_SUFFIX,
+};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::query::{
+    integral_bounds, DenseReader, DenseWriter, QueryBuffer, SparseReader,
+    SparseWriter, Subarray,
+};
+use crate::storage;
+use crate::Result;
+
+// A schema or fragment rewritten by an upgrade. The upgraded URI is only
+// known once the upgrade has run.
+pub struct UpgradeEntry {
+    original: uri::URI,
+    upgraded: Option<uri::URI>,
+}
+
+impl UpgradeEntry {
+    pub fn original(&self) -> &uri::URI {
+        &self.original
+    }
+
+    pub fn upgraded(&self) -> Option<&uri::URI> {
+        self.upgraded.as_ref()
+    }
+}
+
+// Everything rewritten by an upgrade, or that would be rewritten by a dry
+// run.
+#[derive(Default)]
+pub struct UpgradeReport {
+    schemas: Vec<UpgradeEntry>,
+    fragments: Vec<UpgradeEntry>,
+}
+
+impl UpgradeReport {
+    pub fn schemas(&self) -> &[UpgradeEntry] {
+        &self.schemas
+    }
+
+    pub fn fragments(&self) -> &[UpgradeEntry] {
+        &self.fragments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty() && self.fragments.is_empty()
+    }
+}
+
+// Rewrites an array written in older format versions in the current
+// layout. The original __array_schema.tdb is moved under __schema and
+// schemas in older versions are rewritten in place. Fragments in older
+// versions are rewritten under __fragments with their original timestamps
+// and committed in __commits, including those of versions 1 to 4 that are
+// committed by a fragment file and store their coordinates zipped
+// together. The original fragments and schema are only
+// removed once every rewritten fragment reads back the same cells as the
+// fragment it replaces. Fragments replaced by consolidation are left for
+// the vacuumer. Upgrades must not run concurrently with writers.
+pub struct Upgrader {
+    array_uri: uri::URI,
+    dry_run: bool,
+}
+
+impl Upgrader {
+    pub fn new(array_uri: &uri::URI) -> Self {
+        Upgrader {
+            array_uri: array_uri.clone(),
+            dry_run: false,
+        }
+    }
+
+    // Report what would be upgraded without changing the array.
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn upgrade(&self, vfs: &dyn VFSService) -> Result<UpgradeReport> {
+        let mut dir = Directory::new(&self.array_uri);
+        dir.load_all(vfs)?;
+
+        // The original schema is given a timestamped name that still sorts
+        // before every other schema.
+        let mut schemas = Vec::new();
+        for schema_uri in dir.schema_uris() {
+            let schema = storage::ArraySchema::load(vfs, &schema_uri)?;
+            let name = schema_uri.last_path_part();
+            if name == OLD_SCHEMA_NAME {
+                let upgraded = self
+                    .array_uri
+                    .join(SCHEMA_DIR)
+                    .join(&storage::timestamped_name(0, 0));
+                schemas.push((schema_uri, upgraded, schema));
+            } else if schema.version != storage::CURRENT_FORMAT_VERSION {
+                schemas.push((schema_uri.clone(), schema_uri, schema));
+            }
+        }
+
+        let array = array::Array::open(vfs, &self.array_uri)?;
+        let mut fragments = Vec::new();
+        for fragment in array.fragments() {
+            if fragment.format_version() == storage::CURRENT_FORMAT_VERSION
+                && fragment.schema_name() != OLD_SCHEMA_NAME
+            {
+                continue;
+            }
+            if fragment.has_delete_meta() {
+                return Err(anyhow!(
+                    "Unable to upgrade fragments with delete metadata"
+                )
+                .context(format!("Fragment: {}", fragment.uri())));
+            }
+            fragments.push((
+                fragment.uri().remove_trailing_slash(),
+                fragment.name().to_string(),
+                fragment.timestamp_range(),
+            ));
+        }
+
+        let mut report = UpgradeReport::default();
+        if self.dry_run {
+            report.schemas = schemas
+                .into_iter()
+                .map(|(original, _, _)| UpgradeEntry {
+                    original,
+                    upgraded: None,
+                })
+                .collect();
+            report.fragments = fragments
+                .into_iter()
+                .map(|(original, _, _)| UpgradeEntry {
+                    original,
+                    upgraded: None,
+                })
+                .collect();
+            return Ok(report);
+        }
+
+        for name in [
+            SCHEMA_DIR,
+            COMMITS_DIR,
+            FRAGMENTS_DIR,
+            FRAGMENT_META_DIR,
+            METADATA_DIR,
+        ] {
+            let uri = self.array_uri.join(name);
+            if !vfs.dir_exists(&uri)? {
+                vfs.dir_create(&uri)?;
+            }
+        }
+
+        // Upgraded schemas are checked before anything is written as those
+        // upgraded in place replace their originals.
+        let mut schema_names = HashMap::new();
+        let mut upgraded_schemas = Vec::new();
+        for (original, upgraded, schema) in schemas {
+            let data = schema.upgrade().to_bytes()?;
+            let expected = array::Schema::try_from(
+                storage::ArraySchema::load(vfs, &original)?,
+            )?;
+            let actual = array::Schema::try_from(
+                storage::ArraySchema::from_bytes(&data)?,
+            )?;
+            if !same_fields(&expected, &actual) {
+                return Err(anyhow!("Upgraded schema has different fields")
+                    .context(format!("URI: {}", original)));
+            }
+            schema_names
+                .insert(original.last_path_part(), upgraded.last_path_part());
+            upgraded_schemas.push((original, upgraded, data));
+        }
+
+        for (original, upgraded, data) in upgraded_schemas.iter() {
+            if original != upgraded {
+                storage::write_generic_tile(vfs, upgraded, data)?;
+            }
+        }
+
+        let mut written = Vec::new();
+        let result = self.rewrite_fragments(
+            vfs,
+            &fragments,
+            &schema_names,
+            &mut written,
+        );
+        if let Err(err) = result {
+            self.remove_rewritten(vfs, &written, &upgraded_schemas)?;
+            return Err(err);
+        }
+
+        // Only now that the rewritten fragments are verified are the
+        // originals removed, commits first. Commits merged into consolidated
+        // commit files can't be removed and are ignored instead.
+        let consolidated: HashSet<&String> =
+            dir.consolidated_fragment_names().iter().collect();
+        let ignored: Vec<String> = fragments
+            .iter()
+            .filter(|(_, name, _)| consolidated.contains(name))
+            .map(|(_, name, _)| {
+                format!("{}/{}{}", COMMITS_DIR, name, WRITE_FILE_SUFFIX)
+            })
+            .collect();
+        if !ignored.is_empty() {
+            let ts = storage::current_timestamp();
+            let name = storage::timestamped_name(ts, ts) + IGNORE_FILE_SUFFIX;
+            let ign_uri = self.array_uri.join(COMMITS_DIR).join(&name);
+            vfs.file_create(&ign_uri)?;
+            vfs.file_write(
+                &ign_uri,
+                0,
+                (ignored.join("\n") + "\n").as_bytes(),
+            )?;
+        }
+        for (fragment_uri, name, _) in fragments.iter() {
+            let commits = [
+                self.array_uri
+                    .join(COMMITS_DIR)
+                    .join(&(name.clone() + WRITE_FILE_SUFFIX)),
+                self.array_uri.join(&(name.clone() + OK_FILE_SUFFIX)),
+            ];
+            for commit_uri in commits {
+                if vfs.file_exists(&commit_uri)? {
+                    vfs.file_remove(&commit_uri)?;
+                }
+            }
+            vfs.dir_remove(fragment_uri)?;
+        }
+
+        for (original, upgraded, data) in upgraded_schemas {
+            if original == upgraded {
+                storage::write_generic_tile(vfs, &upgraded, &data)?;
+            } else {
+                vfs.file_remove(&original)?;
+            }
+            report.schemas.push(UpgradeEntry {
+                original,
+                upgraded: Some(upgraded),
+            });
+        }
+
+        report.fragments = fragments
+            .into_iter()
+            .zip(written)
+            .map(|((original, _, _), upgraded)| UpgradeEntry {
+                original,
+                upgraded: Some(upgraded),
+            })
+            .collect();
+
+        Ok(report)
+    }
+
+    // Rewrite each fragment on its own and check that the rewritten
+    // fragment holds the same cells, recording every fragment written.
+    fn rewrite_fragments(
+        &self,
+        vfs: &dyn VFSService,
+        fragments: &[(uri::URI, String, (u64, u64))],
+        schema_names: &HashMap<String, String>,
+        written: &mut Vec<uri::URI>,
+    ) -> Result<()> {
+        for (fragment_uri, name, (start, end)) in fragments {
+            let mut array =
+                array::Array::open_at(vfs, &self.array_uri, *start, *end)?;
+            array.isolate_fragment(name)?;
+            let buffers = read_fragment(vfs, &array)?;
+
+            let fragment = &array.fragments()[0];
+            let schema = array.schema();
+            let schema_name = schema_names
+                .get(array.schema_name())
+                .map_or(array.schema_name(), |n| n.as_str());
+            let upgraded = match schema.array_type() {
+                ArrayType::Dense => {
+                    let mut writer =
+                        DenseWriter::new(&self.array_uri, schema_name, schema)
+                            .set_subarray(dense_subarray(&array)?)
+                            .set_non_empty_domain(
+                                fragment.non_empty_domain().to_vec(),
+                            )
+                            .set_timestamp_range(*start, *end);
+                    for (name, buffer) in buffers.iter() {
+                        writer = writer.set_buffer(name, buffer.clone());
+                    }
+                    writer.write(vfs)?
+                }
+                _ => {
+                    let mut writer =
+                        SparseWriter::new(&self.array_uri, schema_name, schema)
+                            .set_timestamp_range(*start, *end);
+                    for (name, buffer) in buffers.iter() {
+                        writer = writer.set_buffer(name, buffer.clone());
+                    }
+                    writer.write(vfs)?
+                }
+            };
+            let upgraded = upgraded.remove_trailing_slash();
+            written.push(upgraded.clone());
+
+            let mut array =
+                array::Array::open_at(vfs, &self.array_uri, *start, *end)?;
+            array.isolate_fragment(&upgraded.last_path_part())?;
+            let rewritten = read_fragment(vfs, &array)?;
+            if !same_buffers(&buffers, &rewritten) {
+                return Err(anyhow!("Upgraded fragment has different cells")
+                    .context(format!("Fragment: {}", fragment_uri)));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Undo a failed upgrade, leaving the original fragments and schemas.
+    fn remove_rewritten(
+        &self,
+        vfs: &dyn VFSService,
+        written: &[uri::URI],
+        schemas: &[(uri::URI, uri::URI, Vec<u8>)],
+    ) -> Result<()> {
+        for fragment_uri in written {
+            let name = fragment_uri.last_path_part() + WRITE_FILE_SUFFIX;
+            vfs.file_remove(&self.array_uri.join(COMMITS_DIR).join(&name))?;
+            vfs.dir_remove(fragment_uri)?;
+        }
+        for (original, upgraded, _) in schemas {
+            if original != upgraded {
+                vfs.file_remove(upgraded)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Read every cell of the only fragment of an isolated array. Sparse
+// fragments keep their cell timestamps.
+fn read_fragment(
+    vfs: &dyn VFSService,
+    array: &array::Array,
+) -> Result<HashMap<String, QueryBuffer>> {
+    let fragment = &array.fragments()[0];
+    if fragment.is_dense() {
+        return DenseReader::new(array)
+            .set_subarray(dense_subarray(array)?)
+            .set_layout(Layout::RowMajor)
+            .read(vfs);
+    }
+
+    SparseReader::new(array)
+        .set_layout(Layout::GlobalOrder)
+        .set_timestamps(fragment.has_timestamps())
+        .read(vfs)
+}
+
+// Dense fragments are read and written over the space tiles they cover.
+fn dense_subarray(array: &array::Array) -> Result<Subarray> {
+    let ned = array.fragments()[0].non_empty_domain();
+    let bounds = integral_bounds(array.schema(), ned)?;
+    tile_aligned_subarray(array.schema(), &bounds)
+}
+
+fn same_buffers(
+    lhs: &HashMap<String, QueryBuffer>,
+    rhs: &HashMap<String, QueryBuffer>,
+) -> bool {
+    lhs.len() == rhs.len()
+        && lhs.iter().all(|(name, l)| {
+            rhs.get(name).is_some_and(|r| {
+                l.data() == r.data()
+                    && l.offsets() == r.offsets()
+                    && l.validity() == r.validity()
+            })
+        })
+}
+
+fn same_fields(lhs: &array::Schema, rhs: &array::Schema) -> bool {
+    let dims = |s: &array::Schema| {
+        s.domain()
+            .dimensions()
+            .iter()
+            .map(|d| {
+                let range =
+                    d.range().map(|(lo, hi)| (lo.to_vec(), hi.to_vec()));
+                (d.name().to_string(), d.data_type(), d.cell_val_num(), range)
+            })
+            .collect::<Vec<_>>()
+    };
+    let attrs = |s: &array::Schema| {
+        s.attributes()
+            .iter()
+            .map(|a| {
+                (
+                    a.name().to_string(),
+                    a.data_type(),
+                    a.cell_val_num(),
+                    a.nullable(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    lhs.array_type() == rhs.array_type()
+        && dims(lhs) == dims(rhs)
+        && attrs(lhs) == attrs(rhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+
+    #[test]
+    fn upgrade_fixtures() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = fixtures::TempDir::new("upgrade")?;
+        let root = dir.uri().clone();
+        vfs.dir_create(&root)?;
+
+        for version in [1, 2, 3, 4, 5, 9, 11, 15] {
+            for array_type in [ArrayType::Dense, ArrayType::Sparse] {
+                let uri = root.join(&format!("{:?}{}", array_type, version));
+                fixtures::create(&vfs, &uri, array_type, version)?;
+
+                let report =
+                    Upgrader::new(&uri).set_dry_run(true).upgrade(&vfs)?;
+                assert_eq!(report.schemas().len(), 1);
+                assert_eq!(report.fragments().len(), 1);
+                assert!(report.fragments()[0].upgraded().is_none());
+
+                let report = Upgrader::new(&uri).upgrade(&vfs)?;
+                let original = report.fragments()[0].original();
+                assert!(!vfs.dir_exists(original)?);
+                assert!(!vfs.file_exists(&uri.join(OLD_SCHEMA_NAME))?);
+                assert!(Upgrader::new(&uri).upgrade(&vfs)?.is_empty());
+
+                let array = array::Array::open(&vfs, &uri)?;
+                let fragment = &array.fragments()[0];
+                assert_eq!(
+                    fragment.format_version(),
+                    storage::CURRENT_FORMAT_VERSION
+                );
+                let ts = fixtures::FIXTURE_TIMESTAMP + 1;
+                assert_eq!(fragment.timestamp_range(), (ts, ts));
+
+                let results = match array_type {
+                    ArrayType::Dense => DenseReader::new(&array).read(&vfs)?,
+                    _ => SparseReader::new(&array).read(&vfs)?,
+                };
+                for (dtype, var) in fixtures::attribute_types() {
+                    let buffer = &results[&fixtures::attribute_name(dtype)];
+                    for cell in 0..fixtures::FIXTURE_CELL_NUM {
+                        let expected = fixtures::cell_value(dtype, var, cell);
+                        assert_eq!(buffer.value(cell), Some(&expected[..]));
+                    }
+                }
+                if array_type == ArrayType::Sparse {
+                    let x: Vec<i64> = (0..fixtures::FIXTURE_CELL_NUM as i64)
+                        .map(|i| i * 7 + 1)
+                        .collect();
+                    assert_eq!(results["x"].values::<i64>(), x);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::HashSet;
+
+use crate::array::{
+    Directory, COMMITS_DIR, IGNORE_FILE_SUFFIX, OK_FILE_SUFFIX,
+    WRITE_FILE_SUFFIX,
+};
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+// A file or directory removed by vacuuming and the number of bytes it held.
+pub struct VacuumEntry {
+    uri: uri::URI,
+    is_dir: bool,
+    size: u64,
+}
+
+impl VacuumEntry {
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+// Everything removed by a vacuum, or that would be removed by a dry run.
+#[derive(Default)]
+pub struct VacuumReport {
+    entries: Vec<VacuumEntry>,
+}
+
+impl VacuumReport {
+    pub fn entries(&self) -> &[VacuumEntry] {
+        &self.entries
+    }
+
+    // The number of bytes reclaimed.
+    pub fn bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.size).sum()
+    }
+}
+
+// Removes the files that consolidation left behind: the fragments listed
+// in vacuum files along with their commits, commit files made redundant by
+// consolidated commits, array metadata replaced by metadata consolidation
+// and all but the newest consolidated fragment metadata file.
+pub struct Vacuumer {
+    array_uri: uri::URI,
+    dry_run: bool,
+}
+
+impl Vacuumer {
+    pub fn new(array_uri: &uri::URI) -> Self {
+        Vacuumer {
+            array_uri: array_uri.clone(),
+            dry_run: false,
+        }
+    }
+
+    // Report what would be removed without changing the array.
+    pub fn set_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn vacuum(&self, vfs: &dyn VFSService) -> Result<VacuumReport> {
+        let mut dir = Directory::new(&self.array_uri);
+        dir.load_all(vfs)?;
+
+        let commits_uri = self.array_uri.join(COMMITS_DIR);
+        let consolidated: HashSet<&String> =
+            dir.consolidated_fragment_names().iter().collect();
+
+        let mut report = VacuumReport::default();
+        let mut seen = HashSet::new();
+        let mut add = |uri: uri::URI, is_dir: bool| -> Result<()> {
+            let exists = if is_dir {
+                vfs.dir_exists(&uri)?
+            } else {
+                vfs.file_exists(&uri)?
+            };
+            if !exists || !seen.insert(uri.clone()) {
+                return Ok(());
+            }
+            let size = if is_dir {
+                vfs.dir_size(&uri)?
+            } else {
+                vfs.file_size(&uri)?
+            };
+            report.entries.push(VacuumEntry { uri, is_dir, size });
+            Ok(())
+        };
+
+        // Commits are removed before fragment data so that an interrupted
+        // vacuum never leaves a committed fragment without its files.
+        // Fragments committed by consolidated commits are ignored instead.
+        let mut ignored = Vec::new();
+        let fragments: Vec<uri::URI> = dir
+            .vacuumed_fragment_uris()?
+            .iter()
+            .map(|u| u.remove_trailing_slash())
+            .collect();
+        for fragment_uri in fragments.iter() {
+            let name = fragment_uri.last_path_part();
+            if consolidated.contains(&name) {
+                ignored.push(format!(
+                    "{}/{}{}",
+                    COMMITS_DIR, name, WRITE_FILE_SUFFIX
+                ));
+            }
+            add(commits_uri.join(&(name.clone() + WRITE_FILE_SUFFIX)), false)?;
+            add(self.array_uri.join(&(name + OK_FILE_SUFFIX)), false)?;
+        }
+        for fragment_uri in fragments {
+            add(fragment_uri, true)?;
+        }
+        for vac_uri in dir.vacuum_file_uris()? {
+            add(vac_uri, false)?;
+        }
+
+        for name in consolidated {
+            add(commits_uri.join(&(name.clone() + WRITE_FILE_SUFFIX)), false)?;
+        }
+
+        for meta_uri in dir.vacuumed_metadata_uris()? {
+            add(meta_uri, false)?;
+        }
+        for vac_uri in dir.metadata_vacuum_file_uris()? {
+            add(vac_uri, false)?;
+        }
+
+        // Newer consolidated fragment metadata takes precedence so only the
+        // newest file is needed.
+        let mut fragment_meta = dir.fragment_meta_uris()?;
+        fragment_meta.pop();
+        for meta_uri in fragment_meta {
+            add(meta_uri, false)?;
+        }
+
+        if self.dry_run {
+            return Ok(report);
+        }
+
+        if !ignored.is_empty() {
+            let ts = storage::current_timestamp();
+            let name = storage::timestamped_name(ts, ts) + IGNORE_FILE_SUFFIX;
+            let ign_uri = commits_uri.join(&name);
+            vfs.file_create(&ign_uri)?;
+            vfs.file_write(
+                &ign_uri,
+                0,
+                (ignored.join("\n") + "\n").as_bytes(),
+            )?;
+        }
+
+        for entry in report.entries.iter() {
+            if entry.is_dir {
+                vfs.dir_remove(&entry.uri)?;
+            } else {
+                vfs.file_remove(&entry.uri)?;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::{self, Consolidator};
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{QueryBuffer, SparseWriter};
+
+    #[test]
+    fn vacuum_consolidated_fragments() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) =
+            fixtures::temp_array(&vfs, "vacuum", &fixtures::sparse_schema())?;
+        let uri = array.uri().clone();
+
+        let mut fragments = Vec::new();
+        let t = storage::current_timestamp();
+        for (x, ts) in [(1i64, t + 1), (2, t + 2)] {
+            let writer =
+                SparseWriter::new(&uri, array.schema_name(), array.schema())
+                    .set_buffer("x", QueryBuffer::from_values(&[x]))
+                    .set_buffer("a", QueryBuffer::from_values(&[0i32]))
+                    .set_timestamp(ts);
+            fragments.push(writer.write(&vfs)?);
+        }
+        Consolidator::new(&uri).consolidate(&vfs)?.unwrap();
+
+        let report = Vacuumer::new(&uri).set_dry_run(true).vacuum(&vfs)?;
+        let dirs: Vec<_> = report
+            .entries()
+            .iter()
+            .filter(|e| e.is_dir())
+            .map(|e| e.uri().clone())
+            .collect();
+        assert!(dirs == fragments);
+        // Along with their commits and the vacuum file.
+        assert_eq!(report.entries().len(), 5);
+        assert!(report.bytes() > 0);
+        assert!(vfs.dir_exists(&fragments[0])?);
+
+        let report = Vacuumer::new(&uri).vacuum(&vfs)?;
+        assert_eq!(report.entries().len(), 5);
+        assert!(!vfs.dir_exists(&fragments[0])?);
+        assert!(!vfs.dir_exists(&fragments[1])?);
+        assert_eq!(array::Array::open(&vfs, &uri)?.fragments().len(), 1);
+        assert!(Vacuumer::new(&uri).vacuum(&vfs)?.entries().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+// Regenerate the fixture corpus used by the integration tests. The output
+// directory defaults to resources/arrays.
+
+use tdbtk::fixtures;
+use tdbtk::io::{uri, PosixVFSService};
+use tdbtk::Result;
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "resources/arrays".to_string());
+    let path = std::fs::canonicalize(".")?.join(path);
+    let root = uri::URI::from_string(&path.to_string_lossy())?;
+
+    let vfs = PosixVFSService::default();
+    for uri in fixtures::generate(&vfs, &root)? {
+        println!("{}", uri);
+    }
+
+    Ok(())
+}
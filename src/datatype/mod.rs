@@ -1,10 +1,12 @@
 // This file is part of tdbtk released under the MIT license.
 // Copyright (c) 2023 TileDB, Inc.
 
+use std::cmp::Ordering;
+use std::fmt;
 use std::mem::size_of;
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub enum DataType {
     #[default]
     Int32 = 0,
@@ -59,14 +61,14 @@ impl DataType {
             DataType::Int64 => size_of::<i64>(),
             DataType::Float32 => size_of::<f32>(),
             DataType::Float64 => size_of::<f64>(),
-            DataType::Char => size_of::<char>(),
+            DataType::Char => size_of::<i8>(),
             DataType::Int8 => size_of::<i8>(),
             DataType::Uint8 => size_of::<u8>(),
             DataType::Int16 => size_of::<i16>(),
             DataType::Uint16 => size_of::<u16>(),
             DataType::Uint32 => size_of::<u32>(),
             DataType::Uint64 => size_of::<u64>(),
-            DataType::StringAscii => size_of::<char>(),
+            DataType::StringAscii => size_of::<u8>(),
             DataType::StringUtf8 => size_of::<u8>(),
             DataType::StringUtf16 => size_of::<u16>(),
             DataType::StringUtf32 => size_of::<u32>(),
@@ -112,6 +114,173 @@ impl DataType {
                 | DataType::StringUcs4
         )
     }
+
+    pub fn is_datetime_type(&self) -> bool {
+        (*self as u8) >= (DataType::DatetimeYear as u8)
+            && (*self as u8) <= (DataType::DatetimeASec as u8)
+    }
+
+    pub fn is_time_type(&self) -> bool {
+        (*self as u8) >= (DataType::TimeHour as u8)
+            && (*self as u8) <= (DataType::TimeASec as u8)
+    }
+
+    pub fn is_integral_type(&self) -> bool {
+        matches!(
+            self,
+            DataType::Int8
+                | DataType::Uint8
+                | DataType::Int16
+                | DataType::Uint16
+                | DataType::Int32
+                | DataType::Uint32
+                | DataType::Int64
+                | DataType::Uint64
+        ) || self.is_datetime_type()
+            || self.is_time_type()
+    }
+
+    pub fn is_real_type(&self) -> bool {
+        matches!(self, DataType::Float32 | DataType::Float64)
+    }
+
+    // Compare two single values of this type. Strings and other byte-like
+    // types compare lexicographically which also handles var sized values.
+    pub fn compare(&self, lhs: &[u8], rhs: &[u8]) -> Ordering {
+        match self {
+            DataType::Int8 => cmp_values::<i8>(lhs, rhs),
+            DataType::Int16 => cmp_values::<i16>(lhs, rhs),
+            DataType::Int32 => cmp_values::<i32>(lhs, rhs),
+            DataType::Int64 => cmp_values::<i64>(lhs, rhs),
+            DataType::Uint16 => cmp_values::<u16>(lhs, rhs),
+            DataType::Uint32 => cmp_values::<u32>(lhs, rhs),
+            DataType::Uint64 => cmp_values::<u64>(lhs, rhs),
+            DataType::Float32 => cmp_values::<f32>(lhs, rhs),
+            DataType::Float64 => cmp_values::<f64>(lhs, rhs),
+            dtype if dtype.is_datetime_type() || dtype.is_time_type() => {
+                cmp_values::<i64>(lhs, rhs)
+            }
+            _ => lhs.cmp(rhs),
+        }
+    }
+
+    // Integral values as i128 so that every integer type, including u64,
+    // can be used for tile arithmetic without overflow.
+    pub fn to_i128(&self, value: &[u8]) -> Option<i128> {
+        match self {
+            DataType::Int8 => Some(i8::from_bytes(value) as i128),
+            DataType::Uint8 => Some(u8::from_bytes(value) as i128),
+            DataType::Int16 => Some(i16::from_bytes(value) as i128),
+            DataType::Uint16 => Some(u16::from_bytes(value) as i128),
+            DataType::Int32 => Some(i32::from_bytes(value) as i128),
+            DataType::Uint32 => Some(u32::from_bytes(value) as i128),
+            DataType::Int64 => Some(i64::from_bytes(value) as i128),
+            DataType::Uint64 => Some(u64::from_bytes(value) as i128),
+            dtype if dtype.is_datetime_type() || dtype.is_time_type() => {
+                Some(i64::from_bytes(value) as i128)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_i128(&self, value: i128) -> Option<Vec<u8>> {
+        match self {
+            DataType::Int8 => Some((value as i8).to_bytes()),
+            DataType::Uint8 => Some((value as u8).to_bytes()),
+            DataType::Int16 => Some((value as i16).to_bytes()),
+            DataType::Uint16 => Some((value as u16).to_bytes()),
+            DataType::Int32 => Some((value as i32).to_bytes()),
+            DataType::Uint32 => Some((value as u32).to_bytes()),
+            DataType::Int64 => Some((value as i64).to_bytes()),
+            DataType::Uint64 => Some((value as u64).to_bytes()),
+            dtype if dtype.is_datetime_type() || dtype.is_time_type() => {
+                Some((value as i64).to_bytes())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self, value: &[u8]) -> Option<f64> {
+        match self {
+            DataType::Float32 => Some(f32::from_bytes(value) as f64),
+            DataType::Float64 => Some(f64::from_bytes(value)),
+            dtype => dtype.to_i128(value).map(|v| v as f64),
+        }
+    }
+}
+
+impl DataType {
+    // The fill value TileDB uses for cells that were never written when the
+    // schema does not record an explicit fill value.
+    pub fn default_fill_value(&self) -> Vec<u8> {
+        match self {
+            DataType::Int8 => i8::MIN.to_bytes(),
+            DataType::Uint8 => u8::MAX.to_bytes(),
+            DataType::Int16 => i16::MIN.to_bytes(),
+            DataType::Uint16 => u16::MAX.to_bytes(),
+            DataType::Int32 => i32::MIN.to_bytes(),
+            DataType::Uint32 => u32::MAX.to_bytes(),
+            DataType::Int64 => i64::MIN.to_bytes(),
+            DataType::Uint64 => u64::MAX.to_bytes(),
+            DataType::Float32 => f32::NAN.to_bytes(),
+            DataType::Float64 => f64::NAN.to_bytes(),
+            DataType::Char => i8::MIN.to_bytes(),
+            dtype if dtype.is_datetime_type() || dtype.is_time_type() => {
+                i64::MIN.to_bytes()
+            }
+            dtype => vec![0; dtype.size()],
+        }
+    }
+}
+
+fn cmp_values<T: Primitive>(lhs: &[u8], rhs: &[u8]) -> Ordering {
+    T::from_bytes(lhs)
+        .partial_cmp(&T::from_bytes(rhs))
+        .unwrap_or(Ordering::Equal)
+}
+
+// Rust types that map directly onto a fixed size TileDB datatype.
+pub trait Primitive: Copy + PartialOrd + fmt::Debug {
+    const SIZE: usize;
+
+    fn from_bytes(bytes: &[u8]) -> Self;
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+macro_rules! impl_primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl Primitive for $ty {
+                const SIZE: usize = size_of::<$ty>();
+
+                fn from_bytes(bytes: &[u8]) -> Self {
+                    let mut buf = [0u8; size_of::<$ty>()];
+                    buf.copy_from_slice(&bytes[..size_of::<$ty>()]);
+                    <$ty>::from_le_bytes(buf)
+                }
+
+                fn to_bytes(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive!(i8, u8, i16, u16, i32, u32, i64, u64, f32, f64);
+
+// Decode a buffer of packed little endian values.
+pub fn values_from_bytes<T: Primitive>(bytes: &[u8]) -> Vec<T> {
+    bytes.chunks_exact(T::SIZE).map(T::from_bytes).collect()
+}
+
+// Encode a slice of values into a packed little endian buffer.
+pub fn values_to_bytes<T: Primitive>(values: &[T]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(values.len() * T::SIZE);
+    for v in values {
+        ret.extend_from_slice(&v.to_bytes());
+    }
+    ret
 }
 
 impl From<u8> for DataType {
@@ -0,0 +1,181 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::{anyhow, Result};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::filters;
+use crate::storage;
+
+// Checksum filters pass data through unchanged. The checksum of each part
+// of the input is prepended to the chunk metadata as a part count for the
+// metadata and data followed by the size and checksum of every part.
+pub struct ChecksumFilter {
+    filter_type: filters::FilterType,
+}
+
+impl ChecksumFilter {
+    pub fn from_config(
+        filter_type: filters::FilterType,
+        config: &storage::FilterConfig,
+    ) -> Result<Box<dyn filters::Filter>> {
+        let is_checksum = matches!(
+            filter_type,
+            filters::FilterType::ChecksumMD5
+                | filters::FilterType::ChecksumSHA256
+        );
+        if is_checksum && matches!(config, storage::FilterConfig::None) {
+            return Ok(Box::from(ChecksumFilter { filter_type }));
+        }
+
+        Err(anyhow!("Invalid config {:?} for ChecksumFilter", config))
+    }
+
+    fn checksum_size(&self) -> usize {
+        match self.filter_type {
+            filters::FilterType::ChecksumMD5 => 16,
+            _ => 32,
+        }
+    }
+
+    pub fn checksum(&self, data: &[u8]) -> Vec<u8> {
+        match self.filter_type {
+            filters::FilterType::ChecksumMD5 => Md5::digest(data).to_vec(),
+            _ => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn write_parts(&self, parts: &[&[u8]], output: &mut Vec<u8>) {
+        for part in parts.iter().filter(|p| !p.is_empty()) {
+            output.extend_from_slice(&(part.len() as u64).to_le_bytes());
+            output.extend_from_slice(&self.checksum(part));
+        }
+    }
+
+    // Verify the checksums of consecutive parts of data starting at offset
+    // pos in the metadata, returning the offset after the last checksum.
+    fn verify_parts(
+        &self,
+        metadata: &[u8],
+        mut pos: usize,
+        num_parts: u32,
+        data: &[u8],
+    ) -> Result<usize> {
+        let mut offset = 0;
+        for _ in 0..num_parts {
+            let size = read_u64(metadata, pos)? as usize;
+            pos += 8;
+            let expected = metadata
+                .get(pos..pos + self.checksum_size())
+                .ok_or_else(|| anyhow!("Truncated checksum metadata"))?;
+            pos += self.checksum_size();
+
+            let part = data
+                .get(offset..offset + size)
+                .ok_or_else(|| anyhow!("Checksummed part exceeds chunk"))?;
+            if self.checksum(part) != expected {
+                return Err(anyhow!(
+                    "{:?} mismatch for {} bytes at offset {}",
+                    self.filter_type,
+                    size,
+                    offset
+                ));
+            }
+            offset += size;
+        }
+        Ok(pos)
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    let bytes = data
+        .get(pos..pos + 4)
+        .ok_or_else(|| anyhow!("Truncated checksum metadata"))?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Result<u64> {
+    let bytes = data
+        .get(pos..pos + 8)
+        .ok_or_else(|| anyhow!("Truncated checksum metadata"))?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+impl filters::Filter for ChecksumFilter {
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        let num_parts = |part: &[u8]| !part.is_empty() as u32;
+        let mut metadata = Vec::new();
+        metadata.extend_from_slice(&num_parts(&input.metadata).to_le_bytes());
+        metadata.extend_from_slice(&num_parts(&input.data).to_le_bytes());
+        self.write_parts(&[&input.metadata, &input.data], &mut metadata);
+        metadata.extend_from_slice(&input.metadata);
+
+        output.metadata = metadata;
+        output.data = std::mem::take(&mut input.data);
+        Ok(())
+    }
+
+    fn unfilter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        let metadata = &input.metadata;
+        let num_metadata_parts = read_u32(metadata, 0)?;
+        let num_data_parts = read_u32(metadata, 4)?;
+
+        // The checksums of the metadata parts precede those of the data but
+        // the metadata they cover follows all of the checksums.
+        let part_size = 8 + self.checksum_size();
+        let end =
+            8 + (num_metadata_parts + num_data_parts) as usize * part_size;
+        let rest = metadata
+            .get(end..)
+            .ok_or_else(|| anyhow!("Truncated checksum metadata"))?;
+        let pos = self.verify_parts(metadata, 8, num_metadata_parts, rest)?;
+        self.verify_parts(metadata, pos, num_data_parts, &input.data)?;
+
+        output.metadata = rest.to_vec();
+        output.data = std::mem::take(&mut input.data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::Filter;
+
+    #[test]
+    fn detects_corruption() -> Result<()> {
+        for filter_type in [
+            filters::FilterType::ChecksumMD5,
+            filters::FilterType::ChecksumSHA256,
+        ] {
+            let filter = ChecksumFilter { filter_type };
+            let data = b"Hello, World!".to_vec();
+            let mut input = storage::Chunk::new(13, vec![1, 2], data.clone());
+            let mut filtered = storage::Chunk::default();
+            filter.filter(&mut input, &mut filtered)?;
+            assert_eq!(filtered.data, data);
+
+            let mut corrupt = storage::Chunk::new(
+                13,
+                filtered.metadata.clone(),
+                b"Hello, world!".to_vec(),
+            );
+            let mut output = storage::Chunk::default();
+            assert!(filter.unfilter(&mut corrupt, &mut output).is_err());
+
+            filter.unfilter(&mut filtered, &mut output)?;
+            assert_eq!(output.metadata, vec![1, 2]);
+            assert_eq!(output.data, data);
+        }
+        Ok(())
+    }
+}
@@ -2,13 +2,48 @@
 // Copyright (c) 2023 TileDB, Inc.
 
 use binrw::io::Cursor;
-use binrw::BinRead;
+use binrw::{BinRead, BinWrite};
 
 use crate::storage;
 use crate::Result;
 
+type CompressionFn<'a> = &'a dyn Fn(&[u8], &mut [u8]) -> Result<()>;
+type CompressFn<'a> = &'a dyn Fn(&[u8]) -> Result<Vec<u8>>;
+
+// Compress the metadata and then the data of a chunk as a single part each.
+// The sizes of the parts are stored in the output metadata.
+pub fn compress(
+    do_compress: CompressFn,
+    input: &mut storage::Chunk,
+    output: &mut storage::Chunk,
+) -> Result<()> {
+    let mut data = Vec::new();
+    let mut compress_part = |part: &[u8]| -> Result<_> {
+        if part.is_empty() {
+            return Ok(Vec::new());
+        }
+        let compressed = do_compress(part)?;
+        let info = storage::CompressionChunkInfo {
+            uncompressed_size: part.len() as u32,
+            compressed_size: compressed.len() as u32,
+        };
+        data.extend_from_slice(&compressed);
+        Ok(vec![info])
+    };
+
+    let metadata_parts = compress_part(&input.metadata)?;
+    let data_parts = compress_part(&input.data)?;
+
+    let mut metadata = Cursor::new(Vec::new());
+    storage::CompressionChunks::new(metadata_parts, data_parts)
+        .write(&mut metadata)?;
+    output.metadata = metadata.into_inner();
+    output.data = data;
+    Ok(())
+}
+
 pub fn decompress(
-    do_decompress: &dyn Fn(&[u8], &mut [u8]) -> Result<()>,
+    do_decompress: CompressionFn,
     input: &mut storage::Chunk,
     output: &mut storage::Chunk,
 ) -> Result<()> {
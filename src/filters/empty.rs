@@ -13,11 +13,8 @@ impl EmptyFilter {
     pub fn from_config(
         config: &storage::FilterConfig,
     ) -> Result<Box<dyn filters::Filter>> {
-        match config {
-            storage::FilterConfig::None => {
-                return Ok(Box::from(EmptyFilter {}));
-            }
-            _ => {}
+        if let storage::FilterConfig::None = config {
+            return Ok(Box::from(EmptyFilter {}));
         }
 
         Err(anyhow!("Invalid config {:?} for EmptyFilter", config))
@@ -25,6 +22,15 @@ impl EmptyFilter {
 }
 
 impl filters::Filter for EmptyFilter {
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        std::mem::swap(output, input);
+        Ok(())
+    }
+
     fn unfilter(
         &self,
         input: &mut storage::Chunk,
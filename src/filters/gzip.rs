@@ -58,6 +58,19 @@ impl GZipFilter {
 }
 
 impl filters::Filter for GZipFilter {
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        let compress = |i: &[u8]| {
+            let mut o = Vec::new();
+            self.compress(i, &mut o)?;
+            Ok(o)
+        };
+        compression::compress(&compress, input, output)
+    }
+
     fn unfilter(
         &self,
         input: &mut storage::Chunk,
@@ -2,40 +2,47 @@
 // Copyright (c) 2023 TileDB, Inc.
 
 use anyhow::{anyhow, Result};
-use zstd_safe;
+use lzzzz::lz4;
 
 use crate::filters;
 use crate::filters::compression;
 use crate::storage;
 
-pub struct LZ4Filter {
-    level: i32,
-}
+// TileDB ignores the configured level for LZ4 and always uses the default
+// acceleration.
+#[derive(Default)]
+pub struct LZ4Filter {}
 
 impl LZ4Filter {
-    fn new(level: i32) -> Self {
-        Self { level }
-    }
-
     pub fn from_config(
         config: &storage::FilterConfig,
     ) -> Result<Box<dyn filters::Filter>> {
         if let storage::FilterConfig::Compression {
             compressor_type: ctype,
-            compression_level: level,
+            compression_level: _,
             reinterpret_type: _,
         } = config
         {
             if matches!(ctype, filters::FilterType::LZ4) {
-                return Ok(Box::from(LZ4Filter::new(*level)));
+                return Ok(Box::<LZ4Filter>::default());
             }
         }
 
         Err(anyhow!("Invalid filter config {:?} for LZ4Filter", config))
     }
 
+    pub fn compress(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        lz4::compress_to_vec(input, &mut output, lz4::ACC_LEVEL_DEFAULT)
+            .map_err(|err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error compressing lz4 data").context(context)
+            })?;
+        Ok(output)
+    }
+
     pub fn decompress(&self, input: &[u8], output: &mut [u8]) -> Result<()> {
-        zstd_safe::decompress(output, input).map_err(|err| {
+        lz4::decompress(input, output).map_err(|err| {
             let context = format!("{:?}", err);
             anyhow!("Error decompressing lz4 data").context(context)
         })?;
@@ -44,6 +51,14 @@ impl LZ4Filter {
 }
 
 impl filters::Filter for LZ4Filter {
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        compression::compress(&|i| self.compress(i), input, output)
+    }
+
     fn unfilter(
         &self,
         input: &mut storage::Chunk,
@@ -7,6 +7,7 @@ use anyhow::{anyhow, Result};
 
 use crate::storage;
 
+mod checksum;
 mod compression;
 mod empty;
 mod gzip;
@@ -18,17 +19,21 @@ pub trait Filter {
     //     config: &storage::FilterConfig,
     // ) -> Result<Box<dyn Filter>, anyhow::Error>;
 
-    // fn filter(
-    //     &self,
-    //     input: &mut storage::Chunk,
-    //     output: &mut storage::Chunk,
-    // ) -> Result<()>;
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()>;
 
     fn unfilter(
         &self,
         input: &mut storage::Chunk,
         output: &mut storage::Chunk,
     ) -> Result<()>;
+
+    fn is_supported(&self) -> bool {
+        true
+    }
 }
 
 #[repr(u8)]
@@ -95,17 +100,93 @@ impl TryFrom<&storage::Filter> for Box<dyn Filter> {
             FilterType::GZip => gzip::GZipFilter::from_config(f.config()),
             FilterType::LZ4 => lz4::LZ4Filter::from_config(f.config()),
             FilterType::Zstd => zstd::ZstdFilter::from_config(f.config()),
-            ftype => Err(anyhow!("Unsupported filter type: {:?}", ftype)),
+            ftype @ (FilterType::ChecksumMD5 | FilterType::ChecksumSHA256) => {
+                checksum::ChecksumFilter::from_config(ftype, f.config())
+            }
+            ftype => Ok(Box::from(UnsupportedFilter { filter_type: ftype })),
         }
     }
 }
 
+// Schemas often list filters for data that is never read, such as validity
+// filters on arrays without nullable attributes. Unsupported filters are
+// only an error once they are actually needed.
+struct UnsupportedFilter {
+    filter_type: FilterType,
+}
+
+impl Filter for UnsupportedFilter {
+    fn filter(
+        &self,
+        _input: &mut storage::Chunk,
+        _output: &mut storage::Chunk,
+    ) -> Result<()> {
+        Err(anyhow!("Unsupported filter type: {:?}", self.filter_type))
+    }
+
+    fn unfilter(
+        &self,
+        _input: &mut storage::Chunk,
+        _output: &mut storage::Chunk,
+    ) -> Result<()> {
+        Err(anyhow!("Unsupported filter type: {:?}", self.filter_type))
+    }
+
+    fn is_supported(&self) -> bool {
+        false
+    }
+}
+
 pub struct FilterChain {
     filter: Box<dyn Filter>,
     next: Option<Box<FilterChain>>,
 }
 
 impl FilterChain {
+    // Whether every filter in the chain can be applied.
+    pub fn is_supported(&self) -> bool {
+        self.filter.is_supported()
+            && self.next.as_ref().is_none_or(|next| next.is_supported())
+    }
+
+    pub fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        self.filter.filter(input, output)?;
+        if let Some(next_filter) = &self.next {
+            std::mem::swap(output, input);
+            next_filter.filter(input, output)?;
+        }
+        Ok(())
+    }
+
+    // Split data into chunks of at most chunk_size bytes and filter each
+    // of them.
+    pub fn filter_chunks(
+        &self,
+        data: &[u8],
+        chunk_size: usize,
+    ) -> Result<storage::ChunkedData> {
+        let mut chunks = Vec::new();
+        for part in data.chunks(chunk_size.max(1)) {
+            let mut input = storage::Chunk::new(
+                part.len() as u32,
+                Vec::new(),
+                part.to_vec(),
+            );
+            let mut output = storage::Chunk::default();
+            self.filter(&mut input, &mut output)?;
+            chunks.push(storage::Chunk::new(
+                part.len() as u32,
+                output.metadata,
+                output.data,
+            ));
+        }
+        Ok(storage::ChunkedData::from_chunks(chunks))
+    }
+
     pub fn unfilter(
         &self,
         input: &mut storage::Chunk,
@@ -126,6 +207,13 @@ impl FilterChain {
         &self,
         chunks: &mut storage::ChunkedData,
     ) -> Result<Vec<u8>> {
+        // Filters may swap chunks so record the sizes up front.
+        let sizes: Vec<usize> = chunks
+            .chunks
+            .iter()
+            .map(|chunk| chunk.original_size as usize)
+            .collect();
+
         let mut scratch = storage::ChunkedData::new(chunks.num_chunks);
         for (input, output) in
             chunks.chunks.iter_mut().zip(scratch.chunks.iter_mut())
@@ -133,21 +221,16 @@ impl FilterChain {
             self.unfilter(input, output)?
         }
 
-        let output_size = chunks
-            .chunks
-            .iter()
-            .map(|chunk| chunk.original_size as usize)
-            .sum();
-
-        let mut output = vec![0; output_size];
-
-        let mut output_offset = 0;
-        for (chunk_in, chunk_out) in
-            chunks.chunks.iter().zip(scratch.chunks.iter())
-        {
-            let output_end = output_offset + chunk_in.original_size as usize;
-            output[output_offset..output_end].copy_from_slice(&chunk_out.data);
-            output_offset += chunk_in.original_size as usize;
+        let mut output = Vec::with_capacity(sizes.iter().sum());
+        for (size, chunk_out) in sizes.iter().zip(scratch.chunks.iter()) {
+            if chunk_out.data.len() != *size {
+                return Err(anyhow!(
+                    "Unfiltered chunk has {} bytes, expected {}",
+                    chunk_out.data.len(),
+                    size
+                ));
+            }
+            output.extend_from_slice(&chunk_out.data);
         }
 
         Ok(output)
@@ -168,11 +251,45 @@ impl TryFrom<&storage::FilterList> for Box<FilterChain> {
             }));
         }
 
+        // An empty filter list is valid and common for attributes that are
+        // stored without any compression.
         match chain {
             Some(filter_chain) => Ok(filter_chain),
-            None => {
-                Err(anyhow!("Error creating filter chain from empty list."))
-            }
+            None => Ok(Box::from(FilterChain {
+                filter: Box::<empty::EmptyFilter>::default(),
+                next: None,
+            })),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_round_trip() -> Result<()> {
+        let list = storage::FilterList::new(
+            65536,
+            vec![
+                storage::Filter::compression(FilterType::Zstd, 3),
+                storage::Filter::compression(FilterType::GZip, 6),
+                storage::Filter::compression(FilterType::LZ4, 0),
+            ],
+        );
+        let chain: Box<FilterChain> = <_>::try_from(&list)?;
+
+        let data: Vec<u8> =
+            (0..100_000u32).flat_map(|v| v.to_le_bytes()).collect();
+        let mut chunks = chain.filter_chunks(&data, 4096)?;
+        assert_eq!(chunks.chunks.len(), 98);
+        assert!(chunks.chunks.iter().all(|c| c.data.len() < 4096));
+        assert_eq!(chain.unfilter_chunks(&mut chunks)?, data);
+
+        let empty: Box<FilterChain> =
+            <_>::try_from(&storage::FilterList::default())?;
+        let mut chunks = empty.filter_chunks(b"abc", 2)?;
+        assert_eq!(empty.unfilter_chunks(&mut chunks)?, b"abc");
+        Ok(())
+    }
+}
@@ -39,6 +39,17 @@ impl ZstdFilter {
         Err(anyhow!("Invalid filter config {:?} for ZstdFilter", config))
     }
 
+    pub fn compress(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = vec![0; zstd_safe::compress_bound(input.len())];
+        let size = zstd_safe::compress(&mut output[..], input, self.level)
+            .map_err(|err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error compressing zstd data").context(context)
+            })?;
+        output.truncate(size);
+        Ok(output)
+    }
+
     pub fn decompress(&self, input: &[u8], output: &mut [u8]) -> Result<()> {
         zstd_safe::decompress(output, input).map_err(|err| {
             let context = format!("{:?}", err);
@@ -49,6 +60,14 @@ impl ZstdFilter {
 }
 
 impl filters::Filter for ZstdFilter {
+    fn filter(
+        &self,
+        input: &mut storage::Chunk,
+        output: &mut storage::Chunk,
+    ) -> Result<()> {
+        compression::compress(&|i| self.compress(i), input, output)
+    }
+
     fn unfilter(
         &self,
         input: &mut storage::Chunk,
@@ -0,0 +1,349 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+// Small arrays written in every supported format version. Each array has
+// one attribute per datatype so that the readers can be checked against
+// the layout of every version without relying on arrays created by TileDB
+// itself.
+
+use crate::array::{self, ArrayType};
+use crate::array::{
+    COMMITS_DIR, FRAGMENTS_DIR, FRAGMENT_META_DIR, METADATA_DIR,
+    OLD_SCHEMA_NAME, SCHEMA_DIR,
+};
+use crate::datatype::DataType;
+use crate::filters::FilterType;
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::query::{DenseWriter, QueryBuffer, SparseWriter};
+use crate::storage;
+use crate::Result;
+
+// Every fixture schema is created at this timestamp and its only fragment
+// is written one millisecond later. Together with UUIDs derived from the
+// format version this makes regenerated fixtures identical.
+pub const FIXTURE_TIMESTAMP: u64 = 1_700_000_000_000;
+
+// The number of cells written to each fixture.
+pub const FIXTURE_CELL_NUM: usize = 4;
+
+// The attribute holding nullable values in versions that support them.
+pub const NULLABLE_ATTRIBUTE: &str = "nullable";
+
+// Create the dense and sparse fixtures of every supported format version
+// as root/v<version>/{dense,sparse}, returning the array URIs.
+pub fn generate(
+    vfs: &dyn VFSService,
+    root: &uri::URI,
+) -> Result<Vec<uri::URI>> {
+    if !vfs.dir_exists(root)? {
+        vfs.dir_create(root)?;
+    }
+
+    let mut ret = Vec::new();
+    for version in storage::MIN_FORMAT_VERSION..=storage::CURRENT_FORMAT_VERSION
+    {
+        let version_uri = root.join(&format!("v{}", version));
+        if !vfs.dir_exists(&version_uri)? {
+            vfs.dir_create(&version_uri)?;
+        }
+        for array_type in [ArrayType::Dense, ArrayType::Sparse] {
+            let name = match array_type {
+                ArrayType::Dense => "dense",
+                _ => "sparse",
+            };
+            let uri = version_uri.join(name);
+            create(vfs, &uri, array_type, version)
+                .map_err(|err| err.context(format!("URI: {}", uri)))?;
+            ret.push(uri);
+        }
+    }
+    Ok(ret)
+}
+
+// Create a single fixture in the given format version.
+pub fn create(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    array_type: ArrayType,
+    version: u32,
+) -> Result<()> {
+    if vfs.dir_exists(uri)? {
+        vfs.dir_remove(uri)?;
+    }
+    vfs.dir_create(uri)?;
+
+    let schema = schema(array_type, version);
+    if version < 10 {
+        schema.store(vfs, &uri.join(OLD_SCHEMA_NAME))?;
+    } else {
+        vfs.dir_create(&uri.join(SCHEMA_DIR))?;
+        let name = storage::uuid_name(
+            FIXTURE_TIMESTAMP,
+            FIXTURE_TIMESTAMP,
+            &fixture_uuid(version, 0),
+            storage::CURRENT_FORMAT_VERSION,
+        );
+        schema.store(vfs, &uri.join(SCHEMA_DIR).join(&name))?;
+    }
+    if version >= 12 {
+        for dir in [COMMITS_DIR, FRAGMENTS_DIR, FRAGMENT_META_DIR, METADATA_DIR]
+        {
+            vfs.dir_create(&uri.join(dir))?;
+        }
+    }
+
+    let array = array::Array::open(vfs, uri)?;
+    let (name, schema) = (array.schema_name(), array.schema());
+    let timestamp = FIXTURE_TIMESTAMP + 1;
+    match array_type {
+        ArrayType::Dense => {
+            let mut writer = DenseWriter::new(uri, name, schema)
+                .set_format_version(version)
+                .set_timestamp(timestamp)
+                .set_uuid(&fixture_uuid(version, 1));
+            for (name, buffer) in attribute_buffers(version) {
+                writer = writer.set_buffer(&name, buffer);
+            }
+            writer.write(vfs)?;
+        }
+        _ => {
+            let x: Vec<i64> =
+                (0..FIXTURE_CELL_NUM as i64).map(|i| i * 7 + 1).collect();
+            let mut writer = SparseWriter::new(uri, name, schema)
+                .set_format_version(version)
+                .set_timestamp(timestamp)
+                .set_uuid(&fixture_uuid(version, 1))
+                .set_buffer("x", QueryBuffer::from_values(&x));
+            if version >= 5 {
+                let s = values(DataType::StringAscii, true, false);
+                writer = writer.set_buffer("s", s);
+            }
+            for (name, buffer) in attribute_buffers(version) {
+                writer = writer.set_buffer(&name, buffer);
+            }
+            writer.write(vfs)?;
+        }
+    }
+
+    Ok(())
+}
+
+// The datatypes of the fixture attributes. String types and Any are var
+// sized.
+pub fn attribute_types() -> Vec<(DataType, bool)> {
+    (0u8..=41)
+        .map(DataType::from)
+        .map(|dtype| {
+            let var = dtype.is_string_type() || dtype == DataType::Any;
+            (dtype, var)
+        })
+        .collect()
+}
+
+// Fixture attributes are named after their datatype.
+pub fn attribute_name(dtype: DataType) -> String {
+    format!("{:?}", dtype).to_lowercase()
+}
+
+// The bytes of the given cell of a fixture attribute.
+pub fn cell_value(dtype: DataType, var: bool, cell: usize) -> Vec<u8> {
+    if var {
+        return format!("v{}", cell)
+            .bytes()
+            .flat_map(|b| {
+                let mut char = vec![0u8; dtype.size()];
+                char[0] = b;
+                char
+            })
+            .collect();
+    }
+
+    match dtype {
+        DataType::Float32 => (cell as f32 + 1.0).to_le_bytes().to_vec(),
+        DataType::Float64 => (cell as f64 + 1.0).to_le_bytes().to_vec(),
+        DataType::Bool => vec![(cell % 2) as u8],
+        dtype => (cell as u64 + 1).to_le_bytes()[..dtype.size()].to_vec(),
+    }
+}
+
+// Whether the given cell of the nullable attribute is valid.
+pub fn cell_is_valid(cell: usize) -> bool {
+    !cell.is_multiple_of(3)
+}
+
+// The UUID of the given file of a fixture, the schema being 0 and the
+// fragment 1.
+fn fixture_uuid(version: u32, file: u32) -> String {
+    format!("{:016x}{:016x}", version, file)
+}
+
+// Sparse fixtures also have a string dimension from version 5, which
+// allows dimensions of different types.
+fn schema(array_type: ArrayType, version: u32) -> storage::ArraySchema {
+    let mut dims = match array_type {
+        ArrayType::Dense => vec![storage::Dimension::new(
+            "d",
+            DataType::Int64,
+            &array::Range::from_values(1i64, FIXTURE_CELL_NUM as i64),
+        )
+        .set_extent(2i64.to_le_bytes().to_vec())],
+        _ => vec![
+            storage::Dimension::new(
+                "x",
+                DataType::Int64,
+                &array::Range::from_values(1i64, 100),
+            )
+            .set_extent(10i64.to_le_bytes().to_vec()),
+            storage::Dimension::new_var("s", DataType::StringAscii),
+        ],
+    };
+    if version < 5 {
+        dims.truncate(1);
+    }
+
+    let zstd = storage::FilterList::new(
+        storage::DEFAULT_MAX_CHUNK_SIZE,
+        vec![storage::Filter::compression(FilterType::Zstd, 3)],
+    );
+    let mut attrs: Vec<storage::Attribute> = attribute_types()
+        .into_iter()
+        .map(|(dtype, var)| {
+            let attr = storage::Attribute::new(&attribute_name(dtype), dtype)
+                .set_filters(zstd.clone());
+            if var {
+                attr.set_cell_val_num(storage::CELL_VAR_SIZE)
+            } else {
+                attr
+            }
+        })
+        .collect();
+    if version >= 7 {
+        attrs.push(
+            storage::Attribute::new(NULLABLE_ATTRIBUTE, DataType::Int32)
+                .set_nullable(true),
+        );
+    }
+
+    storage::ArraySchema::new(array_type, storage::Domain::new(dims), attrs)
+        .set_capacity(2)
+        .set_version(version)
+}
+
+fn values(dtype: DataType, var: bool, nullable: bool) -> QueryBuffer {
+    let mut ret = QueryBuffer::new(var, nullable);
+    for cell in 0..FIXTURE_CELL_NUM {
+        ret.push(&cell_value(dtype, var, cell), cell_is_valid(cell));
+    }
+    ret
+}
+
+fn attribute_buffers(version: u32) -> Vec<(String, QueryBuffer)> {
+    let mut ret: Vec<(String, QueryBuffer)> = attribute_types()
+        .into_iter()
+        .map(|(dtype, var)| (attribute_name(dtype), values(dtype, var, false)))
+        .collect();
+    if version >= 7 {
+        ret.push((
+            NULLABLE_ATTRIBUTE.to_string(),
+            values(DataType::Int32, false, true),
+        ));
+    }
+    ret
+}
+
+// A uniquely named temporary directory that is removed when dropped, so
+// that failing tests do not leave it behind.
+#[cfg(test)]
+pub(crate) struct TempDir {
+    uri: uri::URI,
+}
+
+#[cfg(test)]
+impl TempDir {
+    pub(crate) fn new(prefix: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "tdbtk-{}-{}",
+            prefix,
+            rand::random::<u64>()
+        ));
+        Ok(TempDir {
+            uri: uri::URI::from_string(&path.to_string_lossy())?,
+        })
+    }
+
+    pub(crate) fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(self.uri.path());
+    }
+}
+
+// A sparse schema with an Int64 dimension x over 0..=99 and an Int32
+// attribute a.
+#[cfg(test)]
+pub(crate) fn sparse_schema() -> storage::ArraySchema {
+    storage::ArraySchema::new(
+        ArrayType::Sparse,
+        storage::Domain::new(vec![storage::Dimension::new(
+            "x",
+            DataType::Int64,
+            &array::Range::from_values(0i64, 99),
+        )]),
+        vec![storage::Attribute::new("a", DataType::Int32)],
+    )
+}
+
+// Create an array in a temporary directory that lives as long as the
+// returned guard.
+#[cfg(test)]
+pub(crate) fn temp_array(
+    vfs: &dyn VFSService,
+    prefix: &str,
+    schema: &storage::ArraySchema,
+) -> Result<(TempDir, array::Array)> {
+    let dir = TempDir::new(prefix)?;
+    let array = array::Array::create(vfs, dir.uri(), schema)?;
+    Ok((dir, array))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::PosixVFSService;
+
+    #[test]
+    fn generate_all_versions() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = TempDir::new("fixtures")?;
+        let uris = generate(&vfs, dir.uri())?;
+        let versions =
+            storage::CURRENT_FORMAT_VERSION - storage::MIN_FORMAT_VERSION + 1;
+        assert_eq!(uris.len(), 2 * versions as usize);
+
+        // Regenerating the fixtures reproduces the checked in ones.
+        let resources =
+            format!("{}/resources/arrays", env!("CARGO_MANIFEST_DIR"));
+        let resources = uri::URI::from_string(&resources)?;
+        for uri in uris.iter() {
+            let array = array::Array::open(&vfs, uri)?;
+            assert_eq!(array.fragments().len(), 1);
+
+            let path = uri.path();
+            let relative = path.strip_prefix(&dir.uri().path()).unwrap();
+            let expected = resources.join(relative.trim_start_matches('/'));
+            let expected = array::Array::open(&vfs, &expected)?;
+            assert_eq!(array.schema_name(), expected.schema_name());
+            assert_eq!(
+                array.fragments()[0].name(),
+                expected.fragments()[0].name()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
+use crate::array::directory::{OLD_SCHEMA_NAME, SCHEMA_DIR};
+use crate::array::ArrayMetadata;
+use crate::io::service::VFSService;
+use crate::io::{uri, FSEntryType};
+use crate::storage::{
+    self, ObjectType, GROUP_DETAILS_DIR, GROUP_DETAILS_VERSION, GROUP_FILE_NAME,
+};
+use crate::Result;
+
+// A member of a group. Relative member URIs are relative to the group.
+#[derive(Clone)]
+pub struct Member {
+    object_type: ObjectType,
+    uri: uri::URI,
+    stored_uri: String,
+    relative: bool,
+    name: Option<String>,
+}
+
+impl Member {
+    fn from_storage(
+        group_uri: &uri::URI,
+        member: &storage::GroupMember,
+    ) -> Result<Self> {
+        let uri = if member.is_relative() {
+            group_uri.join(member.uri())
+        } else {
+            uri::URI::from_string(member.uri())?
+        };
+
+        Ok(Member {
+            object_type: member.object_type(),
+            uri,
+            stored_uri: member.uri().to_string(),
+            relative: member.is_relative(),
+            name: member.name().map(|n| n.to_string()),
+        })
+    }
+
+    fn to_storage(&self, deleted: bool) -> storage::GroupMember {
+        storage::GroupMember::new(
+            self.object_type,
+            &self.stored_uri,
+            self.relative,
+            self.name.as_deref(),
+            deleted,
+        )
+    }
+
+    // Members are identified by name when they have one.
+    fn key(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.stored_uri)
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        self.object_type
+    }
+
+    // The member's URI resolved against the group.
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    // The URI as stored in the group, which may be relative.
+    pub fn stored_uri(&self) -> &str {
+        &self.stored_uri
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.relative
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+// A member found while walking nested groups along with the names of the
+// groups containing it, starting below the walked group.
+pub struct WalkedMember {
+    pub path: Vec<String>,
+    pub member: Member,
+}
+
+// A TileDB group. Each group details file under __group records the
+// members added or removed at its timestamp and files are applied from
+// oldest to newest. Changes are buffered until stored.
+pub struct Group {
+    uri: uri::URI,
+    members: Vec<Member>,
+    pending: Vec<(Member, bool)>,
+}
+
+impl Group {
+    pub fn create(vfs: &dyn VFSService, uri: &uri::URI) -> Result<Group> {
+        if vfs.dir_exists(uri)? {
+            if object_type(vfs, uri)?.is_some() {
+                return Err(anyhow!("Object already exists at {}", uri));
+            }
+        } else {
+            vfs.dir_create(uri)?;
+        }
+        vfs.file_create(&uri.join(GROUP_FILE_NAME))?;
+        vfs.dir_create(&uri.join(GROUP_DETAILS_DIR))?;
+
+        Ok(Group {
+            uri: uri.clone(),
+            members: Vec::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn open(vfs: &dyn VFSService, uri: &uri::URI) -> Result<Group> {
+        if !vfs.file_exists(&uri.join(GROUP_FILE_NAME))? {
+            return Err(anyhow!("Not a group").context(format!("URI: {}", uri)));
+        }
+
+        let details_uri = uri.join(GROUP_DETAILS_DIR);
+        let mut files = Vec::new();
+        if vfs.dir_exists(&details_uri)? {
+            for entry in vfs.ls(&details_uri)? {
+                let name = entry.uri().last_path_part();
+                if !matches!(entry.entry_type(), FSEntryType::File) {
+                    continue;
+                }
+                // Skip vacuum files and anything else that isn't a details
+                // file.
+                if let Ok(range) = storage::timestamp_range(&name) {
+                    if !name.contains('.') {
+                        files.push((range, entry.uri()));
+                    }
+                }
+            }
+        }
+        files.sort_by_key(|(range, _)| *range);
+
+        let mut members: Vec<Member> = Vec::new();
+        for (_, file_uri) in files {
+            let data = storage::read_generic_tile(vfs, &file_uri, 0)?;
+            let details = storage::GroupDetails::parse(&data)
+                .map_err(|err| err.context(format!("URI: {}", file_uri)))?;
+
+            // Version one details are a complete listing of the group.
+            if details.version() < GROUP_DETAILS_VERSION {
+                members.clear();
+            }
+
+            for stored in details.members() {
+                let member = Member::from_storage(uri, stored)?;
+                members.retain(|m| m.key() != member.key());
+                if !stored.is_deleted() {
+                    members.push(member);
+                }
+            }
+        }
+
+        Ok(Group {
+            uri: uri.clone(),
+            members,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|m| m.key() == name)
+    }
+
+    pub fn metadata(&self, vfs: &dyn VFSService) -> Result<ArrayMetadata> {
+        ArrayMetadata::load(vfs, &self.uri)
+    }
+
+    // Every member of this group and of the groups nested within it. Groups
+    // reachable more than once are only walked the first time.
+    pub fn walk(&self, vfs: &dyn VFSService) -> Result<Vec<WalkedMember>> {
+        let mut visited = HashSet::new();
+        visited.insert(self.uri.remove_trailing_slash().to_string());
+        let mut ret = Vec::new();
+        self.walk_members(vfs, &mut Vec::new(), &mut visited, &mut ret)?;
+        Ok(ret)
+    }
+
+    fn walk_members(
+        &self,
+        vfs: &dyn VFSService,
+        path: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+        ret: &mut Vec<WalkedMember>,
+    ) -> Result<()> {
+        for member in self.members.iter() {
+            ret.push(WalkedMember {
+                path: path.clone(),
+                member: member.clone(),
+            });
+
+            if member.object_type() != ObjectType::Group
+                || !visited
+                    .insert(member.uri().remove_trailing_slash().to_string())
+            {
+                continue;
+            }
+
+            let group = Group::open(vfs, member.uri())?;
+            path.push(member.key().to_string());
+            group.walk_members(vfs, path, visited, ret)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    // Add an existing array or group as a member. Relative URIs are
+    // resolved against the group.
+    pub fn add_member(
+        &mut self,
+        vfs: &dyn VFSService,
+        member_uri: &str,
+        relative: bool,
+        name: Option<&str>,
+    ) -> Result<()> {
+        let stored = storage::GroupMember::new(
+            ObjectType::Invalid,
+            member_uri,
+            relative,
+            name,
+            false,
+        );
+        let mut member = Member::from_storage(&self.uri, &stored)?;
+        member.object_type = object_type(vfs, member.uri())?
+            .ok_or_else(|| anyhow!("No array or group at {}", member.uri()))?;
+
+        // A pending change to the same member decides whether it exists.
+        let exists =
+            match self.pending.iter().find(|(m, _)| m.key() == member.key()) {
+                Some((_, deleted)) => !deleted,
+                None => self.member(member.key()).is_some(),
+            };
+        if exists {
+            return Err(anyhow!(
+                "Group member '{}' already exists",
+                member.key()
+            ));
+        }
+
+        self.pending.retain(|(m, _)| m.key() != member.key());
+        self.pending.push((member, false));
+        Ok(())
+    }
+
+    // Remove a member by name or, for unnamed members, by URI.
+    pub fn remove_member(&mut self, key: &str) -> Result<()> {
+        let member = self
+            .member(key)
+            .ok_or_else(|| anyhow!("Unknown group member '{}'", key))?
+            .clone();
+        self.pending.retain(|(m, _)| m.key() != key);
+        self.pending.push((member, true));
+        Ok(())
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Write pending changes to a new group details file at the given
+    // timestamp and return its URI.
+    pub fn store(
+        &mut self,
+        vfs: &dyn VFSService,
+        timestamp: u64,
+    ) -> Result<Option<uri::URI>> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+
+        let members = self
+            .pending
+            .iter()
+            .map(|(member, deleted)| member.to_storage(*deleted))
+            .collect();
+        let data = storage::GroupDetails::new(members).to_bytes()?;
+
+        let dir_uri = self.uri.join(GROUP_DETAILS_DIR);
+        if !vfs.dir_exists(&dir_uri)? {
+            vfs.dir_create(&dir_uri)?;
+        }
+        let uri =
+            dir_uri.join(&storage::timestamped_name(timestamp, timestamp));
+        storage::write_generic_tile(vfs, &uri, &data)?;
+
+        for (member, deleted) in std::mem::take(&mut self.pending) {
+            self.members.retain(|m| m.key() != member.key());
+            if !deleted {
+                self.members.push(member);
+            }
+        }
+
+        Ok(Some(uri))
+    }
+}
+
+// Whether a URI holds a group or an array.
+pub fn object_type(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+) -> Result<Option<ObjectType>> {
+    if vfs.file_exists(&uri.join(GROUP_FILE_NAME))? {
+        Ok(Some(ObjectType::Group))
+    } else if vfs.dir_exists(&uri.join(SCHEMA_DIR))?
+        || vfs.file_exists(&uri.join(OLD_SCHEMA_NAME))?
+    {
+        Ok(Some(ObjectType::Array))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::MetadataValue;
+    use crate::datatype::DataType;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+
+    fn fake_array(vfs: &dyn VFSService, uri: &uri::URI) -> Result<()> {
+        vfs.dir_create(uri)?;
+        vfs.dir_create(&uri.join(SCHEMA_DIR))
+    }
+
+    #[test]
+    fn members_and_nesting() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = fixtures::TempDir::new("group")?;
+        let root_uri = dir.uri().clone();
+
+        let mut root = Group::create(&vfs, &root_uri)?;
+        fake_array(&vfs, &root_uri.join("a"))?;
+        let mut sub = Group::create(&vfs, &root_uri.join("sub"))?;
+        fake_array(&vfs, &root_uri.join("sub").join("b"))?;
+
+        sub.add_member(&vfs, "b", true, None)?;
+        sub.store(&vfs, 1)?;
+
+        root.add_member(&vfs, "a", true, Some("first"))?;
+        let sub_uri = format!("file://{}", root_uri.join("sub").path());
+        root.add_member(&vfs, &sub_uri, false, None)?;
+        assert!(root.add_member(&vfs, "a", true, Some("first")).is_err());
+        assert!(root.add_member(&vfs, "missing", true, None).is_err());
+        root.store(&vfs, 1)?;
+
+        let mut root = Group::open(&vfs, &root_uri)?;
+        assert_eq!(root.members().len(), 2);
+        let first = root.member("first").unwrap();
+        assert_eq!(first.object_type(), ObjectType::Array);
+        assert!(first.is_relative());
+        assert_eq!(first.uri().to_string(), root_uri.join("a").to_string());
+
+        let walked = root.walk(&vfs)?;
+        let names: Vec<_> = walked
+            .iter()
+            .map(|w| (w.path.len(), w.member.uri().last_path_part()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (0, "a".to_string()),
+                (0, "sub".to_string()),
+                (1, "b".to_string())
+            ]
+        );
+
+        root.remove_member("first")?;
+        root.store(&vfs, 2)?;
+        let root = Group::open(&vfs, &root_uri)?;
+        assert_eq!(root.members().len(), 1);
+        assert!(root.member("first").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn group_metadata() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let dir = fixtures::TempDir::new("group")?;
+        let uri = dir.uri().clone();
+        let group = Group::create(&vfs, &uri)?;
+
+        let mut meta = group.metadata(&vfs)?;
+        meta.put("k", MetadataValue::from_values(DataType::Int64, &[7i64]))?;
+        meta.store(&vfs, 1)?;
+
+        let group = Group::open(&vfs, &uri)?;
+        let meta = group.metadata(&vfs)?;
+        assert_eq!(meta.get("k").unwrap().values::<i64>(), vec![7]);
+        Ok(())
+    }
+}
@@ -107,8 +107,8 @@ impl VFSService for PosixVFSService {
         Ok(builder.create(uri.path())?)
     }
 
-    fn dir_move(&self, _src_uri: &uri::URI, _dst_uri: &uri::URI) -> Result<()> {
-        unimplemented!("Not implemented.")
+    fn dir_move(&self, src_uri: &uri::URI, dst_uri: &uri::URI) -> Result<()> {
+        Ok(fs::rename(src_uri.path(), dst_uri.path())?)
     }
 
     fn dir_copy(&self, _src_uri: &uri::URI, _dst_uri: &uri::URI) -> Result<()> {
@@ -177,15 +177,13 @@ impl fmt::Display for URI {
         }
         let scheme = scheme.unwrap();
 
-        let authority = if self.authority.is_empty() {
-            None
-        } else {
-            match Authority::new(&self.authority) {
-                Ok(auth) => Some(auth),
-                Err(_) => {
-                    write!(f, "<INVALID_URI_AUTHORITY:{}>", self.authority)?;
-                    return Ok(());
-                }
+        // An empty authority is kept so that file URIs render with the
+        // "file:///" prefix and parse back to the same URI.
+        let authority = match Authority::new(&self.authority) {
+            Ok(auth) => Some(auth),
+            Err(_) => {
+                write!(f, "<INVALID_URI_AUTHORITY:{}>", self.authority)?;
+                return Ok(());
             }
         };
 
@@ -252,6 +250,7 @@ mod tests {
             assert_eq!(uri.scheme(), test.1);
             assert_eq!(uri.authority(), test.2);
             assert_eq!(uri.path(), test.3);
+            assert_eq!(uri.to_string(), test.0);
         }
 
         Ok(())
@@ -6,5 +6,8 @@ pub use anyhow::Result;
 pub mod array;
 pub mod datatype;
 pub mod filters;
+pub mod fixtures;
+pub mod group;
 pub mod io;
+pub mod query;
 pub mod storage;
@@ -0,0 +1,456 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType};
+use crate::datatype::{DataType, Primitive};
+use crate::io::service::VFSService;
+use crate::query::{
+    self, DenseReader, QueryCondition, SparseReader, StatsCache, Subarray,
+};
+use crate::storage::{self, TileStats, TileSum};
+use crate::Result;
+
+// The aggregates of an attribute. Nulls are counted but excluded from the
+// sum, min, max and mean. Sums are only kept for single value numeric
+// attributes, and mins and maxs for those and for strings. Integer sums
+// that overflow are dropped and flagged.
+#[derive(Clone, Debug)]
+pub struct AggregateResult {
+    data_type: DataType,
+    count: u64,
+    null_count: u64,
+    sum: Option<TileSum>,
+    sum_overflowed: bool,
+    min: Option<Vec<u8>>,
+    max: Option<Vec<u8>>,
+    tiles_from_metadata: u64,
+}
+
+impl AggregateResult {
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn null_count(&self) -> u64 {
+        self.null_count
+    }
+
+    pub fn sum(&self) -> Option<TileSum> {
+        self.sum
+    }
+
+    // Whether the sum overflowed its type, in which case it is None.
+    pub fn sum_overflowed(&self) -> bool {
+        self.sum_overflowed
+    }
+
+    pub fn min_bytes(&self) -> Option<&[u8]> {
+        self.min.as_deref()
+    }
+
+    pub fn max_bytes(&self) -> Option<&[u8]> {
+        self.max.as_deref()
+    }
+
+    pub fn min<T: Primitive>(&self) -> Option<T> {
+        self.min
+            .as_deref()
+            .filter(|v| v.len() == T::SIZE)
+            .map(T::from_bytes)
+    }
+
+    pub fn max<T: Primitive>(&self) -> Option<T> {
+        self.max
+            .as_deref()
+            .filter(|v| v.len() == T::SIZE)
+            .map(T::from_bytes)
+    }
+
+    // The mean of the non-null values, if there are any.
+    pub fn mean(&self) -> Option<f64> {
+        let values = self.count - self.null_count;
+        let sum = self.sum?;
+        (values > 0).then(|| sum.to_f64() / values as f64)
+    }
+
+    // The number of tiles answered from the fragment metadata rather than
+    // by decoding them.
+    pub fn tiles_from_metadata(&self) -> u64 {
+        self.tiles_from_metadata
+    }
+
+    fn add_value(&mut self, value: &[u8], valid: bool, min_max: bool) {
+        self.count += 1;
+        if !valid {
+            self.null_count += 1;
+            return;
+        }
+
+        if let Some(value) = TileSum::from_value(self.data_type, value) {
+            self.add_sum(value);
+        }
+        if min_max {
+            self.update_min_max(value, value);
+        }
+    }
+
+    // Add a whole tile from its statistics, returning false without
+    // changing anything when the statistics are incomplete.
+    fn add_stats(
+        &mut self,
+        stats: &TileStats,
+        cell_num: u64,
+        nullable: bool,
+        min_max: bool,
+    ) -> bool {
+        let null_count = match stats.null_count() {
+            Some(count) => count,
+            None if !nullable => 0,
+            None => return false,
+        };
+        // Tiles holding only nulls record zeros as their min and max.
+        let has_values = null_count < cell_num;
+        let (min, max) = (stats.min_bytes(), stats.max_bytes());
+        if (min_max && has_values && (min.is_none() || max.is_none()))
+            || (self.sum.is_some()
+                && stats.sum().is_none_or(|sum| sum.is_saturated()))
+        {
+            return false;
+        }
+
+        self.count += cell_num;
+        self.null_count += null_count;
+        if let Some(tile_sum) = stats.sum() {
+            self.add_sum(tile_sum);
+        }
+        if let (true, true, Some(min), Some(max)) =
+            (min_max, has_values, min, max)
+        {
+            self.update_min_max(min, max);
+        }
+        true
+    }
+
+    fn add_sum(&mut self, value: TileSum) {
+        if let Some(sum) = self.sum {
+            self.sum = sum.checked_add(value);
+            self.sum_overflowed = self.sum.is_none();
+        }
+    }
+
+    // Values that can not be compared, such as NaN, are left out.
+    fn update_min_max(&mut self, min: &[u8], max: &[u8]) {
+        let dtype = self.data_type;
+        let cmp = |lhs: &[u8], rhs: &[u8]| {
+            query::compare_cells(dtype, lhs, rhs).ok().flatten()
+        };
+        if cmp(min, min).is_none() || cmp(max, max).is_none() {
+            return;
+        }
+        if self
+            .min
+            .as_ref()
+            .is_none_or(|m| cmp(min, m) == Some(Ordering::Less))
+        {
+            self.min = Some(min.to_vec());
+        }
+        if self
+            .max
+            .as_ref()
+            .is_none_or(|m| cmp(max, m) == Some(Ordering::Greater))
+        {
+            self.max = Some(max.to_vec());
+        }
+    }
+}
+
+// Computes the aggregates of an attribute over the cells of a subarray
+// that satisfy an optional condition, which are the cells a read would
+// return. Tiles whose every cell is read, and that no other fragment can
+// shadow, are answered from the tile statistics in the fragment metadata.
+// Only the remaining tiles are decoded.
+pub struct Aggregator<'a> {
+    array: &'a array::Array,
+    attribute: String,
+    subarray: Subarray,
+    condition: Option<QueryCondition>,
+}
+
+impl<'a> Aggregator<'a> {
+    pub fn new(array: &'a array::Array, attribute: &str) -> Self {
+        Aggregator {
+            array,
+            attribute: attribute.to_string(),
+            subarray: Subarray::default(),
+            condition: None,
+        }
+    }
+
+    pub fn set_subarray(mut self, subarray: Subarray) -> Self {
+        self.subarray = subarray;
+        self
+    }
+
+    pub fn set_condition(mut self, condition: QueryCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    pub fn compute(&self, vfs: &dyn VFSService) -> Result<AggregateResult> {
+        let schema = self.array.schema();
+        let idx = schema
+            .attribute_idx(&self.attribute)
+            .ok_or_else(|| anyhow!("Unknown attribute '{}'", self.attribute))?;
+        let attr = &schema.attributes()[idx];
+        let field = storage::Field::attribute(schema, idx);
+        let min_max = storage::has_min_max(&field);
+
+        let mut ret = AggregateResult {
+            data_type: attr.data_type(),
+            count: 0,
+            null_count: 0,
+            sum: if storage::has_sum(&field) {
+                TileSum::from_bytes(attr.data_type(), &[0; 8])
+            } else {
+                None
+            },
+            sum_overflowed: false,
+            min: None,
+            max: None,
+            tiles_from_metadata: 0,
+        };
+
+        let full_tiles = match schema.array_type() {
+            ArrayType::Dense => self.dense_reader().full_tiles()?,
+            _ => self.sparse_reader().full_tiles(vfs)?,
+        };
+
+        let condition =
+            self.condition.as_ref().map(|c| c.to_node()).transpose()?;
+        let mut stats = StatsCache::default();
+        let mut skip_tiles = HashSet::new();
+        for (fidx, tile, cell_num) in full_tiles {
+            // Tiles with cells that may satisfy the condition are decoded
+            // so that it can be evaluated for each cell.
+            if let Some(condition) = &condition {
+                if !stats.may_match(vfs, self.array, condition, fidx, tile)? {
+                    skip_tiles.insert((fidx, tile));
+                }
+                continue;
+            }
+
+            let fragment = &self.array.fragments()[fidx];
+            let fragment_schema = self.array.fragment_schema(fragment)?;
+            let Some(field) = query::fragment_attribute_field(
+                fragment,
+                fragment_schema,
+                attr,
+            ) else {
+                continue;
+            };
+            let Some(tile_stats) =
+                stats.load(vfs, fidx, fragment, &field)?.get(tile)
+            else {
+                continue;
+            };
+            if ret.add_stats(tile_stats, cell_num, attr.nullable(), min_max) {
+                ret.tiles_from_metadata += 1;
+                skip_tiles.insert((fidx, tile));
+            }
+        }
+
+        let names = [self.attribute.as_str()];
+        let results = match schema.array_type() {
+            ArrayType::Dense => self
+                .dense_reader()
+                .set_attributes(&names)
+                .set_aggregate_tiles(skip_tiles)
+                .read(vfs)?,
+            _ => self
+                .sparse_reader()
+                .set_attributes(&names)
+                .set_aggregate_tiles(skip_tiles)
+                .read(vfs)?,
+        };
+        let buffer = &results[&self.attribute];
+        for cell in 0..buffer.cell_num() {
+            let value = buffer.value(cell).unwrap_or_default();
+            ret.add_value(value, buffer.is_valid(cell), min_max);
+        }
+
+        Ok(ret)
+    }
+
+    fn dense_reader(&self) -> DenseReader<'a> {
+        let reader =
+            DenseReader::new(self.array).set_subarray(self.subarray.clone());
+        match &self.condition {
+            Some(condition) => reader.set_condition(condition.clone()),
+            None => reader,
+        }
+    }
+
+    fn sparse_reader(&self) -> SparseReader<'a> {
+        let reader =
+            SparseReader::new(self.array).set_subarray(self.subarray.clone());
+        match &self.condition {
+            Some(condition) => reader.set_condition(condition.clone()),
+            None => reader,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::{uri, PosixVFSService};
+    use crate::query::{DenseWriter, QueryBuffer, SparseWriter};
+    use crate::storage::ConditionOp;
+
+    #[test]
+    fn aggregate() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let path = format!(
+            "{}/resources/arrays/v{}/sparse",
+            env!("CARGO_MANIFEST_DIR"),
+            storage::CURRENT_FORMAT_VERSION
+        );
+        let array = array::Array::open(&vfs, &uri::URI::from_string(&path)?)?;
+
+        // Both tiles of 1, 2 and 3, 4 are answered from their statistics.
+        let ret = Aggregator::new(&array, "int32").compute(&vfs)?;
+        assert_eq!(ret.count(), 4);
+        assert_eq!(ret.null_count(), 0);
+        assert_eq!(ret.sum(), Some(TileSum::Signed(10)));
+        assert_eq!(ret.min::<i32>(), Some(1));
+        assert_eq!(ret.max::<i32>(), Some(4));
+        assert_eq!(ret.mean(), Some(2.5));
+        assert_eq!(ret.tiles_from_metadata(), 2);
+
+        // The first tile can not match and the second is decoded.
+        let ret = Aggregator::new(&array, "int32")
+            .set_condition(QueryCondition::compare(
+                "int32",
+                ConditionOp::Gt,
+                2i32,
+            ))
+            .compute(&vfs)?;
+        assert_eq!(ret.count(), 2);
+        assert_eq!(ret.sum(), Some(TileSum::Signed(7)));
+        assert_eq!(ret.min::<i32>(), Some(3));
+        assert_eq!(ret.tiles_from_metadata(), 0);
+
+        // Only the second tile, with x of 15 and 22, is partly covered.
+        let ret = Aggregator::new(&array, "int32")
+            .set_subarray(Subarray::new().set_typed_range(0, 1i64, 15))
+            .compute(&vfs)?;
+        assert_eq!(ret.count(), 3);
+        assert_eq!(ret.sum(), Some(TileSum::Signed(6)));
+        assert_eq!(ret.max::<i32>(), Some(3));
+        assert_eq!(ret.tiles_from_metadata(), 1);
+
+        assert!(Aggregator::new(&array, "missing").compute(&vfs).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn dense_aggregate() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "d",
+                DataType::Int64,
+                &array::Range::from_values(1i64, 8),
+            )
+            .set_extent(2i64.to_le_bytes().to_vec())]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        );
+        let (_dir, array) = fixtures::temp_array(&vfs, "aggregate", &schema)?;
+
+        // Four tiles of 1 to 8, then the second tile again in a newer
+        // fragment.
+        let t = storage::current_timestamp();
+        let writes = [
+            (t + 1, (1i64, 8i64), (1..=8).collect::<Vec<i32>>()),
+            (t + 2, (3, 4), vec![30, 40]),
+        ];
+        for (ts, (lo, hi), values) in writes {
+            DenseWriter::new(array.uri(), array.schema_name(), array.schema())
+                .set_subarray(Subarray::new().set_typed_range(0, lo, hi))
+                .set_buffer("a", QueryBuffer::from_values(&values))
+                .set_timestamp(ts)
+                .write(&vfs)?;
+        }
+        let array = array::Array::open(&vfs, array.uri())?;
+
+        // Every tile is full, and the shadowed tile of 3 and 4 in the older
+        // fragment is not counted.
+        let ret = Aggregator::new(&array, "a").compute(&vfs)?;
+        assert_eq!(ret.count(), 8);
+        assert_eq!(ret.sum(), Some(TileSum::Signed(99)));
+        assert_eq!(ret.min::<i32>(), Some(1));
+        assert_eq!(ret.max::<i32>(), Some(40));
+        assert_eq!(ret.tiles_from_metadata(), 4);
+
+        // The first and last tiles are only partly covered and decoded.
+        let ret = Aggregator::new(&array, "a")
+            .set_subarray(Subarray::new().set_typed_range(0, 2i64, 7))
+            .compute(&vfs)?;
+        assert_eq!(ret.count(), 6);
+        assert_eq!(ret.sum(), Some(TileSum::Signed(90)));
+        assert_eq!(ret.min::<i32>(), Some(2));
+        assert_eq!(ret.tiles_from_metadata(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn sum_overflow() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "x",
+                DataType::Int64,
+                &array::Range::from_values(0i64, 99),
+            )]),
+            vec![storage::Attribute::new("a", DataType::Int64)],
+        )
+        .set_capacity(2);
+        let (_dir, array) = fixtures::temp_array(&vfs, "aggregate", &schema)?;
+        let uri = array.uri().clone();
+        SparseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(&[1i64, 2, 3, 4]))
+            .set_buffer("a", QueryBuffer::from_values(&[i64::MAX, 1, -1, 1]))
+            .write(&vfs)?;
+        let array = array::Array::open(&vfs, &uri)?;
+
+        // The saturated sum of the first tile is not trusted, and decoding
+        // it overflows.
+        let ret = Aggregator::new(&array, "a").compute(&vfs)?;
+        assert_eq!(ret.count(), 4);
+        assert_eq!(ret.sum(), None);
+        assert!(ret.sum_overflowed());
+        assert_eq!(ret.mean(), None);
+        assert_eq!(ret.max::<i64>(), Some(i64::MAX));
+        assert_eq!(ret.tiles_from_metadata(), 1);
+
+        // Without the second cell the sum fits.
+        let ret = Aggregator::new(&array, "a")
+            .set_subarray(Subarray::new().set_typed_range(0, 1i64, 1))
+            .compute(&vfs)?;
+        assert_eq!(ret.sum(), Some(TileSum::Signed(i64::MAX)));
+        assert!(!ret.sum_overflowed());
+        Ok(())
+    }
+}
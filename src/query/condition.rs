@@ -0,0 +1,830 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::datatype::{DataType, Primitive};
+use crate::storage::{
+    self, CombinationOp, ConditionExpression, ConditionLeaf, ConditionNode,
+    ConditionOp, TileStats,
+};
+use crate::Result;
+
+// The value of a field for a single cell. A value of None is a null cell.
+pub type CellValue = (DataType, Option<Vec<u8>>);
+
+// A filter on the values of cells. Comparisons use the Lt to Ne ops and
+// values are the bytes of a single cell of the field. Null cells only
+// satisfy null checks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryCondition {
+    Compare {
+        field: String,
+        op: ConditionOp,
+        value: Vec<u8>,
+    },
+    In {
+        field: String,
+        values: Vec<Vec<u8>>,
+        negated: bool,
+    },
+    IsNull {
+        field: String,
+        negated: bool,
+    },
+    And(Vec<QueryCondition>),
+    Or(Vec<QueryCondition>),
+    Not(Box<QueryCondition>),
+}
+
+impl QueryCondition {
+    pub fn compare<T: Primitive>(
+        field: &str,
+        op: ConditionOp,
+        value: T,
+    ) -> Self {
+        QueryCondition::compare_bytes(field, op, &value.to_bytes())
+    }
+
+    // Compare against the raw bytes of a value, such as a string.
+    pub fn compare_bytes(field: &str, op: ConditionOp, value: &[u8]) -> Self {
+        QueryCondition::Compare {
+            field: field.to_string(),
+            op,
+            value: value.to_vec(),
+        }
+    }
+
+    pub fn is_in(field: &str, values: &[&[u8]]) -> Self {
+        QueryCondition::In {
+            field: field.to_string(),
+            values: values.iter().map(|v| v.to_vec()).collect(),
+            negated: false,
+        }
+    }
+
+    pub fn not_in(field: &str, values: &[&[u8]]) -> Self {
+        QueryCondition::In {
+            field: field.to_string(),
+            values: values.iter().map(|v| v.to_vec()).collect(),
+            negated: true,
+        }
+    }
+
+    pub fn is_null(field: &str) -> Self {
+        QueryCondition::IsNull {
+            field: field.to_string(),
+            negated: false,
+        }
+    }
+
+    pub fn is_not_null(field: &str) -> Self {
+        QueryCondition::IsNull {
+            field: field.to_string(),
+            negated: true,
+        }
+    }
+
+    // Nested conjunctions are flattened.
+    pub fn and(self, other: QueryCondition) -> Self {
+        match (self, other) {
+            (QueryCondition::And(mut lhs), QueryCondition::And(rhs)) => {
+                lhs.extend(rhs);
+                QueryCondition::And(lhs)
+            }
+            (QueryCondition::And(mut lhs), rhs) => {
+                lhs.push(rhs);
+                QueryCondition::And(lhs)
+            }
+            (lhs, rhs) => QueryCondition::And(vec![lhs, rhs]),
+        }
+    }
+
+    pub fn or(self, other: QueryCondition) -> Self {
+        match (self, other) {
+            (QueryCondition::Or(mut lhs), QueryCondition::Or(rhs)) => {
+                lhs.extend(rhs);
+                QueryCondition::Or(lhs)
+            }
+            (QueryCondition::Or(mut lhs), rhs) => {
+                lhs.push(rhs);
+                QueryCondition::Or(lhs)
+            }
+            (lhs, rhs) => QueryCondition::Or(vec![lhs, rhs]),
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        QueryCondition::Not(Box::new(self))
+    }
+
+    // The names of the fields the condition refers to.
+    pub fn fields(&self) -> Vec<&str> {
+        let mut ret = Vec::new();
+        self.collect_fields(&mut ret);
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    fn collect_fields<'a>(&'a self, ret: &mut Vec<&'a str>) {
+        match self {
+            QueryCondition::Compare { field, .. }
+            | QueryCondition::In { field, .. }
+            | QueryCondition::IsNull { field, .. } => ret.push(field),
+            QueryCondition::And(children) | QueryCondition::Or(children) => {
+                for child in children {
+                    child.collect_fields(ret);
+                }
+            }
+            QueryCondition::Not(child) => child.collect_fields(ret),
+        }
+    }
+
+    // The condition in the serialized form used by delete commits. Null
+    // checks are stored as comparisons against an empty value, so empty
+    // values can not be compared.
+    pub fn to_node(&self) -> Result<ConditionNode> {
+        Ok(match self {
+            QueryCondition::Compare { field, op, value } => {
+                if op.is_set_op() || *op == ConditionOp::Invalid {
+                    return Err(anyhow!(
+                        "Invalid comparison op {:?} for '{}'",
+                        op,
+                        field
+                    ));
+                }
+                if value.is_empty() {
+                    return Err(anyhow!(
+                        "Unable to compare '{}' against an empty value",
+                        field
+                    ));
+                }
+                ConditionNode::Leaf(ConditionLeaf::new(*op, field, value))
+            }
+            QueryCondition::In {
+                field,
+                values,
+                negated,
+            } => {
+                let op = if *negated {
+                    ConditionOp::NotIn
+                } else {
+                    ConditionOp::In
+                };
+                let members: Vec<&[u8]> =
+                    values.iter().map(|v| &v[..]).collect();
+                ConditionNode::Leaf(ConditionLeaf::new_set(op, field, &members))
+            }
+            QueryCondition::IsNull { field, negated } => {
+                let op = if *negated {
+                    ConditionOp::Ne
+                } else {
+                    ConditionOp::Eq
+                };
+                ConditionNode::Leaf(ConditionLeaf::new(op, field, &[]))
+            }
+            QueryCondition::And(children) | QueryCondition::Or(children) => {
+                let op = match self {
+                    QueryCondition::And(_) => CombinationOp::And,
+                    _ => CombinationOp::Or,
+                };
+                let children = children
+                    .iter()
+                    .map(|c| c.to_node())
+                    .collect::<Result<Vec<_>>>()?;
+                ConditionNode::Expression(ConditionExpression::new(
+                    op, children,
+                ))
+            }
+            QueryCondition::Not(child) => {
+                ConditionNode::Expression(ConditionExpression::new(
+                    CombinationOp::Not,
+                    vec![child.to_node()?],
+                ))
+            }
+        })
+    }
+
+    // Prepare the condition for evaluating cells.
+    pub fn compile(&self) -> Result<CompiledCondition> {
+        CompiledCondition::new(&self.to_node()?)
+    }
+
+    // Whether each cell of a set of decoded tiles satisfies the condition.
+    // Every tile must hold the same number of cells.
+    pub fn evaluate_tiles(
+        &self,
+        tiles: &HashMap<String, (DataType, &storage::Tile)>,
+    ) -> Result<Vec<bool>> {
+        let condition = self.compile()?;
+        let mut cell_num = None;
+        for (name, (_, tile)) in tiles.iter() {
+            if cell_num.is_some_and(|n| n != tile.cell_num()) {
+                return Err(anyhow!(
+                    "Tile of '{}' has {} cells, expected {}",
+                    name,
+                    tile.cell_num(),
+                    cell_num.unwrap_or_default()
+                ));
+            }
+            cell_num = Some(tile.cell_num());
+        }
+
+        let fields = condition
+            .fields()
+            .iter()
+            .map(|name| {
+                tiles.get(name).ok_or_else(|| {
+                    anyhow!("Unknown field '{}' in condition", name)
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut ret = Vec::with_capacity(cell_num.unwrap_or_default());
+        for cell in 0..cell_num.unwrap_or_default() {
+            ret.push(condition.root.evaluate(&mut |leaf| {
+                let (dtype, tile) = fields[leaf.field];
+                let value = tile.is_valid(cell).then(|| tile.value(cell));
+                leaf.matches(*dtype, value)
+            })?);
+        }
+        Ok(ret)
+    }
+}
+
+// A condition prepared for evaluating many cells, with its fields resolved
+// to indexes into fields() and its values decoded once. Negations are
+// pushed down to the leaves as TileDB does, so a null cell satisfies
+// neither a comparison nor its negation.
+pub struct CompiledCondition {
+    fields: Vec<String>,
+    root: CompiledNode,
+}
+
+enum CompiledNode {
+    Leaf(CompiledLeaf),
+    And(Vec<CompiledNode>),
+    Or(Vec<CompiledNode>),
+}
+
+struct CompiledLeaf {
+    field: usize,
+    op: ConditionOp,
+    value: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+impl CompiledCondition {
+    pub fn new(node: &ConditionNode) -> Result<Self> {
+        let mut fields = Vec::new();
+        let root = CompiledNode::new(node, false, &mut fields)?;
+        Ok(CompiledCondition { fields, root })
+    }
+
+    // The names of the fields the condition refers to.
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    // Whether a single cell satisfies the condition. The lookup function
+    // returns the value of the field at the given index of fields() for the
+    // cell being tested.
+    pub fn evaluate(
+        &self,
+        lookup: &mut dyn FnMut(usize) -> Result<CellValue>,
+    ) -> Result<bool> {
+        self.root.evaluate(&mut |leaf| {
+            let (dtype, value) = lookup(leaf.field)?;
+            leaf.matches(dtype, value.as_deref())
+        })
+    }
+}
+
+impl CompiledNode {
+    fn new(
+        node: &ConditionNode,
+        negated: bool,
+        fields: &mut Vec<String>,
+    ) -> Result<Self> {
+        match node {
+            ConditionNode::Leaf(leaf) => {
+                let name = leaf.field_name();
+                let op = match (leaf.op(), negated) {
+                    (ConditionOp::Invalid, _) => {
+                        return Err(anyhow!(
+                            "Invalid condition op for '{}'",
+                            name
+                        ))
+                    }
+                    (op, false) => op,
+                    (op, true) => negate_op(op),
+                };
+                let field = match fields.iter().position(|f| f == name) {
+                    Some(field) => field,
+                    None => {
+                        fields.push(name.to_string());
+                        fields.len() - 1
+                    }
+                };
+                let members = if op.is_set_op() {
+                    leaf.members().into_iter().map(|m| m.to_vec()).collect()
+                } else {
+                    Vec::new()
+                };
+                Ok(CompiledNode::Leaf(CompiledLeaf {
+                    field,
+                    op,
+                    value: leaf.value().to_vec(),
+                    members,
+                }))
+            }
+            ConditionNode::Expression(expr) => {
+                let mut children = |negated| {
+                    expr.children()
+                        .iter()
+                        .map(|c| CompiledNode::new(c, negated, fields))
+                        .collect::<Result<Vec<_>>>()
+                };
+                match (expr.op(), negated) {
+                    (CombinationOp::And, false) | (CombinationOp::Or, true) => {
+                        Ok(CompiledNode::And(children(negated)?))
+                    }
+                    (CombinationOp::Or, false) | (CombinationOp::And, true) => {
+                        Ok(CompiledNode::Or(children(negated)?))
+                    }
+                    (CombinationOp::Not, _) => {
+                        let child =
+                            expr.children().first().ok_or_else(|| {
+                                anyhow!("Negated condition has no children")
+                            })?;
+                        CompiledNode::new(child, !negated, fields)
+                    }
+                    (CombinationOp::Invalid, _) => {
+                        Err(anyhow!("Invalid condition combination op"))
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(
+        &self,
+        matches: &mut dyn FnMut(&CompiledLeaf) -> Result<bool>,
+    ) -> Result<bool> {
+        match self {
+            CompiledNode::Leaf(leaf) => matches(leaf),
+            CompiledNode::And(children) => {
+                for child in children {
+                    if !child.evaluate(matches)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            CompiledNode::Or(children) => {
+                for child in children {
+                    if child.evaluate(matches)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl CompiledLeaf {
+    // An empty condition value compares against null so that `x = NULL`
+    // only matches null cells and `x != NULL` only matches non-null cells.
+    fn matches(&self, dtype: DataType, value: Option<&[u8]>) -> Result<bool> {
+        let op = self.op;
+        let is_null_check = self.value.is_empty() && !op.is_set_op();
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(is_null_check && op == ConditionOp::Eq),
+        };
+
+        if is_null_check {
+            return Ok(op == ConditionOp::Ne);
+        }
+
+        if op.is_set_op() {
+            let mut member = false;
+            for m in self.members.iter() {
+                if compare_cells(dtype, value, m)? == Some(Ordering::Equal) {
+                    member = true;
+                    break;
+                }
+            }
+            return Ok(member == (op == ConditionOp::In));
+        }
+
+        // Comparisons involving NaN are false, except for inequality.
+        let ord = match compare_cells(dtype, value, &self.value)? {
+            Some(ord) => ord,
+            None => return Ok(op == ConditionOp::Ne),
+        };
+        Ok(match op {
+            ConditionOp::Lt => ord == Ordering::Less,
+            ConditionOp::Le => ord != Ordering::Greater,
+            ConditionOp::Gt => ord == Ordering::Greater,
+            ConditionOp::Ge => ord != Ordering::Less,
+            ConditionOp::Eq => ord == Ordering::Equal,
+            ConditionOp::Ne => ord != Ordering::Equal,
+            _ => unreachable!(),
+        })
+    }
+}
+
+// The op satisfied by exactly the non-null cells that do not satisfy op.
+fn negate_op(op: ConditionOp) -> ConditionOp {
+    match op {
+        ConditionOp::Lt => ConditionOp::Ge,
+        ConditionOp::Le => ConditionOp::Gt,
+        ConditionOp::Gt => ConditionOp::Le,
+        ConditionOp::Ge => ConditionOp::Lt,
+        ConditionOp::Eq => ConditionOp::Ne,
+        ConditionOp::Ne => ConditionOp::Eq,
+        ConditionOp::In => ConditionOp::NotIn,
+        ConditionOp::NotIn => ConditionOp::In,
+        ConditionOp::Invalid => ConditionOp::Invalid,
+    }
+}
+
+impl TryFrom<&ConditionNode> for QueryCondition {
+    type Error = anyhow::Error;
+
+    fn try_from(node: &ConditionNode) -> Result<Self> {
+        match node {
+            ConditionNode::Leaf(leaf) => {
+                let field = leaf.field_name().to_string();
+                let op = leaf.op();
+                Ok(match op {
+                    ConditionOp::In | ConditionOp::NotIn => {
+                        QueryCondition::In {
+                            field,
+                            values: leaf
+                                .members()
+                                .into_iter()
+                                .map(|m| m.to_vec())
+                                .collect(),
+                            negated: op == ConditionOp::NotIn,
+                        }
+                    }
+                    ConditionOp::Eq | ConditionOp::Ne
+                        if leaf.value().is_empty() =>
+                    {
+                        QueryCondition::IsNull {
+                            field,
+                            negated: op == ConditionOp::Ne,
+                        }
+                    }
+                    ConditionOp::Invalid => {
+                        return Err(anyhow!(
+                            "Invalid condition op for '{}'",
+                            field
+                        ))
+                    }
+                    op => QueryCondition::Compare {
+                        field,
+                        op,
+                        value: leaf.value().to_vec(),
+                    },
+                })
+            }
+            ConditionNode::Expression(expr) => {
+                let children = expr
+                    .children()
+                    .iter()
+                    .map(QueryCondition::try_from)
+                    .collect::<Result<Vec<_>>>()?;
+                match expr.op() {
+                    CombinationOp::And => Ok(QueryCondition::And(children)),
+                    CombinationOp::Or => Ok(QueryCondition::Or(children)),
+                    CombinationOp::Not => {
+                        let child =
+                            children.into_iter().next().ok_or_else(|| {
+                                anyhow!("Negated condition has no children")
+                            })?;
+                        Ok(child.negate())
+                    }
+                    CombinationOp::Invalid => {
+                        Err(anyhow!("Invalid condition combination op"))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Compare two cells of a datatype. Numeric cells holding several values
+// compare value by value. None is returned when either cell holds NaN.
+pub(crate) fn compare_cells(
+    dtype: DataType,
+    lhs: &[u8],
+    rhs: &[u8],
+) -> Result<Option<Ordering>> {
+    if !dtype.is_integral_type() && !dtype.is_real_type() {
+        return Ok(Some(lhs.cmp(rhs)));
+    }
+
+    let size = dtype.size();
+    if !lhs.len().is_multiple_of(size) || !rhs.len().is_multiple_of(size) {
+        return Err(anyhow!(
+            "Unable to compare {} and {} bytes as {:?}",
+            lhs.len(),
+            rhs.len(),
+            dtype
+        ));
+    }
+
+    for (l, r) in lhs.chunks(size).zip(rhs.chunks(size)) {
+        if dtype.to_f64(l).is_some_and(f64::is_nan)
+            || dtype.to_f64(r).is_some_and(f64::is_nan)
+        {
+            return Ok(None);
+        }
+        match dtype.compare(l, r) {
+            Ordering::Equal => continue,
+            ord => return Ok(Some(ord)),
+        }
+    }
+    Ok(Some(lhs.len().cmp(&rhs.len())))
+}
+
+// Whether any cell of a tile may satisfy a condition given the tile's
+// statistics. The lookup returns the statistics of the named field, or None
+// when the tile has none. Negations, null checks and NOT IN can not be
+// decided from a min and max so they never rule out a tile.
+pub fn may_match(
+    node: &ConditionNode,
+    lookup: &mut dyn FnMut(&str) -> Result<Option<TileStats>>,
+) -> Result<bool> {
+    match node {
+        ConditionNode::Leaf(leaf) => {
+            let op = leaf.op();
+            let is_null_check = leaf.value().is_empty() && !op.is_set_op();
+            if is_null_check
+                || matches!(op, ConditionOp::NotIn | ConditionOp::Invalid)
+            {
+                return Ok(true);
+            }
+
+            let stats = match lookup(leaf.field_name())? {
+                Some(stats) => stats,
+                None => return Ok(true),
+            };
+            let (min, max) = match (stats.min_bytes(), stats.max_bytes()) {
+                (Some(min), Some(max)) => (min, max),
+                _ => return Ok(true),
+            };
+
+            // Statistics that can not be compared never rule out a tile.
+            let dtype = stats.data_type();
+            let cmp = |lhs: &[u8], rhs: &[u8]| {
+                compare_cells(dtype, lhs, rhs).ok().flatten()
+            };
+            let in_range =
+                |value: &[u8]| match (cmp(min, value), cmp(max, value)) {
+                    (Some(lo), Some(hi)) => {
+                        lo != Ordering::Greater && hi != Ordering::Less
+                    }
+                    _ => true,
+                };
+            let value = leaf.value();
+            Ok(match op {
+                ConditionOp::Lt => {
+                    cmp(min, value).is_none_or(|ord| ord == Ordering::Less)
+                }
+                ConditionOp::Le => {
+                    cmp(min, value).is_none_or(|ord| ord != Ordering::Greater)
+                }
+                ConditionOp::Gt => {
+                    cmp(max, value).is_none_or(|ord| ord == Ordering::Greater)
+                }
+                ConditionOp::Ge => {
+                    cmp(max, value).is_none_or(|ord| ord != Ordering::Less)
+                }
+                ConditionOp::Eq => in_range(value),
+                // NaN cells satisfy inequalities but are left out of the
+                // min and max.
+                ConditionOp::Ne => {
+                    dtype.is_real_type()
+                        || cmp(min, value) != Some(Ordering::Equal)
+                        || cmp(max, value) != Some(Ordering::Equal)
+                }
+                ConditionOp::In => leaf.members().iter().any(|m| in_range(m)),
+                ConditionOp::NotIn | ConditionOp::Invalid => true,
+            })
+        }
+        ConditionNode::Expression(expr) => match expr.op() {
+            CombinationOp::And => {
+                for child in expr.children() {
+                    if !may_match(child, lookup)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            CombinationOp::Or => {
+                for child in expr.children() {
+                    if may_match(child, lookup)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            CombinationOp::Not | CombinationOp::Invalid => Ok(true),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinRead;
+
+    fn leaf(op: ConditionOp, name: &str, value: &[u8]) -> ConditionNode {
+        ConditionNode::Leaf(ConditionLeaf {
+            op: op as u8,
+            field_name_size: name.len() as u32,
+            field_name: name.to_string(),
+            value_size: value.len() as u64,
+            value: value.to_vec(),
+            offsets_size: 0,
+            offsets: Vec::new(),
+        })
+    }
+
+    fn expr(op: CombinationOp, children: Vec<ConditionNode>) -> ConditionNode {
+        ConditionNode::Expression(ConditionExpression {
+            op: op as u8,
+            num_children: children.len() as u64,
+            children,
+        })
+    }
+
+    // Evaluate a condition for a cell with an Int32 field `a` and an ASCII
+    // field `b`.
+    fn evaluate(
+        node: &ConditionNode,
+        a: Option<i32>,
+        b: Option<&str>,
+    ) -> Result<bool> {
+        let condition = CompiledCondition::new(node)?;
+        condition.evaluate(
+            &mut |field| match condition.fields()[field].as_str() {
+                "a" => {
+                    Ok((DataType::Int32, a.map(|a| a.to_le_bytes().to_vec())))
+                }
+                "b" => Ok((
+                    DataType::StringAscii,
+                    b.map(|b| b.as_bytes().to_vec()),
+                )),
+                name => Err(anyhow!("Unknown field '{}'", name)),
+            },
+        )
+    }
+
+    #[test]
+    fn comparisons() -> Result<()> {
+        let five = 5i32.to_le_bytes();
+        let cases = [
+            (ConditionOp::Lt, 4, true),
+            (ConditionOp::Lt, 5, false),
+            (ConditionOp::Le, 5, true),
+            (ConditionOp::Gt, 6, true),
+            (ConditionOp::Ge, 4, false),
+            (ConditionOp::Eq, 5, true),
+            (ConditionOp::Ne, 5, false),
+        ];
+        for (op, a, expect) in cases {
+            let node = leaf(op, "a", &five);
+            assert_eq!(evaluate(&node, Some(a), None)?, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn combinations() -> Result<()> {
+        let node = expr(
+            CombinationOp::And,
+            vec![
+                leaf(ConditionOp::Ge, "a", &0i32.to_le_bytes()),
+                expr(
+                    CombinationOp::Not,
+                    vec![leaf(ConditionOp::Eq, "b", b"skip")],
+                ),
+            ],
+        );
+        assert!(evaluate(&node, Some(1), Some("keep"))?);
+        assert!(!evaluate(&node, Some(1), Some("skip"))?);
+        assert!(!evaluate(&node, Some(-1), Some("keep"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn nulls() -> Result<()> {
+        let is_null = leaf(ConditionOp::Eq, "b", b"");
+        let not_null = leaf(ConditionOp::Ne, "b", b"");
+        let equals = leaf(ConditionOp::Eq, "b", b"foo");
+        assert!(evaluate(&is_null, Some(0), None)?);
+        assert!(!evaluate(&is_null, Some(0), Some("foo"))?);
+        assert!(evaluate(&not_null, Some(0), Some("foo"))?);
+        assert!(!evaluate(&equals, Some(0), None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn nulls_under_not() -> Result<()> {
+        // A null cell satisfies neither a comparison nor its negation.
+        let gt = leaf(ConditionOp::Gt, "a", &5i32.to_le_bytes());
+        let not_gt = expr(CombinationOp::Not, vec![gt.clone()]);
+        assert!(!evaluate(&gt, None, None)?);
+        assert!(!evaluate(&not_gt, None, None)?);
+        assert!(evaluate(&not_gt, Some(1), None)?);
+        assert!(!evaluate(&not_gt, Some(6), None)?);
+
+        // Double negation is the comparison again.
+        let not_not_gt = expr(CombinationOp::Not, vec![not_gt]);
+        assert!(!evaluate(&not_not_gt, None, None)?);
+        assert!(evaluate(&not_not_gt, Some(6), None)?);
+
+        // NOT (a > 5 OR b IS NULL) is a <= 5 AND b IS NOT NULL.
+        let node = expr(
+            CombinationOp::Not,
+            vec![expr(
+                CombinationOp::Or,
+                vec![gt, leaf(ConditionOp::Eq, "b", b"")],
+            )],
+        );
+        assert!(evaluate(&node, Some(1), Some("x"))?);
+        assert!(!evaluate(&node, Some(1), None)?);
+        assert!(!evaluate(&node, None, Some("x"))?);
+
+        // Set membership of null cells is false either way.
+        let is_in = QueryCondition::is_in("a", &[&1i32.to_le_bytes()]);
+        assert!(!evaluate(&is_in.clone().negate().to_node()?, None, None)?);
+        assert!(evaluate(&is_in.negate().to_node()?, Some(2), None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn query_condition_tiles() -> Result<()> {
+        // temp > 30 AND station IN ('a', 'b') AND val IS NOT NULL
+        let condition =
+            QueryCondition::compare("temp", ConditionOp::Gt, 30.0f64)
+                .and(QueryCondition::is_in("station", &[b"a", b"b"]))
+                .and(QueryCondition::is_not_null("val"));
+        assert_eq!(condition.fields(), vec!["station", "temp", "val"]);
+
+        // The serialized form converts back to the same condition.
+        let node = condition.to_node()?;
+        let bytes = node.to_bytes()?;
+        let node = ConditionNode::read(&mut binrw::io::Cursor::new(bytes))?;
+        assert_eq!(QueryCondition::try_from(&node)?, condition);
+
+        let temps: Vec<f64> = vec![31.0, 40.0, f64::NAN, 35.0, 10.0];
+        let temp = storage::Tile::new(
+            crate::datatype::values_to_bytes(&temps),
+            8,
+            None,
+            None,
+        );
+        let station = storage::Tile::new(
+            b"abbcab".to_vec(),
+            0,
+            Some(vec![0, 1, 2, 3, 4]),
+            None,
+        );
+        let val =
+            storage::Tile::new(vec![0; 5], 1, None, Some(vec![1, 0, 1, 1, 1]));
+        let tiles = HashMap::from([
+            ("temp".to_string(), (DataType::Float64, &temp)),
+            ("station".to_string(), (DataType::StringAscii, &station)),
+            ("val".to_string(), (DataType::Int8, &val)),
+        ]);
+        assert_eq!(
+            condition.evaluate_tiles(&tiles)?,
+            vec![true, false, false, false, false]
+        );
+
+        // NaN only satisfies inequality.
+        let ne = QueryCondition::compare("temp", ConditionOp::Ne, 40.0f64);
+        assert_eq!(
+            ne.negate().evaluate_tiles(&tiles)?,
+            vec![false, true, false, false, false]
+        );
+
+        assert!(
+            QueryCondition::compare_bytes("station", ConditionOp::Eq, b"")
+                .to_node()
+                .is_err()
+        );
+        Ok(())
+    }
+}
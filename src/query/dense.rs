@@ -0,0 +1,782 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType, Layout};
+use crate::io::service::VFSService;
+use crate::query::{
+    self, QueryBuffer, QueryCondition, ReadStats, StatsCache, Subarray,
+    TileCache,
+};
+use crate::Result;
+
+// Inclusive integer bounds of a rectangular region, one pair per dimension.
+pub type Bounds = Vec<(i128, i128)>;
+
+// Tile arithmetic for a dense fragment. Tiles are numbered in the schema's
+// tile order over the region of space tiles covering the fragment, and the
+// cells of each tile are numbered in the schema's cell order.
+pub struct DenseTiling {
+    domain_lo: Vec<i128>,
+    extents: Vec<i128>,
+    tile_lo: Vec<i128>,
+    tile_hi: Vec<i128>,
+    tile_order: Layout,
+    cell_order: Layout,
+}
+
+impl DenseTiling {
+    pub fn new(
+        schema: &array::Schema,
+        region: &[(i128, i128)],
+    ) -> Result<Self> {
+        let mut domain_lo = Vec::new();
+        let mut extents = Vec::new();
+        for dim in schema.domain().dimensions() {
+            let dtype = dim.data_type();
+            let (lo, hi) = dim
+                .range()
+                .and_then(|(lo, hi)| {
+                    Some((dtype.to_i128(lo)?, dtype.to_i128(hi)?))
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Dense dimension '{}' has non-integral type {:?}",
+                        dim.name(),
+                        dtype
+                    )
+                })?;
+
+            // A missing tile extent means the whole domain is a single tile.
+            let extent = match dim.extent() {
+                Some(extent) => dtype.to_i128(extent).unwrap_or(hi - lo + 1),
+                None => hi - lo + 1,
+            };
+
+            if extent <= 0 {
+                return Err(anyhow!(
+                    "Invalid tile extent for dimension '{}'",
+                    dim.name()
+                ));
+            }
+
+            domain_lo.push(lo);
+            extents.push(extent);
+        }
+
+        let tile_lo = region
+            .iter()
+            .zip(domain_lo.iter().zip(extents.iter()))
+            .map(|((lo, _), (dlo, ext))| (lo - dlo) / ext)
+            .collect();
+        let tile_hi = region
+            .iter()
+            .zip(domain_lo.iter().zip(extents.iter()))
+            .map(|((_, hi), (dlo, ext))| (hi - dlo) / ext)
+            .collect();
+
+        Ok(DenseTiling {
+            domain_lo,
+            extents,
+            tile_lo,
+            tile_hi,
+            tile_order: schema.tile_order(),
+            cell_order: schema.cell_order(),
+        })
+    }
+
+    pub fn tile_num(&self) -> u64 {
+        self.tile_lo
+            .iter()
+            .zip(self.tile_hi.iter())
+            .map(|(lo, hi)| (hi - lo + 1) as u64)
+            .product()
+    }
+
+    pub fn cell_num_per_tile(&self) -> u64 {
+        self.extents.iter().map(|e| *e as u64).product()
+    }
+
+    // True if a region starts and ends on space tile boundaries.
+    pub fn is_aligned(&self, region: &[(i128, i128)]) -> bool {
+        region.iter().enumerate().all(|(d, (lo, hi))| {
+            (lo - self.domain_lo[d]) % self.extents[d] == 0
+                && (hi - self.domain_lo[d] + 1) % self.extents[d] == 0
+        })
+    }
+
+    // The coordinates of the space tiles intersecting a region.
+    pub fn tile_range(&self, region: &[(i128, i128)]) -> Bounds {
+        region
+            .iter()
+            .enumerate()
+            .map(|(d, (lo, hi))| {
+                (
+                    (lo - self.domain_lo[d]) / self.extents[d],
+                    (hi - self.domain_lo[d]) / self.extents[d],
+                )
+            })
+            .collect()
+    }
+
+    // The cells covered by a space tile.
+    pub fn tile_bounds(&self, tile: &[i128]) -> Bounds {
+        tile.iter()
+            .enumerate()
+            .map(|(d, t)| {
+                let lo = self.domain_lo[d] + t * self.extents[d];
+                (lo, lo + self.extents[d] - 1)
+            })
+            .collect()
+    }
+
+    // The position of a space tile within the fragment.
+    pub fn tile_idx(&self, tile: &[i128]) -> u64 {
+        let pos: Vec<i128> = tile
+            .iter()
+            .zip(self.tile_lo.iter())
+            .map(|(t, lo)| t - lo)
+            .collect();
+        let shape: Vec<i128> = self
+            .tile_lo
+            .iter()
+            .zip(self.tile_hi.iter())
+            .map(|(lo, hi)| hi - lo + 1)
+            .collect();
+        linear_index(&pos, &shape, self.tile_order)
+    }
+
+    // The position of a cell within its space tile.
+    pub fn cell_idx(&self, coord: &[i128]) -> u64 {
+        let pos: Vec<i128> = coord
+            .iter()
+            .enumerate()
+            .map(|(d, c)| (c - self.domain_lo[d]) % self.extents[d])
+            .collect();
+        linear_index(&pos, &self.extents, self.cell_order)
+    }
+}
+
+// The position of a point within a rectangle of the given shape. Row major
+// varies the last dimension fastest, col major the first.
+pub fn linear_index(pos: &[i128], shape: &[i128], layout: Layout) -> u64 {
+    let mut idx: i128 = 0;
+    if matches!(layout, Layout::ColMajor) {
+        for d in (0..pos.len()).rev() {
+            idx = idx * shape[d] + pos[d];
+        }
+    } else {
+        for d in 0..pos.len() {
+            idx = idx * shape[d] + pos[d];
+        }
+    }
+    idx as u64
+}
+
+// The point at a position within a region, the inverse of linear_index.
+pub fn coord_at(
+    idx: u64,
+    bounds: &[(i128, i128)],
+    layout: Layout,
+) -> Vec<i128> {
+    let mut rem = idx as i128;
+    let mut ret = vec![0; bounds.len()];
+    let dims: Vec<usize> = if matches!(layout, Layout::ColMajor) {
+        (0..bounds.len()).collect()
+    } else {
+        (0..bounds.len()).rev().collect()
+    };
+    for d in dims {
+        let (lo, hi) = bounds[d];
+        let extent = hi - lo + 1;
+        ret[d] = lo + rem % extent;
+        rem /= extent;
+    }
+    ret
+}
+
+pub fn intersect(lhs: &[(i128, i128)], rhs: &[(i128, i128)]) -> Option<Bounds> {
+    let mut ret = Vec::new();
+    for ((llo, lhi), (rlo, rhi)) in lhs.iter().zip(rhs.iter()) {
+        let lo = *llo.max(rlo);
+        let hi = *lhi.min(rhi);
+        if lo > hi {
+            return None;
+        }
+        ret.push((lo, hi));
+    }
+    Some(ret)
+}
+
+// Visit every point of a region in row major order.
+pub fn for_each_coord(bounds: &[(i128, i128)], f: &mut dyn FnMut(&[i128])) {
+    if bounds.is_empty() || bounds.iter().any(|(lo, hi)| lo > hi) {
+        return;
+    }
+
+    let mut coord: Vec<i128> = bounds.iter().map(|(lo, _)| *lo).collect();
+    loop {
+        f(&coord);
+
+        let mut d = bounds.len();
+        loop {
+            if d == 0 {
+                return;
+            }
+            d -= 1;
+            if coord[d] < bounds[d].1 {
+                coord[d] += 1;
+                break;
+            }
+            coord[d] = bounds[d].0;
+        }
+    }
+}
+
+pub(crate) fn integral_bounds(
+    schema: &array::Schema,
+    ranges: &[array::Range],
+) -> Result<Bounds> {
+    let mut ret = Vec::new();
+    for (dim, range) in schema.domain().dimensions().iter().zip(ranges) {
+        let bounds = range.to_i128(dim.data_type()).ok_or_else(|| {
+            anyhow!(
+                "Dense dimension '{}' has non-integral type {:?}",
+                dim.name(),
+                dim.data_type()
+            )
+        })?;
+        ret.push(bounds);
+    }
+    Ok(ret)
+}
+
+#[derive(Clone, Copy)]
+struct CellSource {
+    fragment: usize,
+    tile: usize,
+    cell: usize,
+}
+
+// Reads a subarray of a dense array. Each cell is taken from the newest
+// fragment that wrote it and cells that were never written are filled with
+// the attribute's fill value, as are cells that do not satisfy the query
+// condition.
+pub struct DenseReader<'a> {
+    array: &'a array::Array,
+    subarray: Subarray,
+    layout: Layout,
+    attributes: Option<Vec<String>>,
+    condition: Option<QueryCondition>,
+    decode_enumerations: bool,
+    stats: Cell<ReadStats>,
+    skip_tiles: HashSet<(usize, usize)>,
+    omit_filtered: bool,
+}
+
+impl<'a> DenseReader<'a> {
+    pub fn new(array: &'a array::Array) -> Self {
+        DenseReader {
+            array,
+            subarray: Subarray::default(),
+            layout: Layout::RowMajor,
+            attributes: None,
+            condition: None,
+            decode_enumerations: false,
+            stats: Cell::new(ReadStats::default()),
+            skip_tiles: HashSet::new(),
+            omit_filtered: false,
+        }
+    }
+
+    pub fn set_subarray(mut self, subarray: Subarray) -> Self {
+        self.subarray = subarray;
+        self
+    }
+
+    pub fn set_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn set_attributes(mut self, names: &[&str]) -> Self {
+        self.attributes = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    // Fill the cells that do not satisfy the condition. Tiles whose
+    // statistics show that none of their cells can match are not read.
+    pub fn set_condition(mut self, condition: QueryCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    // Return the labels of enumerated attributes rather than their keys.
+    pub fn set_decode_enumerations(mut self, decode: bool) -> Self {
+        self.decode_enumerations = decode;
+        self
+    }
+
+    // The tiles considered and pruned by the last read.
+    pub fn stats(&self) -> ReadStats {
+        self.stats.get()
+    }
+
+    pub fn read(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<HashMap<String, QueryBuffer>> {
+        let schema = self.array.schema();
+        if schema.array_type() != ArrayType::Dense {
+            return Err(anyhow!("DenseReader requires a dense array"));
+        }
+
+        if !matches!(self.layout, Layout::RowMajor | Layout::ColMajor) {
+            return Err(anyhow!(
+                "Unsupported layout {:?} for dense reads",
+                self.layout
+            ));
+        }
+
+        let bounds = self.bounds()?;
+        let sources = self.cell_sources(schema, &bounds)?;
+        let sources = self.filter_sources(vfs, &bounds, sources)?;
+
+        let names = match &self.attributes {
+            Some(names) => names.clone(),
+            None => schema
+                .attributes()
+                .iter()
+                .map(|a| a.name().to_string())
+                .collect(),
+        };
+
+        let mut ret = HashMap::new();
+        for name in names {
+            let buffer = self.read_attribute(vfs, &name, &sources)?;
+            ret.insert(name, buffer);
+        }
+
+        if self.decode_enumerations {
+            query::decode_enumerations(vfs, self.array, &mut ret)?;
+        }
+
+        Ok(ret)
+    }
+
+    fn bounds(&self) -> Result<Bounds> {
+        let schema = self.array.schema();
+        let ranges = self
+            .subarray
+            .resolve(schema)?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("Dense arrays require a bounded domain"))?;
+        integral_bounds(schema, &ranges)
+    }
+
+    // Omit the cells of the given tiles along with the cells that do not
+    // satisfy the condition, for aggregates that answer those tiles from
+    // their statistics.
+    pub(crate) fn set_aggregate_tiles(
+        mut self,
+        skip_tiles: HashSet<(usize, usize)>,
+    ) -> Self {
+        self.skip_tiles = skip_tiles;
+        self.omit_filtered = true;
+        self
+    }
+
+    // The fragment tiles every cell of which is read, along with the number
+    // of cells in each.
+    pub(crate) fn full_tiles(&self) -> Result<Vec<(usize, usize, u64)>> {
+        let schema = self.array.schema();
+        let bounds = self.bounds()?;
+        let mut counts: HashMap<(usize, usize), u64> = HashMap::new();
+        for source in self.cell_sources(schema, &bounds)?.iter().flatten() {
+            *counts.entry((source.fragment, source.tile)).or_default() += 1;
+        }
+
+        let mut ret = Vec::new();
+        for ((fidx, tile), count) in counts {
+            let fragment = &self.array.fragments()[fidx];
+            let ned = integral_bounds(schema, fragment.non_empty_domain())?;
+            let cell_num = DenseTiling::new(schema, &ned)?.cell_num_per_tile();
+            if count == cell_num {
+                ret.push((fidx, tile, cell_num));
+            }
+        }
+        ret.sort();
+        Ok(ret)
+    }
+
+    // Map every result cell to the newest fragment tile cell holding it.
+    fn cell_sources(
+        &self,
+        schema: &array::Schema,
+        bounds: &[(i128, i128)],
+    ) -> Result<Vec<Option<CellSource>>> {
+        let shape: Vec<i128> =
+            bounds.iter().map(|(lo, hi)| hi - lo + 1).collect();
+        let cell_num = shape.iter().product::<i128>() as usize;
+
+        let mut sources = vec![None; cell_num];
+        let mut remaining = cell_num;
+
+        let fragments = self.array.fragments();
+        for (fidx, fragment) in fragments.iter().enumerate().rev() {
+            if remaining == 0 {
+                break;
+            }
+
+            if fragment.non_empty_domain().is_empty() {
+                continue;
+            }
+
+            if !fragment.is_dense() {
+                return Err(anyhow!(
+                    "Sparse fragments in dense arrays are not supported"
+                )
+                .context(format!("Fragment: {}", fragment.uri())));
+            }
+
+            let fragment_bounds =
+                integral_bounds(schema, fragment.non_empty_domain())?;
+            let region = match intersect(bounds, &fragment_bounds) {
+                Some(region) => region,
+                None => continue,
+            };
+
+            let tiling = DenseTiling::new(schema, &fragment_bounds)?;
+            for_each_coord(&tiling.tile_range(&region), &mut |tile| {
+                let tile_idx = tiling.tile_idx(tile) as usize;
+                let cells = match intersect(&tiling.tile_bounds(tile), &region)
+                {
+                    Some(cells) => cells,
+                    None => return,
+                };
+
+                for_each_coord(&cells, &mut |coord| {
+                    let pos: Vec<i128> = coord
+                        .iter()
+                        .zip(bounds.iter())
+                        .map(|(c, (lo, _))| c - lo)
+                        .collect();
+                    let out = linear_index(&pos, &shape, self.layout) as usize;
+                    if sources[out].is_none() {
+                        sources[out] = Some(CellSource {
+                            fragment: fidx,
+                            tile: tile_idx,
+                            cell: tiling.cell_idx(coord) as usize,
+                        });
+                        remaining -= 1;
+                    }
+                });
+            });
+        }
+
+        Ok(sources)
+    }
+
+    // Apply the query condition. Cells that do not satisfy it are filled,
+    // or omitted when reading for an aggregate, and cells of pruned tiles
+    // are dropped without reading the tile. Cells of skipped tiles are
+    // always omitted.
+    fn filter_sources(
+        &self,
+        vfs: &dyn VFSService,
+        bounds: &[(i128, i128)],
+        sources: Vec<Option<CellSource>>,
+    ) -> Result<Vec<Option<CellSource>>> {
+        let condition =
+            self.condition.as_ref().map(|c| c.to_node()).transpose()?;
+        let compiled = condition
+            .as_ref()
+            .map(query::CompiledCondition::new)
+            .transpose()?;
+        let schema = self.array.schema();
+        let dims = schema.domain().dimensions();
+        let mut stats = ReadStats::default();
+        let mut tile_stats = StatsCache::default();
+        let mut pruned: HashMap<(usize, usize), bool> = HashMap::new();
+        let mut cache = TileCache::default();
+
+        let mut ret = Vec::with_capacity(sources.len());
+        for (out, source) in sources.into_iter().enumerate() {
+            if let Some(source) = source {
+                let key = (source.fragment, source.tile);
+                if self.skip_tiles.contains(&key) {
+                    continue;
+                }
+
+                let is_pruned = match pruned.get(&key) {
+                    Some(is_pruned) => *is_pruned,
+                    None => {
+                        let is_pruned = match &condition {
+                            Some(condition) => !tile_stats.may_match(
+                                vfs,
+                                self.array,
+                                condition,
+                                source.fragment,
+                                source.tile,
+                            )?,
+                            None => false,
+                        };
+                        stats.add_tile(is_pruned);
+                        pruned.insert(key, is_pruned);
+                        is_pruned
+                    }
+                };
+                if is_pruned {
+                    if !self.omit_filtered {
+                        ret.push(None);
+                    }
+                    continue;
+                }
+            }
+
+            // Unwritten cells are filled whether or not they match.
+            let compiled = match &compiled {
+                Some(compiled) if source.is_some() || self.omit_filtered => {
+                    compiled
+                }
+                _ => {
+                    ret.push(source);
+                    continue;
+                }
+            };
+
+            let coord = coord_at(out as u64, bounds, self.layout);
+            let fields = compiled.fields();
+            let mut lookup = |field: usize| -> Result<query::CellValue> {
+                let name = fields[field].as_str();
+                if let Some(d) = schema.domain().dimension_idx(name) {
+                    let dtype = dims[d].data_type();
+                    return Ok((dtype, dtype.from_i128(coord[d])));
+                }
+                match source {
+                    Some(source) => query::attribute_value(
+                        vfs,
+                        self.array,
+                        &mut cache,
+                        source.fragment,
+                        source.tile,
+                        source.cell,
+                        name,
+                    ),
+                    None => {
+                        let attr = schema.attribute(name).ok_or_else(|| {
+                            anyhow!("Unknown field '{}' in condition", name)
+                        })?;
+                        let value = attr
+                            .fill_value_validity()
+                            .then(|| attr.fill_value());
+                        Ok((attr.data_type(), value))
+                    }
+                }
+            };
+            if compiled.evaluate(&mut lookup)? {
+                ret.push(source);
+            } else if !self.omit_filtered {
+                ret.push(None);
+            }
+        }
+
+        self.stats.set(stats);
+        Ok(ret)
+    }
+    fn read_attribute(
+        &self,
+        vfs: &dyn VFSService,
+        name: &str,
+        sources: &[Option<CellSource>],
+    ) -> Result<QueryBuffer> {
+        let attr = self
+            .array
+            .schema()
+            .attribute(name)
+            .ok_or_else(|| anyhow!("Unknown attribute '{}'", name))?;
+        let fill_value = attr.fill_value();
+        let fill_validity = attr.fill_value_validity();
+
+        let mut buffer = QueryBuffer::new(attr.is_var_sized(), attr.nullable());
+        let mut cache = TileCache::default();
+
+        let fragments = self.array.fragments();
+        for source in sources {
+            let source = match source {
+                Some(source) => source,
+                None => {
+                    buffer.push(&fill_value, fill_validity);
+                    continue;
+                }
+            };
+
+            let fragment = &fragments[source.fragment];
+            let fragment_schema = self.array.fragment_schema(fragment)?;
+
+            let field = match query::fragment_attribute_field(
+                fragment,
+                fragment_schema,
+                attr,
+            ) {
+                Some(field) => field,
+                None => {
+                    buffer.push(&fill_value, fill_validity);
+                    continue;
+                }
+            };
+
+            let tile = cache.load(
+                vfs,
+                source.fragment,
+                fragment,
+                &field,
+                source.tile,
+            )?;
+            buffer.push(tile.value(source.cell), tile.is_valid(source.cell));
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::ArrayType;
+    use crate::datatype::DataType;
+    use crate::fixtures;
+    use crate::io::{uri, PosixVFSService};
+    use crate::query::DenseWriter;
+    use crate::storage;
+
+    #[test]
+    fn linear_index_test() {
+        let shape = vec![3, 4];
+        assert_eq!(linear_index(&[0, 0], &shape, Layout::RowMajor), 0);
+        assert_eq!(linear_index(&[0, 3], &shape, Layout::RowMajor), 3);
+        assert_eq!(linear_index(&[1, 0], &shape, Layout::RowMajor), 4);
+        assert_eq!(linear_index(&[2, 3], &shape, Layout::RowMajor), 11);
+        assert_eq!(linear_index(&[1, 0], &shape, Layout::ColMajor), 1);
+        assert_eq!(linear_index(&[0, 1], &shape, Layout::ColMajor), 3);
+        assert_eq!(linear_index(&[2, 3], &shape, Layout::ColMajor), 11);
+
+        let bounds = vec![(1, 3), (5, 8)];
+        assert_eq!(coord_at(4, &bounds, Layout::RowMajor), vec![2, 5]);
+        assert_eq!(coord_at(4, &bounds, Layout::ColMajor), vec![2, 6]);
+    }
+
+    #[test]
+    fn prune_tiles() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let path = format!(
+            "{}/resources/arrays/v{}/dense",
+            env!("CARGO_MANIFEST_DIR"),
+            storage::CURRENT_FORMAT_VERSION
+        );
+        let array = array::Array::open(&vfs, &uri::URI::from_string(&path)?)?;
+
+        // Cells of the second tile can not be less than 2 so it is filled
+        // without being read, while the first tile is filtered cell by cell.
+        let condition =
+            QueryCondition::compare("int32", storage::ConditionOp::Lt, 2i32);
+        let reader = DenseReader::new(&array).set_condition(condition);
+        let results = reader.read(&vfs)?;
+        assert_eq!(
+            results["int32"].values::<i32>(),
+            vec![1, i32::MIN, i32::MIN, i32::MIN]
+        );
+        assert_eq!(reader.stats().tiles(), 2);
+        assert_eq!(reader.stats().tiles_pruned(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn read_overlapping_fragments() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let domain = array::Range::from_values(1i32, 4);
+        let schema = storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![
+                storage::Dimension::new("rows", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+                storage::Dimension::new("cols", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+            ]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        );
+        let (_dir, array) = fixtures::temp_array(&vfs, "dense", &schema)?;
+
+        // The top two rows, then their right half again in a newer fragment.
+        let t = storage::current_timestamp();
+        let writes = [
+            (t + 1, (1i32, 4i32), (1..=8).collect::<Vec<i32>>()),
+            (t + 2, (3, 4), vec![-1, -2, -3, -4]),
+        ];
+        for (ts, (lo, hi), values) in writes {
+            let subarray = Subarray::new()
+                .set_typed_range(0, 1i32, 2)
+                .set_typed_range(1, lo, hi);
+            DenseWriter::new(array.uri(), array.schema_name(), array.schema())
+                .set_subarray(subarray)
+                .set_buffer("a", QueryBuffer::from_values(&values))
+                .set_timestamp(ts)
+                .write(&vfs)?;
+        }
+
+        // The newest fragment wins and unwritten cells hold the fill value.
+        let array = array::Array::open(&vfs, array.uri())?;
+        let results = DenseReader::new(&array).read(&vfs)?;
+        let fill = i32::MIN;
+        let mut expected = vec![1, 2, -1, -2, 5, 6, -3, -4];
+        expected.extend([fill; 8]);
+        assert_eq!(results["a"].values::<i32>(), expected);
+
+        let results = DenseReader::new(&array)
+            .set_subarray(
+                Subarray::new()
+                    .set_typed_range(0, 1i32, 3)
+                    .set_typed_range(1, 2i32, 3),
+            )
+            .set_layout(Layout::ColMajor)
+            .read(&vfs)?;
+        // Columns 2 and 3 of rows 1 to 3.
+        assert_eq!(
+            results["a"].values::<i32>(),
+            vec![2, 6, fill, -1, -3, fill]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn intersect_test() {
+        let lhs = vec![(1, 10), (5, 7)];
+        assert_eq!(
+            intersect(&lhs, &[(3, 20), (1, 5)]),
+            Some(vec![(3, 10), (5, 5)])
+        );
+        assert_eq!(intersect(&lhs, &[(11, 20), (1, 5)]), None);
+    }
+
+    #[test]
+    fn for_each_coord_test() {
+        let mut coords = Vec::new();
+        for_each_coord(&[(1, 2), (5, 7)], &mut |c| coords.push(c.to_vec()));
+        assert_eq!(
+            coords,
+            vec![
+                vec![1, 5],
+                vec![1, 6],
+                vec![1, 7],
+                vec![2, 5],
+                vec![2, 6],
+                vec![2, 7]
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use crate::datatype::DataType;
+
+// Maps coordinates to their position along a Hilbert curve, as TileDB does
+// for arrays with a Hilbert cell order. Like TileDB, each dimension is
+// given an equal share of 63 bits of the index.
+pub struct Hilbert {
+    bits: u32,
+    dim_num: usize,
+}
+
+impl Hilbert {
+    pub fn new(dim_num: usize) -> Self {
+        Hilbert {
+            bits: (u64::BITS - 1) / dim_num.clamp(1, 63) as u32,
+            dim_num,
+        }
+    }
+
+    // The number of bits used for each dimension.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    // The Hilbert index of coordinates that have been mapped to buckets
+    // within 0..=max_bucket. This is Skilling's transform of the axes
+    // followed by interleaving the bits of the transposed coordinates.
+    pub fn index(&self, coords: &[u64]) -> u64 {
+        let mut x = coords.to_vec();
+        let n = self.dim_num;
+        let m = 1u64 << (self.bits - 1);
+
+        // Inverse undo.
+        let mut q = m;
+        while q > 1 {
+            let p = q - 1;
+            for i in 0..n {
+                if x[i] & q != 0 {
+                    x[0] ^= p;
+                } else {
+                    let t = (x[0] ^ x[i]) & p;
+                    x[0] ^= t;
+                    x[i] ^= t;
+                }
+            }
+            q >>= 1;
+        }
+
+        // Gray encode.
+        for i in 1..n {
+            x[i] ^= x[i - 1];
+        }
+        let mut t = 0;
+        let mut q = m;
+        while q > 1 {
+            if x[n - 1] & q != 0 {
+                t ^= q - 1;
+            }
+            q >>= 1;
+        }
+        for value in x.iter_mut() {
+            *value ^= t;
+        }
+
+        let mut ret = 0u64;
+        for bit in (0..self.bits).rev() {
+            for value in &x {
+                ret = (ret << 1) | ((value >> bit) & 1);
+            }
+        }
+        ret
+    }
+}
+
+// Map a coordinate to a bucket of the Hilbert curve. Numeric values are
+// scaled from the domain onto 0..=max_bucket. Strings, which have no
+// domain, use their first eight bytes as a big endian number reduced to the
+// bits available.
+pub fn hilbert_bucket(
+    dtype: DataType,
+    domain: Option<(&[u8], &[u8])>,
+    value: &[u8],
+    bits: u32,
+) -> u64 {
+    let max_bucket = (1u64 << bits) - 1;
+    let (low, high) = match domain {
+        Some((low, high)) if dtype.to_f64(value).is_some() => (low, high),
+        _ => {
+            let mut bytes = [0u8; 8];
+            let len = value.len().min(8);
+            bytes[..len].copy_from_slice(&value[..len]);
+            return u64::from_be_bytes(bytes) >> (64 - bits);
+        }
+    };
+
+    let value = dtype.to_f64(value).unwrap_or(0.0);
+    let low = dtype.to_f64(low).unwrap_or(0.0);
+    let high = dtype.to_f64(high).unwrap_or(0.0);
+    if high <= low {
+        return 0;
+    }
+    ((value - low) / (high - low) * max_bucket as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hilbert_index() {
+        let hilbert = Hilbert::new(2);
+        assert_eq!(hilbert.bits(), 31);
+        assert_eq!(Hilbert::new(1).bits(), 63);
+        assert_eq!(Hilbert::new(3).bits(), 21);
+
+        // Indexes computed with TileDB's algorithm and bit budget.
+        let max = (1 << 31) - 1;
+        let cases = [
+            ([0, 0], 0),
+            ([0, 1], 1),
+            ([1, 1], 2),
+            ([1, 0], 3),
+            ([2, 0], 4),
+            ([3, 3], 10),
+            ([0, max], 1537228672809129301),
+            ([max, max], 3074457345618258602),
+            ([max, 0], 4611686018427387903),
+        ];
+        for (coords, index) in cases {
+            assert_eq!(hilbert.index(&coords), index);
+        }
+        let hilbert = Hilbert::new(3);
+        let cases = [
+            ([0, 0, 0], 0),
+            ([1, 0, 0], 1),
+            ([0, 1, 0], 7),
+            ([0, 0, 1], 3),
+            ([1, 1, 1], 5),
+        ];
+        for (coords, index) in cases {
+            assert_eq!(hilbert.index(&coords), index);
+        }
+
+        let low = 0i32.to_le_bytes();
+        let high = 100i32.to_le_bytes();
+        let domain = Some((&low[..], &high[..]));
+        let bucket = |v: i32| {
+            hilbert_bucket(DataType::Int32, domain, &v.to_le_bytes(), 31)
+        };
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(100), max);
+        assert_eq!(
+            hilbert_bucket(DataType::StringAscii, None, b"a", 31),
+            b'a' as u64 * (1 << 23)
+        );
+    }
+}
@@ -0,0 +1,498 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+pub mod aggregate;
+pub mod condition;
+pub mod dense;
+pub mod hilbert;
+pub mod offsets;
+pub mod order;
+pub mod parse;
+pub mod sparse;
+pub mod subarray;
+pub mod writer;
+
+pub use aggregate::*;
+pub use condition::*;
+pub use dense::*;
+pub use hilbert::*;
+pub use offsets::*;
+pub use order::*;
+pub use parse::*;
+pub use sparse::*;
+pub use subarray::*;
+pub use writer::*;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::array;
+use crate::datatype::{self, DataType, Primitive};
+use crate::io::service::VFSService;
+use crate::storage;
+use crate::Result;
+
+// The result of reading a single field. Var sized fields record the byte
+// offset of each cell into the data buffer and nullable fields have one
+// validity byte per cell.
+#[derive(Clone, Debug, Default)]
+pub struct QueryBuffer {
+    data: Vec<u8>,
+    offsets: Option<Vec<u64>>,
+    validity: Option<Vec<u8>>,
+    cell_num: usize,
+}
+
+impl QueryBuffer {
+    pub fn new(var_sized: bool, nullable: bool) -> Self {
+        QueryBuffer {
+            data: Vec::new(),
+            offsets: if var_sized { Some(Vec::new()) } else { None },
+            validity: if nullable { Some(Vec::new()) } else { None },
+            cell_num: 0,
+        }
+    }
+
+    // A fixed size buffer holding one value per cell.
+    pub fn from_values<T: Primitive>(values: &[T]) -> Self {
+        QueryBuffer {
+            data: datatype::values_to_bytes(values),
+            offsets: None,
+            validity: None,
+            cell_num: values.len(),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn offsets(&self) -> Option<&[u64]> {
+        self.offsets.as_deref()
+    }
+
+    pub fn validity(&self) -> Option<&[u8]> {
+        self.validity.as_deref()
+    }
+
+    pub fn cell_num(&self) -> usize {
+        self.cell_num
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cell_num == 0
+    }
+
+    // Decode a fixed size buffer into typed values.
+    pub fn values<T: Primitive>(&self) -> Vec<T> {
+        datatype::values_from_bytes(&self.data)
+    }
+
+    // The bytes of a single cell.
+    pub fn value(&self, cell: usize) -> Option<&[u8]> {
+        if self.offsets.is_some() {
+            return self.var_value(cell);
+        }
+
+        if cell >= self.cell_num {
+            return None;
+        }
+        let size = self.data.len() / self.cell_num;
+        Some(&self.data[cell * size..(cell + 1) * size])
+    }
+
+    // The bytes of a single var sized cell.
+    pub fn var_value(&self, cell: usize) -> Option<&[u8]> {
+        let offsets = self.offsets.as_ref()?;
+        let start = *offsets.get(cell)? as usize;
+        let end = offsets
+            .get(cell + 1)
+            .map_or(self.data.len(), |o| *o as usize);
+        Some(&self.data[start..end])
+    }
+
+    // The offsets of a var sized buffer in a user chosen format.
+    pub fn formatted_offsets(
+        &self,
+        data_type: DataType,
+        format: &OffsetsFormat,
+    ) -> Result<Vec<u8>> {
+        let offsets = self
+            .offsets
+            .as_ref()
+            .ok_or_else(|| anyhow!("Buffer is not var sized"))?;
+        format.encode(data_type, offsets, self.data.len())
+    }
+
+    // A var sized buffer from user data and offsets in the given format,
+    // with an optional validity byte per cell.
+    pub fn from_var_data(
+        data_type: DataType,
+        data: Vec<u8>,
+        offsets: &[u8],
+        format: &OffsetsFormat,
+        validity: Option<Vec<u8>>,
+    ) -> Result<Self> {
+        let offsets = format.decode(data_type, offsets, data.len())?;
+        if let Some(validity) = &validity {
+            if validity.len() != offsets.len() {
+                return Err(anyhow!(
+                    "Validity buffer has {} cells, expected {}",
+                    validity.len(),
+                    offsets.len()
+                ));
+            }
+        }
+        Ok(QueryBuffer {
+            data,
+            cell_num: offsets.len(),
+            offsets: Some(offsets),
+            validity,
+        })
+    }
+
+    pub fn is_valid(&self, cell: usize) -> bool {
+        match &self.validity {
+            Some(validity) => validity[cell] != 0,
+            None => true,
+        }
+    }
+
+    // Append a cell. The validity is ignored for non-nullable buffers.
+    pub fn push(&mut self, value: &[u8], valid: bool) {
+        if let Some(offsets) = self.offsets.as_mut() {
+            offsets.push(self.data.len() as u64);
+        }
+        self.data.extend_from_slice(value);
+        if let Some(validity) = self.validity.as_mut() {
+            validity.push(valid as u8);
+        }
+        self.cell_num += 1;
+    }
+}
+
+// Counts of the tiles a read considered. Tiles are pruned when their
+// statistics show that no cell can satisfy the query condition, in which
+// case their attributes are never read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadStats {
+    tiles: u64,
+    tiles_pruned: u64,
+}
+
+impl ReadStats {
+    // The number of tiles intersecting the query.
+    pub fn tiles(&self) -> u64 {
+        self.tiles
+    }
+
+    pub fn tiles_pruned(&self) -> u64 {
+        self.tiles_pruned
+    }
+
+    pub(crate) fn add_tile(&mut self, pruned: bool) {
+        self.tiles += 1;
+        self.tiles_pruned += pruned as u64;
+    }
+}
+
+// Replace the integer keys of enumerated attributes with their labels.
+pub(crate) fn decode_enumerations(
+    vfs: &dyn VFSService,
+    array: &array::Array,
+    buffers: &mut HashMap<String, QueryBuffer>,
+) -> Result<()> {
+    for (name, buffer) in buffers.iter_mut() {
+        let attr = match array.schema().attribute(name) {
+            Some(attr) => attr,
+            None => continue,
+        };
+        if let Some(enumeration_name) = attr.enumeration_name() {
+            let enumeration = array.load_enumeration(vfs, enumeration_name)?;
+            *buffer = decode_buffer(buffer, attr.data_type(), &enumeration)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode_buffer(
+    buffer: &QueryBuffer,
+    key_type: DataType,
+    enumeration: &array::Enumeration,
+) -> Result<QueryBuffer> {
+    let null_label = if enumeration.is_var_sized() {
+        Vec::new()
+    } else {
+        let size = enumeration.data_type().size();
+        vec![0; size * enumeration.cell_val_num() as usize]
+    };
+
+    let mut ret = QueryBuffer::new(
+        enumeration.is_var_sized(),
+        buffer.validity().is_some(),
+    );
+    for cell in 0..buffer.cell_num() {
+        if !buffer.is_valid(cell) {
+            ret.push(&null_label, false);
+            continue;
+        }
+
+        let label = buffer
+            .value(cell)
+            .and_then(|key| key_type.to_i128(key))
+            .and_then(|key| usize::try_from(key).ok())
+            .and_then(|key| enumeration.value(key))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid key for enumeration '{}' at cell {}",
+                    enumeration.name(),
+                    cell
+                )
+            })?;
+        ret.push(label, true);
+    }
+
+    Ok(ret)
+}
+
+// The field holding an attribute in a fragment written with an older
+// schema. Fragments written before the attribute was added, or before it
+// was dropped and added again with a different type, have no data for it.
+pub(crate) fn fragment_attribute_field<'a>(
+    fragment: &storage::FragmentMetadata,
+    fragment_schema: &'a array::Schema,
+    attr: &array::Attribute,
+) -> Option<storage::Field<'a>> {
+    let written = fragment_schema.attribute(attr.name())?;
+    if written.data_type() != attr.data_type()
+        || written.cell_val_num() != attr.cell_val_num()
+        || written.nullable() != attr.nullable()
+    {
+        return None;
+    }
+    fragment.attribute_field(fragment_schema, attr.name())
+}
+
+// The value of an attribute for a single cell of a fragment. Conditions
+// may refer to attributes that have since been dropped, and attributes
+// added after the fragment was written read as their fill value.
+pub(crate) fn attribute_value(
+    vfs: &dyn VFSService,
+    array: &array::Array,
+    cache: &mut TileCache,
+    fragment_idx: usize,
+    tile: usize,
+    cell: usize,
+    name: &str,
+) -> Result<CellValue> {
+    let fragment = &array.fragments()[fragment_idx];
+    let schema = array.fragment_schema(fragment)?;
+
+    let attr = array.schema().attribute(name);
+    let field = match attr {
+        Some(attr) => fragment_attribute_field(fragment, schema, attr),
+        None => fragment.attribute_field(schema, name),
+    };
+    if let Some(field) = field {
+        let tile = cache.load(vfs, fragment_idx, fragment, &field, tile)?;
+        let value = if tile.is_valid(cell) {
+            Some(tile.value(cell).to_vec())
+        } else {
+            None
+        };
+        return Ok((field.data_type(), value));
+    }
+
+    let attr =
+        attr.ok_or_else(|| anyhow!("Unknown field '{}' in condition", name))?;
+    let value = if attr.fill_value_validity() {
+        Some(attr.fill_value())
+    } else {
+        None
+    };
+    Ok((attr.data_type(), value))
+}
+
+// Tile statistics keyed by fragment and field, loaded on first use.
+#[derive(Default)]
+pub(crate) struct StatsCache {
+    stats: HashMap<(usize, usize), Vec<storage::TileStats>>,
+}
+
+impl StatsCache {
+    // Whether a tile of a fragment may hold cells satisfying a condition.
+    // Fields the fragment has no statistics for never rule out the tile.
+    pub(crate) fn may_match(
+        &mut self,
+        vfs: &dyn VFSService,
+        array: &array::Array,
+        condition: &storage::ConditionNode,
+        fragment_idx: usize,
+        tile: usize,
+    ) -> Result<bool> {
+        let fragment = &array.fragments()[fragment_idx];
+        let schema = array.fragment_schema(fragment)?;
+
+        let mut lookup = |name: &str| -> Result<Option<storage::TileStats>> {
+            let field = match schema.domain().dimension_idx(name) {
+                Some(d) => Some(fragment.dimension_field(schema, d)),
+                None => array.schema().attribute(name).and_then(|attr| {
+                    fragment_attribute_field(fragment, schema, attr)
+                }),
+            };
+            let Some(field) = field else {
+                return Ok(None);
+            };
+
+            let stats = self.load(vfs, fragment_idx, fragment, &field)?;
+            Ok(stats.get(tile).cloned())
+        };
+        may_match(condition, &mut lookup)
+    }
+
+    pub(crate) fn load(
+        &mut self,
+        vfs: &dyn VFSService,
+        fragment_idx: usize,
+        fragment: &storage::FragmentMetadata,
+        field: &storage::Field,
+    ) -> Result<&[storage::TileStats]> {
+        match self.stats.entry((fragment_idx, field.idx())) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                Ok(entry.insert(fragment.load_tile_stats(vfs, field)?))
+            }
+        }
+    }
+}
+
+// Decoded tiles keyed by fragment, field and tile so that each tile is only
+// read once per query.
+#[derive(Default)]
+pub(crate) struct TileCache {
+    indexes: HashMap<(usize, usize), storage::TileIndex>,
+    tiles: HashMap<(usize, usize, usize), storage::Tile>,
+}
+
+impl TileCache {
+    pub(crate) fn load(
+        &mut self,
+        vfs: &dyn VFSService,
+        fragment_idx: usize,
+        fragment: &storage::FragmentMetadata,
+        field: &storage::Field,
+        tile: usize,
+    ) -> Result<&storage::Tile> {
+        match self.tiles.entry((fragment_idx, field.idx(), tile)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let index =
+                    match self.indexes.entry((fragment_idx, field.idx())) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            entry.insert(fragment.load_tile_index(vfs, field)?)
+                        }
+                    };
+                let data = fragment.read_tile(vfs, field, index, tile)?;
+                Ok(entry.insert(data))
+            }
+        }
+    }
+
+    // Panics if the tile has not been loaded.
+    pub(crate) fn get(
+        &self,
+        fragment_idx: usize,
+        field_idx: usize,
+        tile: usize,
+    ) -> &storage::Tile {
+        &self.tiles[&(fragment_idx, field_idx, tile)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::{ArrayType, Layout};
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+
+    #[test]
+    fn decode_labels() -> Result<()> {
+        let enumeration = array::Enumeration::try_from(storage::Enumeration {
+            version: 0,
+            name_size: 6,
+            name: b"colors".to_vec(),
+            path_size: 4,
+            path: b"path".to_vec(),
+            datatype: DataType::StringUtf8 as u8,
+            cell_val_num: storage::CELL_VAR_SIZE,
+            ordered: 0,
+            data_size: 12,
+            data: b"redgreenblue".to_vec(),
+            offsets_size: 24,
+            offsets: vec![0, 3, 8],
+        })?;
+
+        let mut keys = QueryBuffer::new(false, true);
+        for (key, valid) in [(2u8, true), (0, false), (0, true)] {
+            keys.push(&[key], valid);
+        }
+        let labels = decode_buffer(&keys, DataType::Uint8, &enumeration)?;
+        assert_eq!(labels.var_value(0), Some(&b"blue"[..]));
+        assert!(!labels.is_valid(1));
+        assert_eq!(labels.var_value(2), Some(&b"red"[..]));
+
+        keys.push(&[3], true);
+        assert!(decode_buffer(&keys, DataType::Uint8, &enumeration).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_stored_enumeration() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let mut schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "x",
+                DataType::Int64,
+                &array::Range::from_values(0i64, 99),
+            )]),
+            vec![storage::Attribute::new("color", DataType::Uint8)
+                .set_enumeration_name("colors")],
+        );
+        let path = "colors_0".to_string();
+        schema
+            .enumeration_map
+            .insert("colors".to_string(), path.clone());
+        let (_dir, array) = fixtures::temp_array(&vfs, "enumeration", &schema)?;
+        array::Enumeration::new(
+            "colors",
+            DataType::StringUtf8,
+            storage::CELL_VAR_SIZE,
+            false,
+            vec![b"red".to_vec(), b"green".to_vec()],
+        )?
+        .store(&vfs, array.uri(), &path)?;
+        SparseWriter::new(array.uri(), array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(&[1i64, 2]))
+            .set_buffer("color", QueryBuffer::from_values(&[1u8, 0]))
+            .write(&vfs)?;
+
+        // The enumeration map is parsed from the schema file.
+        let array = array::Array::open(&vfs, array.uri())?;
+        assert_eq!(
+            array.schema().attributes()[0].enumeration_name(),
+            Some("colors")
+        );
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .set_decode_enumerations(true)
+            .read(&vfs)?;
+        assert_eq!(results["color"].var_value(0), Some(&b"green"[..]));
+        assert_eq!(results["color"].var_value(1), Some(&b"red"[..]));
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+
+use crate::datatype::{DataType, Primitive};
+use crate::Result;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OffsetsMode {
+    // Offsets count bytes into the data buffer.
+    #[default]
+    Bytes,
+    // Offsets count values of the field's datatype.
+    Elements,
+}
+
+// How the offsets of var sized cells are laid out in user buffers, as set
+// by TileDB's sm.var_offsets.bitsize, sm.var_offsets.mode and
+// sm.var_offsets.extra_element options. The default of 64 bit byte offsets
+// without an extra element matches TileDB's and the offsets of a
+// QueryBuffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetsFormat {
+    bitsize: u32,
+    mode: OffsetsMode,
+    extra_element: bool,
+}
+
+impl Default for OffsetsFormat {
+    fn default() -> Self {
+        OffsetsFormat {
+            bitsize: 64,
+            mode: OffsetsMode::Bytes,
+            extra_element: false,
+        }
+    }
+}
+
+impl OffsetsFormat {
+    pub fn new() -> Self {
+        OffsetsFormat::default()
+    }
+
+    pub fn set_bitsize(mut self, bitsize: u32) -> Result<Self> {
+        if !matches!(bitsize, 32 | 64) {
+            return Err(anyhow!("Invalid offsets bitsize {}", bitsize));
+        }
+        self.bitsize = bitsize;
+        Ok(self)
+    }
+
+    pub fn set_mode(mut self, mode: OffsetsMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    // An extra trailing offset holds the size of the data buffer.
+    pub fn set_extra_element(mut self, extra_element: bool) -> Self {
+        self.extra_element = extra_element;
+        self
+    }
+
+    // Apply a TileDB config option by name.
+    pub fn set_option(self, key: &str, value: &str) -> Result<Self> {
+        match key {
+            "sm.var_offsets.bitsize" => {
+                let bitsize = value.parse().map_err(|_| {
+                    anyhow!("Invalid offsets bitsize '{}'", value)
+                })?;
+                self.set_bitsize(bitsize)
+            }
+            "sm.var_offsets.mode" => match value {
+                "bytes" => Ok(self.set_mode(OffsetsMode::Bytes)),
+                "elements" => Ok(self.set_mode(OffsetsMode::Elements)),
+                _ => Err(anyhow!("Invalid offsets mode '{}'", value)),
+            },
+            "sm.var_offsets.extra_element" => match value {
+                "true" => Ok(self.set_extra_element(true)),
+                "false" => Ok(self.set_extra_element(false)),
+                _ => Err(anyhow!("Invalid offsets extra element '{}'", value)),
+            },
+            _ => Err(anyhow!("Unknown offsets option '{}'", key)),
+        }
+    }
+
+    pub fn bitsize(&self) -> u32 {
+        self.bitsize
+    }
+
+    pub fn mode(&self) -> OffsetsMode {
+        self.mode
+    }
+
+    pub fn extra_element(&self) -> bool {
+        self.extra_element
+    }
+
+    fn unit(&self, data_type: DataType) -> u64 {
+        match self.mode {
+            OffsetsMode::Bytes => 1,
+            OffsetsMode::Elements => data_type.size().max(1) as u64,
+        }
+    }
+
+    // Convert byte offsets without an extra element into this format.
+    pub fn encode(
+        &self,
+        data_type: DataType,
+        offsets: &[u64],
+        data_size: usize,
+    ) -> Result<Vec<u8>> {
+        let unit = self.unit(data_type);
+        let extra = self.extra_element.then_some(data_size as u64);
+
+        let mut ret =
+            Vec::with_capacity((offsets.len() + 1) * self.bitsize as usize / 8);
+        for offset in offsets.iter().copied().chain(extra) {
+            if !offset.is_multiple_of(unit) {
+                return Err(anyhow!(
+                    "Offset {} is not a multiple of the {} byte element size",
+                    offset,
+                    unit
+                ));
+            }
+            let offset = offset / unit;
+            if self.bitsize == 32 {
+                let offset = u32::try_from(offset).map_err(|_| {
+                    anyhow!("Offset {} does not fit in 32 bits", offset)
+                })?;
+                ret.extend_from_slice(&offset.to_le_bytes());
+            } else {
+                ret.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        Ok(ret)
+    }
+
+    // Convert offsets in this format into byte offsets without an extra
+    // element, checking that they lie within the data buffer.
+    pub fn decode(
+        &self,
+        data_type: DataType,
+        offsets: &[u8],
+        data_size: usize,
+    ) -> Result<Vec<u64>> {
+        let width = self.bitsize as usize / 8;
+        if !offsets.len().is_multiple_of(width) {
+            return Err(anyhow!(
+                "Offsets buffer of {} bytes is not a multiple of {} bytes",
+                offsets.len(),
+                width
+            ));
+        }
+
+        let unit = self.unit(data_type);
+        let mut ret: Vec<u64> = offsets
+            .chunks(width)
+            .map(|bytes| {
+                let offset = if width == 4 {
+                    u32::from_bytes(bytes) as u64
+                } else {
+                    u64::from_bytes(bytes)
+                };
+                offset.saturating_mul(unit)
+            })
+            .collect();
+
+        if self.extra_element {
+            match ret.pop() {
+                Some(last) if last == data_size as u64 => (),
+                Some(last) => {
+                    return Err(anyhow!(
+                        "Extra offset {} does not match the data size {}",
+                        last,
+                        data_size
+                    ))
+                }
+                None => return Err(anyhow!("Missing extra offset")),
+            }
+        }
+
+        let mut prev = 0;
+        for (cell, offset) in ret.iter().enumerate() {
+            if *offset < prev || *offset > data_size as u64 {
+                return Err(anyhow!("Invalid offset for cell {}", cell));
+            }
+            prev = *offset;
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype;
+    use crate::query::QueryBuffer;
+
+    #[test]
+    fn offsets_format() -> Result<()> {
+        let mut buffer = QueryBuffer::new(true, false);
+        for value in [&b"a"[..], b"bcd", b""] {
+            buffer.push(value, true);
+        }
+        let dtype = DataType::StringAscii;
+
+        let format = OffsetsFormat::new()
+            .set_option("sm.var_offsets.bitsize", "32")?
+            .set_option("sm.var_offsets.extra_element", "true")?;
+        let offsets = buffer.formatted_offsets(dtype, &format)?;
+        assert_eq!(
+            datatype::values_from_bytes::<u32>(&offsets),
+            vec![0, 1, 4, 4]
+        );
+        let copy = QueryBuffer::from_var_data(
+            dtype,
+            buffer.data().to_vec(),
+            &offsets,
+            &format,
+            None,
+        )?;
+        assert_eq!(copy.cell_num(), 3);
+        assert_eq!(copy.var_value(1), Some(&b"bcd"[..]));
+
+        // Element offsets count values rather than bytes.
+        let mut buffer = QueryBuffer::new(true, false);
+        buffer.push(&datatype::values_to_bytes(&[1i32]), true);
+        buffer.push(&datatype::values_to_bytes(&[2i32, 3]), true);
+        let format = OffsetsFormat::new().set_mode(OffsetsMode::Elements);
+        let offsets = buffer.formatted_offsets(DataType::Int32, &format)?;
+        assert_eq!(datatype::values_from_bytes::<u64>(&offsets), vec![0, 1]);
+
+        // The extra element must match the size of the data.
+        let format = format.set_extra_element(true);
+        let offsets = datatype::values_to_bytes(&[0u64, 1, 2]);
+        let data = datatype::values_to_bytes(&[1i32, 2, 3]);
+        assert!(QueryBuffer::from_var_data(
+            DataType::Int32,
+            data,
+            &offsets,
+            &format,
+            None
+        )
+        .is_err());
+        assert!(OffsetsFormat::new().set_bitsize(16).is_err());
+        assert!(OffsetsFormat::new()
+            .set_option("sm.var_offsets.mode", "bits")
+            .is_err());
+        Ok(())
+    }
+}
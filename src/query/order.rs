@@ -0,0 +1,247 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+
+use crate::array::{self, Layout};
+use crate::datatype::DataType;
+use crate::query::{hilbert_bucket, Hilbert};
+use crate::Result;
+
+struct DimOrder {
+    data_type: DataType,
+    domain: Option<(Vec<u8>, Vec<u8>)>,
+    extent: Option<Vec<u8>>,
+}
+
+impl DimOrder {
+    // The index of the space tile containing a value. Dimensions without a
+    // tile extent, such as string dimensions, form a single tile.
+    fn space_tile(&self, value: &[u8]) -> i128 {
+        let (low, extent) = match (&self.domain, &self.extent) {
+            (Some((low, _)), Some(extent)) => (low, extent),
+            _ => return 0,
+        };
+
+        let dtype = self.data_type;
+        if dtype.is_real_type() {
+            let value = dtype.to_f64(value).unwrap_or(0.0);
+            let low = dtype.to_f64(low).unwrap_or(0.0);
+            let extent = dtype.to_f64(extent).unwrap_or(1.0);
+            ((value - low) / extent).floor() as i128
+        } else {
+            let value = dtype.to_i128(value).unwrap_or(0);
+            let low = dtype.to_i128(low).unwrap_or(0);
+            let extent = dtype.to_i128(extent).unwrap_or(1).max(1);
+            (value - low) / extent
+        }
+    }
+}
+
+// Compares cell coordinates according to a query layout. Global order sorts
+// by space tile in the schema's tile order, then by the schema's cell order
+// within each tile. A Hilbert cell order ignores space tiles and sorts by
+// Hilbert index, breaking ties in row major order.
+pub struct CellOrder {
+    layout: Layout,
+    tile_order: Layout,
+    cell_order: Layout,
+    dims: Vec<DimOrder>,
+    hilbert: Hilbert,
+}
+
+impl CellOrder {
+    pub fn new(schema: &array::Schema, layout: Layout) -> Result<Self> {
+        if matches!(layout, Layout::Hilbert | Layout::Invalid) {
+            return Err(anyhow!("Invalid query layout {:?}", layout));
+        }
+
+        if matches!(layout, Layout::GlobalOrder)
+            && !matches!(
+                schema.cell_order(),
+                Layout::RowMajor | Layout::ColMajor | Layout::Hilbert
+            )
+        {
+            return Err(anyhow!(
+                "Unsupported cell order {:?} for global order results",
+                schema.cell_order()
+            ));
+        }
+
+        let dims = schema
+            .domain()
+            .dimensions()
+            .iter()
+            .map(|dim| DimOrder {
+                data_type: dim.data_type(),
+                domain: dim
+                    .range()
+                    .map(|(low, high)| (low.to_vec(), high.to_vec())),
+                extent: dim.extent().map(|extent| extent.to_vec()),
+            })
+            .collect();
+
+        Ok(CellOrder {
+            layout,
+            tile_order: schema.tile_order(),
+            cell_order: schema.cell_order(),
+            dims,
+            hilbert: Hilbert::new(schema.domain().ndim()),
+        })
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn space_tile(&self, coords: &[&[u8]]) -> Vec<i128> {
+        self.dims
+            .iter()
+            .zip(coords)
+            .map(|(dim, value)| dim.space_tile(value))
+            .collect()
+    }
+
+    // The Hilbert index of cell coordinates over the array domain.
+    pub fn hilbert_index(&self, coords: &[&[u8]]) -> u64 {
+        let buckets: Vec<u64> = self
+            .dims
+            .iter()
+            .zip(coords)
+            .map(|(dim, value)| {
+                let domain = dim
+                    .domain
+                    .as_ref()
+                    .map(|(low, high)| (low.as_slice(), high.as_slice()));
+                hilbert_bucket(
+                    dim.data_type,
+                    domain,
+                    value,
+                    self.hilbert.bits(),
+                )
+            })
+            .collect();
+        self.hilbert.index(&buckets)
+    }
+
+    // Hilbert indexes are costly to compute, so sorts compute them once
+    // per cell with hilbert_key and compare with compare_keyed. The key is
+    // zero unless cells are sorted by Hilbert index.
+    pub fn hilbert_key(&self, coords: &[&[u8]]) -> u64 {
+        if self.layout == Layout::GlobalOrder
+            && self.cell_order == Layout::Hilbert
+        {
+            self.hilbert_index(coords)
+        } else {
+            0
+        }
+    }
+
+    pub fn compare(&self, lhs: &[&[u8]], rhs: &[&[u8]]) -> Ordering {
+        self.compare_keyed(
+            (self.hilbert_key(lhs), lhs),
+            (self.hilbert_key(rhs), rhs),
+        )
+    }
+
+    // Compare cells along with their hilbert_key.
+    pub fn compare_keyed(
+        &self,
+        (lkey, lhs): (u64, &[&[u8]]),
+        (rkey, rhs): (u64, &[&[u8]]),
+    ) -> Ordering {
+        match self.layout {
+            Layout::RowMajor | Layout::ColMajor => {
+                self.compare_cells(self.layout, lhs, rhs)
+            }
+            Layout::GlobalOrder if self.cell_order == Layout::Hilbert => lkey
+                .cmp(&rkey)
+                .then_with(|| self.compare_cells(Layout::RowMajor, lhs, rhs)),
+            Layout::GlobalOrder => {
+                let ltile = self.space_tile(lhs);
+                let rtile = self.space_tile(rhs);
+                let ord = if matches!(self.tile_order, Layout::ColMajor) {
+                    ltile.iter().rev().cmp(rtile.iter().rev())
+                } else {
+                    ltile.cmp(&rtile)
+                };
+                ord.then_with(|| self.compare_cells(self.cell_order, lhs, rhs))
+            }
+            _ => Ordering::Equal,
+        }
+    }
+
+    fn compare_cells(
+        &self,
+        layout: Layout,
+        lhs: &[&[u8]],
+        rhs: &[&[u8]],
+    ) -> Ordering {
+        let compare_dim =
+            |d: usize| self.dims[d].data_type.compare(lhs[d], rhs[d]);
+
+        let ndim = self.dims.len();
+        if matches!(layout, Layout::ColMajor) {
+            (0..ndim)
+                .rev()
+                .map(compare_dim)
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        } else {
+            (0..ndim)
+                .map(compare_dim)
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{uri, PosixVFSService};
+    use crate::storage;
+
+    fn load_schema() -> Result<array::Schema> {
+        let vfs = PosixVFSService::default();
+        let uri = uri::URI::from_string("resources/schema/schema_1")?;
+        array::Schema::try_from(storage::ArraySchema::load(&vfs, &uri)?)
+    }
+
+    #[test]
+    fn row_major_order() -> Result<()> {
+        let schema = load_schema()?;
+        let order = CellOrder::new(&schema, Layout::RowMajor)?;
+        let lhs = (-5i64).to_le_bytes();
+        let rhs = 3i64.to_le_bytes();
+        assert_eq!(order.compare(&[&lhs], &[&rhs]), Ordering::Less);
+        assert_eq!(order.compare(&[&rhs], &[&lhs]), Ordering::Greater);
+        assert_eq!(order.compare(&[&rhs], &[&rhs]), Ordering::Equal);
+        Ok(())
+    }
+
+    #[test]
+    fn global_order_space_tiles() -> Result<()> {
+        let schema = load_schema()?;
+        let order = CellOrder::new(&schema, Layout::GlobalOrder)?;
+        let first = i64::MIN.to_le_bytes();
+        let second = (i64::MIN + 10000).to_le_bytes();
+        assert_eq!(order.space_tile(&[&first]), vec![0]);
+        assert_eq!(order.space_tile(&[&second]), vec![1]);
+        assert_eq!(order.compare(&[&first], &[&second]), Ordering::Less);
+        Ok(())
+    }
+
+    #[test]
+    fn unordered_is_equal() -> Result<()> {
+        let schema = load_schema()?;
+        let order = CellOrder::new(&schema, Layout::Unordered)?;
+        let lhs = 1i64.to_le_bytes();
+        let rhs = 2i64.to_le_bytes();
+        assert_eq!(order.compare(&[&lhs], &[&rhs]), Ordering::Equal);
+        assert!(CellOrder::new(&schema, Layout::Hilbert).is_err());
+        Ok(())
+    }
+}
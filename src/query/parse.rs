@@ -0,0 +1,639 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+// A textual syntax for query conditions such as
+//
+//   a >= 5 and (b == "x" or c is null) and d not in (1, 2)
+//
+// Keywords are case insensitive and field names that are not plain
+// identifiers can be quoted with backticks. Literals are checked against
+// the type of the field they are compared to.
+
+use anyhow::anyhow;
+
+use crate::array;
+use crate::datatype::{DataType, Primitive};
+use crate::query::QueryCondition;
+use crate::storage::ConditionOp;
+use crate::Result;
+
+// Parse a condition, checking its fields and literals against a schema.
+// Errors report the 1-based column of the offending token.
+pub fn parse_condition(
+    schema: &array::Schema,
+    text: &str,
+) -> Result<QueryCondition> {
+    let tokens = tokenize(text)
+        .map_err(|err| err.context(format!("Condition: {}", text)))?;
+    let mut parser = Parser {
+        schema,
+        tokens,
+        pos: 0,
+    };
+    parser
+        .parse()
+        .map_err(|err| err.context(format!("Condition: {}", text)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    // A backquoted field name, which is never a keyword.
+    Quoted(String),
+    Str(String),
+    Int(i128),
+    Float(f64),
+    Op(ConditionOp),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Ident(s) if s.eq_ignore_ascii_case(keyword))
+    }
+
+    fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Ident(s) => format!("'{}'", s),
+            TokenKind::Quoted(s) => format!("`{}`", s),
+            TokenKind::Str(s) => format!("\"{}\"", s),
+            TokenKind::Int(v) => v.to_string(),
+            TokenKind::Float(v) => v.to_string(),
+            TokenKind::Op(op) => format!("'{}'", op_symbol(*op)),
+            TokenKind::LParen => "'('".to_string(),
+            TokenKind::RParen => "')'".to_string(),
+            TokenKind::Comma => "','".to_string(),
+            TokenKind::End => "end of input".to_string(),
+        }
+    }
+}
+
+fn op_symbol(op: ConditionOp) -> &'static str {
+    match op {
+        ConditionOp::Lt => "<",
+        ConditionOp::Le => "<=",
+        ConditionOp::Gt => ">",
+        ConditionOp::Ge => ">=",
+        ConditionOp::Eq => "==",
+        ConditionOp::Ne => "!=",
+        ConditionOp::In => "in",
+        ConditionOp::NotIn => "not in",
+        ConditionOp::Invalid => "?",
+    }
+}
+
+fn error_at(column: usize, message: String) -> anyhow::Error {
+    anyhow!("{} at column {}", message, column)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let next = chars.get(i + 1).copied();
+        let (kind, len) = match c {
+            '(' => (TokenKind::LParen, 1),
+            ')' => (TokenKind::RParen, 1),
+            ',' => (TokenKind::Comma, 1),
+            '<' if next == Some('=') => (TokenKind::Op(ConditionOp::Le), 2),
+            '<' if next == Some('>') => (TokenKind::Op(ConditionOp::Ne), 2),
+            '<' => (TokenKind::Op(ConditionOp::Lt), 1),
+            '>' if next == Some('=') => (TokenKind::Op(ConditionOp::Ge), 2),
+            '>' => (TokenKind::Op(ConditionOp::Gt), 1),
+            '=' if next == Some('=') => (TokenKind::Op(ConditionOp::Eq), 2),
+            '=' => (TokenKind::Op(ConditionOp::Eq), 1),
+            '!' if next == Some('=') => (TokenKind::Op(ConditionOp::Ne), 2),
+            '"' | '\'' => {
+                let (value, len) = string_literal(&chars[i..], column)?;
+                (TokenKind::Str(value), len)
+            }
+            '`' => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .position(|c| *c == '`')
+                    .ok_or_else(|| {
+                        error_at(column, "Unterminated field name".to_string())
+                    })?;
+                let name: String = chars[i + 1..i + 1 + len].iter().collect();
+                (TokenKind::Quoted(name), len + 2)
+            }
+            c if c.is_ascii_digit()
+                || (matches!(c, '-' | '+' | '.')
+                    && next
+                        .is_some_and(|n| n.is_ascii_digit() || n == '.')) =>
+            {
+                number_literal(&chars[i..], column)?
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| !(c.is_alphanumeric() || *c == '_'))
+                    .unwrap_or(chars.len() - i);
+                let ident: String = chars[i..i + len].iter().collect();
+                (TokenKind::Ident(ident), len)
+            }
+            c => {
+                return Err(error_at(
+                    column,
+                    format!("Unexpected character '{}'", c),
+                ))
+            }
+        };
+        tokens.push(Token { kind, column });
+        i += len;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        column: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+// A string quoted with the character it starts with. Backslash escapes the
+// quote, backslash, n and t.
+fn string_literal(chars: &[char], column: usize) -> Result<(String, usize)> {
+    let quote = chars[0];
+    let mut value = String::new();
+    let mut i = 1;
+    while i < chars.len() {
+        match chars[i] {
+            c if c == quote => return Ok((value, i + 1)),
+            '\\' => {
+                let escaped = chars.get(i + 1).ok_or_else(|| {
+                    error_at(column, "Unterminated string".to_string())
+                })?;
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    '\\' | '"' | '\'' => *escaped,
+                    c => {
+                        return Err(error_at(
+                            column + i,
+                            format!("Invalid escape '\\{}'", c),
+                        ))
+                    }
+                });
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(error_at(column, "Unterminated string".to_string()))
+}
+
+fn number_literal(chars: &[char], column: usize) -> Result<(TokenKind, usize)> {
+    let mut len = 1;
+    let mut prev = chars[0];
+    while let Some(c) = chars.get(len) {
+        let part_of_number = c.is_ascii_alphanumeric()
+            || *c == '.'
+            || (matches!(c, '-' | '+') && matches!(prev, 'e' | 'E'));
+        if !part_of_number {
+            break;
+        }
+        prev = *c;
+        len += 1;
+    }
+
+    let text: String = chars[..len].iter().collect();
+    let is_float = text.contains(['.', 'e', 'E']);
+    let kind = if is_float {
+        text.parse().map(TokenKind::Float).ok()
+    } else {
+        text.parse().map(TokenKind::Int).ok()
+    };
+    let kind = kind.ok_or_else(|| {
+        error_at(column, format!("Invalid number '{}'", text))
+    })?;
+    Ok((kind, len))
+}
+
+struct Parser<'a> {
+    schema: &'a array::Schema,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+// The type of a field as far as literals are concerned.
+struct FieldType {
+    data_type: DataType,
+    var_sized: bool,
+    cell_val_num: u32,
+    nullable: bool,
+}
+
+impl Parser<'_> {
+    fn parse(&mut self) -> Result<QueryCondition> {
+        let ret = self.parse_or()?;
+        let token = self.peek();
+        if token.kind != TokenKind::End {
+            return Err(error_at(
+                token.column,
+                format!("Unexpected {}", token.describe()),
+            ));
+        }
+        Ok(ret)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<()> {
+        let token = self.next();
+        if token.kind != kind {
+            return Err(error_at(
+                token.column,
+                format!("Expected {}, found {}", what, token.describe()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        let token = self.next();
+        if !token.is_keyword(keyword) {
+            return Err(error_at(
+                token.column,
+                format!("Expected '{}', found {}", keyword, token.describe()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<QueryCondition> {
+        let mut ret = self.parse_and()?;
+        while self.accept_keyword("or") {
+            ret = ret.or(self.parse_and()?);
+        }
+        Ok(ret)
+    }
+
+    fn parse_and(&mut self) -> Result<QueryCondition> {
+        let mut ret = self.parse_not()?;
+        while self.accept_keyword("and") {
+            ret = ret.and(self.parse_not()?);
+        }
+        Ok(ret)
+    }
+
+    fn parse_not(&mut self) -> Result<QueryCondition> {
+        if self.accept_keyword("not") {
+            return Ok(self.parse_not()?.negate());
+        }
+
+        if self.peek().kind == TokenKind::LParen {
+            self.pos += 1;
+            let ret = self.parse_or()?;
+            self.expect(TokenKind::RParen, "')'")?;
+            return Ok(ret);
+        }
+
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<QueryCondition> {
+        let token = self.next();
+        let name = match &token.kind {
+            TokenKind::Quoted(name) => name.clone(),
+            TokenKind::Ident(name)
+                if !KEYWORDS.iter().any(|k| name.eq_ignore_ascii_case(k)) =>
+            {
+                name.clone()
+            }
+            _ => {
+                return Err(error_at(
+                    token.column,
+                    format!(
+                        "Expected a field name, found {}",
+                        token.describe()
+                    ),
+                ))
+            }
+        };
+        let field = self.field_type(&name).ok_or_else(|| {
+            error_at(token.column, format!("Unknown field '{}'", name))
+        })?;
+
+        let op_token = self.next();
+        match &op_token.kind {
+            TokenKind::Op(op) => {
+                let value = self.parse_literal(&name, &field)?;
+                Ok(QueryCondition::compare_bytes(&name, *op, &value))
+            }
+            _ if op_token.is_keyword("is") => {
+                let negated = self.accept_keyword("not");
+                self.expect_keyword("null")?;
+                if !field.nullable {
+                    return Err(error_at(
+                        op_token.column,
+                        format!("Field '{}' is not nullable", name),
+                    ));
+                }
+                Ok(if negated {
+                    QueryCondition::is_not_null(&name)
+                } else {
+                    QueryCondition::is_null(&name)
+                })
+            }
+            _ if op_token.is_keyword("in") || op_token.is_keyword("not") => {
+                let negated = op_token.is_keyword("not");
+                if negated {
+                    self.expect_keyword("in")?;
+                }
+                self.expect(TokenKind::LParen, "'('")?;
+                let mut values = vec![self.parse_literal(&name, &field)?];
+                while self.peek().kind == TokenKind::Comma {
+                    self.pos += 1;
+                    values.push(self.parse_literal(&name, &field)?);
+                }
+                self.expect(TokenKind::RParen, "')'")?;
+
+                let values: Vec<&[u8]> =
+                    values.iter().map(|v| &v[..]).collect();
+                Ok(if negated {
+                    QueryCondition::not_in(&name, &values)
+                } else {
+                    QueryCondition::is_in(&name, &values)
+                })
+            }
+            _ => Err(error_at(
+                op_token.column,
+                format!(
+                    "Expected a comparison, IN or IS after '{}', found {}",
+                    name,
+                    op_token.describe()
+                ),
+            )),
+        }
+    }
+
+    fn field_type(&self, name: &str) -> Option<FieldType> {
+        if let Some(dim) = self.schema.domain().dimension(name) {
+            return Some(FieldType {
+                data_type: dim.data_type(),
+                var_sized: dim.is_var_sized(),
+                cell_val_num: dim.cell_val_num(),
+                nullable: false,
+            });
+        }
+        let attr = self.schema.attribute(name)?;
+        Some(FieldType {
+            data_type: attr.data_type(),
+            var_sized: attr.is_var_sized(),
+            cell_val_num: attr.cell_val_num(),
+            nullable: attr.nullable(),
+        })
+    }
+
+    // The bytes of a literal as a cell of the field.
+    fn parse_literal(
+        &mut self,
+        name: &str,
+        field: &FieldType,
+    ) -> Result<Vec<u8>> {
+        let token = self.next();
+        let dtype = field.data_type;
+        let mismatch = || {
+            error_at(
+                token.column,
+                format!(
+                    "Expected a {:?} value for '{}', found {}",
+                    dtype,
+                    name,
+                    token.describe()
+                ),
+            )
+        };
+
+        if token.is_keyword("null") {
+            return Err(error_at(
+                token.column,
+                format!("Use IS NULL to compare '{}' against null", name),
+            ));
+        }
+
+        let is_text = dtype.is_string_type()
+            || matches!(dtype, DataType::Char | DataType::Blob | DataType::Any);
+        if !is_text && field.cell_val_num != 1 {
+            return Err(error_at(
+                token.column,
+                format!(
+                    "Field '{}' holds {} values per cell which can not be \
+                    compared",
+                    name,
+                    if field.var_sized {
+                        "a variable number of".to_string()
+                    } else {
+                        field.cell_val_num.to_string()
+                    }
+                ),
+            ));
+        }
+
+        let value = match (&token.kind, dtype) {
+            (TokenKind::Str(s), _) if is_text => encode_string(dtype, s),
+            (TokenKind::Ident(s), DataType::Bool)
+                if s.eq_ignore_ascii_case("true")
+                    || s.eq_ignore_ascii_case("false") =>
+            {
+                vec![s.eq_ignore_ascii_case("true") as u8]
+            }
+            (TokenKind::Int(v), DataType::Bool) if (0..=1).contains(v) => {
+                vec![*v as u8]
+            }
+            (TokenKind::Int(v), DataType::Float32) => (*v as f32).to_bytes(),
+            (TokenKind::Int(v), DataType::Float64) => (*v as f64).to_bytes(),
+            (TokenKind::Float(v), DataType::Float32) => (*v as f32).to_bytes(),
+            (TokenKind::Float(v), DataType::Float64) => v.to_bytes(),
+            (TokenKind::Int(v), dtype) if dtype.is_integral_type() => {
+                let (lo, hi) = integral_range(dtype);
+                if *v < lo || *v > hi {
+                    return Err(error_at(
+                        token.column,
+                        format!(
+                            "Value {} is out of range for '{}' of type {:?}",
+                            v, name, dtype
+                        ),
+                    ));
+                }
+                dtype.from_i128(*v).ok_or_else(mismatch)?
+            }
+            _ => return Err(mismatch()),
+        };
+
+        // Fixed size strings must match the cell size exactly.
+        if is_text
+            && !field.var_sized
+            && value.len() != dtype.size() * field.cell_val_num as usize
+        {
+            return Err(error_at(
+                token.column,
+                format!(
+                    "Expected {} characters for '{}', found {}",
+                    field.cell_val_num,
+                    name,
+                    value.len() / dtype.size().max(1)
+                ),
+            ));
+        }
+        if is_text && value.is_empty() {
+            return Err(error_at(
+                token.column,
+                format!("Unable to compare '{}' against an empty string", name),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+// Words that can not be used as field names without backticks.
+const KEYWORDS: [&str; 6] = ["and", "or", "not", "in", "is", "null"];
+
+// Strings are stored in the width of the datatype's characters.
+fn encode_string(dtype: DataType, value: &str) -> Vec<u8> {
+    match dtype {
+        DataType::StringUtf16 | DataType::StringUcs2 => {
+            value.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+        }
+        DataType::StringUtf32 | DataType::StringUcs4 => value
+            .chars()
+            .flat_map(|c| (c as u32).to_le_bytes())
+            .collect(),
+        _ => value.as_bytes().to_vec(),
+    }
+}
+
+fn integral_range(dtype: DataType) -> (i128, i128) {
+    match dtype {
+        DataType::Int8 => (i8::MIN as i128, i8::MAX as i128),
+        DataType::Uint8 => (0, u8::MAX as i128),
+        DataType::Int16 => (i16::MIN as i128, i16::MAX as i128),
+        DataType::Uint16 => (0, u16::MAX as i128),
+        DataType::Int32 => (i32::MIN as i128, i32::MAX as i128),
+        DataType::Uint32 => (0, u32::MAX as i128),
+        DataType::Uint64 => (0, u64::MAX as i128),
+        _ => (i64::MIN as i128, i64::MAX as i128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::ArrayType;
+    use crate::storage;
+
+    fn schema() -> Result<array::Schema> {
+        let schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![storage::Dimension::new(
+                "d",
+                DataType::Int64,
+                &array::Range::from_values(0i64, 99),
+            )]),
+            vec![
+                storage::Attribute::new("a", DataType::Uint8),
+                storage::Attribute::new("b", DataType::StringUtf8)
+                    .set_cell_val_num(storage::CELL_VAR_SIZE),
+                storage::Attribute::new("c", DataType::Float32)
+                    .set_nullable(true),
+                storage::Attribute::new("my field", DataType::Bool),
+            ],
+        );
+        array::Schema::try_from(schema)
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        let schema = schema()?;
+        let condition = parse_condition(
+            &schema,
+            "a >= 5 AND (b == \"x\" or c is null) and not d in (1, -2) \
+            and `my field` = true",
+        )?;
+        let expected = QueryCondition::compare("a", ConditionOp::Ge, 5u8)
+            .and(
+                QueryCondition::compare_bytes("b", ConditionOp::Eq, b"x")
+                    .or(QueryCondition::is_null("c")),
+            )
+            .and(
+                QueryCondition::is_in(
+                    "d",
+                    &[&1i64.to_le_bytes(), &(-2i64).to_le_bytes()],
+                )
+                .negate(),
+            )
+            .and(QueryCondition::compare("my field", ConditionOp::Eq, 1u8));
+        assert_eq!(condition, expected);
+
+        let condition = parse_condition(&schema, "c < 1 or c > 2.5e1")?;
+        assert_eq!(
+            condition,
+            QueryCondition::compare("c", ConditionOp::Lt, 1f32)
+                .or(QueryCondition::compare("c", ConditionOp::Gt, 25f32))
+        );
+
+        let errors = [
+            ("a >= 5 and e < 1", "Unknown field 'e' at column 12"),
+            ("a > 256", "out of range for 'a' of type Uint8 at column 5"),
+            ("a == 'x'", "Expected a Uint8 value for 'a'"),
+            (
+                "b > 5",
+                "Expected a StringUtf8 value for 'b', found 5 at column 5",
+            ),
+            ("a is null", "Field 'a' is not nullable at column 3"),
+            ("(a > 1", "Expected ')', found end of input at column 7"),
+            ("a > 1 b", "Unexpected 'b' at column 7"),
+            ("b == \"x", "Unterminated string at column 6"),
+            ("a ~ 1", "Unexpected character '~' at column 3"),
+        ];
+        for (text, message) in errors {
+            let err = parse_condition(&schema, text).unwrap_err();
+            assert!(
+                err.root_cause().to_string().contains(message),
+                "{}: {:#}",
+                text,
+                err
+            );
+        }
+        Ok(())
+    }
+}
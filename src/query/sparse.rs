@@ -0,0 +1,768 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType, Layout};
+use crate::datatype::Primitive;
+use crate::io::service::VFSService;
+use crate::query::{
+    self, CellOrder, QueryBuffer, QueryCondition, ReadStats, StatsCache,
+    Subarray, TileCache,
+};
+use crate::storage;
+use crate::Result;
+
+// A cell of a data tile that satisfies the query's subarray along with the
+// timestamp at which it was written.
+#[derive(Clone, Copy, Debug)]
+struct ResultCell {
+    fragment: usize,
+    tile: usize,
+    cell: usize,
+    timestamp: u64,
+    // Pruned cells are only kept until duplicates have been removed so
+    // that they still shadow older versions of the same cell.
+    pruned: bool,
+}
+
+// Whether every range of an MBR intersects the corresponding query range.
+// Unbounded query ranges match everything.
+fn mbr_intersects(
+    schema: &array::Schema,
+    ranges: &[Option<array::Range>],
+    mbr: &[array::Range],
+) -> bool {
+    schema
+        .domain()
+        .dimensions()
+        .iter()
+        .zip(ranges.iter().zip(mbr))
+        .all(|(dim, (range, mbr))| match range {
+            Some(range) => range.intersects(dim.data_type(), mbr),
+            None => true,
+        })
+}
+
+fn mbr_covered(
+    schema: &array::Schema,
+    ranges: &[Option<array::Range>],
+    mbr: &[array::Range],
+) -> bool {
+    schema
+        .domain()
+        .dimensions()
+        .iter()
+        .zip(ranges.iter().zip(mbr))
+        .all(|(dim, (range, mbr))| match range {
+            Some(range) => range.covers(dim.data_type(), mbr),
+            None => true,
+        })
+}
+
+// Reads the cells of a sparse array that fall within a subarray. Results
+// from every fragment are merged and returned in the requested layout along
+// with their coordinates. When the schema disallows duplicates only the
+// cell from the most recent fragment is kept for each coordinate. Cells
+// removed by delete conditions, or that do not satisfy the query condition,
+// are never returned.
+pub struct SparseReader<'a> {
+    array: &'a array::Array,
+    subarray: Subarray,
+    layout: Layout,
+    attributes: Option<Vec<String>>,
+    condition: Option<QueryCondition>,
+    decode_enumerations: bool,
+    timestamps: bool,
+    stats: Cell<ReadStats>,
+    skip_tiles: HashSet<(usize, usize)>,
+}
+
+impl<'a> SparseReader<'a> {
+    pub fn new(array: &'a array::Array) -> Self {
+        SparseReader {
+            array,
+            subarray: Subarray::default(),
+            layout: Layout::Unordered,
+            attributes: None,
+            condition: None,
+            decode_enumerations: false,
+            timestamps: false,
+            stats: Cell::new(ReadStats::default()),
+            skip_tiles: HashSet::new(),
+        }
+    }
+
+    pub fn set_subarray(mut self, subarray: Subarray) -> Self {
+        self.subarray = subarray;
+        self
+    }
+
+    pub fn set_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn set_attributes(mut self, names: &[&str]) -> Self {
+        self.attributes = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    // Only return cells satisfying the condition. Tiles whose statistics
+    // show that none of their cells can match are skipped.
+    pub fn set_condition(mut self, condition: QueryCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    // Return the labels of enumerated attributes rather than their keys.
+    pub fn set_decode_enumerations(mut self, decode: bool) -> Self {
+        self.decode_enumerations = decode;
+        self
+    }
+
+    // Return the timestamp of each cell in a u64 buffer named __timestamps.
+    // Every version of a cell is returned, even when the schema disallows
+    // duplicates, so that the results can be rewritten without losing the
+    // array's history.
+    pub fn set_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    // The tiles considered and pruned by the last read.
+    pub fn stats(&self) -> ReadStats {
+        self.stats.get()
+    }
+
+    // Omit the cells of the given tiles, for aggregates that answer those
+    // tiles from their statistics.
+    pub(crate) fn set_aggregate_tiles(
+        mut self,
+        skip_tiles: HashSet<(usize, usize)>,
+    ) -> Self {
+        self.skip_tiles = skip_tiles;
+        self
+    }
+
+    // The fragment tiles every cell of which is read, along with the number
+    // of cells in each. Tiles of fragments with delete conditions or cell
+    // timestamps are never full, nor are tiles that may share coordinates
+    // with another fragment when the schema disallows duplicates.
+    pub(crate) fn full_tiles(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<Vec<(usize, usize, u64)>> {
+        let schema = self.array.schema();
+        let ranges = self.subarray.resolve(schema)?;
+        let fragments = self.array.fragments();
+        let neds: Vec<Vec<Option<array::Range>>> = fragments
+            .iter()
+            .map(|f| f.non_empty_domain().iter().cloned().map(Some).collect())
+            .collect();
+
+        let mut ret = Vec::new();
+        if !self.array.delete_conditions().is_empty() {
+            return Ok(ret);
+        }
+        for (fidx, fragment) in fragments.iter().enumerate() {
+            if fragment.non_empty_domain().is_empty()
+                || fragment.has_delete_meta()
+                || fragment.has_timestamps()
+            {
+                continue;
+            }
+
+            let fragment_schema = self.array.fragment_schema(fragment)?;
+            let rtree = fragment.load_rtree(vfs, fragment_schema)?;
+            let tile_num = rtree.leaves().len();
+            for (tile, mbr) in rtree.leaves().iter().enumerate() {
+                if !mbr_covered(schema, &ranges, mbr) {
+                    continue;
+                }
+
+                let shadowed = !schema.allows_dups()
+                    && neds.iter().enumerate().any(|(other, ned)| {
+                        other != fidx
+                            && !ned.is_empty()
+                            && mbr_intersects(schema, ned, mbr)
+                    });
+                if shadowed {
+                    continue;
+                }
+
+                let cell_num = if tile + 1 == tile_num {
+                    fragment.last_tile_cell_num()
+                } else {
+                    fragment_schema.capacity()
+                };
+                ret.push((fidx, tile, cell_num));
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn read(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<HashMap<String, QueryBuffer>> {
+        let schema = self.array.schema();
+        if schema.array_type() != ArrayType::Sparse {
+            return Err(anyhow!("SparseReader requires a sparse array"));
+        }
+
+        let order = CellOrder::new(schema, self.layout)?;
+        let ranges = self.subarray.resolve(schema)?;
+
+        let mut coords = TileCache::default();
+        let condition =
+            self.condition.as_ref().map(|c| c.to_node()).transpose()?;
+        let results =
+            self.collect_cells(vfs, &ranges, condition.as_ref(), &mut coords)?;
+        let mut results = self.apply_deletes(vfs, results, &coords)?;
+
+        let ndim = schema.domain().ndim();
+        let fragments = self.array.fragments();
+        let cell_coords = |r: &ResultCell| -> Vec<&[u8]> {
+            let fragment = &fragments[r.fragment];
+            (0..ndim)
+                .map(|d| {
+                    let field_idx = fragment.dimension_field_idx(d);
+                    coords.get(r.fragment, field_idx, r.tile).value(r.cell)
+                })
+                .collect()
+        };
+
+        if self.dedups() {
+            // Sort duplicates together with the newest cell first and keep
+            // only that first cell.
+            let row_major = CellOrder::new(schema, Layout::RowMajor)?;
+            results.sort_by(|a, b| {
+                row_major
+                    .compare(&cell_coords(a), &cell_coords(b))
+                    .then_with(|| {
+                        (b.timestamp, b.fragment)
+                            .cmp(&(a.timestamp, a.fragment))
+                    })
+            });
+            results.dedup_by(|next, prev| {
+                row_major.compare(&cell_coords(next), &cell_coords(prev))
+                    == Ordering::Equal
+            });
+        }
+
+        if let Some(condition) = &condition {
+            results = self.apply_condition(vfs, condition, results, &coords)?;
+        }
+
+        if !matches!(self.layout, Layout::Unordered) {
+            let mut keyed: Vec<(u64, ResultCell)> = results
+                .into_iter()
+                .map(|r| (order.hilbert_key(&cell_coords(&r)), r))
+                .collect();
+            keyed.sort_by(|(lkey, a), (rkey, b)| {
+                let lhs = (*lkey, &cell_coords(a)[..]);
+                let rhs = (*rkey, &cell_coords(b)[..]);
+                order
+                    .compare_keyed(lhs, rhs)
+                    .then_with(|| a.timestamp.cmp(&b.timestamp))
+            });
+            results = keyed.into_iter().map(|(_, r)| r).collect();
+        }
+
+        let mut ret = HashMap::new();
+        for (d, dim) in schema.domain().dimensions().iter().enumerate() {
+            let mut buffer = QueryBuffer::new(dim.is_var_sized(), false);
+            for result in results.iter() {
+                buffer.push(cell_coords(result)[d], true);
+            }
+            ret.insert(dim.name().to_string(), buffer);
+        }
+
+        let names = match &self.attributes {
+            Some(names) => names.clone(),
+            None => schema
+                .attributes()
+                .iter()
+                .map(|a| a.name().to_string())
+                .collect(),
+        };
+
+        for name in names {
+            let buffer = self.read_attribute(vfs, &name, &results)?;
+            ret.insert(name, buffer);
+        }
+
+        if self.timestamps {
+            let timestamps: Vec<u64> =
+                results.iter().map(|r| r.timestamp).collect();
+            ret.insert(
+                storage::TIMESTAMPS_NAME.to_string(),
+                QueryBuffer::from_values(&timestamps),
+            );
+        }
+
+        if self.decode_enumerations {
+            query::decode_enumerations(vfs, self.array, &mut ret)?;
+        }
+
+        Ok(ret)
+    }
+
+    // Use each fragment's R-tree to find the tiles intersecting the query
+    // and then check the coordinates of every cell in those tiles.
+    fn collect_cells(
+        &self,
+        vfs: &dyn VFSService,
+        ranges: &[Option<array::Range>],
+        condition: Option<&storage::ConditionNode>,
+        coords: &mut TileCache,
+    ) -> Result<Vec<ResultCell>> {
+        let schema = self.array.schema();
+        let dims = schema.domain().dimensions();
+        let visible_range =
+            self.array.timestamp_start()..=self.array.timestamp_end();
+        let mut results = Vec::new();
+        let mut stats = ReadStats::default();
+        let mut tile_stats = StatsCache::default();
+
+        for (fidx, fragment) in self.array.fragments().iter().enumerate() {
+            let ned = fragment.non_empty_domain();
+            if ned.is_empty() || !mbr_intersects(schema, ranges, ned) {
+                continue;
+            }
+
+            let fragment_schema = self.array.fragment_schema(fragment)?;
+            let rtree = fragment.load_rtree(vfs, fragment_schema)?;
+            let fields: Vec<_> = (0..dims.len())
+                .map(|d| fragment.dimension_field(fragment_schema, d))
+                .collect();
+            let timestamps = fragment.timestamps_field(fragment_schema);
+
+            for (tile, mbr) in rtree.leaves().iter().enumerate() {
+                if !mbr_intersects(schema, ranges, mbr)
+                    || self.skip_tiles.contains(&(fidx, tile))
+                {
+                    continue;
+                }
+
+                let pruned = match condition {
+                    Some(condition) => !tile_stats
+                        .may_match(vfs, self.array, condition, fidx, tile)?,
+                    None => false,
+                };
+                stats.add_tile(pruned);
+                if pruned && !self.dedups() {
+                    continue;
+                }
+
+                for field in fields.iter().chain(timestamps.iter()) {
+                    coords.load(vfs, fidx, fragment, field, tile)?;
+                }
+
+                let covered = mbr_covered(schema, ranges, mbr);
+                let cell_num =
+                    coords.get(fidx, fields[0].idx(), tile).cell_num();
+                for cell in 0..cell_num {
+                    let matches = covered
+                        || fields.iter().zip(dims.iter().zip(ranges)).all(
+                            |(field, (dim, range))| {
+                                let value = coords
+                                    .get(fidx, field.idx(), tile)
+                                    .value(cell);
+                                match range {
+                                    Some(range) => {
+                                        range.contains(dim.data_type(), value)
+                                    }
+                                    None => true,
+                                }
+                            },
+                        );
+
+                    // Cells of consolidated fragments that store their
+                    // timestamps are only visible within the array's
+                    // timestamp range.
+                    let (timestamp, visible) = match &timestamps {
+                        Some(field) => {
+                            let tile = coords.get(fidx, field.idx(), tile);
+                            let ts = u64::from_bytes(tile.value(cell));
+                            (ts, visible_range.contains(&ts))
+                        }
+                        None => (fragment.timestamp_range().0, true),
+                    };
+
+                    if matches && visible {
+                        results.push(ResultCell {
+                            fragment: fidx,
+                            tile,
+                            cell,
+                            timestamp,
+                            pruned,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.stats.set(stats);
+        Ok(results)
+    }
+
+    // Whether only the newest version of each cell is returned.
+    fn dedups(&self) -> bool {
+        !self.array.schema().allows_dups() && !self.timestamps
+    }
+
+    // Keep the cells satisfying the query condition.
+    fn apply_condition(
+        &self,
+        vfs: &dyn VFSService,
+        condition: &storage::ConditionNode,
+        results: Vec<ResultCell>,
+        coords: &TileCache,
+    ) -> Result<Vec<ResultCell>> {
+        let condition = query::CompiledCondition::new(condition)?;
+        let fields = condition.fields();
+        let mut cache = TileCache::default();
+        let mut ret = Vec::with_capacity(results.len());
+        for result in results.into_iter().filter(|r| !r.pruned) {
+            let mut lookup = |field: usize| -> Result<query::CellValue> {
+                self.cell_value(
+                    vfs,
+                    &mut cache,
+                    coords,
+                    &result,
+                    &fields[field],
+                )
+            };
+            if condition.evaluate(&mut lookup)? {
+                ret.push(result);
+            }
+        }
+        Ok(ret)
+    }
+
+    // Remove cells that were deleted, either by a delete condition newer
+    // than the cell or by a condition that was processed when the fragment
+    // was written and recorded in its delete timestamps.
+    fn apply_deletes(
+        &self,
+        vfs: &dyn VFSService,
+        results: Vec<ResultCell>,
+        coords: &TileCache,
+    ) -> Result<Vec<ResultCell>> {
+        let conditions = self.array.delete_conditions();
+        let fragments = self.array.fragments();
+        let timestamp_end = self.array.timestamp_end();
+
+        if conditions.is_empty()
+            && !fragments.iter().any(|f| f.has_delete_meta())
+        {
+            return Ok(results);
+        }
+
+        let mut processed = Vec::new();
+        for fragment in fragments {
+            let names: HashSet<String> = fragment
+                .load_processed_conditions(vfs)?
+                .into_iter()
+                .collect();
+            processed.push(names);
+        }
+        let compiled = conditions
+            .iter()
+            .map(|c| query::CompiledCondition::new(c.condition()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut cache = TileCache::default();
+        let mut ret = Vec::with_capacity(results.len());
+        for result in results {
+            let fragment = &fragments[result.fragment];
+            let schema = self.array.fragment_schema(fragment)?;
+
+            let mut load_u64 = |field: Option<storage::Field>| -> Result<_> {
+                let Some(field) = field else {
+                    return Ok(None);
+                };
+                let tile = cache.load(
+                    vfs,
+                    result.fragment,
+                    fragment,
+                    &field,
+                    result.tile,
+                )?;
+                Ok(Some(u64::from_bytes(tile.value(result.cell))))
+            };
+
+            let deleted_at =
+                load_u64(fragment.delete_timestamps_field(schema))?;
+            // Cells that were not deleted store a sentinel rather than a
+            // timestamp.
+            if deleted_at.is_some_and(|ts| {
+                ts != 0 && ts != u64::MAX && ts <= timestamp_end
+            }) {
+                continue;
+            }
+
+            let mut deleted = false;
+            for (condition, compiled) in conditions.iter().zip(&compiled) {
+                if condition.timestamp() < result.timestamp
+                    || processed[result.fragment].contains(condition.name())
+                {
+                    continue;
+                }
+
+                let fields = compiled.fields();
+                let mut lookup = |field: usize| -> Result<query::CellValue> {
+                    let name = &fields[field];
+                    self.cell_value(vfs, &mut cache, coords, &result, name)
+                };
+                if compiled.evaluate(&mut lookup)? {
+                    deleted = true;
+                    break;
+                }
+            }
+
+            if !deleted {
+                ret.push(result);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // The value of a dimension or attribute for a single result cell.
+    fn cell_value(
+        &self,
+        vfs: &dyn VFSService,
+        cache: &mut TileCache,
+        coords: &TileCache,
+        result: &ResultCell,
+        name: &str,
+    ) -> Result<query::CellValue> {
+        let fragment = &self.array.fragments()[result.fragment];
+        let schema = self.array.fragment_schema(fragment)?;
+
+        if let Some(d) = schema.domain().dimension_idx(name) {
+            let dim = &schema.domain().dimensions()[d];
+            let field_idx = fragment.dimension_field_idx(d);
+            let tile = coords.get(result.fragment, field_idx, result.tile);
+            return Ok((
+                dim.data_type(),
+                Some(tile.value(result.cell).to_vec()),
+            ));
+        }
+
+        query::attribute_value(
+            vfs,
+            self.array,
+            cache,
+            result.fragment,
+            result.tile,
+            result.cell,
+            name,
+        )
+    }
+
+    fn read_attribute(
+        &self,
+        vfs: &dyn VFSService,
+        name: &str,
+        results: &[ResultCell],
+    ) -> Result<QueryBuffer> {
+        let attr = self
+            .array
+            .schema()
+            .attribute(name)
+            .ok_or_else(|| anyhow!("Unknown attribute '{}'", name))?;
+        let fill_value = attr.fill_value();
+        let fill_validity = attr.fill_value_validity();
+
+        let mut buffer = QueryBuffer::new(attr.is_var_sized(), attr.nullable());
+        let mut cache = TileCache::default();
+
+        let fragments = self.array.fragments();
+        for result in results {
+            let fragment = &fragments[result.fragment];
+            let fragment_schema = self.array.fragment_schema(fragment)?;
+
+            let field = match query::fragment_attribute_field(
+                fragment,
+                fragment_schema,
+                attr,
+            ) {
+                Some(field) => field,
+                None => {
+                    buffer.push(&fill_value, fill_validity);
+                    continue;
+                }
+            };
+
+            let tile = cache.load(
+                vfs,
+                result.fragment,
+                fragment,
+                &field,
+                result.tile,
+            )?;
+            buffer.push(tile.value(result.cell), tile.is_valid(result.cell));
+        }
+
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::io::{uri, PosixVFSService};
+    use crate::query::SparseWriter;
+    use crate::storage::ConditionOp;
+
+    fn fixture(name: &str) -> Result<uri::URI> {
+        let path = format!(
+            "{}/resources/arrays/v{}/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            storage::CURRENT_FORMAT_VERSION,
+            name
+        );
+        uri::URI::from_string(&path)
+    }
+
+    #[test]
+    fn merge_fragments() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        for allows_dups in [false, true] {
+            let schema = fixtures::sparse_schema().set_allows_dups(allows_dups);
+            let (_dir, array) = fixtures::temp_array(&vfs, "sparse", &schema)?;
+
+            // The newer fragment is written first so that the merge has to
+            // go by timestamp rather than by the order of the writes.
+            let t = storage::current_timestamp();
+            let writes = [
+                (t + 2, vec![2i64, 4], vec![21i32, 40]),
+                (t + 1, vec![3, 2, 1], vec![30, 20, 10]),
+            ];
+            for (ts, x, a) in writes {
+                SparseWriter::new(
+                    array.uri(),
+                    array.schema_name(),
+                    array.schema(),
+                )
+                .set_buffer("x", QueryBuffer::from_values(&x))
+                .set_buffer("a", QueryBuffer::from_values(&a))
+                .set_timestamp(ts)
+                .write(&vfs)?;
+            }
+
+            let array = array::Array::open(&vfs, array.uri())?;
+            let results = SparseReader::new(&array)
+                .set_layout(Layout::RowMajor)
+                .read(&vfs)?;
+            if allows_dups {
+                // Every version is kept, oldest first.
+                assert_eq!(results["x"].values::<i64>(), vec![1, 2, 2, 3, 4]);
+                assert_eq!(
+                    results["a"].values::<i32>(),
+                    vec![10, 20, 21, 30, 40]
+                );
+            } else {
+                assert_eq!(results["x"].values::<i64>(), vec![1, 2, 3, 4]);
+                assert_eq!(results["a"].values::<i32>(), vec![10, 21, 30, 40]);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn apply_deletes() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) =
+            fixtures::temp_array(&vfs, "deletes", &fixtures::sparse_schema())?;
+        let uri = array.uri().clone();
+        let write = |x: &[i64], a: &[i32], ts, processed: &[&str]| {
+            SparseWriter::new(&uri, array.schema_name(), array.schema())
+                .set_buffer("x", QueryBuffer::from_values(x))
+                .set_buffer("a", QueryBuffer::from_values(a))
+                .set_timestamp(ts)
+                .set_processed_conditions(processed)
+                .write(&vfs)
+        };
+
+        // Delete every cell with a of at least 20 written up to t + 2.
+        let t = storage::current_timestamp();
+        write(&[1, 2, 3], &[10, 20, 30], t + 1, &[])?;
+        let condition = QueryCondition::compare("a", ConditionOp::Ge, 20i32);
+        let commit = array::DeleteCondition::create(
+            &vfs,
+            &uri,
+            &condition.to_node()?,
+            t + 2,
+        )?;
+        let name = commit.last_path_part();
+
+        // A later write survives, as does a fragment that already
+        // processed the condition.
+        write(&[4], &[40], t + 3, &[])?;
+        write(&[5], &[50], t + 1, &[&name])?;
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.delete_conditions().len(), 1);
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .read(&vfs)?;
+        assert_eq!(results["x"].values::<i64>(), vec![1, 4, 5]);
+        assert_eq!(results["a"].values::<i32>(), vec![10, 40, 50]);
+
+        // Reads before the delete still see every cell written by then.
+        let array = array::Array::open_at(&vfs, &uri, 0, t + 1)?;
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .read(&vfs)?;
+        assert_eq!(results["x"].values::<i64>(), vec![1, 2, 3, 5]);
+        Ok(())
+    }
+
+    #[test]
+    fn prune_tiles() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let array = array::Array::open(&vfs, &fixture("sparse")?)?;
+
+        // The fixture holds 1, 2 in its first tile and 3, 4 in its second.
+        let fragment = &array.fragments()[0];
+        let field = fragment.attribute_field(array.schema(), "int32").unwrap();
+        let stats = fragment.load_tile_stats(&vfs, &field)?;
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].min::<i32>(), Some(1));
+        assert_eq!(stats[1].max::<i32>(), Some(4));
+        assert_eq!(stats[1].sum(), Some(storage::TileSum::Signed(7)));
+        assert_eq!(stats[1].null_count(), None);
+
+        let reader = SparseReader::new(&array).set_condition(
+            QueryCondition::compare("int32", ConditionOp::Gt, 2i32),
+        );
+        let results = reader.read(&vfs)?;
+        assert_eq!(results["int32"].values::<i32>(), vec![3, 4]);
+        assert_eq!(reader.stats().tiles(), 2);
+        assert_eq!(reader.stats().tiles_pruned(), 1);
+
+        // Strings are pruned using their var sized min and max.
+        let reader = SparseReader::new(&array)
+            .set_layout(Layout::RowMajor)
+            .set_condition(QueryCondition::compare_bytes(
+                "stringascii",
+                ConditionOp::Le,
+                b"v1",
+            ));
+        let results = reader.read(&vfs)?;
+        assert_eq!(results["x"].values::<i64>(), vec![1, 8]);
+        assert_eq!(reader.stats().tiles_pruned(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+
+use crate::array;
+use crate::datatype::Primitive;
+use crate::io::service::VFSService;
+use crate::Result;
+
+// The region of an array to read, with at most one range per dimension.
+// Dimensions without a range cover their entire domain.
+#[derive(Clone, Debug, Default)]
+pub struct Subarray {
+    ranges: Vec<Option<array::Range>>,
+}
+
+impl Subarray {
+    pub fn new() -> Self {
+        Subarray::default()
+    }
+
+    pub fn set_range(mut self, dim_idx: usize, range: array::Range) -> Self {
+        if self.ranges.len() <= dim_idx {
+            self.ranges.resize(dim_idx + 1, None);
+        }
+        self.ranges[dim_idx] = Some(range);
+        self
+    }
+
+    pub fn set_typed_range<T: Primitive>(
+        self,
+        dim_idx: usize,
+        start: T,
+        end: T,
+    ) -> Self {
+        self.set_range(dim_idx, array::Range::from_values(start, end))
+    }
+
+    // Set the range of a labelled dimension to the indexes whose labels lie
+    // within a range of label values.
+    pub fn set_label_range(
+        self,
+        vfs: &dyn VFSService,
+        label: &array::LabelArray,
+        range: &array::Range,
+    ) -> Result<Self> {
+        let indexes = label.index_range(vfs, range)?.ok_or_else(|| {
+            anyhow!("No labels of '{}' lie within the range", label.name())
+        })?;
+        Ok(self.set_range(label.dimension_idx(), indexes))
+    }
+
+    pub fn range(&self, dim_idx: usize) -> Option<&array::Range> {
+        self.ranges.get(dim_idx).and_then(|r| r.as_ref())
+    }
+
+    // Validate the ranges against a schema and fill in defaults from the
+    // domain. Var sized dimensions have no domain so an unset range on one
+    // of them stays None, meaning unbounded.
+    pub fn resolve(
+        &self,
+        schema: &array::Schema,
+    ) -> Result<Vec<Option<array::Range>>> {
+        let dims = schema.domain().dimensions();
+        if self.ranges.len() > dims.len() {
+            return Err(anyhow!(
+                "Subarray has {} ranges but the domain has {} dimensions",
+                self.ranges.len(),
+                dims.len()
+            ));
+        }
+
+        let mut ret = Vec::new();
+        for (idx, dim) in dims.iter().enumerate() {
+            let domain = dim
+                .range()
+                .map(|(lo, hi)| array::Range::new(lo.to_vec(), hi.to_vec()));
+
+            let range = match self.range(idx) {
+                Some(range) => range,
+                None => {
+                    ret.push(domain);
+                    continue;
+                }
+            };
+
+            let dtype = dim.data_type();
+            if !dim.is_var_sized()
+                && (range.start().len() != dim.coord_size()
+                    || range.end().len() != dim.coord_size())
+            {
+                return Err(anyhow!(
+                    "Invalid range size for dimension '{}' of type {:?}",
+                    dim.name(),
+                    dtype
+                ));
+            }
+
+            if dtype.compare(range.start(), range.end()) == Ordering::Greater {
+                return Err(anyhow!(
+                    "Invalid range for dimension '{}': start is after end",
+                    dim.name()
+                ));
+            }
+
+            if let Some(domain) = domain {
+                if !domain.covers(dtype, range) {
+                    return Err(anyhow!(
+                        "Range for dimension '{}' is outside of the domain",
+                        dim.name()
+                    ));
+                }
+            }
+
+            ret.push(Some(range.clone()));
+        }
+
+        Ok(ret)
+    }
+}
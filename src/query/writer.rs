@@ -0,0 +1,791 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::array::{self, ArrayType, Layout};
+use crate::datatype::Primitive;
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::query::{
+    for_each_coord, integral_bounds, linear_index, CellOrder, DenseTiling,
+    QueryBuffer, Subarray,
+};
+use crate::storage;
+use crate::Result;
+
+// Writes a new dense fragment covering a subarray of whole space tiles.
+// Buffers hold one cell per point of the subarray in the writer's layout.
+pub struct DenseWriter<'a> {
+    array_uri: uri::URI,
+    schema_name: String,
+    schema: &'a array::Schema,
+    subarray: Subarray,
+    layout: Layout,
+    buffers: HashMap<String, QueryBuffer>,
+    timestamp_range: Option<(u64, u64)>,
+    non_empty_domain: Option<Vec<array::Range>>,
+    format_version: u32,
+    uuid: Option<String>,
+}
+
+impl<'a> DenseWriter<'a> {
+    pub fn new(
+        array_uri: &uri::URI,
+        schema_name: &str,
+        schema: &'a array::Schema,
+    ) -> Self {
+        DenseWriter {
+            array_uri: array_uri.clone(),
+            schema_name: schema_name.to_string(),
+            schema,
+            subarray: Subarray::default(),
+            layout: Layout::RowMajor,
+            buffers: HashMap::new(),
+            timestamp_range: None,
+            non_empty_domain: None,
+            format_version: storage::CURRENT_FORMAT_VERSION,
+            uuid: None,
+        }
+    }
+
+    pub fn set_subarray(mut self, subarray: Subarray) -> Self {
+        self.subarray = subarray;
+        self
+    }
+
+    pub fn set_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn set_buffer(mut self, name: &str, buffer: QueryBuffer) -> Self {
+        self.buffers.insert(name.to_string(), buffer);
+        self
+    }
+
+    // Defaults to the current time.
+    pub fn set_timestamp(self, timestamp: u64) -> Self {
+        self.set_timestamp_range(timestamp, timestamp)
+    }
+
+    // Fragments covering a range of timestamps are written by
+    // consolidation.
+    pub fn set_timestamp_range(mut self, start: u64, end: u64) -> Self {
+        self.timestamp_range = Some((start, end));
+        self
+    }
+
+    // Record a non-empty domain smaller than the subarray, which must still
+    // cover the same space tiles. Readers ignore the cells outside of it.
+    // This preserves fragments written by unaligned dense writes when they
+    // are rewritten.
+    pub fn set_non_empty_domain(mut self, ranges: Vec<array::Range>) -> Self {
+        self.non_empty_domain = Some(ranges);
+        self
+    }
+
+    // Write the fragment in an older format version, which is mostly
+    // useful for testing.
+    pub fn set_format_version(mut self, version: u32) -> Self {
+        self.format_version = version;
+        self
+    }
+
+    // Name the fragment with a fixed rather than a random UUID of 32 hex
+    // digits so that its files are reproducible.
+    pub fn set_uuid(mut self, uuid: &str) -> Self {
+        self.uuid = Some(uuid.to_string());
+        self
+    }
+
+    // Write and commit the fragment, returning its URI.
+    pub fn write(&self, vfs: &dyn VFSService) -> Result<uri::URI> {
+        let schema = self.schema;
+        if schema.array_type() != ArrayType::Dense {
+            return Err(anyhow!("DenseWriter requires a dense array"));
+        }
+
+        if !matches!(self.layout, Layout::RowMajor | Layout::ColMajor) {
+            return Err(anyhow!(
+                "Unsupported layout {:?} for dense writes",
+                self.layout
+            ));
+        }
+
+        let ranges = self
+            .subarray
+            .resolve(schema)?
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("Dense arrays require a bounded domain"))?;
+        let bounds = integral_bounds(schema, &ranges)?;
+        let tiling = DenseTiling::new(schema, &bounds)?;
+        if !tiling.is_aligned(&bounds) {
+            return Err(anyhow!(
+                "Dense writes must cover whole space tiles: {:?}",
+                bounds
+            ));
+        }
+
+        let non_empty_domain = match &self.non_empty_domain {
+            Some(ned) => {
+                let ned_bounds = integral_bounds(schema, ned)?;
+                if tiling.tile_range(&ned_bounds) != tiling.tile_range(&bounds)
+                {
+                    return Err(anyhow!(
+                        "Non-empty domain {:?} does not cover the space tiles \
+                        of the subarray {:?}",
+                        ned_bounds,
+                        bounds
+                    ));
+                }
+                ned.clone()
+            }
+            None => ranges,
+        };
+
+        let shape: Vec<i128> =
+            bounds.iter().map(|(lo, hi)| hi - lo + 1).collect();
+        let cell_num = shape.iter().product::<i128>() as usize;
+        let fields: Vec<_> = (0..schema.attributes().len())
+            .map(|idx| storage::Field::attribute(schema, idx))
+            .collect();
+        check_buffers(&self.buffers, &fields, cell_num)?;
+
+        // Tiles are stored in tile order with their cells in cell order.
+        let mut tiles = Vec::new();
+        for_each_coord(&tiling.tile_range(&bounds), &mut |tile| {
+            tiles.push((tiling.tile_idx(tile), tile.to_vec()));
+        });
+        tiles.sort();
+
+        let name = fragment_name(
+            self.timestamp_range,
+            self.uuid.as_deref(),
+            self.format_version,
+        );
+        let mut writer = storage::FragmentWriter::new(
+            vfs,
+            &self.array_uri,
+            &self.schema_name,
+            schema,
+            &name,
+            true,
+            self.format_version,
+        )?;
+        writer.set_non_empty_domain(non_empty_domain);
+
+        let mut positions = vec![0; tiling.cell_num_per_tile() as usize];
+        for (_, tile) in tiles {
+            for_each_coord(&tiling.tile_bounds(&tile), &mut |coord| {
+                let pos: Vec<i128> = coord
+                    .iter()
+                    .zip(bounds.iter())
+                    .map(|(c, (lo, _))| c - lo)
+                    .collect();
+                positions[tiling.cell_idx(coord) as usize] =
+                    linear_index(&pos, &shape, self.layout) as usize;
+            });
+
+            for field in fields.iter() {
+                let buffer = &self.buffers[field.name()];
+                writer.write_tile(
+                    vfs,
+                    field,
+                    &gather(field, buffer, &positions),
+                )?;
+            }
+        }
+
+        writer.finish(vfs)
+    }
+}
+
+// Writes a new sparse fragment from unordered cells. Cells are sorted into
+// the schema's global order and split into data tiles of at most the
+// schema's capacity, each recorded in the fragment's R-tree by its MBR.
+// An optional buffer of u64 cell timestamps named __timestamps is stored
+// with the cells, in which case a coordinate may repeat at different
+// timestamps even when the schema disallows duplicates.
+pub struct SparseWriter<'a> {
+    array_uri: uri::URI,
+    schema_name: String,
+    schema: &'a array::Schema,
+    buffers: HashMap<String, QueryBuffer>,
+    timestamp_range: Option<(u64, u64)>,
+    format_version: u32,
+    processed_conditions: Vec<String>,
+    uuid: Option<String>,
+}
+
+impl<'a> SparseWriter<'a> {
+    pub fn new(
+        array_uri: &uri::URI,
+        schema_name: &str,
+        schema: &'a array::Schema,
+    ) -> Self {
+        SparseWriter {
+            array_uri: array_uri.clone(),
+            schema_name: schema_name.to_string(),
+            schema,
+            buffers: HashMap::new(),
+            timestamp_range: None,
+            format_version: storage::CURRENT_FORMAT_VERSION,
+            processed_conditions: Vec::new(),
+            uuid: None,
+        }
+    }
+
+    // Buffers are required for every dimension and attribute.
+    // Timestamps are optional.
+    pub fn set_buffer(mut self, name: &str, buffer: QueryBuffer) -> Self {
+        self.buffers.insert(name.to_string(), buffer);
+        self
+    }
+
+    // Defaults to the current time.
+    pub fn set_timestamp(self, timestamp: u64) -> Self {
+        self.set_timestamp_range(timestamp, timestamp)
+    }
+
+    // Fragments covering a range of timestamps are written by
+    // consolidation.
+    pub fn set_timestamp_range(mut self, start: u64, end: u64) -> Self {
+        self.timestamp_range = Some((start, end));
+        self
+    }
+
+    // Write the fragment in an older format version, which is mostly
+    // useful for testing.
+    pub fn set_format_version(mut self, version: u32) -> Self {
+        self.format_version = version;
+        self
+    }
+
+    // Name the fragment with a fixed rather than a random UUID of 32 hex
+    // digits so that its files are reproducible.
+    pub fn set_uuid(mut self, uuid: &str) -> Self {
+        self.uuid = Some(uuid.to_string());
+        self
+    }
+
+    // Record delete commits whose conditions the buffers already reflect,
+    // as consolidation does, so that readers do not apply them again.
+    pub fn set_processed_conditions(mut self, names: &[&str]) -> Self {
+        self.processed_conditions =
+            names.iter().map(|n| n.to_string()).collect();
+        self
+    }
+
+    // Write and commit the fragment, returning its URI.
+    pub fn write(&self, vfs: &dyn VFSService) -> Result<uri::URI> {
+        let schema = self.schema;
+        if schema.array_type() != ArrayType::Sparse {
+            return Err(anyhow!("SparseWriter requires a sparse array"));
+        }
+
+        let dims = schema.domain().dimensions();
+        let has_timestamps =
+            self.buffers.contains_key(storage::TIMESTAMPS_NAME);
+        let fields: Vec<_> = (0..dims.len())
+            .map(|d| storage::Field::dimension(schema, d))
+            .chain(
+                (0..schema.attributes().len())
+                    .map(|idx| storage::Field::attribute(schema, idx)),
+            )
+            .chain(has_timestamps.then(|| storage::Field::timestamps(schema)))
+            .collect();
+
+        let cell_num = dims
+            .first()
+            .and_then(|d| self.buffers.get(d.name()))
+            .map_or(0, |b| b.cell_num());
+        check_buffers(&self.buffers, &fields, cell_num)?;
+        if cell_num == 0 {
+            return Err(anyhow!("Sparse writes require at least one cell"));
+        }
+
+        let coords = |cell: usize| -> Vec<&[u8]> {
+            dims.iter()
+                .map(|d| self.buffers[d.name()].value(cell).unwrap_or_default())
+                .collect()
+        };
+
+        for (dim, field) in dims.iter().zip(fields.iter()) {
+            let Some((lo, hi)) = dim.range() else {
+                continue;
+            };
+            let domain = array::Range::new(lo.to_vec(), hi.to_vec());
+            let buffer = &self.buffers[field.name()];
+            if let Some(cell) = (0..cell_num).find(|c| {
+                !domain.contains(
+                    dim.data_type(),
+                    buffer.value(*c).unwrap_or_default(),
+                )
+            }) {
+                return Err(anyhow!(
+                    "Coordinate {} of dimension '{}' is outside of the domain",
+                    cell,
+                    dim.name()
+                ));
+            }
+        }
+
+        // Versions of the same cell are ordered from oldest to newest.
+        let order = CellOrder::new(schema, Layout::GlobalOrder)?;
+        let timestamp = |cell: usize| -> Option<&[u8]> {
+            self.buffers.get(storage::TIMESTAMPS_NAME)?.value(cell)
+        };
+        let keys: Vec<u64> = (0..cell_num)
+            .map(|c| order.hilbert_key(&coords(c)))
+            .collect();
+        let compare = |a: usize, b: usize| {
+            let lhs = (keys[a], &coords(a)[..]);
+            let rhs = (keys[b], &coords(b)[..]);
+            order.compare_keyed(lhs, rhs).then_with(|| {
+                let ts = |c| timestamp(c).map(u64::from_bytes);
+                ts(a).cmp(&ts(b))
+            })
+        };
+
+        let mut cells: Vec<usize> = (0..cell_num).collect();
+        cells.sort_by(|a, b| compare(*a, *b));
+
+        if !schema.allows_dups() {
+            if let Some(pair) = cells
+                .windows(2)
+                .find(|pair| compare(pair[0], pair[1]) == Ordering::Equal)
+            {
+                return Err(anyhow!(
+                    "Duplicate coordinates at cells {} and {} in an array \
+                    that does not allow duplicates",
+                    pair[0],
+                    pair[1]
+                ));
+            }
+        }
+
+        let name = fragment_name(
+            self.timestamp_range,
+            self.uuid.as_deref(),
+            self.format_version,
+        );
+        let mut writer = storage::FragmentWriter::new(
+            vfs,
+            &self.array_uri,
+            &self.schema_name,
+            schema,
+            &name,
+            false,
+            self.format_version,
+        )?;
+        writer.set_timestamps(has_timestamps);
+        writer.set_processed_conditions(self.processed_conditions.clone());
+
+        let dtypes: Vec<_> = dims.iter().map(|d| d.data_type()).collect();
+        let mut mbrs = Vec::new();
+        for tile in cells.chunks(schema.capacity().max(1) as usize) {
+            let mbr: storage::Mbr = tile
+                .iter()
+                .map(|cell| {
+                    coords(*cell)
+                        .into_iter()
+                        .map(|c| array::Range::new(c.to_vec(), c.to_vec()))
+                        .collect()
+                })
+                .reduce(|lhs: storage::Mbr, rhs| {
+                    storage::mbr_union(&dtypes, &[lhs, rhs])
+                })
+                .unwrap_or_default();
+            writer.add_mbr(mbr.clone());
+            mbrs.push(mbr);
+
+            for field in fields.iter() {
+                let buffer = &self.buffers[field.name()];
+                writer.write_tile(vfs, field, &gather(field, buffer, tile))?;
+            }
+        }
+
+        writer.set_non_empty_domain(storage::mbr_union(&dtypes, &mbrs));
+        writer.finish(vfs)
+    }
+}
+
+// The name of a new fragment. Fragments are written at the current time
+// unless a timestamp is given, and get a random UUID unless one is given.
+fn fragment_name(
+    timestamp_range: Option<(u64, u64)>,
+    uuid: Option<&str>,
+    version: u32,
+) -> String {
+    let (start, end) = timestamp_range.unwrap_or_else(|| {
+        let now = storage::current_timestamp();
+        (now, now)
+    });
+    match uuid {
+        Some(uuid) => storage::uuid_name(start, end, uuid, version),
+        None => storage::versioned_name(start, end, version),
+    }
+}
+
+// Every buffer must belong to one of the fields and every field needs a
+// buffer with a matching number of cells, var size and nullability.
+fn check_buffers(
+    buffers: &HashMap<String, QueryBuffer>,
+    fields: &[storage::Field],
+    cell_num: usize,
+) -> Result<()> {
+    if let Some(name) = buffers
+        .keys()
+        .find(|name| !fields.iter().any(|f| f.name() == *name))
+    {
+        return Err(anyhow!("Unknown field '{}'", name));
+    }
+
+    for field in fields {
+        let buffer = buffers.get(field.name()).ok_or_else(|| {
+            anyhow!("Missing buffer for field '{}'", field.name())
+        })?;
+
+        if buffer.cell_num() != cell_num {
+            return Err(anyhow!(
+                "Buffer for field '{}' has {} cells, expected {}",
+                field.name(),
+                buffer.cell_num(),
+                cell_num
+            ));
+        }
+
+        if buffer.offsets().is_some() != field.is_var_sized()
+            || buffer.validity().is_some() != field.nullable()
+        {
+            return Err(anyhow!(
+                "Buffer for field '{}' does not match its var size or \
+                nullability",
+                field.name()
+            ));
+        }
+
+        if !field.is_var_sized()
+            && buffer.data().len() != cell_num * field.cell_size()
+        {
+            return Err(anyhow!(
+                "Buffer for field '{}' has {} bytes, expected {}",
+                field.name(),
+                buffer.data().len(),
+                cell_num * field.cell_size()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Copy the cells at the given buffer positions into a tile.
+fn gather(
+    field: &storage::Field,
+    buffer: &QueryBuffer,
+    positions: &[usize],
+) -> storage::Tile {
+    let mut data = Vec::new();
+    let mut offsets = field.is_var_sized().then(Vec::new);
+    let mut validity = field.nullable().then(Vec::new);
+    for pos in positions {
+        if let Some(offsets) = offsets.as_mut() {
+            offsets.push(data.len() as u64);
+        }
+        data.extend_from_slice(buffer.value(*pos).unwrap_or_default());
+        if let Some(validity) = validity.as_mut() {
+            validity.push(buffer.is_valid(*pos) as u8);
+        }
+    }
+    storage::Tile::new(data, field.cell_size(), offsets, validity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::DataType;
+    use crate::filters::FilterType;
+    use crate::fixtures;
+    use crate::io::PosixVFSService;
+    use crate::query::{DenseReader, SparseReader};
+
+    fn create_dense_array(
+        vfs: &dyn VFSService,
+    ) -> Result<(fixtures::TempDir, array::Array)> {
+        let domain = array::Range::from_values(1i32, 4);
+        let zstd = storage::FilterList::new(
+            storage::DEFAULT_MAX_CHUNK_SIZE,
+            vec![storage::Filter::compression(FilterType::Zstd, 3)],
+        );
+        let schema = storage::ArraySchema::new(
+            ArrayType::Dense,
+            storage::Domain::new(vec![
+                storage::Dimension::new("rows", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+                storage::Dimension::new("cols", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+            ]),
+            vec![
+                storage::Attribute::new("a", DataType::Int32).set_filters(zstd),
+                storage::Attribute::new("s", DataType::StringUtf8)
+                    .set_cell_val_num(storage::CELL_VAR_SIZE),
+                storage::Attribute::new("n", DataType::Float64)
+                    .set_nullable(true),
+            ],
+        )
+        .set_cell_order(Layout::ColMajor);
+        fixtures::temp_array(vfs, "writer", &schema)
+    }
+
+    #[test]
+    fn write_and_read() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let (_dir, array) = create_dense_array(&vfs)?;
+        let uri = array.uri().clone();
+
+        let mut s = QueryBuffer::new(true, false);
+        let mut n = QueryBuffer::new(false, true);
+        for i in 0..16 {
+            s.push("x".repeat(i % 3).as_bytes(), true);
+            n.push(&(i as f64).to_le_bytes(), i % 4 != 0);
+        }
+        let values: Vec<i32> = (0..16).collect();
+        DenseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_buffer("a", QueryBuffer::from_values(&values))
+            .set_buffer("s", s)
+            .set_buffer("n", n)
+            .set_timestamp(1)
+            .write(&vfs)?;
+
+        // Overwrite the bottom left tile in a later fragment.
+        let mut s = QueryBuffer::new(true, false);
+        let mut n = QueryBuffer::new(false, true);
+        for _ in 0..4 {
+            s.push(b"new", true);
+            n.push(&0f64.to_le_bytes(), false);
+        }
+        let subarray = Subarray::new()
+            .set_typed_range(0, 3i32, 4)
+            .set_typed_range(1, 1i32, 2);
+        DenseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_subarray(subarray.clone())
+            .set_buffer("a", QueryBuffer::from_values(&[-1i32; 4]))
+            .set_buffer("s", s)
+            .set_buffer("n", n)
+            .set_timestamp(2)
+            .write(&vfs)?;
+
+        let array = array::Array::open(&vfs, &uri)?;
+        assert_eq!(array.fragments().len(), 2);
+        assert!(array.fragments()[1].is_dense());
+        assert_eq!(
+            array.fragments()[1].non_empty_domain(),
+            &[
+                array::Range::from_values(3i32, 4),
+                array::Range::from_values(1i32, 2)
+            ]
+        );
+
+        let results = DenseReader::new(&array).read(&vfs)?;
+        let mut expected: Vec<i32> = (0..16).collect();
+        for idx in [8, 9, 12, 13] {
+            expected[idx] = -1;
+        }
+        assert_eq!(results["a"].values::<i32>(), expected);
+        assert_eq!(results["s"].var_value(1), Some(&b"x"[..]));
+        assert_eq!(results["s"].var_value(9), Some(&b"new"[..]));
+        assert_eq!(results["s"].var_value(15), Some(&b""[..]));
+        assert!(!results["n"].is_valid(4));
+        assert!(results["n"].is_valid(5));
+        assert!(!results["n"].is_valid(13));
+        assert_eq!(results["n"].values::<f64>()[7], 7.0);
+
+        // Subarrays must cover whole tiles.
+        let unaligned = Subarray::new().set_typed_range(0, 2i32, 3);
+        assert!(DenseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_subarray(unaligned)
+            .write(&vfs)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_global_order() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![
+                storage::Dimension::new(
+                    "x",
+                    DataType::Int64,
+                    &array::Range::from_values(0i64, 99),
+                )
+                .set_extent(10i64.to_le_bytes().to_vec()),
+                storage::Dimension::new_var("s", DataType::StringAscii),
+            ]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        )
+        .set_capacity(2);
+        let (_dir, array) = fixtures::temp_array(&vfs, "writer", &schema)?;
+        let uri = array.uri().clone();
+
+        let cells = [(42i64, "b"), (3, "z"), (42, "a"), (15, "m"), (4, "a")];
+        let mut s = QueryBuffer::new(true, false);
+        for (_, value) in cells {
+            s.push(value.as_bytes(), true);
+        }
+        let x: Vec<i64> = cells.iter().map(|(x, _)| *x).collect();
+        let a: Vec<i32> = (0..cells.len() as i32).collect();
+        let writer =
+            SparseWriter::new(&uri, array.schema_name(), array.schema())
+                .set_buffer("x", QueryBuffer::from_values(&x))
+                .set_buffer("s", s)
+                .set_buffer("a", QueryBuffer::from_values(&a))
+                .set_timestamp(1);
+        writer.write(&vfs)?;
+
+        let array = array::Array::open(&vfs, &uri)?;
+        let fragment = &array.fragments()[0];
+        assert!(!fragment.is_dense());
+        assert_eq!(fragment.sparse_tile_num(), 3);
+        assert_eq!(fragment.last_tile_cell_num(), 1);
+        assert_eq!(
+            fragment.non_empty_domain(),
+            &[
+                array::Range::from_values(3i64, 42),
+                array::Range::new(b"a".to_vec(), b"z".to_vec())
+            ]
+        );
+
+        let rtree = fragment.load_rtree(&vfs, array.schema())?;
+        assert_eq!(rtree.levels().len(), 2);
+        assert_eq!(
+            rtree.leaves()[0],
+            vec![
+                array::Range::from_values(3i64, 4),
+                array::Range::new(b"a".to_vec(), b"z".to_vec())
+            ]
+        );
+
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::GlobalOrder)
+            .read(&vfs)?;
+        assert_eq!(results["x"].values::<i64>(), vec![3, 4, 15, 42, 42]);
+        assert_eq!(results["a"].values::<i32>(), vec![1, 4, 3, 2, 0]);
+        assert_eq!(results["s"].var_value(0), Some(&b"z"[..]));
+
+        // Duplicates are rejected unless the schema allows them.
+        let dups = SparseWriter::new(&uri, array.schema_name(), array.schema())
+            .set_buffer("x", QueryBuffer::from_values(&[1i64, 1]))
+            .set_buffer("s", {
+                let mut s = QueryBuffer::new(true, false);
+                s.push(b"k", true);
+                s.push(b"k", true);
+                s
+            })
+            .set_buffer("a", QueryBuffer::from_values(&[0i32, 1]));
+        assert!(dups.write(&vfs).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_hilbert_order() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let domain = array::Range::from_values(0i32, 1);
+        let schema = storage::ArraySchema::new(
+            ArrayType::Sparse,
+            storage::Domain::new(vec![
+                storage::Dimension::new("x", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+                storage::Dimension::new("y", DataType::Int32, &domain)
+                    .set_extent(2i32.to_le_bytes().to_vec()),
+            ]),
+            vec![storage::Attribute::new("a", DataType::Int32)],
+        )
+        .set_cell_order(Layout::Hilbert)
+        .set_capacity(2);
+        let (_dir, array) = fixtures::temp_array(&vfs, "writer", &schema)?;
+        let uri = array.uri().clone();
+
+        let write = |x: &[i32], y: &[i32], a: &[i32], timestamp| {
+            SparseWriter::new(&uri, array.schema_name(), array.schema())
+                .set_buffer("x", QueryBuffer::from_values(x))
+                .set_buffer("y", QueryBuffer::from_values(y))
+                .set_buffer("a", QueryBuffer::from_values(a))
+                .set_timestamp(timestamp)
+                .write(&vfs)
+        };
+        write(&[1, 0, 1], &[0, 0, 1], &[0, 1, 2], 1)?;
+        write(&[0], &[1], &[3], 2)?;
+
+        // Both fragments merge into a single walk of the corners, where each
+        // cell is next to the one before it.
+        let array = array::Array::open(&vfs, &uri)?;
+        let results = SparseReader::new(&array)
+            .set_layout(Layout::GlobalOrder)
+            .read(&vfs)?;
+        assert_eq!(results["x"].values::<i32>(), vec![0, 0, 1, 1]);
+        assert_eq!(results["y"].values::<i32>(), vec![0, 1, 1, 0]);
+        assert_eq!(results["a"].values::<i32>(), vec![1, 3, 2, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn sparse_old_versions() -> Result<()> {
+        let vfs = PosixVFSService::default();
+        let domain = array::Range::from_values(0i32, 9);
+        let zstd = storage::FilterList::new(
+            storage::DEFAULT_MAX_CHUNK_SIZE,
+            vec![storage::Filter::compression(FilterType::Zstd, 3)],
+        );
+
+        // Versions 1 to 4 store both dimensions in one coordinates file,
+        // and versions 1 and 2 have no footer.
+        for version in [1, 3] {
+            let schema = storage::ArraySchema::new(
+                ArrayType::Sparse,
+                storage::Domain::new(vec![
+                    storage::Dimension::new("x", DataType::Int32, &domain),
+                    storage::Dimension::new("y", DataType::Int32, &domain),
+                ]),
+                vec![storage::Attribute::new("a", DataType::Int32)],
+            )
+            .set_coords_filters(zstd.clone())
+            .set_capacity(2)
+            .set_version(version);
+            let dir = fixtures::TempDir::new("writer")?;
+            let uri = dir.uri();
+            vfs.dir_create(uri)?;
+            schema.store(&vfs, &uri.join(array::OLD_SCHEMA_NAME))?;
+
+            let array = array::Array::open(&vfs, uri)?;
+            SparseWriter::new(uri, array.schema_name(), array.schema())
+                .set_format_version(version)
+                .set_buffer("x", QueryBuffer::from_values(&[3i32, 1, 2]))
+                .set_buffer("y", QueryBuffer::from_values(&[0i32, 5, 9]))
+                .set_buffer("a", QueryBuffer::from_values(&[30i32, 10, 20]))
+                .write(&vfs)?;
+
+            let array = array::Array::open(&vfs, uri)?;
+            let fragment = &array.fragments()[0];
+            assert_eq!(fragment.format_version(), version);
+            assert_eq!(
+                fragment.load_rtree(&vfs, array.schema())?.leaves().len(),
+                2
+            );
+            let results = SparseReader::new(&array)
+                .set_layout(Layout::GlobalOrder)
+                .read(&vfs)?;
+            assert_eq!(results["x"].values::<i32>(), vec![1, 2, 3]);
+            assert_eq!(results["y"].values::<i32>(), vec![5, 9, 0]);
+            assert_eq!(results["a"].values::<i32>(), vec![10, 20, 30]);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead, BinWrite};
+
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+pub const DELETE_FILE_SUFFIX: &str = ".del";
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConditionOp {
+    #[default]
+    Lt = 0,
+    Le = 1,
+    Gt = 2,
+    Ge = 3,
+    Eq = 4,
+    Ne = 5,
+    In = 6,
+    NotIn = 7,
+    Invalid = 255,
+}
+
+impl From<u8> for ConditionOp {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0 => ConditionOp::Lt,
+            1 => ConditionOp::Le,
+            2 => ConditionOp::Gt,
+            3 => ConditionOp::Ge,
+            4 => ConditionOp::Eq,
+            5 => ConditionOp::Ne,
+            6 => ConditionOp::In,
+            7 => ConditionOp::NotIn,
+            _ => ConditionOp::Invalid,
+        }
+    }
+}
+
+impl ConditionOp {
+    pub fn is_set_op(&self) -> bool {
+        matches!(self, ConditionOp::In | ConditionOp::NotIn)
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CombinationOp {
+    #[default]
+    And = 0,
+    Or = 1,
+    Not = 2,
+    Invalid = 255,
+}
+
+impl From<u8> for CombinationOp {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0 => CombinationOp::And,
+            1 => CombinationOp::Or,
+            2 => CombinationOp::Not,
+            _ => CombinationOp::Invalid,
+        }
+    }
+}
+
+// A comparison of a single field against a value. Set membership operators
+// store every member in value along with the byte offset of each member.
+#[derive(Clone, Debug)]
+#[binrw]
+#[brw(little)]
+pub struct ConditionLeaf {
+    pub(crate) op: u8,
+
+    pub(crate) field_name_size: u32,
+
+    #[br(count(field_name_size))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(map = |n: &String| n.as_bytes().to_vec())]
+    pub(crate) field_name: String,
+
+    pub(crate) value_size: u64,
+
+    #[br(count(value_size))]
+    pub(crate) value: Vec<u8>,
+
+    #[br(if(ConditionOp::from(op).is_set_op()))]
+    #[bw(if(ConditionOp::from(*op).is_set_op()))]
+    pub(crate) offsets_size: u64,
+
+    #[br(count(offsets_size / 8))]
+    pub(crate) offsets: Vec<u64>,
+}
+
+impl ConditionLeaf {
+    pub fn new(op: ConditionOp, field_name: &str, value: &[u8]) -> Self {
+        ConditionLeaf {
+            op: op as u8,
+            field_name_size: field_name.len() as u32,
+            field_name: field_name.to_string(),
+            value_size: value.len() as u64,
+            value: value.to_vec(),
+            offsets_size: 0,
+            offsets: Vec::new(),
+        }
+    }
+
+    // A set membership condition with every member concatenated.
+    pub fn new_set(
+        op: ConditionOp,
+        field_name: &str,
+        members: &[&[u8]],
+    ) -> Self {
+        let mut leaf = ConditionLeaf::new(op, field_name, &members.concat());
+        let mut offset = 0;
+        for member in members {
+            leaf.offsets.push(offset);
+            offset += member.len() as u64;
+        }
+        leaf.offsets_size = 8 * leaf.offsets.len() as u64;
+        leaf
+    }
+
+    pub fn op(&self) -> ConditionOp {
+        self.op.into()
+    }
+
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    // The members of a set membership condition.
+    pub fn members(&self) -> Vec<&[u8]> {
+        self.offsets
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                let end = self
+                    .offsets
+                    .get(i + 1)
+                    .map_or(self.value.len(), |o| *o as usize);
+                &self.value[*start as usize..end]
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+#[binrw]
+#[brw(little)]
+pub struct ConditionExpression {
+    pub(crate) op: u8,
+
+    pub(crate) num_children: u64,
+
+    #[br(count(num_children))]
+    pub(crate) children: Vec<ConditionNode>,
+}
+
+impl ConditionExpression {
+    pub fn new(op: CombinationOp, children: Vec<ConditionNode>) -> Self {
+        ConditionExpression {
+            op: op as u8,
+            num_children: children.len() as u64,
+            children,
+        }
+    }
+
+    pub fn op(&self) -> CombinationOp {
+        self.op.into()
+    }
+
+    pub fn children(&self) -> &[ConditionNode] {
+        &self.children
+    }
+}
+
+// A serialized query condition, as stored by delete commits.
+#[derive(Clone, Debug)]
+#[binrw]
+#[brw(little)]
+pub enum ConditionNode {
+    #[brw(magic = 0u8)]
+    Leaf(ConditionLeaf),
+
+    #[brw(magic = 1u8)]
+    Expression(ConditionExpression),
+}
+
+impl ConditionNode {
+    pub fn load(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        offset: u64,
+    ) -> Result<Self> {
+        let data = storage::read_generic_tile(vfs, uri, offset)?;
+        ConditionNode::read(&mut Cursor::new(data)).map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error reading condition from {}", uri).context(context)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
+
+    // Store the condition as a generic tile, as in a delete commit.
+    pub fn store(&self, vfs: &dyn VFSService, uri: &uri::URI) -> Result<()> {
+        storage::write_generic_tile(vfs, uri, &self.to_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binrw::BinWrite;
+
+    fn leaf(op: ConditionOp, name: &str, value: &[u8]) -> ConditionNode {
+        ConditionNode::Leaf(ConditionLeaf {
+            op: op as u8,
+            field_name_size: name.len() as u32,
+            field_name: name.to_string(),
+            value_size: value.len() as u64,
+            value: value.to_vec(),
+            offsets_size: 0,
+            offsets: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let node = ConditionNode::Expression(ConditionExpression {
+            op: CombinationOp::And as u8,
+            num_children: 2,
+            children: vec![
+                leaf(ConditionOp::Ge, "a", &5i32.to_le_bytes()),
+                leaf(ConditionOp::Ne, "b", b"foo"),
+            ],
+        });
+
+        let mut data = Cursor::new(Vec::new());
+        node.write(&mut data)?;
+        let data = data.into_inner();
+        assert_eq!(data[0], 1);
+        assert_eq!(data[1], CombinationOp::And as u8);
+
+        let parsed = ConditionNode::read(&mut Cursor::new(data))?;
+        let ConditionNode::Expression(expr) = parsed else {
+            panic!("Expected an expression");
+        };
+        assert_eq!(expr.op(), CombinationOp::And);
+        assert_eq!(expr.children().len(), 2);
+        let ConditionNode::Leaf(leaf) = &expr.children()[1] else {
+            panic!("Expected a leaf");
+        };
+        assert_eq!(leaf.op(), ConditionOp::Ne);
+        assert_eq!(leaf.field_name(), "b");
+        assert_eq!(leaf.value(), b"foo");
+        Ok(())
+    }
+
+    #[test]
+    fn set_members() -> Result<()> {
+        let mut data = vec![0u8, ConditionOp::In as u8];
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.push(b'x');
+        data.extend_from_slice(&5u64.to_le_bytes());
+        data.extend_from_slice(b"abcde");
+        data.extend_from_slice(&16u64.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes());
+
+        let ConditionNode::Leaf(leaf) =
+            ConditionNode::read(&mut Cursor::new(data))?
+        else {
+            panic!("Expected a leaf");
+        };
+        assert_eq!(leaf.members(), vec![&b"ab"[..], &b"cde"[..]]);
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead};
+
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::Result;
+
+pub const META_FILE_SUFFIX: &str = ".meta";
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+struct ConsolidatedEntry {
+    name_size: u64,
+
+    #[br(count(name_size))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(map = |n: &String| n.as_bytes().to_vec())]
+    name: String,
+
+    offset: u64,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+struct ConsolidatedHeader {
+    num: u32,
+
+    #[br(count(num))]
+    entries: Vec<ConsolidatedEntry>,
+}
+
+// The footers of many fragments gathered into a single generic tile by
+// fragment metadata consolidation. Footers are keyed by fragment name.
+#[derive(Debug, Default)]
+pub struct ConsolidatedFragmentMetadata {
+    footers: HashMap<String, Vec<u8>>,
+}
+
+impl ConsolidatedFragmentMetadata {
+    pub fn load(vfs: &dyn VFSService, uri: &uri::URI) -> Result<Self> {
+        let data = storage::read_generic_tile(vfs, uri, 0)?;
+        ConsolidatedFragmentMetadata::parse(&data)
+            .map_err(|err| err.context(format!("URI: {}", uri)))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header = ConsolidatedHeader::read(&mut Cursor::new(data)).map_err(
+            |err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error reading consolidated fragment metadata")
+                    .context(context)
+            },
+        )?;
+
+        // Each footer extends to the start of the next one.
+        let mut offsets: Vec<u64> =
+            header.entries.iter().map(|e| e.offset).collect();
+        offsets.sort();
+
+        let mut footers = HashMap::new();
+        for entry in header.entries {
+            let start = entry.offset as usize;
+            let end = offsets
+                .iter()
+                .find(|o| **o > entry.offset)
+                .map_or(data.len(), |o| *o as usize);
+            if start > end || end > data.len() {
+                return Err(anyhow!(
+                    "Invalid footer offset {} for {}",
+                    entry.offset,
+                    entry.name
+                ));
+            }
+
+            // Names may be stored relative to the array directory.
+            let name = entry
+                .name
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            footers.insert(name, data[start..end].to_vec());
+        }
+
+        Ok(ConsolidatedFragmentMetadata { footers })
+    }
+
+    // Add the footers of another file. Footers already present are kept so
+    // files should be merged from newest to oldest.
+    pub fn merge(&mut self, other: ConsolidatedFragmentMetadata) {
+        for (name, footer) in other.footers {
+            self.footers.entry(name).or_insert(footer);
+        }
+    }
+
+    pub fn footer(&self, name: &str) -> Option<&[u8]> {
+        self.footers.get(name).map(|f| f.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.footers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.footers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(data: &mut Vec<u8>, name: &str, offset: u64) {
+        data.extend_from_slice(&(name.len() as u64).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    #[test]
+    fn parse_footers() -> Result<()> {
+        let first = "__fragments/__1_1_0123456789abcdef0123456789abcdef_21";
+        let second = "__2_2_0123456789abcdef0123456789abcdef_21";
+        let header_size = 4 + (8 + first.len() + 8) + (8 + second.len() + 8);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        entry(&mut data, first, header_size as u64);
+        entry(&mut data, second, header_size as u64 + 3);
+        data.extend_from_slice(b"abcdefgh");
+
+        let meta = ConsolidatedFragmentMetadata::parse(&data)?;
+        assert_eq!(meta.len(), 2);
+        assert_eq!(
+            meta.footer("__1_1_0123456789abcdef0123456789abcdef_21"),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(meta.footer(second), Some(&b"defgh"[..]));
+        assert_eq!(meta.footer("missing"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_offset() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes());
+        entry(&mut data, "__1_1_uuid_21", 1000);
+        assert!(ConsolidatedFragmentMetadata::parse(&data).is_err());
+    }
+}
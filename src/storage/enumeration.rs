@@ -0,0 +1,74 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead, BinWrite};
+
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage;
+use crate::storage::CELL_VAR_SIZE;
+use crate::Result;
+
+pub const ENUMERATIONS_DIR: &str = "__enumerations";
+pub const ENUMERATION_VERSION: u32 = 0;
+
+// An enumeration stored in its own generic tile under
+// __schema/__enumerations. Var sized enumerations store the offset of each
+// value into data.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct Enumeration {
+    pub(crate) version: u32,
+
+    pub(crate) name_size: u32,
+
+    #[br(count(name_size))]
+    pub(crate) name: Vec<u8>,
+
+    pub(crate) path_size: u32,
+
+    #[br(count(path_size))]
+    pub(crate) path: Vec<u8>,
+
+    pub(crate) datatype: u8,
+
+    pub(crate) cell_val_num: u32,
+
+    pub(crate) ordered: u8,
+
+    pub(crate) data_size: u64,
+
+    #[br(count(data_size))]
+    pub(crate) data: Vec<u8>,
+
+    #[br(if(cell_val_num == CELL_VAR_SIZE))]
+    #[bw(if(*cell_val_num == CELL_VAR_SIZE))]
+    pub(crate) offsets_size: u64,
+
+    #[br(count(offsets_size / 8))]
+    pub(crate) offsets: Vec<u64>,
+}
+
+impl Enumeration {
+    pub fn load(
+        vfs: &dyn VFSService,
+        schema_dir: &uri::URI,
+        path: &str,
+    ) -> Result<Enumeration> {
+        let uri = schema_dir.join(ENUMERATIONS_DIR).join(path);
+        let data = storage::read_generic_tile(vfs, &uri, 0)?;
+        Enumeration::read(&mut Cursor::new(data)).map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error reading enumeration from {}", uri).context(context)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
+}
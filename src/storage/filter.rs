@@ -1,9 +1,11 @@
 // This file is part of tdbtk released under the MIT license.
 // Copyright (c) 2023 TileDB, Inc.
 
-use binrw::binrw;
+use binrw::io::Cursor;
+use binrw::{binrw, BinWrite};
 
 use crate::filters::FilterType;
+use crate::storage;
 
 fn is_compression_filter(ftype: FilterType) -> bool {
     matches!(
@@ -69,6 +71,10 @@ pub enum FilterConfig {
         compression_level: i32,
 
         #[br(if(has_reinterpret_type(version, filter_type)))]
+        #[bw(if(has_reinterpret_type(
+            storage::CURRENT_FORMAT_VERSION,
+            FilterType::from(compressor_type)
+        )))]
         reinterpret_type: u8,
     },
     #[br(pre_assert(is_bit_width_reduction_filter(filter_type)))]
@@ -111,6 +117,29 @@ pub struct Filter {
 }
 
 impl Filter {
+    pub fn new(filter_type: FilterType, config: FilterConfig) -> Self {
+        let mut data = Cursor::new(Vec::new());
+        // Writing to memory can't fail.
+        config.write(&mut data).unwrap();
+        Filter {
+            filter_type,
+            metadata_len: data.into_inner().len() as u32,
+            config,
+        }
+    }
+
+    // A compression filter at the given level.
+    pub fn compression(filter_type: FilterType, level: i32) -> Self {
+        Filter::new(
+            filter_type,
+            FilterConfig::Compression {
+                compressor_type: filter_type,
+                compression_level: level,
+                reinterpret_type: 0,
+            },
+        )
+    }
+
     pub fn filter_type(&self) -> FilterType {
         self.filter_type
     }
@@ -120,7 +149,10 @@ impl Filter {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+// The chunk size TileDB uses for filter lists created without one.
+pub const DEFAULT_MAX_CHUNK_SIZE: u32 = 64 * 1024;
+
+#[derive(Clone, Debug)]
 #[binrw]
 #[brw(little)]
 #[br(import ( version: u32 ))]
@@ -134,7 +166,25 @@ pub struct FilterList {
     filters: Vec<Filter>,
 }
 
+impl Default for FilterList {
+    fn default() -> Self {
+        FilterList::new(DEFAULT_MAX_CHUNK_SIZE, Vec::new())
+    }
+}
+
 impl FilterList {
+    pub fn new(max_chunk_size: u32, filters: Vec<Filter>) -> Self {
+        FilterList {
+            max_chunk_size,
+            num_filters: filters.len() as u32,
+            filters,
+        }
+    }
+
+    pub fn max_chunk_size(&self) -> u32 {
+        self.max_chunk_size
+    }
+
     pub fn filters(&self) -> &[Filter] {
         &self.filters
     }
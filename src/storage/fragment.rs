@@ -2,15 +2,35 @@
 // Copyright (c) 2023 TileDB, Inc.
 
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::anyhow;
-use binrw::binrw;
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead, BinResult, BinWrite, VecArgs};
 
 use crate::array;
+use crate::array::directory::OLD_SCHEMA_NAME;
+use crate::datatype::{self, DataType};
+use crate::filters;
+use crate::io::service::VFSService;
 use crate::io::uri;
 use crate::storage;
 use crate::Result;
 
+pub const FRAGMENT_METADATA_FILENAME: &str = "__fragment_metadata.tdb";
+pub const FILE_SUFFIX: &str = ".tdb";
+pub const VAR_FILE_SUFFIX: &str = "_var.tdb";
+pub const VALIDITY_FILE_SUFFIX: &str = "_validity.tdb";
+
+// Fragments before version 5 are committed by this file rather than by an
+// ok file, and store the coordinates of every dimension in a single field.
+pub const FRAGMENT_FILENAME: &str = "__tiledb_fragment.tdb";
+const COORDS_NAME: &str = "__coords";
+
+pub const TIMESTAMPS_NAME: &str = "__timestamps";
+const DELETE_TIMESTAMPS_NAME: &str = "__delete_timestamps";
+const DELETE_CONDITION_INDEX_NAME: &str = "__delete_condition_index";
+
 #[derive(Debug, PartialEq, Eq)]
 enum FragmentNameVersion {
     One,
@@ -18,7 +38,7 @@ enum FragmentNameVersion {
     Three,
 }
 
-fn get_fragment_name_version(name: &String) -> FragmentNameVersion {
+fn get_fragment_name_version(name: &str) -> FragmentNameVersion {
     let num_underscores = name.chars().filter(|c| *c == '_').count();
     if num_underscores == 5 {
         return FragmentNameVersion::Three;
@@ -33,7 +53,7 @@ fn get_fragment_name_version(name: &String) -> FragmentNameVersion {
     FragmentNameVersion::One
 }
 
-fn get_fragment_version(name: &String) -> Result<u32> {
+pub(crate) fn get_fragment_version(name: &str) -> Result<u32> {
     let name_version = get_fragment_name_version(name);
 
     if name_version == FragmentNameVersion::One {
@@ -57,166 +77,1333 @@ fn get_fragment_version(name: &String) -> Result<u32> {
     }
 }
 
+// Parse the timestamp range encoded in a fragment or schema name. Version
+// one names are `__uuid_t1[_t2]` while later versions are `__t1_t2_uuid[_v]`.
+pub fn timestamp_range(name: &str) -> Result<(u64, u64)> {
+    let parse = |value: &str| -> Result<u64> {
+        value.parse().map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error parsing timestamp from: {}", name).context(context)
+        })
+    };
+
+    let parts: Vec<&str> = name
+        .strip_prefix("__")
+        .ok_or_else(|| anyhow!("Invalid fragment name: {}", name))?
+        .split('_')
+        .collect();
+
+    if get_fragment_name_version(name) == FragmentNameVersion::One {
+        let start = parse(parts.get(1).ok_or_else(|| {
+            anyhow!("Missing timestamp in fragment name: {}", name)
+        })?)?;
+        let end = match parts.get(2) {
+            Some(end) => parse(end)?,
+            None => start,
+        };
+        return Ok((start, end));
+    }
+
+    if parts.len() < 2 {
+        return Err(anyhow!("Missing timestamps in fragment name: {}", name));
+    }
+
+    Ok((parse(parts[0])?, parse(parts[1])?))
+}
+
+// Generate a name in the `__t1_t2_uuid_v` format used for fragments and
+// other timestamped files.
+pub fn timestamped_name(start: u64, end: u64) -> String {
+    versioned_name(start, end, storage::CURRENT_FORMAT_VERSION)
+}
+
+// Like timestamped_name but for files written in an older format version.
+pub fn versioned_name(start: u64, end: u64, version: u32) -> String {
+    let uuid: String = (0..32)
+        .map(|_| {
+            let idx = rand::random::<usize>() % 16;
+            b"0123456789abcdef"[idx] as char
+        })
+        .collect();
+    uuid_name(start, end, &uuid, version)
+}
+
+// Like versioned_name but with a given rather than a random UUID of 32 hex
+// digits, for files that must be reproducible. Fragments before version 5
+// have no version in their name, and versions 1 and 2 put the UUID first.
+pub fn uuid_name(start: u64, end: u64, uuid: &str, version: u32) -> String {
+    match version {
+        0..=2 => format!("__{}_{}_{}", uuid, start, end),
+        3..=4 => format!("__{}_{}_{}", start, end, uuid),
+        _ => format!("__{}_{}_{}_{}", start, end, uuid, version),
+    }
+}
+
+// The current time in milliseconds since the epoch, which is the timestamp
+// used for new commits.
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
-#[br(import (nfields: u32))]
-struct FragmentFileOffsets {
+#[br(import (version: u32, nfields: u32))]
+#[bw(import (version: u32))]
+pub struct FragmentFileOffsets {
     #[br(count(nfields))]
-    fixed_sizes: Vec<u64>,
+    pub(crate) fixed_sizes: Vec<u64>,
 
     #[br(count(nfields))]
-    var_sizes: Vec<u64>,
+    pub(crate) var_sizes: Vec<u64>,
 
+    #[brw(if(version >= 7))]
     #[br(count(nfields))]
-    validity_sizes: Vec<u64>,
+    pub(crate) validity_sizes: Vec<u64>,
 }
 
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
-#[br(import (nfields: u32))]
-struct FragmentTileOffsets {
-    rtree: u64,
+#[br(import (version: u32, nfields: u32))]
+#[bw(import (version: u32))]
+pub struct FragmentTileOffsets {
+    pub(crate) rtree: u64,
 
     #[br(count(nfields))]
-    fixed_offsets: Vec<u64>,
+    pub(crate) fixed_offsets: Vec<u64>,
 
     #[br(count(nfields))]
-    var_offsets: Vec<u64>,
+    pub(crate) var_offsets: Vec<u64>,
 
     #[br(count(nfields))]
-    var_sizes: Vec<u64>,
+    pub(crate) var_sizes: Vec<u64>,
 
+    #[brw(if(version >= 7))]
     #[br(count(nfields))]
-    validity_offsets: Vec<u64>,
+    pub(crate) validity_offsets: Vec<u64>,
 
+    #[brw(if(version >= 11))]
     #[br(count(nfields))]
-    min_offsets: Vec<u64>,
+    pub(crate) min_offsets: Vec<u64>,
 
+    #[brw(if(version >= 11))]
     #[br(count(nfields))]
-    max_offsets: Vec<u64>,
+    pub(crate) max_offsets: Vec<u64>,
 
+    #[brw(if(version >= 11))]
     #[br(count(nfields))]
-    sum_offsets: Vec<u64>,
+    pub(crate) sum_offsets: Vec<u64>,
 
+    #[brw(if(version >= 11))]
     #[br(count(nfields))]
-    null_count_offsets: Vec<u64>,
+    pub(crate) null_count_offsets: Vec<u64>,
 
-    frag_meta_offset: u64,
+    #[brw(if(version >= 11))]
+    pub(crate) frag_meta_offset: u64,
 
-    processed_conditions_offset: u64,
+    #[brw(if(version >= 16))]
+    pub(crate) processed_conditions_offset: u64,
 }
 
+// The version and schema name are read ahead of the rest of the footer
+// because the schema determines how the remainder is laid out.
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
-#[br(import (schema: storage::ArraySchema))]
-struct FragmentMetadataPreFooter {
+struct FragmentFooterPrefix {
     version: u32,
 
-    domain_size: u64,
+    #[br(if(version >= 10, 0))]
+    array_schema_name_size: u64,
 
-    // Need to add a map here to decode the non-empty domain
-    #[br(count(domain_size))]
-    #[br(if(domain_size != 0, Vec::new()))]
-    non_empty_domain: Vec<u8>,
-
-    num_mbrs: u64,
+    #[br(count(array_schema_name_size))]
+    array_schema_name: Vec<u8>,
 }
 
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
-#[br(import (nfields: u32))]
-struct FragmentFooter {
-    version: u32,
+#[br(import (nfields: u32, dims: Vec<Option<usize>>))]
+#[bw(import (dims: Vec<Option<usize>>))]
+pub struct FragmentFooter {
+    pub(crate) version: u32,
 
-    array_schema_size: u64,
+    #[br(if(version >= 10, 0))]
+    #[bw(if(*version >= 10))]
+    pub(crate) array_schema_name_size: u64,
 
-    #[br(count(array_schema_size))]
-    #[br(map = |v: Vec<u8>| String::from_utf8(v).unwrap())]
+    #[br(count(array_schema_name_size))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(if(*version >= 10))]
     #[bw(map = |n: &String| n.as_bytes().to_vec())]
-    array_scheam: String,
+    pub(crate) array_schema_name: String,
+
+    pub(crate) dense: u8,
+
+    #[br(parse_with = non_empty_domain_parser, args(version, dims.clone()))]
+    #[bw(write_with = non_empty_domain_writer, args(*version, dims.clone()))]
+    pub(crate) non_empty_domain: Vec<array::Range>,
+
+    pub(crate) sparse_tile_num: u64,
+
+    pub(crate) last_tile_cell_num: u64,
+
+    #[br(if(version >= 14, 0))]
+    #[bw(if(*version >= 14))]
+    pub(crate) has_timestamps: u8,
+
+    #[br(if(version >= 15, 0))]
+    #[bw(if(*version >= 15))]
+    pub(crate) has_delete_meta: u8,
+
+    #[br(args(
+        version,
+        nfields + has_timestamps as u32 + 2 * has_delete_meta as u32
+    ))]
+    #[bw(args(*version))]
+    pub(crate) file_offsets: FragmentFileOffsets,
+
+    #[br(args(
+        version,
+        nfields + has_timestamps as u32 + 2 * has_delete_meta as u32
+    ))]
+    #[bw(args(*version))]
+    pub(crate) tile_offsets: FragmentTileOffsets,
+}
+
+#[binrw::parser(reader, endian)]
+fn non_empty_domain_parser(
+    version: u32,
+    dims: Vec<Option<usize>>,
+) -> BinResult<Vec<array::Range>> {
+    let read_bytes = |reader: &mut _, count: usize| -> BinResult<Vec<u8>> {
+        <Vec<u8>>::read_options(
+            reader,
+            endian,
+            VecArgs {
+                count,
+                inner: <_>::default(),
+            },
+        )
+    };
+
+    // Versions 1 and 2 prefix the domain by its total size rather than by a
+    // null flag. Every dimension shares a single datatype before version 5.
+    if version < 3 {
+        let domain_size = <u64>::read_options(reader, endian, ())?;
+        if domain_size == 0 || dims.is_empty() {
+            return Ok(Vec::new());
+        }
+        let size = domain_size as usize / (2 * dims.len());
+        let mut ranges = Vec::new();
+        for _ in dims.iter() {
+            let start = read_bytes(reader, size)?;
+            let end = read_bytes(reader, size)?;
+            ranges.push(array::Range::new(start, end));
+        }
+        return Ok(ranges);
+    }
+
+    let null_non_empty_domain = <u8>::read_options(reader, endian, ())?;
+    let ranges = ranges_parser(reader, endian, (dims,))?;
+
+    if null_non_empty_domain != 0 {
+        return Ok(Vec::new());
+    }
+
+    Ok(ranges)
+}
+
+// A list of ranges, one per dimension, as stored in non-empty domains and
+// R-tree MBRs.
+#[binrw::parser(reader, endian)]
+pub(crate) fn ranges_parser(
+    dims: Vec<Option<usize>>,
+) -> BinResult<Vec<array::Range>> {
+    let read_bytes = |reader: &mut _, count: usize| -> BinResult<Vec<u8>> {
+        <Vec<u8>>::read_options(
+            reader,
+            endian,
+            VecArgs {
+                count,
+                inner: <_>::default(),
+            },
+        )
+    };
+
+    let mut ranges = Vec::new();
+    for dim in dims.iter() {
+        match dim {
+            Some(size) => {
+                let start = read_bytes(reader, *size)?;
+                let end = read_bytes(reader, *size)?;
+                ranges.push(array::Range::new(start, end));
+            }
+            None => {
+                let range_size = <u64>::read_options(reader, endian, ())?;
+                let start_size = <u64>::read_options(reader, endian, ())?;
+                let mut start = read_bytes(reader, range_size as usize)?;
+                let end = start.split_off(start_size as usize);
+                ranges.push(array::Range::new(start, end));
+            }
+        }
+    }
+
+    Ok(ranges)
+}
+
+#[binrw::writer(writer, endian)]
+fn non_empty_domain_writer(
+    ranges: &Vec<array::Range>,
+    version: u32,
+    dims: Vec<Option<usize>>,
+) -> BinResult<()> {
+    if version < 3 {
+        let size: usize =
+            ranges.iter().map(|r| r.start().len() + r.end().len()).sum();
+        <u64>::write_options(&(size as u64), writer, endian, ())?;
+        for range in ranges {
+            range.start().to_vec().write_options(writer, endian, ())?;
+            range.end().to_vec().write_options(writer, endian, ())?;
+        }
+        return Ok(());
+    }
+
+    let null_non_empty_domain = ranges.is_empty() as u8;
+    <u8>::write_options(&null_non_empty_domain, writer, endian, ())?;
+    ranges_writer(ranges, writer, endian, (dims,))
+}
+
+// Missing ranges are written as empty values.
+#[binrw::writer(writer, endian)]
+pub(crate) fn ranges_writer(
+    ranges: &[array::Range],
+    dims: Vec<Option<usize>>,
+) -> BinResult<()> {
+    for (idx, dim) in dims.iter().enumerate() {
+        match (dim, ranges.get(idx)) {
+            (Some(size), None) => {
+                vec![0u8; 2 * size].write_options(writer, endian, ())?;
+            }
+            (Some(_), Some(range)) => {
+                range.start().to_vec().write_options(writer, endian, ())?;
+                range.end().to_vec().write_options(writer, endian, ())?;
+            }
+            (None, None) => {
+                <u64>::write_options(&0, writer, endian, ())?;
+                <u64>::write_options(&0, writer, endian, ())?;
+            }
+            (None, Some(range)) => {
+                let size = (range.start().len() + range.end().len()) as u64;
+                let start_size = range.start().len() as u64;
+                <u64>::write_options(&size, writer, endian, ())?;
+                <u64>::write_options(&start_size, writer, endian, ())?;
+                range.start().to_vec().write_options(writer, endian, ())?;
+                range.end().to_vec().write_options(writer, endian, ())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A count followed by that many u64 values.
+#[derive(Debug, Default)]
+#[binrw]
+#[brw(little)]
+pub struct U64List {
+    pub(crate) num: u64,
+
+    #[br(count(num))]
+    pub(crate) values: Vec<u64>,
+}
+
+impl U64List {
+    pub fn new(values: Vec<u64>) -> Self {
+        U64List {
+            num: values.len() as u64,
+            values,
+        }
+    }
+}
+
+// Versions 1 and 2 store the fragment metadata as a single generic tile
+// without a footer. The MBRs and tile offsets are stored inline rather
+// than in generic tiles of their own, and the first and last coordinates
+// of each sparse tile are kept as its bounding coordinates.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+#[br(import (nfields: u32, dims: Vec<Option<usize>>))]
+#[bw(import (dims: Vec<Option<usize>>))]
+pub struct InlineMetadata {
+    pub(crate) version: u32,
+
+    #[br(parse_with = non_empty_domain_parser, args(version, dims.clone()))]
+    #[bw(write_with = non_empty_domain_writer, args(*version, dims.clone()))]
+    pub(crate) non_empty_domain: Vec<array::Range>,
 
-    fragment_type: u8,
+    pub(crate) mbr_num: u64,
 
-    null_non_empty_domain: u8,
+    #[br(parse_with = mbrs_parser, args(mbr_num, dims.clone()))]
+    #[bw(write_with = mbrs_writer, args(dims.clone()))]
+    pub(crate) mbrs: Vec<storage::Mbr>,
 
-    #[br(if(null_non_empty_domain == 0, Vec::new()))]
-    #[br(count(4))]
-    non_empty_domain: Vec<f64>,
+    pub(crate) bounding_coords_num: u64,
 
-    sparse_tile_num: u64,
-    last_tile_cell_num: u64,
-    has_timestamps: u8,
-    has_delete_meta: u8,
+    #[br(count(bounding_coords_num as usize * 2 * coords_size(&dims)))]
+    pub(crate) bounding_coords: Vec<u8>,
+
+    #[br(count(nfields))]
+    pub(crate) tile_offsets: Vec<U64List>,
 
-    #[br(args(nfields))]
-    file_offsets: FragmentFileOffsets,
+    #[br(count(nfields))]
+    pub(crate) tile_var_offsets: Vec<U64List>,
+
+    #[br(count(nfields))]
+    pub(crate) tile_var_sizes: Vec<U64List>,
+
+    pub(crate) last_tile_cell_num: u64,
+
+    #[br(count(nfields))]
+    pub(crate) file_sizes: Vec<u64>,
+
+    #[br(count(nfields))]
+    pub(crate) file_var_sizes: Vec<u64>,
+}
+
+fn coords_size(dims: &[Option<usize>]) -> usize {
+    dims.iter().map(|d| d.unwrap_or(0)).sum()
+}
+
+#[binrw::parser(reader, endian)]
+fn mbrs_parser(
+    mbr_num: u64,
+    dims: Vec<Option<usize>>,
+) -> BinResult<Vec<storage::Mbr>> {
+    let mut mbrs = Vec::new();
+    for _ in 0..mbr_num {
+        mbrs.push(ranges_parser(reader, endian, (dims.clone(),))?);
+    }
+    Ok(mbrs)
+}
+
+#[binrw::writer(writer, endian)]
+fn mbrs_writer(
+    mbrs: &Vec<storage::Mbr>,
+    dims: Vec<Option<usize>>,
+) -> BinResult<()> {
+    for mbr in mbrs {
+        ranges_writer(mbr, writer, endian, (dims.clone(),))?;
+    }
+    Ok(())
+}
+
+// The per-dimension layout of the non-empty domain. Var sized dimensions
+// are None.
+pub(crate) fn dimension_sizes(schema: &array::Schema) -> Vec<Option<usize>> {
+    schema
+        .domain()
+        .dimensions()
+        .iter()
+        .map(|d| {
+            if d.is_var_sized() {
+                None
+            } else {
+                Some(d.coord_size())
+            }
+        })
+        .collect()
+}
+
+// The number of fields in the footer excluding timestamps and delete meta.
+// Attributes come first, followed by the zipped coordinates and then each
+// dimension. Before version 5 the zipped coordinates hold every dimension.
+fn num_base_fields(version: u32, schema: &array::Schema) -> u32 {
+    let ndim = if version < 5 {
+        0
+    } else {
+        schema.domain().ndim()
+    };
+    (schema.attributes().len() + 1 + ndim) as u32
+}
+
+// Footers written before version 10 did not record their own size when all
+// dimensions are fixed so we have to calculate it.
+fn footer_size_pre_v10(version: u32, schema: &array::Schema) -> u64 {
+    let nfields = num_base_fields(version, schema) as u64;
+    let domain_size: u64 = schema
+        .domain()
+        .dimensions()
+        .iter()
+        .map(|d| 2 * d.coord_size() as u64)
+        .sum();
+    let (file_sizes, tile_offsets) = if version >= 7 { (3, 4) } else { (2, 3) };
+
+    4 + 1
+        + 1
+        + domain_size
+        + 8
+        + 8
+        + file_sizes * nfields * 8
+        + 8
+        + tile_offsets * nfields * 8
+}
+
+// A field stored in a fragment, along with the filters needed to read it.
+pub struct Field<'a> {
+    name: String,
+    idx: usize,
+    data_type: DataType,
+    cell_size: usize,
+    var_sized: bool,
+    nullable: bool,
+    filters: &'a filters::FilterChain,
+    offsets_filters: &'a filters::FilterChain,
+    validity_filters: &'a filters::FilterChain,
+}
+
+impl<'a> Field<'a> {
+    pub fn attribute(schema: &'a array::Schema, idx: usize) -> Field<'a> {
+        let attr = &schema.attributes()[idx];
+        Field {
+            name: attr.name().to_string(),
+            idx,
+            data_type: attr.data_type(),
+            cell_size: attr.cell_size(),
+            var_sized: attr.is_var_sized(),
+            nullable: attr.nullable(),
+            filters: attr.filters(),
+            offsets_filters: schema.cell_var_filters(),
+            validity_filters: schema.cell_validity_filters(),
+        }
+    }
+
+    pub fn dimension(schema: &'a array::Schema, dim_idx: usize) -> Field<'a> {
+        let dim = &schema.domain().dimensions()[dim_idx];
+        Field {
+            name: dim.name().to_string(),
+            idx: schema.attributes().len() + 1 + dim_idx,
+            data_type: dim.data_type(),
+            cell_size: dim.coord_size(),
+            var_sized: dim.is_var_sized(),
+            nullable: false,
+            filters: schema.dimension_filters(dim),
+            offsets_filters: schema.cell_var_filters(),
+            validity_filters: schema.cell_validity_filters(),
+        }
+    }
+
+    // The zipped coordinates, which hold the values of every dimension
+    // before version 5. They are stored one dimension after another.
+    pub fn coords(schema: &'a array::Schema) -> Field<'a> {
+        let dim = &schema.domain().dimensions()[0];
+        Field {
+            name: COORDS_NAME.to_string(),
+            idx: schema.attributes().len(),
+            data_type: dim.data_type(),
+            cell_size: dim.coord_size(),
+            var_sized: false,
+            nullable: false,
+            filters: schema.dimension_filters(dim),
+            offsets_filters: schema.cell_var_filters(),
+            validity_filters: schema.cell_validity_filters(),
+        }
+    }
+
+    // The timestamp of each cell, stored after the dimensions by
+    // consolidation that keeps timestamps.
+    pub fn timestamps(schema: &'a array::Schema) -> Field<'a> {
+        let version = storage::CURRENT_FORMAT_VERSION;
+        let idx = num_base_fields(version, schema) as usize;
+        u64_field(schema, TIMESTAMPS_NAME, idx)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn cell_size(&self) -> usize {
+        self.cell_size
+    }
+
+    pub fn is_var_sized(&self) -> bool {
+        self.var_sized
+    }
+
+    pub fn nullable(&self) -> bool {
+        self.nullable
+    }
+
+    pub fn filters(&self) -> &'a filters::FilterChain {
+        self.filters
+    }
+
+    pub fn offsets_filters(&self) -> &'a filters::FilterChain {
+        self.offsets_filters
+    }
+
+    pub fn validity_filters(&self) -> &'a filters::FilterChain {
+        self.validity_filters
+    }
+}
+
+// Timestamps and delete metadata are stored as u64 fields using the
+// coordinate filters.
+fn u64_field<'a>(
+    schema: &'a array::Schema,
+    name: &str,
+    idx: usize,
+) -> Field<'a> {
+    Field {
+        name: name.to_string(),
+        idx,
+        data_type: DataType::Uint64,
+        cell_size: 8,
+        var_sized: false,
+        nullable: false,
+        filters: schema.coords_filters(),
+        offsets_filters: schema.cell_var_filters(),
+        validity_filters: schema.cell_validity_filters(),
+    }
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+struct ProcessedCondition {
+    name_size: u64,
+
+    #[br(count(name_size))]
+    name: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+struct ProcessedConditions {
+    num: u64,
+
+    #[br(count(num))]
+    names: Vec<ProcessedCondition>,
+}
+
+// The offsets needed to locate every tile of a single field.
+pub struct TileIndex {
+    offsets: Vec<u64>,
+    file_size: u64,
+    var_offsets: Vec<u64>,
+    var_file_size: u64,
+    validity_offsets: Vec<u64>,
+    validity_file_size: u64,
+}
+
+impl TileIndex {
+    pub fn tile_num(&self) -> usize {
+        self.offsets.len()
+    }
+}
 
-    #[br(args(nfields))]
-    tile_offsets: FragmentTileOffsets,
+fn persisted_range(offsets: &[u64], file_size: u64, tile: usize) -> (u64, u64) {
+    let start = offsets[tile];
+    let end = offsets.get(tile + 1).copied().unwrap_or(file_size);
+    (start, end - start)
 }
 
 pub struct FragmentMetadata {
     uri: uri::URI,
+    name: String,
     format_version: u32,
-    footer: Option<FragmentFooter>,
+    timestamp_range: (u64, u64),
+    num_attributes: usize,
+    num_dimensions: usize,
+    footer: FragmentFooter,
+    inline: Option<InlineMetadata>,
 }
 
 impl FragmentMetadata {
     pub fn load(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        schemas: &HashMap<String, array::Schema>,
+    ) -> Result<FragmentMetadata> {
+        let data = FragmentMetadata::read_footer(vfs, uri, schemas)?;
+        FragmentMetadata::from_footer(uri, &data, schemas)
+    }
+
+    // Read the serialized footer of a fragment. Fragments older than
+    // version 10 only store the footer size if they have var sized
+    // dimensions so the original schema is needed to locate the footer.
+    // Versions 1 and 2 have no footer, so all of their metadata is read.
+    pub fn read_footer(
+        vfs: &dyn VFSService,
+        uri: &uri::URI,
+        schemas: &HashMap<String, array::Schema>,
+    ) -> Result<Vec<u8>> {
+        let uri = uri.remove_trailing_slash();
+        let name = uri.last_path_part();
+        let vsn = get_fragment_version(&name)?;
+
+        let get_schema = |schema_name: &str| {
+            schemas.get(schema_name).ok_or_else(|| {
+                let context =
+                    format!("While loading fragment metadata for {}", uri);
+                anyhow!("Failed finding array schema '{}'", schema_name)
+                    .context(context)
+            })
+        };
+
+        let fmd_uri = uri.join(FRAGMENT_METADATA_FILENAME);
+        if vsn <= 2 {
+            return storage::read_generic_tile(vfs, &fmd_uri, 0);
+        }
+        let file_size = vfs.file_size(&fmd_uri)?;
+
+        let read_footer_size = || -> Result<u64> {
+            let data = vfs.file_read_vec(&fmd_uri, 8, file_size - 8)?;
+            Ok(u64::from_le_bytes(data[..].try_into()?))
+        };
+
+        let (footer_offset, footer_size) = if vsn >= 10 {
+            let size = read_footer_size()?;
+            (file_size - size - 8, size)
+        } else {
+            let schema = get_schema(OLD_SCHEMA_NAME)?;
+            if schema.domain().all_dims_fixed() {
+                let size = footer_size_pre_v10(vsn, schema);
+                (file_size - size, size)
+            } else {
+                let size = read_footer_size()?;
+                (file_size - size - 8, size)
+            }
+        };
+
+        let data = vfs.file_read_vec(&fmd_uri, footer_size, footer_offset)?;
+        Ok(data)
+    }
+
+    // Build the metadata of a fragment from its serialized footer. This is
+    // used both for footers read from the fragment itself and for footers
+    // stored in consolidated fragment metadata.
+    pub fn from_footer(
         uri: &uri::URI,
+        data: &[u8],
         schemas: &HashMap<String, array::Schema>,
     ) -> Result<FragmentMetadata> {
-        let name = uri.remove_trailing_slash().last_path_part();
+        let uri = uri.remove_trailing_slash();
+        let name = uri.last_path_part();
         let vsn = get_fragment_version(&name)?;
+        let timestamp_range = timestamp_range(&name)?;
+
+        let schema_name = FragmentMetadata::footer_schema_name(data)?;
+        let schema = schemas.get(&schema_name).ok_or_else(|| {
+            let context =
+                format!("While loading fragment metadata for {}", uri);
+            anyhow!("Failed finding array schema '{}'", schema_name)
+                .context(context)
+        })?;
 
-        // if vsn <= 2 {
-        //     Fragment::load_v1_v2(uri, vsn, schemas)
-        // } else {
-        //     panic!("Still working on v1/v2 loading");
-        //     //Fragment::load_v3_or_newer(uri, vsn, schemas)
-        // }
+        let (footer, inline) = if vsn <= 2 {
+            let inline = InlineMetadata::read_args(
+                &mut Cursor::new(data),
+                (num_base_fields(vsn, schema), dimension_sizes(schema)),
+            )
+            .map_err(|err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error reading fragment metadata for {}", uri)
+                    .context(context)
+            })?;
+            (inline_footer(&inline), Some(inline))
+        } else {
+            let footer = FragmentFooter::read_args(
+                &mut Cursor::new(data),
+                (num_base_fields(vsn, schema), dimension_sizes(schema)),
+            )
+            .map_err(|err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error reading fragment footer for {}", uri)
+                    .context(context)
+            })?;
+            (footer, None)
+        };
+
+        // Names before version 5 only tell versions 1 and 2 apart from
+        // versions 3 and 4, so the stored version is used instead.
+        let format_version = if vsn < 5 { footer.version } else { vsn };
 
         Ok(FragmentMetadata {
             uri: uri.clone(),
-            format_version: vsn,
-            footer: None,
+            name,
+            format_version,
+            timestamp_range,
+            num_attributes: schema.attributes().len(),
+            num_dimensions: schema.domain().ndim(),
+            footer,
+            inline,
         })
     }
 
-    //     fn load_v1_v2(
-    //         uri: &uri::URI,
-    //         vsn: u32,
-    //         schemas: HashMap<String, storage::ArraySchema>,
-    //     ) -> Result<Fragment> {
-    //         // Pre v10 fragments have an __array_schema.tdb as their schema.
-    //         let schema = schemas.get("__array_schema.tdb");
-    //         if schema.is_none() {
-    //             let context =
-    //                 format!("While loading fragment metadata for {}", uri);
-    //             return Err(anyhow!(
-    //                 "Failed finding array schema '__array_schema.tdb'"
-    //             )
-    //             .context(context));
-    //         }
-    //
-    //         let schema = schema.unwrap();
-    //
-    //         let fmd_uri = uri.join("__fragment_metadata.tdb");
-    //         let data = storage::read_generic_tile(&fmd_uri, 0);
-    //
-    //         Ok(Fragment {})
-    //     }
-
-    //     fn load_v3_or_newer(
-    //         uri: &uri::URI,
-    //         vsn: u32,
-    //         schemas: HashMap<String, storage::ArraySchema>,
-    //     ) -> Result<Fragment> {
-    //     }
+    // The name of the schema a serialized footer was written with.
+    pub fn footer_schema_name(data: &[u8]) -> Result<String> {
+        let prefix = FragmentFooterPrefix::read(&mut Cursor::new(data))?;
+        if prefix.version >= 10 {
+            Ok(String::from_utf8(prefix.array_schema_name)?)
+        } else {
+            Ok(OLD_SCHEMA_NAME.to_string())
+        }
+    }
+
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    // The inclusive range of timestamps covered by this fragment.
+    pub fn timestamp_range(&self) -> (u64, u64) {
+        self.timestamp_range
+    }
+
+    // The name of the schema this fragment was written with.
+    pub fn schema_name(&self) -> &str {
+        if self.footer.version >= 10 {
+            &self.footer.array_schema_name
+        } else {
+            OLD_SCHEMA_NAME
+        }
+    }
+
+    pub fn is_dense(&self) -> bool {
+        self.footer.dense != 0
+    }
+
+    // An empty non-empty domain means the fragment contains no data.
+    pub fn non_empty_domain(&self) -> &[array::Range] {
+        &self.footer.non_empty_domain
+    }
+
+    pub fn sparse_tile_num(&self) -> u64 {
+        self.footer.sparse_tile_num
+    }
+
+    pub fn last_tile_cell_num(&self) -> u64 {
+        self.footer.last_tile_cell_num
+    }
+
+    pub fn has_timestamps(&self) -> bool {
+        self.footer.has_timestamps != 0
+    }
+
+    pub fn has_delete_meta(&self) -> bool {
+        self.footer.has_delete_meta != 0
+    }
+
+    pub fn footer(&self) -> &FragmentFooter {
+        &self.footer
+    }
+
+    pub fn dimension_field_idx(&self, dim_idx: usize) -> usize {
+        self.num_attributes + 1 + dim_idx
+    }
+
+    pub fn timestamps_field_idx(&self) -> Option<usize> {
+        if self.has_timestamps() {
+            Some(self.num_attributes + 1 + self.num_dimensions)
+        } else {
+            None
+        }
+    }
+
+    pub fn delete_timestamps_field_idx(&self) -> Option<usize> {
+        if self.has_delete_meta() {
+            Some(
+                self.num_attributes
+                    + 1
+                    + self.num_dimensions
+                    + self.has_timestamps() as usize,
+            )
+        } else {
+            None
+        }
+    }
+
+    pub fn delete_condition_index_field_idx(&self) -> Option<usize> {
+        self.delete_timestamps_field_idx().map(|idx| idx + 1)
+    }
+
+    // The index of the footer entry of a field. Before version 5 every
+    // dimension is stored in the zipped coordinates.
+    pub fn stored_field_idx(&self, field: &Field) -> usize {
+        if self.format_version < 5 && field.idx > self.num_attributes {
+            self.num_attributes
+        } else {
+            field.idx
+        }
+    }
+
+    pub fn attribute_field<'a>(
+        &self,
+        schema: &'a array::Schema,
+        name: &str,
+    ) -> Option<Field<'a>> {
+        let idx = schema.attribute_idx(name)?;
+        Some(Field::attribute(schema, idx))
+    }
+
+    pub fn dimension_field<'a>(
+        &self,
+        schema: &'a array::Schema,
+        dim_idx: usize,
+    ) -> Field<'a> {
+        Field::dimension(schema, dim_idx)
+    }
+
+    pub fn timestamps_field<'a>(
+        &self,
+        schema: &'a array::Schema,
+    ) -> Option<Field<'a>> {
+        let idx = self.timestamps_field_idx()?;
+        Some(u64_field(schema, TIMESTAMPS_NAME, idx))
+    }
+
+    // The timestamp at which each cell was deleted by a condition that
+    // was processed when the fragment was written.
+    pub fn delete_timestamps_field<'a>(
+        &self,
+        schema: &'a array::Schema,
+    ) -> Option<Field<'a>> {
+        let idx = self.delete_timestamps_field_idx()?;
+        Some(u64_field(schema, DELETE_TIMESTAMPS_NAME, idx))
+    }
+
+    // The index into the processed conditions of the condition that
+    // deleted each cell.
+    pub fn delete_condition_index_field<'a>(
+        &self,
+        schema: &'a array::Schema,
+    ) -> Option<Field<'a>> {
+        let idx = self.delete_condition_index_field_idx()?;
+        Some(u64_field(schema, DELETE_CONDITION_INDEX_NAME, idx))
+    }
+
+    fn field_file_name(&self, field: &Field) -> String {
+        field_file_name(self.format_version, self.num_attributes, field)
+    }
+
+    pub fn field_uri(&self, field: &Field) -> uri::URI {
+        self.uri.join(&(self.field_file_name(field) + FILE_SUFFIX))
+    }
+
+    pub fn field_var_uri(&self, field: &Field) -> uri::URI {
+        self.uri
+            .join(&(self.field_file_name(field) + VAR_FILE_SUFFIX))
+    }
+
+    pub fn field_validity_uri(&self, field: &Field) -> uri::URI {
+        self.uri
+            .join(&(self.field_file_name(field) + VALIDITY_FILE_SUFFIX))
+    }
+
+    pub fn metadata_uri(&self) -> uri::URI {
+        self.uri.join(FRAGMENT_METADATA_FILENAME)
+    }
+
+    // Offset tiles are generic tiles holding a count followed by that many
+    // u64 values.
+    fn load_u64_tile(
+        &self,
+        vfs: &dyn VFSService,
+        offset: u64,
+    ) -> Result<Vec<u64>> {
+        let data =
+            storage::read_generic_tile(vfs, &self.metadata_uri(), offset)?;
+        let mut reader = Cursor::new(data);
+        let count = <u64>::read_le(&mut reader)?;
+        let values = <Vec<u64>>::read_le_args(
+            &mut reader,
+            VecArgs {
+                count: count as usize,
+                inner: <_>::default(),
+            },
+        )?;
+        Ok(values)
+    }
+
+    // The names of the delete conditions that were applied when this
+    // fragment was written. Cells removed by them are either absent or
+    // recorded in the delete timestamps.
+    pub fn load_processed_conditions(
+        &self,
+        vfs: &dyn VFSService,
+    ) -> Result<Vec<String>> {
+        if self.footer.version < 16 {
+            return Ok(Vec::new());
+        }
+
+        let offset = self.footer.tile_offsets.processed_conditions_offset;
+        let data =
+            storage::read_generic_tile(vfs, &self.metadata_uri(), offset)?;
+        let conditions = ProcessedConditions::read(&mut Cursor::new(data))
+            .map_err(|err| {
+                let context = format!("{:?}", err);
+                anyhow!("Error reading processed conditions from {}", self.uri)
+                    .context(context)
+            })?;
+        Ok(conditions
+            .names
+            .into_iter()
+            .map(|n| String::from_utf8_lossy(&n.name).to_string())
+            .collect())
+    }
+
+    pub fn load_rtree(
+        &self,
+        vfs: &dyn VFSService,
+        schema: &array::Schema,
+    ) -> Result<storage::RTree> {
+        if let Some(inline) = &self.inline {
+            let dtypes: Vec<DataType> = schema
+                .domain()
+                .dimensions()
+                .iter()
+                .map(|d| d.data_type())
+                .collect();
+            return Ok(storage::RTree::build(
+                &dtypes,
+                storage::RTREE_FANOUT,
+                inline.mbrs.clone(),
+            ));
+        }
+
+        let offset = self.footer.tile_offsets.rtree;
+        let data =
+            storage::read_generic_tile(vfs, &self.metadata_uri(), offset)?;
+        storage::RTree::read_args(
+            &mut Cursor::new(data),
+            (self.footer.version, dimension_sizes(schema)),
+        )
+        .map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error reading R-tree from {}", self.uri).context(context)
+        })
+    }
+
+    // The min, max, sum and null count of every tile of a field. Fragments
+    // older than version 11 record no statistics.
+    pub fn load_tile_stats(
+        &self,
+        vfs: &dyn VFSService,
+        field: &Field,
+    ) -> Result<Vec<storage::TileStats>> {
+        if self.footer.version < 11 {
+            return Ok(Vec::new());
+        }
+
+        let tile_offsets = &self.footer.tile_offsets;
+        let idx = field.idx;
+        let tile_num = self
+            .load_u64_tile(vfs, tile_offsets.fixed_offsets[idx])?
+            .len();
+
+        let uri = self.metadata_uri();
+        let min = storage::read_generic_tile(
+            vfs,
+            &uri,
+            tile_offsets.min_offsets[idx],
+        )?;
+        let max = storage::read_generic_tile(
+            vfs,
+            &uri,
+            tile_offsets.max_offsets[idx],
+        )?;
+        let sum = storage::read_generic_tile(
+            vfs,
+            &uri,
+            tile_offsets.sum_offsets[idx],
+        )?;
+        let null_counts =
+            self.load_u64_tile(vfs, tile_offsets.null_count_offsets[idx])?;
+
+        storage::tile_stats(field, tile_num, &min, &max, &sum, &null_counts)
+            .map_err(|err| {
+                err.context(format!(
+                    "Error reading tile statistics of '{}' from {}",
+                    field.name, self.uri
+                ))
+            })
+    }
+
+    pub fn load_tile_index(
+        &self,
+        vfs: &dyn VFSService,
+        field: &Field,
+    ) -> Result<TileIndex> {
+        let file_offsets = &self.footer.file_offsets;
+        let tile_offsets = &self.footer.tile_offsets;
+        let idx = self.stored_field_idx(field);
+
+        if let Some(inline) = &self.inline {
+            let var_offsets = if field.var_sized {
+                inline.tile_var_offsets[idx].values.clone()
+            } else {
+                Vec::new()
+            };
+            return Ok(TileIndex {
+                offsets: inline.tile_offsets[idx].values.clone(),
+                file_size: file_offsets.fixed_sizes[idx],
+                var_offsets,
+                var_file_size: file_offsets.var_sizes[idx],
+                validity_offsets: Vec::new(),
+                validity_file_size: 0,
+            });
+        }
+
+        let offsets =
+            self.load_u64_tile(vfs, tile_offsets.fixed_offsets[idx])?;
+
+        let var_offsets = if field.var_sized {
+            self.load_u64_tile(vfs, tile_offsets.var_offsets[idx])?
+        } else {
+            Vec::new()
+        };
+
+        let validity_offsets = if field.nullable {
+            self.load_u64_tile(vfs, tile_offsets.validity_offsets[idx])?
+        } else {
+            Vec::new()
+        };
+
+        Ok(TileIndex {
+            offsets,
+            file_size: file_offsets.fixed_sizes[idx],
+            var_offsets,
+            var_file_size: file_offsets.var_sizes[idx],
+            validity_offsets,
+            validity_file_size: file_offsets
+                .validity_sizes
+                .get(idx)
+                .copied()
+                .unwrap_or(0),
+        })
+    }
+
+    pub fn read_tile(
+        &self,
+        vfs: &dyn VFSService,
+        field: &Field,
+        index: &TileIndex,
+        tile: usize,
+    ) -> Result<storage::Tile> {
+        if tile >= index.tile_num() {
+            return Err(anyhow!(
+                "Invalid tile {} for field '{}' in {}",
+                tile,
+                field.name,
+                self.uri
+            ));
+        }
+
+        let (offset, size) =
+            persisted_range(&index.offsets, index.file_size, tile);
+
+        let (data, offsets) = if field.var_sized {
+            let raw = storage::read_data_tile(
+                vfs,
+                &self.field_uri(field),
+                offset,
+                size,
+                field.offsets_filters,
+            )?;
+            let (var_offset, var_size) =
+                persisted_range(&index.var_offsets, index.var_file_size, tile);
+            let data = storage::read_data_tile(
+                vfs,
+                &self.field_var_uri(field),
+                var_offset,
+                var_size,
+                field.filters,
+            )?;
+            (data, Some(datatype::values_from_bytes::<u64>(&raw)))
+        } else {
+            let mut data = storage::read_data_tile(
+                vfs,
+                &self.field_uri(field),
+                offset,
+                size,
+                field.filters,
+            )?;
+            if self.stored_field_idx(field) != field.idx {
+                let dim = field.idx - self.num_attributes - 1;
+                let size = data.len() / self.num_dimensions;
+                data = data[dim * size..(dim + 1) * size].to_vec();
+            }
+            (data, None)
+        };
+
+        let validity = if field.nullable {
+            let (offset, size) = persisted_range(
+                &index.validity_offsets,
+                index.validity_file_size,
+                tile,
+            );
+            Some(storage::read_data_tile(
+                vfs,
+                &self.field_validity_uri(field),
+                offset,
+                size,
+                field.validity_filters,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(storage::Tile::new(data, field.cell_size, offsets, validity))
+    }
+
+    // Parse the chunk headers of every file storing a tile without
+    // unfiltering its data.
+    pub fn read_tile_chunks(
+        &self,
+        vfs: &dyn VFSService,
+        field: &Field,
+        index: &TileIndex,
+        tile: usize,
+    ) -> Result<()> {
+        let (offset, size) =
+            persisted_range(&index.offsets, index.file_size, tile);
+        storage::read_data_tile_chunks(
+            vfs,
+            &self.field_uri(field),
+            offset,
+            size,
+        )?;
+
+        if field.var_sized {
+            let (offset, size) =
+                persisted_range(&index.var_offsets, index.var_file_size, tile);
+            storage::read_data_tile_chunks(
+                vfs,
+                &self.field_var_uri(field),
+                offset,
+                size,
+            )?;
+        }
+
+        if field.nullable {
+            let (offset, size) = persisted_range(
+                &index.validity_offsets,
+                index.validity_file_size,
+                tile,
+            );
+            storage::read_data_tile_chunks(
+                vfs,
+                &self.field_validity_uri(field),
+                offset,
+                size,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// The footer equivalent of the metadata of versions 1 and 2. Their
+// fragments are dense unless they have coordinates and hence MBRs.
+fn inline_footer(inline: &InlineMetadata) -> FragmentFooter {
+    FragmentFooter {
+        version: inline.version,
+        array_schema_name_size: 0,
+        array_schema_name: String::new(),
+        dense: inline.mbrs.is_empty() as u8,
+        non_empty_domain: inline.non_empty_domain.clone(),
+        sparse_tile_num: inline.mbr_num,
+        last_tile_cell_num: inline.last_tile_cell_num,
+        has_timestamps: 0,
+        has_delete_meta: 0,
+        file_offsets: FragmentFileOffsets {
+            fixed_sizes: inline.file_sizes.clone(),
+            var_sizes: inline.file_var_sizes.clone(),
+            validity_sizes: Vec::new(),
+        },
+        tile_offsets: FragmentTileOffsets {
+            rtree: 0,
+            fixed_offsets: Vec::new(),
+            var_offsets: Vec::new(),
+            var_sizes: Vec::new(),
+            validity_offsets: Vec::new(),
+            min_offsets: Vec::new(),
+            max_offsets: Vec::new(),
+            sum_offsets: Vec::new(),
+            null_count_offsets: Vec::new(),
+            frag_meta_offset: 0,
+            processed_conditions_offset: 0,
+        },
+    }
+}
+
+// The base name of the files storing a field. Starting with version 9
+// files are named by field index rather than by field name. Before
+// version 5 the dimensions share the coordinates file.
+pub(crate) fn field_file_name(
+    format_version: u32,
+    num_attributes: usize,
+    field: &Field,
+) -> String {
+    if format_version < 5 && field.idx >= num_attributes {
+        return COORDS_NAME.to_string();
+    }
+
+    if format_version <= 7 {
+        return field.name.clone();
+    }
+
+    if format_version == 8 {
+        return encode_name(&field.name);
+    }
+
+    match field.name.as_str() {
+        TIMESTAMPS_NAME => return "t".to_string(),
+        DELETE_TIMESTAMPS_NAME => return "dt".to_string(),
+        DELETE_CONDITION_INDEX_NAME => return "dci".to_string(),
+        _ => (),
+    }
+
+    if field.idx < num_attributes {
+        format!("a{}", field.idx)
+    } else {
+        format!("d{}", field.idx - num_attributes - 1)
+    }
+}
+
+// Format version 8 percent encoded characters that are not allowed in file
+// names on some file systems.
+fn encode_name(name: &str) -> String {
+    let mut ret = String::new();
+    for c in name.chars() {
+        if "!#$%&'()*+,/:;=?@[]".contains(c) {
+            ret += &format!("%{:02X}", c as u32);
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
 }
 
 #[cfg(test)]
@@ -313,6 +1500,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn timestamp_range_test() {
+        assert_eq!(
+            timestamp_range("__0123456789abcdef0123456789abcdef_5").unwrap(),
+            (5, 5)
+        );
+        assert_eq!(
+            timestamp_range("__0123456789abcdef0123456789abcdef_5_7").unwrap(),
+            (5, 7)
+        );
+        assert_eq!(
+            timestamp_range("__3_4_0123456789abcdef0123456789abcdef").unwrap(),
+            (3, 4)
+        );
+        assert_eq!(
+            timestamp_range("__1_2_0123456789abcdef0123456789abcdef_20")
+                .unwrap(),
+            (1, 2)
+        );
+        assert!(timestamp_range("__a_b_0123456789abcdef0123456789abcdef_20")
+            .is_err());
+        assert!(timestamp_range("not_a_fragment").is_err());
+
+        let name = timestamped_name(8, 9);
+        assert_eq!(timestamp_range(&name).unwrap(), (8, 9));
+        assert_eq!(
+            get_fragment_name_version(&name),
+            FragmentNameVersion::Three
+        );
+    }
+
     #[test]
     fn fragment_version_error() {
         let (name, _) = generate_v3_name();
@@ -0,0 +1,201 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead, BinWrite};
+
+use anyhow::anyhow;
+
+use crate::Result;
+
+pub const GROUP_FILE_NAME: &str = "__tiledb_group.tdb";
+pub const GROUP_DETAILS_DIR: &str = "__group";
+
+// Group details before version 2 hold every member of the group. Later
+// versions only hold the members added or removed at their timestamp.
+pub const GROUP_DETAILS_VERSION: u32 = 2;
+pub const GROUP_MEMBER_VERSION: u32 = 2;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectType {
+    #[default]
+    Invalid = 0,
+    Group = 1,
+    Array = 2,
+}
+
+impl From<u8> for ObjectType {
+    fn from(orig: u8) -> Self {
+        match orig {
+            1 => ObjectType::Group,
+            2 => ObjectType::Array,
+            _ => ObjectType::Invalid,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[binrw]
+#[brw(little)]
+pub struct GroupMember {
+    pub(crate) version: u32,
+
+    pub(crate) object_type: u8,
+
+    pub(crate) relative: u8,
+
+    pub(crate) uri_size: u32,
+
+    #[br(count(uri_size))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(map = |u: &String| u.as_bytes().to_vec())]
+    pub(crate) uri: String,
+
+    pub(crate) name_set: u8,
+
+    #[br(if(name_set != 0))]
+    #[bw(if(*name_set != 0))]
+    pub(crate) name_size: u32,
+
+    #[br(count(name_size))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(map = |n: &String| n.as_bytes().to_vec())]
+    pub(crate) name: String,
+
+    #[br(if(version >= 2))]
+    #[bw(if(*version >= 2))]
+    pub(crate) deleted: u8,
+}
+
+impl GroupMember {
+    pub fn new(
+        object_type: ObjectType,
+        uri: &str,
+        relative: bool,
+        name: Option<&str>,
+        deleted: bool,
+    ) -> Self {
+        let name = name.unwrap_or_default();
+        GroupMember {
+            version: GROUP_MEMBER_VERSION,
+            object_type: object_type as u8,
+            relative: relative as u8,
+            uri_size: uri.len() as u32,
+            uri: uri.to_string(),
+            name_set: (!name.is_empty()) as u8,
+            name_size: name.len() as u32,
+            name: name.to_string(),
+            deleted: deleted as u8,
+        }
+    }
+
+    pub fn object_type(&self) -> ObjectType {
+        self.object_type.into()
+    }
+
+    pub fn is_relative(&self) -> bool {
+        self.relative != 0
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        if self.name_set != 0 {
+            Some(&self.name)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted != 0
+    }
+}
+
+// The contents of a group details file, which is a single generic tile.
+#[derive(Debug)]
+#[binrw]
+#[brw(little)]
+pub struct GroupDetails {
+    pub(crate) version: u32,
+
+    pub(crate) num_members: u64,
+
+    #[br(count(num_members))]
+    pub(crate) members: Vec<GroupMember>,
+}
+
+impl GroupDetails {
+    pub fn new(members: Vec<GroupMember>) -> Self {
+        GroupDetails {
+            version: GROUP_DETAILS_VERSION,
+            num_members: members.len() as u64,
+            members,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        GroupDetails::read(&mut Cursor::new(data)).map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error reading group details").context(context)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn members(&self) -> &[GroupMember] {
+        &self.members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_one_members() -> Result<()> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&[ObjectType::Array as u8, 1]);
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(b"arr");
+        data.push(0);
+
+        let details = GroupDetails::parse(&data)?;
+        assert_eq!(details.version(), 1);
+        let member = &details.members()[0];
+        assert_eq!(member.object_type(), ObjectType::Array);
+        assert!(member.is_relative());
+        assert_eq!(member.uri(), "arr");
+        assert_eq!(member.name(), None);
+        assert!(!member.is_deleted());
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let details = GroupDetails::new(vec![
+            GroupMember::new(ObjectType::Group, "sub", true, Some("s"), false),
+            GroupMember::new(ObjectType::Array, "file:///a", false, None, true),
+        ]);
+        let parsed = GroupDetails::parse(&details.to_bytes()?)?;
+        assert_eq!(parsed.version(), GROUP_DETAILS_VERSION);
+        assert_eq!(parsed.members()[0].name(), Some("s"));
+        assert_eq!(parsed.members()[1].uri(), "file:///a");
+        assert!(parsed.members()[1].is_deleted());
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use binrw::helpers::until_eof;
+use binrw::io::Cursor;
+use binrw::{binrw, BinRead, BinWrite};
+
+use anyhow::anyhow;
+
+use crate::datatype::DataType;
+use crate::Result;
+
+// A single key of a metadata file. Every entry records its type and number
+// of values, and deleted keys have a type of zero and no values.
+#[derive(Clone, Debug)]
+#[binrw]
+#[brw(little)]
+pub struct MetadataEntry {
+    pub(crate) key_len: u32,
+
+    #[br(count(key_len))]
+    #[br(map = |v: Vec<u8>| String::from_utf8_lossy(&v).to_string())]
+    #[bw(map = |k: &String| k.as_bytes().to_vec())]
+    pub(crate) key: String,
+
+    pub(crate) del: u8,
+
+    pub(crate) value_type: u8,
+
+    pub(crate) value_num: u32,
+
+    #[br(count(value_num as usize * DataType::from(value_type).size()))]
+    pub(crate) value: Vec<u8>,
+}
+
+impl MetadataEntry {
+    pub fn new(key: &str, data_type: DataType, value: &[u8]) -> Self {
+        let size = data_type.size().max(1);
+        MetadataEntry {
+            key_len: key.len() as u32,
+            key: key.to_string(),
+            del: 0,
+            value_type: data_type as u8,
+            value_num: (value.len() / size) as u32,
+            value: value.to_vec(),
+        }
+    }
+
+    pub fn deleted(key: &str) -> Self {
+        MetadataEntry {
+            key_len: key.len() as u32,
+            key: key.to_string(),
+            del: 1,
+            value_type: 0,
+            value_num: 0,
+            value: Vec::new(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.del != 0
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.value_type.into()
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+// The contents of a metadata file, which is a single generic tile holding a
+// sequence of entries.
+#[derive(Debug, Default)]
+#[binrw]
+#[brw(little)]
+pub struct MetadataEntries {
+    #[br(parse_with = until_eof)]
+    pub(crate) entries: Vec<MetadataEntry>,
+}
+
+impl MetadataEntries {
+    pub fn new(entries: Vec<MetadataEntry>) -> Self {
+        MetadataEntries { entries }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        MetadataEntries::read(&mut Cursor::new(data)).map_err(|err| {
+            let context = format!("{:?}", err);
+            anyhow!("Error reading metadata entries").context(context)
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
+
+    pub fn entries(&self) -> &[MetadataEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let entries = MetadataEntries::new(vec![
+            MetadataEntry::new("a", DataType::Int32, &7i32.to_le_bytes()),
+            MetadataEntry::deleted("b"),
+            MetadataEntry::new("c", DataType::StringUtf8, b"hello"),
+        ]);
+        let data = entries.to_bytes()?;
+
+        // key_len, key, del, type, num, value
+        assert_eq!(&data[..4], &1u32.to_le_bytes());
+        assert_eq!(data[4], b'a');
+        assert_eq!(data[5], 0);
+        assert_eq!(data[6], DataType::Int32 as u8);
+
+        let parsed = MetadataEntries::parse(&data)?;
+        let parsed = parsed.entries();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].key(), "a");
+        assert_eq!(parsed[0].value(), &7i32.to_le_bytes());
+        assert!(parsed[1].is_deleted());
+        assert_eq!(parsed[2].data_type(), DataType::StringUtf8);
+        assert_eq!(parsed[2].value(), b"hello");
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_entry_layout() -> Result<()> {
+        // TileDB writes the type and number of values of deleted keys too.
+        let entries = MetadataEntries::new(vec![MetadataEntry::deleted("key")]);
+        let data = entries.to_bytes()?;
+        let mut expected = 3u32.to_le_bytes().to_vec();
+        expected.extend(b"key");
+        expected.extend([1, 0]);
+        expected.extend(0u32.to_le_bytes());
+        assert_eq!(data, expected);
+
+        // Followed by a live entry to check that nothing is misaligned.
+        expected.extend(1u32.to_le_bytes());
+        expected.extend(b"k");
+        expected.extend([0, DataType::Int64 as u8]);
+        expected.extend(1u32.to_le_bytes());
+        expected.extend(5i64.to_le_bytes());
+        let parsed = MetadataEntries::parse(&expected)?;
+        let parsed = parsed.entries();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed[0].is_deleted());
+        assert_eq!(parsed[0].key(), "key");
+        assert_eq!(parsed[1].key(), "k");
+        assert_eq!(parsed[1].value(), &5i64.to_le_bytes());
+        assert_eq!(MetadataEntries::new(parsed.to_vec()).to_bytes()?, expected);
+        Ok(())
+    }
+}
@@ -1,14 +1,33 @@
 // This file is part of tdbtk released under the MIT license.
 // Copyright (c) 2023 TileDB, Inc.
 
+pub mod condition;
+pub mod consolidated;
+pub mod enumeration;
 pub mod filter;
 pub mod fragment;
+pub mod group;
+pub mod metadata;
+pub mod rtree;
 pub mod schema;
+pub mod stats;
 pub mod tile;
+pub mod writer;
 
 pub const CURRENT_FORMAT_VERSION: u32 = 21;
 
+// The oldest format version whose fragments can be read and written.
+pub const MIN_FORMAT_VERSION: u32 = 1;
+
+pub use crate::storage::condition::*;
+pub use crate::storage::consolidated::*;
+pub use crate::storage::enumeration::*;
 pub use crate::storage::filter::*;
 pub use crate::storage::fragment::*;
+pub use crate::storage::group::*;
+pub use crate::storage::metadata::*;
+pub use crate::storage::rtree::*;
 pub use crate::storage::schema::*;
+pub use crate::storage::stats::*;
 pub use crate::storage::tile::*;
+pub use crate::storage::writer::*;
@@ -0,0 +1,167 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use binrw::{binrw, BinRead, BinResult, BinWrite};
+
+use crate::array;
+use crate::datatype::DataType;
+use crate::storage::fragment::{ranges_parser, ranges_writer};
+
+// A minimum bounding rectangle, one range per dimension.
+pub type Mbr = Vec<array::Range>;
+
+// The fanout of R-trees built by the writer, which matches TileDB's.
+pub const RTREE_FANOUT: u32 = 10;
+
+// The R-tree of a sparse fragment. Levels are stored from the root down so
+// the last level holds one MBR per data tile. Before version 5 the tree
+// also records the number of dimensions and their shared datatype.
+#[derive(Debug, Default)]
+#[binrw]
+#[brw(little)]
+#[br(import (version: u32, dims: Vec<Option<usize>>))]
+#[bw(import (version: u32, dims: Vec<Option<usize>>))]
+pub struct RTree {
+    #[brw(if(version < 5))]
+    pub(crate) dim_num: u32,
+
+    pub(crate) fanout: u32,
+
+    #[brw(if(version < 5))]
+    pub(crate) data_type: u8,
+
+    pub(crate) level_num: u32,
+
+    #[br(parse_with = levels_parser, args(level_num, dims))]
+    #[bw(write_with = levels_writer, args(dims))]
+    pub(crate) levels: Vec<Vec<Mbr>>,
+}
+
+impl RTree {
+    // Build the tree over the MBRs of a fragment's data tiles by grouping
+    // each level's MBRs by the fanout until a single root remains.
+    pub fn build(dtypes: &[DataType], fanout: u32, leaves: Vec<Mbr>) -> Self {
+        let mut levels = Vec::new();
+        if !leaves.is_empty() {
+            levels.push(leaves);
+        }
+
+        while let Some(level) = levels.last().filter(|l| l.len() > 1) {
+            let parents = level
+                .chunks(fanout.max(2) as usize)
+                .map(|children| mbr_union(dtypes, children))
+                .collect();
+            levels.push(parents);
+        }
+        levels.reverse();
+
+        RTree {
+            dim_num: dtypes.len() as u32,
+            fanout,
+            data_type: dtypes.first().map_or(0, |d| *d as u8),
+            level_num: levels.len() as u32,
+            levels,
+        }
+    }
+
+    pub fn fanout(&self) -> u32 {
+        self.fanout
+    }
+
+    pub fn levels(&self) -> &[Vec<Mbr>] {
+        &self.levels
+    }
+
+    pub fn leaves(&self) -> &[Mbr] {
+        self.levels.last().map_or(&[], |l| l.as_slice())
+    }
+}
+
+// The smallest MBR containing every given MBR.
+pub fn mbr_union(dtypes: &[DataType], mbrs: &[Mbr]) -> Mbr {
+    let mut ret: Mbr = match mbrs.first() {
+        Some(first) => first.clone(),
+        None => return Vec::new(),
+    };
+
+    for mbr in mbrs[1..].iter() {
+        for ((dtype, range), other) in
+            dtypes.iter().zip(ret.iter_mut()).zip(mbr)
+        {
+            let start = if dtype.compare(other.start(), range.start())
+                == Ordering::Less
+            {
+                other.start()
+            } else {
+                range.start()
+            };
+            let end = if dtype.compare(other.end(), range.end())
+                == Ordering::Greater
+            {
+                other.end()
+            } else {
+                range.end()
+            };
+            *range = array::Range::new(start.to_vec(), end.to_vec());
+        }
+    }
+    ret
+}
+
+#[binrw::parser(reader, endian)]
+fn levels_parser(
+    level_num: u32,
+    dims: Vec<Option<usize>>,
+) -> BinResult<Vec<Vec<Mbr>>> {
+    let mut levels = Vec::new();
+    for _ in 0..level_num {
+        let mbr_num = <u64>::read_options(reader, endian, ())?;
+        let mut level = Vec::new();
+        for _ in 0..mbr_num {
+            level.push(ranges_parser(reader, endian, (dims.clone(),))?);
+        }
+        levels.push(level);
+    }
+    Ok(levels)
+}
+
+#[binrw::writer(writer, endian)]
+fn levels_writer(
+    levels: &Vec<Vec<Mbr>>,
+    dims: Vec<Option<usize>>,
+) -> BinResult<()> {
+    for level in levels {
+        <u64>::write_options(&(level.len() as u64), writer, endian, ())?;
+        for mbr in level {
+            ranges_writer(mbr, writer, endian, (dims.clone(),))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_levels() {
+        let leaves: Vec<Mbr> = (0..5i32)
+            .map(|i| vec![array::Range::from_values(i * 10, i * 10 + 5)])
+            .collect();
+        let rtree = RTree::build(&[DataType::Int32], 2, leaves);
+        let sizes: Vec<usize> =
+            rtree.levels().iter().map(|l| l.len()).collect();
+        assert_eq!(sizes, vec![1, 2, 3, 5]);
+        assert_eq!(
+            rtree.levels()[0][0],
+            vec![array::Range::from_values(0i32, 45)]
+        );
+        assert_eq!(
+            rtree.levels()[2][2],
+            vec![array::Range::from_values(40i32, 45)]
+        );
+        assert_eq!(RTree::build(&[DataType::Int32], 2, vec![]).level_num, 0);
+    }
+}
@@ -7,18 +7,21 @@ use anyhow::anyhow;
 use binrw::io::Cursor;
 use binrw::{binrw, BinRead, BinResult, BinWrite, Error, VecArgs};
 
-use crate::array::{ArrayType, DataOrder, Layout};
+use crate::array::{self, ArrayType, DataOrder, Layout};
 use crate::datatype::DataType;
+use crate::io::service::VFSService;
 use crate::io::uri;
 use crate::storage;
 use crate::Result;
 
 pub const CELL_VAR_SIZE: u32 = u32::MAX;
+pub const DEFAULT_CAPACITY: u64 = 10000;
 
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
 #[br(import (version: u32, dtype: DataType, coords_filters: storage::FilterList))]
+#[bw(import (version: u32))]
 pub struct Dimension {
     name_size: u32,
 
@@ -26,18 +29,22 @@ pub struct Dimension {
     pub(crate) name: Vec<u8>,
 
     #[br(if(version >= 5, dtype))]
+    #[bw(if(version >= 5))]
     #[br(map = |dtype: u8| dtype.into())]
     #[bw(map = |dtype: &DataType| *dtype as u8)]
     #[brw(assert(!matches!(data_type, DataType::Invalid)))]
     pub(crate) data_type: DataType,
 
     #[br(if(version >= 5, cell_val_size(dtype)))]
+    #[bw(if(version >= 5))]
     pub(crate) cell_val_num: u32,
 
     #[br(if(version >= 5, coords_filters))]
+    #[bw(if(version >= 5))]
     pub(crate) coords_filters: storage::FilterList,
 
     #[br(if(version >= 5, 2 * data_type.size() as u64))]
+    #[bw(if(version >= 5))]
     domain_size: u64,
 
     #[br(count = domain_size)]
@@ -50,15 +57,63 @@ pub struct Dimension {
     pub(crate) tile_extent: Vec<u8>,
 }
 
+impl Dimension {
+    // A fixed size dimension over an inclusive domain.
+    pub fn new(name: &str, data_type: DataType, domain: &array::Range) -> Self {
+        let mut range = domain.start().to_vec();
+        range.extend_from_slice(domain.end());
+        Dimension {
+            name_size: name.len() as u32,
+            name: name.as_bytes().to_vec(),
+            data_type,
+            cell_val_num: 1,
+            coords_filters: storage::FilterList::default(),
+            domain_size: range.len() as u64,
+            range,
+            null_tile_extent: 1,
+            tile_extent: Vec::new(),
+        }
+    }
+
+    // A var sized string dimension, which has no domain or tile extent.
+    pub fn new_var(name: &str, data_type: DataType) -> Self {
+        Dimension {
+            name_size: name.len() as u32,
+            name: name.as_bytes().to_vec(),
+            data_type,
+            cell_val_num: CELL_VAR_SIZE,
+            coords_filters: storage::FilterList::default(),
+            domain_size: 0,
+            range: Vec::new(),
+            null_tile_extent: 1,
+            tile_extent: Vec::new(),
+        }
+    }
+
+    pub fn set_extent(mut self, extent: Vec<u8>) -> Self {
+        self.null_tile_extent = 0;
+        self.tile_extent = extent;
+        self
+    }
+
+    pub fn set_filters(mut self, filters: storage::FilterList) -> Self {
+        self.coords_filters = filters;
+        self
+    }
+}
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
 #[br(import { version: u32, coords_filters: storage::FilterList })]
+#[bw(import ( version: u32 ))]
 pub struct Domain {
+    // Only written before version 5 when every dimension shared a type.
     #[br(if(version < 5, DataType::Int32))]
+    #[bw(if(version < 5))]
     #[br(map = |dtype: u8| dtype.into())]
     #[bw(map = |dtype: &DataType| *dtype as u8)]
-    #[brw(assert(!matches!(data_type, DataType::Invalid)))]
+    #[br(assert(!matches!(data_type, DataType::Invalid)))]
     data_type: DataType,
 
     num_dimensions: u32,
@@ -68,13 +123,27 @@ pub struct Domain {
         data_type,
         coords_filters
     )})]
+    #[bw(args(version))]
     pub(crate) dimensions: Vec<Dimension>,
 }
 
-#[derive(Debug)]
+impl Domain {
+    pub fn new(dimensions: Vec<Dimension>) -> Self {
+        Domain {
+            data_type: dimensions
+                .first()
+                .map_or(DataType::Int32, |d| d.data_type),
+            num_dimensions: dimensions.len() as u32,
+            dimensions,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 #[binrw]
 #[brw(little)]
 #[br(import ( version: u32 ))]
+#[bw(import ( version: u32 ))]
 pub struct Attribute {
     name_size: u32,
 
@@ -94,30 +163,97 @@ pub struct Attribute {
     pub(crate) filters: storage::FilterList,
 
     #[br(if(version >= 6, 0))]
+    #[bw(if(version >= 6))]
     fill_value_size: u64,
 
     #[br(count = fill_value_size)]
+    #[bw(if(version >= 6))]
     pub(crate) fill_value: Vec<u8>,
 
     #[br(if(version >= 7, 0))]
+    #[bw(if(version >= 7))]
     pub(crate) nullable: u8,
 
     #[br(if(version >= 7, 0))]
+    #[bw(if(version >= 7))]
     pub(crate) fill_value_validity: u8,
 
     #[br(if(version >= 17, DataOrder::Unordered))]
+    #[bw(if(version >= 17))]
     #[br(map = |order: u8| order.into())]
     #[bw(map = |order: &DataOrder| *order as u8)]
     #[brw(assert(!matches!(data_order, DataOrder::Invalid)))]
     pub(crate) data_order: DataOrder,
 
     #[br(if(version >= 20, 0))]
+    #[bw(if(version >= 20))]
     enmr_name_length: u32,
 
     #[br(count = enmr_name_length)]
+    #[bw(if(version >= 20))]
     pub(crate) enumeration_name: Vec<u8>,
 }
 
+impl Attribute {
+    pub fn new(name: &str, data_type: DataType) -> Self {
+        Attribute {
+            name_size: name.len() as u32,
+            name: name.to_string(),
+            data_type,
+            cell_val_num: 1,
+            filters: storage::FilterList::default(),
+            fill_value_size: 0,
+            fill_value: Vec::new(),
+            nullable: 0,
+            fill_value_validity: 0,
+            data_order: DataOrder::Unordered,
+            enmr_name_length: 0,
+            enumeration_name: Vec::new(),
+        }
+        .set_default_fill_value()
+    }
+
+    // Changing the number of values per cell resets the fill value to the
+    // default for the new cell size.
+    pub fn set_cell_val_num(mut self, cell_val_num: u32) -> Self {
+        self.cell_val_num = cell_val_num;
+        self.set_default_fill_value()
+    }
+
+    pub fn set_nullable(mut self, nullable: bool) -> Self {
+        self.nullable = nullable as u8;
+        self
+    }
+
+    pub fn set_filters(mut self, filters: storage::FilterList) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn set_fill_value(mut self, value: Vec<u8>, valid: bool) -> Self {
+        self.fill_value_size = value.len() as u64;
+        self.fill_value = value;
+        self.fill_value_validity = valid as u8;
+        self
+    }
+
+    pub fn set_enumeration_name(mut self, name: &str) -> Self {
+        self.enmr_name_length = name.len() as u32;
+        self.enumeration_name = name.as_bytes().to_vec();
+        self
+    }
+
+    fn set_default_fill_value(self) -> Self {
+        let value = self.data_type.default_fill_value();
+        let value = if self.cell_val_num == CELL_VAR_SIZE {
+            value
+        } else {
+            value.repeat(self.cell_val_num as usize)
+        };
+        self.set_fill_value(value, false)
+    }
+}
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
@@ -156,6 +292,34 @@ pub struct DimensionLabel {
     pub(crate) is_external: u8,
 }
 
+impl DimensionLabel {
+    // A label stored in an array at a path relative to the labelled array,
+    // holding one label per index in a single value attribute.
+    pub fn new(
+        dimension_id: u32,
+        name: &str,
+        uri: &str,
+        attribute_name: &str,
+        data_order: DataOrder,
+        data_type: DataType,
+    ) -> Self {
+        DimensionLabel {
+            dimension_id,
+            name_len: name.len() as u32,
+            name: name.as_bytes().to_vec(),
+            relative_uri: 1,
+            uri_size: uri.len() as u64,
+            uri: uri.as_bytes().to_vec(),
+            attribute_name_len: attribute_name.len() as u32,
+            attribute_name: attribute_name.as_bytes().to_vec(),
+            data_order,
+            data_type,
+            cell_val_num: 1,
+            is_external: 0,
+        }
+    }
+}
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
@@ -166,6 +330,7 @@ pub struct ArraySchema {
     pub(crate) version: u32,
 
     #[br(if(version >= 5, 0))]
+    #[bw(if(*version >= 5))]
     pub(crate) allows_dups: u8,
 
     #[br(map = |atype: u8| atype.into())]
@@ -193,9 +358,11 @@ pub struct ArraySchema {
 
     #[br(args(version))]
     #[br(if(version >= 7, storage::FilterList::default()))]
+    #[bw(if(*version >= 7))]
     pub(crate) cell_validity_filters: storage::FilterList,
 
     #[br(args { version, coords_filters: coords_filters.clone() })]
+    #[bw(args(*version))]
     pub(crate) domain: Domain,
 
     pub(crate) num_attributes: u32,
@@ -203,30 +370,173 @@ pub struct ArraySchema {
     #[br(count = num_attributes, args {inner: (
         version,
     )})]
+    #[bw(args(*version))]
     pub(crate) attributes: Vec<Attribute>,
 
     #[br(if(version >= 18, 0))]
+    #[bw(if(*version >= 18))]
     pub(crate) num_dimension_labels: u32,
 
     #[br(count = num_dimension_labels)]
+    #[bw(if(*version >= 18))]
     pub(crate) dimension_labels: Vec<DimensionLabel>,
 
-    #[br(parse_with = enumeration_name_map_parser)]
+    #[br(parse_with = enumeration_name_map_parser, args(version))]
+    #[bw(if(*version >= 20))]
     #[bw(write_with = enumeration_name_map_writer)]
     pub(crate) enumeration_map: HashMap<String, String>,
 }
 
 impl ArraySchema {
-    pub fn load(uri: &uri::URI) -> Result<ArraySchema> {
-        let data = storage::read_generic_tile(uri, 0)?;
+    pub fn new(
+        array_type: ArrayType,
+        domain: Domain,
+        attributes: Vec<Attribute>,
+    ) -> Self {
+        ArraySchema {
+            version: storage::CURRENT_FORMAT_VERSION,
+            allows_dups: 0,
+            array_type,
+            tile_order: Layout::RowMajor,
+            cell_order: Layout::RowMajor,
+            capacity: DEFAULT_CAPACITY,
+            coords_filters: storage::FilterList::default(),
+            cell_var_filters: storage::FilterList::default(),
+            cell_validity_filters: storage::FilterList::default(),
+            domain,
+            num_attributes: attributes.len() as u32,
+            attributes,
+            num_dimension_labels: 0,
+            dimension_labels: Vec::new(),
+            enumeration_map: HashMap::new(),
+        }
+    }
+
+    // Defaults to the current format version.
+    pub fn set_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    // Convert a schema loaded from an older format version to the current
+    // version. Versions before 6 did not store fill values so attributes
+    // get the default fill value of their datatype.
+    pub fn upgrade(mut self) -> Self {
+        if self.version < 6 {
+            self.attributes = self
+                .attributes
+                .into_iter()
+                .map(|attr| attr.set_default_fill_value())
+                .collect();
+        }
+        self.version = storage::CURRENT_FORMAT_VERSION;
+        self
+    }
+
+    // Dimension labels require version 18.
+    pub fn add_dimension_label(mut self, label: DimensionLabel) -> Self {
+        self.dimension_labels.push(label);
+        self.num_dimension_labels = self.dimension_labels.len() as u32;
+        self
+    }
+
+    pub fn set_allows_dups(mut self, allows_dups: bool) -> Self {
+        self.allows_dups = allows_dups as u8;
+        self
+    }
+
+    pub fn set_tile_order(mut self, layout: Layout) -> Self {
+        self.tile_order = layout;
+        self
+    }
+
+    pub fn set_cell_order(mut self, layout: Layout) -> Self {
+        self.cell_order = layout;
+        self
+    }
+
+    pub fn set_capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn set_coords_filters(mut self, filters: storage::FilterList) -> Self {
+        self.coords_filters = filters;
+        self
+    }
+
+    pub fn set_cell_var_filters(
+        mut self,
+        filters: storage::FilterList,
+    ) -> Self {
+        self.cell_var_filters = filters;
+        self
+    }
+
+    pub fn set_cell_validity_filters(
+        mut self,
+        filters: storage::FilterList,
+    ) -> Self {
+        self.cell_validity_filters = filters;
+        self
+    }
+
+    pub fn load(vfs: &dyn VFSService, uri: &uri::URI) -> Result<ArraySchema> {
+        let data = storage::read_generic_tile(vfs, uri, 0)?;
+        ArraySchema::from_bytes(&data).map_err(|err| {
+            err.context(format!("Error reading schema data from {}", uri))
+        })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<ArraySchema> {
         let mut reader = Cursor::new(data);
-        let s = ArraySchema::read(&mut reader).map_err(|err| {
-            let context = format!("{:?}", err);
-            anyhow!("Error reading schema data from {}", uri.to_string())
-                .context(context)
-        })?;
+        ArraySchema::read(&mut reader)
+            .map_err(|err| anyhow!("Error parsing schema: {:?}", err))
+    }
 
-        Ok(s)
+    // Schemas may be written in older format versions as long as they only
+    // use features that the version supports.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let versions =
+            storage::MIN_FORMAT_VERSION..=storage::CURRENT_FORMAT_VERSION;
+        if !versions.contains(&self.version) {
+            return Err(anyhow!(
+                "Unable to write schema version {}, only versions {} to {} \
+                are supported",
+                self.version,
+                versions.start(),
+                versions.end()
+            ));
+        }
+        let dims = &self.domain.dimensions;
+        let heterogeneous = dims.iter().any(|d| {
+            d.cell_val_num != 1
+                || !d.coords_filters.filters().is_empty()
+                || d.data_type != dims[0].data_type
+        });
+        let required = [
+            (heterogeneous, 5),
+            (self.attributes.iter().any(|a| a.nullable != 0), 7),
+            (!self.dimension_labels.is_empty(), 18),
+            (!self.enumeration_map.is_empty(), 20),
+        ];
+        if let Some((_, version)) = required
+            .iter()
+            .find(|(used, version)| *used && self.version < *version)
+        {
+            return Err(anyhow!(
+                "Schema version {} does not support features of version {}",
+                self.version,
+                version
+            ));
+        }
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
+
+    pub fn store(&self, vfs: &dyn VFSService, uri: &uri::URI) -> Result<()> {
+        storage::write_generic_tile(vfs, uri, &self.to_bytes()?)
     }
 }
 
@@ -304,19 +614,28 @@ fn enumeration_name_map_writer(map: &HashMap<String, String>) -> BinResult<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::io::PosixVFSService;
 
     #[test]
     fn basic_read() -> Result<()> {
-        let _ = ArraySchema::load(&uri::URI::from_string(
-            "resources/schema/schema_1",
-        )?)?;
+        let vfs = PosixVFSService::default();
+        let _ = ArraySchema::load(
+            &vfs,
+            &uri::URI::from_string("resources/schema/schema_1")?,
+        )?;
         Ok(())
     }
 
     #[test]
     fn test_read() -> Result<()> {
-        let uri = uri::URI::from_string("/Users/davisp/github/tiledb/unit-test-arrays/v2_9_1/SPARSE_v2_9_1_UINT16_DATETIME_US/__schema/__1653499966512_1653499966512_8135e35bf7c9483892957c6e0bcbd86a")?;
-        let _ = ArraySchema::load(&uri)?;
+        let uri = uri::URI::from_string(
+            "resources/arrays/v9/sparse/__array_schema.tdb",
+        )?;
+        let vfs = PosixVFSService::default();
+        let schema = ArraySchema::load(&vfs, &uri)?;
+        assert_eq!(schema.version, 9);
+        assert_eq!(schema.domain.dimensions.len(), 2);
+        assert!(schema.attributes.iter().any(|a| a.nullable != 0));
 
         Ok(())
     }
@@ -0,0 +1,254 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use anyhow::anyhow;
+use binrw::io::Cursor;
+use binrw::BinRead;
+
+use crate::datatype::{DataType, Primitive};
+use crate::storage::Field;
+use crate::Result;
+
+// The sum of the values of a tile, stored as eight bytes holding an i64,
+// u64 or f64 depending on the type of the field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileSum {
+    Signed(i64),
+    Unsigned(u64),
+    Real(f64),
+}
+
+impl TileSum {
+    // The kind of sum kept for a datatype, if any, read from its bytes.
+    pub fn from_bytes(dtype: DataType, bytes: &[u8]) -> Option<TileSum> {
+        match dtype {
+            DataType::Uint8
+            | DataType::Uint16
+            | DataType::Uint32
+            | DataType::Uint64 => {
+                Some(TileSum::Unsigned(u64::from_bytes(bytes)))
+            }
+            dtype if dtype.is_integral_type() => {
+                Some(TileSum::Signed(i64::from_bytes(bytes)))
+            }
+            dtype if dtype.is_real_type() => {
+                Some(TileSum::Real(f64::from_bytes(bytes)))
+            }
+            _ => None,
+        }
+    }
+
+    // The sum of a single value, converted to the kind of sum kept for the
+    // datatype.
+    pub fn from_value(dtype: DataType, value: &[u8]) -> Option<TileSum> {
+        match TileSum::from_bytes(dtype, &[0; 8])? {
+            TileSum::Signed(_) => {
+                dtype.to_i128(value).map(|v| TileSum::Signed(v as i64))
+            }
+            TileSum::Unsigned(_) => {
+                dtype.to_i128(value).map(|v| TileSum::Unsigned(v as u64))
+            }
+            TileSum::Real(_) => dtype.to_f64(value).map(TileSum::Real),
+        }
+    }
+
+    // Integer sums that overflow return None.
+    pub fn checked_add(self, other: TileSum) -> Option<TileSum> {
+        match (self, other) {
+            (TileSum::Signed(lhs), TileSum::Signed(rhs)) => {
+                lhs.checked_add(rhs).map(TileSum::Signed)
+            }
+            (TileSum::Unsigned(lhs), TileSum::Unsigned(rhs)) => {
+                lhs.checked_add(rhs).map(TileSum::Unsigned)
+            }
+            (TileSum::Real(lhs), TileSum::Real(rhs)) => {
+                Some(TileSum::Real(lhs + rhs))
+            }
+            (sum, _) => Some(sum),
+        }
+    }
+
+    // Integer sums saturate rather than overflow when they are written, so
+    // a sum at either limit may be wrong.
+    pub fn is_saturated(self) -> bool {
+        match self {
+            TileSum::Signed(sum) => sum == i64::MIN || sum == i64::MAX,
+            TileSum::Unsigned(sum) => sum == u64::MAX,
+            TileSum::Real(_) => false,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            TileSum::Signed(sum) => sum as f64,
+            TileSum::Unsigned(sum) => sum as f64,
+            TileSum::Real(sum) => sum,
+        }
+    }
+}
+
+// The statistics recorded in the fragment metadata for a single tile of a
+// field. Null cells are excluded from the min, max and sum. Fixed sized
+// tiles holding only nulls record a min and max of zeros.
+#[derive(Clone, Debug)]
+pub struct TileStats {
+    data_type: DataType,
+    min: Option<Vec<u8>>,
+    max: Option<Vec<u8>>,
+    sum: Option<TileSum>,
+    null_count: Option<u64>,
+}
+
+impl TileStats {
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn min_bytes(&self) -> Option<&[u8]> {
+        self.min.as_deref()
+    }
+
+    pub fn max_bytes(&self) -> Option<&[u8]> {
+        self.max.as_deref()
+    }
+
+    // The min decoded as a single value, if the tile has one of that size.
+    pub fn min<T: Primitive>(&self) -> Option<T> {
+        decode(self.min.as_deref())
+    }
+
+    pub fn max<T: Primitive>(&self) -> Option<T> {
+        decode(self.max.as_deref())
+    }
+
+    pub fn sum(&self) -> Option<TileSum> {
+        self.sum
+    }
+
+    // Only nullable fields record null counts.
+    pub fn null_count(&self) -> Option<u64> {
+        self.null_count
+    }
+}
+
+fn decode<T: Primitive>(value: Option<&[u8]>) -> Option<T> {
+    value.filter(|v| v.len() == T::SIZE).map(T::from_bytes)
+}
+
+// Assemble the statistics of each tile from the decoded min, max, sum and
+// null count tiles of a field.
+pub(crate) fn tile_stats(
+    field: &Field,
+    tile_num: usize,
+    min: &[u8],
+    max: &[u8],
+    sum: &[u8],
+    null_counts: &[u64],
+) -> Result<Vec<TileStats>> {
+    let data_type = field.data_type();
+    let (cell_size, var_sized) = (field.cell_size(), field.is_var_sized());
+    let mins = min_max_values(min, cell_size, var_sized, tile_num)?;
+    let maxs = min_max_values(max, cell_size, var_sized, tile_num)?;
+    let sums = sum_values(data_type, sum, tile_num)?;
+
+    let mut ret = Vec::with_capacity(tile_num);
+    for tile in 0..tile_num {
+        ret.push(TileStats {
+            data_type,
+            min: mins.as_ref().map(|v| v[tile].clone()),
+            max: maxs.as_ref().map(|v| v[tile].clone()),
+            sum: sums.as_ref().map(|v| v[tile]),
+            null_count: null_counts.get(tile).copied(),
+        });
+    }
+    Ok(ret)
+}
+
+// Min and max tiles hold the sizes of a fixed buffer and a var buffer
+// followed by both buffers. Var sized fields store the offset of each
+// tile's value into the var buffer. An empty buffer means the field has no
+// min or max.
+fn min_max_values(
+    data: &[u8],
+    cell_size: usize,
+    var_sized: bool,
+    tile_num: usize,
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let mut reader = Cursor::new(data);
+    let size = <u64>::read_le(&mut reader)? as usize;
+    let var_size = <u64>::read_le(&mut reader)? as usize;
+    if size == 0 {
+        return Ok(None);
+    }
+
+    let start = reader.position() as usize;
+    if data.len() < start + size + var_size {
+        return Err(anyhow!("Truncated min/max tile"));
+    }
+    let buffer = &data[start..start + size];
+    let var_buffer = &data[start + size..start + size + var_size];
+
+    if !var_sized {
+        if size != cell_size * tile_num {
+            return Err(anyhow!(
+                "Min/max tile has {} bytes, expected {}",
+                size,
+                cell_size * tile_num
+            ));
+        }
+        return Ok(Some(
+            buffer.chunks(cell_size).map(|v| v.to_vec()).collect(),
+        ));
+    }
+
+    if size != 8 * tile_num {
+        return Err(anyhow!(
+            "Min/max tile has {} offsets, expected {}",
+            size / 8,
+            tile_num
+        ));
+    }
+    let offsets: Vec<usize> = buffer
+        .chunks(8)
+        .map(|o| u64::from_bytes(o) as usize)
+        .collect();
+    let mut ret = Vec::with_capacity(tile_num);
+    for (tile, start) in offsets.iter().enumerate() {
+        let end = offsets.get(tile + 1).copied().unwrap_or(var_size);
+        if *start > end || end > var_size {
+            return Err(anyhow!("Invalid min/max offset for tile {}", tile));
+        }
+        ret.push(var_buffer[*start..end].to_vec());
+    }
+    Ok(Some(ret))
+}
+
+// Sum tiles hold a count followed by eight bytes per tile, or a count of
+// zero when the field has no sums.
+fn sum_values(
+    data_type: DataType,
+    data: &[u8],
+    tile_num: usize,
+) -> Result<Option<Vec<TileSum>>> {
+    let mut reader = Cursor::new(data);
+    let count = <u64>::read_le(&mut reader)? as usize;
+    if count == 0 {
+        return Ok(None);
+    }
+    if count != tile_num || data.len() < 8 + 8 * count {
+        return Err(anyhow!(
+            "Sum tile has {} values, expected {}",
+            count,
+            tile_num
+        ));
+    }
+
+    let mut ret = Vec::with_capacity(count);
+    for bytes in data[8..8 + 8 * count].chunks(8) {
+        let sum = TileSum::from_bytes(data_type, bytes).ok_or_else(|| {
+            anyhow!("Sums are not supported for {:?}", data_type)
+        })?;
+        ret.push(sum);
+    }
+    Ok(Some(ret))
+}
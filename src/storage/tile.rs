@@ -3,16 +3,17 @@
 
 use anyhow::anyhow;
 use binrw::io::Cursor;
-use binrw::{binrw, BinRead};
+use binrw::{binrw, BinRead, BinWrite};
 
+use crate::datatype::DataType;
 use crate::filters;
 use crate::io::service::VFSService;
 use crate::io::uri;
-use crate::io::PosixVFSService;
 use crate::storage;
 use crate::Result;
 
 pub const GENERIC_TILE_HEADER_SIZE: u64 = 34;
+pub const GENERIC_TILE_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Default)]
 #[binrw]
@@ -39,12 +40,37 @@ pub struct ChunkedData {
     pub chunks: Vec<Chunk>,
 }
 
+impl Chunk {
+    pub fn new(original_size: u32, metadata: Vec<u8>, data: Vec<u8>) -> Self {
+        Chunk {
+            original_size,
+            data_size: data.len() as u32,
+            metadata_size: metadata.len() as u32,
+            metadata,
+            data,
+        }
+    }
+}
+
 impl ChunkedData {
     pub fn new(num_chunks: u64) -> Self {
         let mut chunks = Vec::new();
         chunks.resize_with(num_chunks as usize, Chunk::default);
         ChunkedData { num_chunks, chunks }
     }
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        ChunkedData {
+            num_chunks: chunks.len() as u64,
+            chunks,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        self.write(&mut data)?;
+        Ok(data.into_inner())
+    }
 }
 
 #[derive(Debug)]
@@ -69,6 +95,20 @@ pub struct CompressionChunks {
     pub data_parts: Vec<CompressionChunkInfo>,
 }
 
+impl CompressionChunks {
+    pub fn new(
+        metadata_parts: Vec<CompressionChunkInfo>,
+        data_parts: Vec<CompressionChunkInfo>,
+    ) -> Self {
+        CompressionChunks {
+            num_metadata_parts: metadata_parts.len() as u32,
+            num_data_parts: data_parts.len() as u32,
+            metadata_parts,
+            data_parts,
+        }
+    }
+}
+
 #[derive(Debug)]
 #[binrw]
 #[brw(little)]
@@ -82,9 +122,11 @@ pub struct GenericTileHeader {
     pub filter_pipeline_size: u32,
 }
 
-pub fn read_generic_tile(uri: &uri::URI, offset: u64) -> Result<Vec<u8>> {
-    let vfs = PosixVFSService::default();
-
+pub fn read_generic_tile(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    offset: u64,
+) -> Result<Vec<u8>> {
     let size = GENERIC_TILE_HEADER_SIZE;
     let data = vfs.file_read_vec(uri, size, offset)?;
     let mut reader = Cursor::new(data);
@@ -113,3 +155,146 @@ pub fn read_generic_tile(uri: &uri::URI, offset: u64) -> Result<Vec<u8>> {
 
     Ok(data)
 }
+
+// Serialize data as an unfiltered generic tile.
+pub fn generic_tile_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    let mut pipeline = Cursor::new(Vec::new());
+    storage::FilterList::new(GENERIC_TILE_CHUNK_SIZE as u32, Vec::new())
+        .write(&mut pipeline)?;
+    let pipeline = pipeline.into_inner();
+
+    let chunks = data
+        .chunks(GENERIC_TILE_CHUNK_SIZE)
+        .map(|part| Chunk::new(part.len() as u32, Vec::new(), part.to_vec()))
+        .collect();
+    let chunks = ChunkedData::from_chunks(chunks).to_bytes()?;
+
+    let header = GenericTileHeader {
+        version: storage::CURRENT_FORMAT_VERSION,
+        persisted_size: chunks.len() as u64,
+        tile_size: data.len() as u64,
+        datatype: DataType::Char as u8,
+        cell_size: 1,
+        encryption_type: 0,
+        filter_pipeline_size: pipeline.len() as u32,
+    };
+    let mut ret = Cursor::new(Vec::new());
+    header.write(&mut ret)?;
+    let mut ret = ret.into_inner();
+    ret.extend_from_slice(&pipeline);
+    ret.extend_from_slice(&chunks);
+    Ok(ret)
+}
+
+// Write data to a new file holding a single generic tile.
+pub fn write_generic_tile(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    data: &[u8],
+) -> Result<()> {
+    let tile = generic_tile_bytes(data)?;
+    vfs.file_create(uri)?;
+    vfs.file_write(uri, 0, &tile)
+}
+
+// Data tiles in fragment files are stored as chunked data without a header.
+// The filter pipeline comes from the array schema rather than the file.
+// Read the filtered chunks of a data tile without unfiltering them.
+pub fn read_data_tile_chunks(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    offset: u64,
+    size: u64,
+) -> Result<ChunkedData> {
+    let data = vfs.file_read_vec(uri, size, offset)?;
+    let mut reader = Cursor::new(data);
+    storage::ChunkedData::read(&mut reader).map_err(|err| {
+        let context = format!("{:?}", err);
+        anyhow!("Error reading tile at offset {} from {}", offset, uri)
+            .context(context)
+    })
+}
+
+pub fn read_data_tile(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    offset: u64,
+    size: u64,
+    chain: &filters::FilterChain,
+) -> Result<Vec<u8>> {
+    let mut chunks = read_data_tile_chunks(vfs, uri, offset, size)?;
+    chain.unfilter_chunks(&mut chunks).map_err(|err| {
+        let context = format!("{:?}", err);
+        anyhow!("Error unfiltering tile at offset {} from {}", offset, uri)
+            .context(context)
+    })
+}
+
+// An unfiltered data tile. Var sized tiles carry the byte offsets of each
+// cell into the data buffer.
+#[derive(Debug, Default)]
+pub struct Tile {
+    data: Vec<u8>,
+    cell_size: usize,
+    offsets: Option<Vec<u64>>,
+    validity: Option<Vec<u8>>,
+}
+
+impl Tile {
+    pub fn new(
+        data: Vec<u8>,
+        cell_size: usize,
+        offsets: Option<Vec<u64>>,
+        validity: Option<Vec<u8>>,
+    ) -> Self {
+        Tile {
+            data,
+            cell_size,
+            offsets,
+            validity,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn offsets(&self) -> Option<&[u64]> {
+        self.offsets.as_deref()
+    }
+
+    pub fn validity(&self) -> Option<&[u8]> {
+        self.validity.as_deref()
+    }
+
+    pub fn cell_num(&self) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.len(),
+            None if self.cell_size == 0 => 0,
+            None => self.data.len() / self.cell_size,
+        }
+    }
+
+    pub fn value(&self, cell: usize) -> &[u8] {
+        match &self.offsets {
+            Some(offsets) => {
+                let start = offsets[cell] as usize;
+                let end = offsets
+                    .get(cell + 1)
+                    .map_or(self.data.len(), |o| *o as usize);
+                &self.data[start..end]
+            }
+            None => {
+                let start = cell * self.cell_size;
+                &self.data[start..start + self.cell_size]
+            }
+        }
+    }
+
+    pub fn is_valid(&self, cell: usize) -> bool {
+        match &self.validity {
+            Some(validity) => validity[cell] != 0,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,828 @@
+// This file is part of tdbtk released under the MIT license.
+// Copyright (c) 2023 TileDB, Inc.
+
+use std::cmp::Ordering;
+
+use anyhow::anyhow;
+use binrw::io::Cursor;
+use binrw::BinWrite;
+
+use crate::array::{
+    self, COMMITS_DIR, FRAGMENTS_DIR, OK_FILE_SUFFIX, WRITE_FILE_SUFFIX,
+};
+use crate::datatype::{self, DataType, Primitive};
+use crate::filters::FilterChain;
+use crate::io::service::VFSService;
+use crate::io::uri;
+use crate::storage::{self, Field};
+use crate::Result;
+
+// The statistics recorded for a single tile. Null cells are excluded from
+// the min, max and sum.
+#[derive(Clone, Default)]
+struct TileStats {
+    min: Option<Vec<u8>>,
+    max: Option<Vec<u8>>,
+    sum: Sum,
+    null_count: u64,
+}
+
+// Sums are stored as eight bytes holding an i64, u64 or f64 depending on
+// the type of the field. Integer sums saturate rather than overflow.
+#[derive(Clone, Copy, Default)]
+enum Sum {
+    #[default]
+    None,
+    Signed(i64),
+    Unsigned(u64),
+    Real(f64),
+}
+
+impl Sum {
+    fn zero(dtype: DataType) -> Sum {
+        match dtype {
+            DataType::Uint8
+            | DataType::Uint16
+            | DataType::Uint32
+            | DataType::Uint64 => Sum::Unsigned(0),
+            dtype if dtype.is_integral_type() => Sum::Signed(0),
+            dtype if dtype.is_real_type() => Sum::Real(0.0),
+            _ => Sum::None,
+        }
+    }
+
+    fn add_value(self, dtype: DataType, value: &[u8]) -> Sum {
+        match self {
+            Sum::None => Sum::None,
+            Sum::Signed(sum) => {
+                let value = dtype.to_i128(value).unwrap_or(0) as i64;
+                Sum::Signed(sum.saturating_add(value))
+            }
+            Sum::Unsigned(sum) => {
+                let value = dtype.to_i128(value).unwrap_or(0) as u64;
+                Sum::Unsigned(sum.saturating_add(value))
+            }
+            Sum::Real(sum) => {
+                Sum::Real(sum + dtype.to_f64(value).unwrap_or(0.0))
+            }
+        }
+    }
+
+    fn merge(self, other: Sum) -> Sum {
+        match (self, other) {
+            (Sum::Signed(lhs), Sum::Signed(rhs)) => {
+                Sum::Signed(lhs.saturating_add(rhs))
+            }
+            (Sum::Unsigned(lhs), Sum::Unsigned(rhs)) => {
+                Sum::Unsigned(lhs.saturating_add(rhs))
+            }
+            (Sum::Real(lhs), Sum::Real(rhs)) => Sum::Real(lhs + rhs),
+            (sum, _) => sum,
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Sum::None => vec![0; 8],
+            Sum::Signed(sum) => sum.to_bytes(),
+            Sum::Unsigned(sum) => sum.to_bytes(),
+            Sum::Real(sum) => sum.to_bytes(),
+        }
+    }
+}
+
+// Mins and maxs are kept for numeric cells holding a single value and for
+// strings. Sums are only kept for single value numeric cells.
+pub(crate) fn has_min_max(field: &Field) -> bool {
+    let dtype = field.data_type();
+    if matches!(dtype, DataType::Any | DataType::Blob) {
+        return false;
+    }
+
+    let is_string = dtype.is_string_type() || dtype == DataType::Char;
+    if field.is_var_sized() {
+        return is_string;
+    }
+
+    is_string || field.cell_size() == dtype.size()
+}
+
+pub(crate) fn has_sum(field: &Field) -> bool {
+    let dtype = field.data_type();
+    !field.is_var_sized()
+        && field.cell_size() == dtype.size()
+        && (dtype.is_integral_type() || dtype.is_real_type())
+}
+
+fn update_min_max(
+    dtype: DataType,
+    min: &mut Option<Vec<u8>>,
+    max: &mut Option<Vec<u8>>,
+    value: &[u8],
+) {
+    if min
+        .as_ref()
+        .is_none_or(|m| dtype.compare(value, m) == Ordering::Less)
+    {
+        *min = Some(value.to_vec());
+    }
+    if max
+        .as_ref()
+        .is_none_or(|m| dtype.compare(value, m) == Ordering::Greater)
+    {
+        *max = Some(value.to_vec());
+    }
+}
+
+fn tile_stats(field: &Field, tile: &storage::Tile) -> TileStats {
+    let dtype = field.data_type();
+    let min_max = has_min_max(field);
+    let mut stats = TileStats {
+        sum: if has_sum(field) {
+            Sum::zero(dtype)
+        } else {
+            Sum::None
+        },
+        ..TileStats::default()
+    };
+
+    for cell in 0..tile.cell_num() {
+        if !tile.is_valid(cell) {
+            stats.null_count += 1;
+            continue;
+        }
+
+        let value = tile.value(cell);
+        if min_max {
+            update_min_max(dtype, &mut stats.min, &mut stats.max, value);
+        }
+        stats.sum = stats.sum.add_value(dtype, value);
+    }
+
+    stats
+}
+
+// The tiles written for a single field along with the offsets needed to
+// find them again.
+#[derive(Default)]
+struct FieldTiles {
+    offsets: Vec<u64>,
+    file_size: u64,
+    var_offsets: Vec<u64>,
+    var_file_size: u64,
+    var_sizes: Vec<u64>,
+    validity_offsets: Vec<u64>,
+    validity_file_size: u64,
+    stats: Vec<TileStats>,
+    var_min_max: bool,
+    has_sum: bool,
+    cell_size: usize,
+    data_type: DataType,
+}
+
+// Writes the files of a new fragment. Tiles are filtered and appended to the
+// field files as they are added, and the fragment metadata and commit are
+// written by finish. Fragments older than version 12 are stored in the
+// array directory and committed by an ok file next to them, or before
+// version 5 by a fragment file within them. The fragment name, which
+// includes its timestamps, is chosen by the caller.
+pub struct FragmentWriter<'a> {
+    schema: &'a array::Schema,
+    schema_name: String,
+    format_version: u32,
+    array_uri: uri::URI,
+    name: String,
+    uri: uri::URI,
+    dense: bool,
+    non_empty_domain: Vec<array::Range>,
+    mbrs: Vec<storage::Mbr>,
+    last_tile_cell_num: u64,
+    has_timestamps: bool,
+    processed_conditions: Vec<String>,
+    fields: Vec<FieldTiles>,
+    coords: Vec<Vec<u8>>,
+    bounding_coords: Vec<u8>,
+}
+
+impl<'a> FragmentWriter<'a> {
+    pub fn new(
+        vfs: &dyn VFSService,
+        array_uri: &uri::URI,
+        schema_name: &str,
+        schema: &'a array::Schema,
+        name: &str,
+        dense: bool,
+        format_version: u32,
+    ) -> Result<Self> {
+        let versions =
+            storage::MIN_FORMAT_VERSION..=storage::CURRENT_FORMAT_VERSION;
+        if !versions.contains(&format_version) {
+            return Err(anyhow!(
+                "Unable to write fragment version {}, only versions {} to \
+                {} are supported",
+                format_version,
+                versions.start(),
+                versions.end()
+            ));
+        }
+
+        // Before version 5 the dimensions are stored together, so they must
+        // be fixed sized and share a datatype.
+        let dims = schema.domain().dimensions();
+        if format_version < 5
+            && dims.iter().any(|d| {
+                d.is_var_sized() || d.data_type() != dims[0].data_type()
+            })
+        {
+            return Err(anyhow!(
+                "Fragment version {} requires fixed sized dimensions of a \
+                single datatype",
+                format_version
+            ));
+        }
+
+        let fragments_uri = if format_version >= 12 {
+            array_uri.join(FRAGMENTS_DIR)
+        } else {
+            array_uri.clone()
+        };
+        if !vfs.dir_exists(&fragments_uri)? {
+            vfs.dir_create(&fragments_uri)?;
+        }
+
+        let uri = fragments_uri.join(name);
+        vfs.dir_create(&uri)?;
+
+        let nfields = schema.attributes().len() + 1 + schema.domain().ndim();
+        let mut fields = Vec::new();
+        fields.resize_with(nfields, FieldTiles::default);
+
+        Ok(FragmentWriter {
+            schema,
+            schema_name: schema_name.to_string(),
+            format_version,
+            array_uri: array_uri.clone(),
+            name: name.to_string(),
+            uri,
+            dense,
+            non_empty_domain: Vec::new(),
+            mbrs: Vec::new(),
+            last_tile_cell_num: 0,
+            has_timestamps: false,
+            processed_conditions: Vec::new(),
+            fields,
+            coords: Vec::new(),
+            bounding_coords: Vec::new(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn uri(&self) -> &uri::URI {
+        &self.uri
+    }
+
+    pub fn set_non_empty_domain(&mut self, ranges: Vec<array::Range>) {
+        self.non_empty_domain = ranges;
+    }
+
+    // Fragments with timestamps hold a tile of the timestamps field for
+    // every data tile. They require version 14.
+    pub fn set_timestamps(&mut self, has_timestamps: bool) {
+        let nfields = self.schema.attributes().len()
+            + 1
+            + self.schema.domain().ndim()
+            + has_timestamps as usize;
+        self.fields.resize_with(nfields, FieldTiles::default);
+        self.has_timestamps = has_timestamps;
+    }
+
+    // The names of the delete commits whose conditions were already
+    // applied to the cells of this fragment, so that readers skip them.
+    // They require version 16.
+    pub fn set_processed_conditions(&mut self, names: Vec<String>) {
+        self.processed_conditions = names;
+    }
+
+    // Sparse fragments record the MBR of each data tile in their R-tree.
+    pub fn add_mbr(&mut self, mbr: storage::Mbr) {
+        self.mbrs.push(mbr);
+    }
+
+    // Append the next tile of a field. Tiles of every field must be added
+    // in the same order.
+    pub fn write_tile(
+        &mut self,
+        vfs: &dyn VFSService,
+        field: &Field,
+        tile: &storage::Tile,
+    ) -> Result<()> {
+        let nattrs = self.schema.attributes().len();
+        let ndim = self.schema.domain().ndim();
+        if self.format_version < 5 && field.idx() > nattrs {
+            return self.write_coords_tile(vfs, tile, ndim);
+        }
+
+        let file_name = storage::field_file_name(
+            self.format_version,
+            self.schema.attributes().len(),
+            field,
+        );
+        if tile.validity().is_some() && self.format_version < 7 {
+            return Err(anyhow!(
+                "Fragment version {} does not support nullable field '{}'",
+                self.format_version,
+                field.name()
+            ));
+        }
+        let stats = tile_stats(field, tile);
+        let state = self.fields.get_mut(field.idx()).ok_or_else(|| {
+            anyhow!(
+                "Invalid field index {} for '{}'",
+                field.idx(),
+                field.name()
+            )
+        })?;
+        state.var_min_max = field.is_var_sized() && has_min_max(field);
+        state.has_sum = has_sum(field);
+        state.cell_size = field.cell_size();
+        state.data_type = field.data_type();
+
+        let uri = self.uri.join(&(file_name.clone() + storage::FILE_SUFFIX));
+        if let Some(offsets) = tile.offsets() {
+            let offsets = datatype::values_to_bytes(offsets);
+            state.offsets.push(state.file_size);
+            state.file_size += append_tile(
+                vfs,
+                &uri,
+                state.file_size,
+                field.offsets_filters(),
+                &offsets,
+                8,
+            )?;
+
+            let var_uri = self
+                .uri
+                .join(&(file_name.clone() + storage::VAR_FILE_SUFFIX));
+            state.var_offsets.push(state.var_file_size);
+            state.var_sizes.push(tile.data().len() as u64);
+            state.var_file_size += append_tile(
+                vfs,
+                &var_uri,
+                state.var_file_size,
+                field.filters(),
+                tile.data(),
+                field.data_type().size(),
+            )?;
+        } else {
+            state.offsets.push(state.file_size);
+            state.file_size += append_tile(
+                vfs,
+                &uri,
+                state.file_size,
+                field.filters(),
+                tile.data(),
+                field.cell_size(),
+            )?;
+        }
+
+        if let Some(validity) = tile.validity() {
+            let validity_uri =
+                self.uri.join(&(file_name + storage::VALIDITY_FILE_SUFFIX));
+            state.validity_offsets.push(state.validity_file_size);
+            state.validity_file_size += append_tile(
+                vfs,
+                &validity_uri,
+                state.validity_file_size,
+                field.validity_filters(),
+                validity,
+                1,
+            )?;
+        }
+
+        state.stats.push(stats);
+        self.last_tile_cell_num = tile.cell_num() as u64;
+        Ok(())
+    }
+
+    // Before version 5 the tiles of each dimension are collected and
+    // written as a single tile of the zipped coordinates, one dimension
+    // after another.
+    fn write_coords_tile(
+        &mut self,
+        vfs: &dyn VFSService,
+        tile: &storage::Tile,
+        ndim: usize,
+    ) -> Result<()> {
+        self.coords.push(tile.data().to_vec());
+        if self.coords.len() < ndim {
+            return Ok(());
+        }
+
+        let cell_num = tile.cell_num();
+        let coords = std::mem::take(&mut self.coords);
+        // Versions 1 and 2 record the first and last coordinates of each
+        // tile.
+        for cell in [0, cell_num.saturating_sub(1)] {
+            for dim in coords.iter() {
+                let size = dim.len() / cell_num.max(1);
+                self.bounding_coords
+                    .extend_from_slice(&dim[cell * size..(cell + 1) * size]);
+            }
+        }
+
+        let schema = self.schema;
+        let field = Field::coords(schema);
+        let tile =
+            storage::Tile::new(coords.concat(), field.cell_size(), None, None);
+        self.write_tile(vfs, &field, &tile)?;
+        self.last_tile_cell_num = cell_num as u64;
+        Ok(())
+    }
+
+    // Write the fragment metadata and commit the fragment.
+    pub fn finish(self, vfs: &dyn VFSService) -> Result<uri::URI> {
+        let nattrs = self.schema.attributes().len();
+        let tile_num = self.fields[0].offsets.len();
+        if let Some(field) = self.fields[..nattrs]
+            .iter()
+            .position(|f| f.offsets.len() != tile_num)
+        {
+            return Err(anyhow!(
+                "Attribute '{}' has {} tiles, expected {}",
+                self.schema.attributes()[field].name(),
+                self.fields[field].offsets.len(),
+                tile_num
+            )
+            .context(format!("Fragment: {}", self.uri)));
+        }
+
+        let version = self.format_version;
+        if self.has_timestamps && version < 14 {
+            return Err(anyhow!(
+                "Fragment version {} does not support timestamps",
+                version
+            )
+            .context(format!("Fragment: {}", self.uri)));
+        }
+
+        // Before version 5 the dimensions are only stored in the zipped
+        // coordinates.
+        let nfields = if version < 5 {
+            nattrs + 1
+        } else {
+            self.fields.len()
+        };
+        let fields = &self.fields[..nfields];
+        let meta = if version < 3 {
+            self.inline_metadata(fields, tile_num)?
+        } else {
+            self.footer_metadata(fields, tile_num)?
+        };
+
+        let meta_uri = self.uri.join(storage::FRAGMENT_METADATA_FILENAME);
+        vfs.file_create(&meta_uri)?;
+        vfs.file_write(&meta_uri, 0, &meta)?;
+
+        if version < 5 {
+            vfs.file_create(&self.uri.join(storage::FRAGMENT_FILENAME))?;
+            return Ok(self.uri);
+        }
+
+        if version < 12 {
+            let ok_uri = self.array_uri.join(&(self.name + OK_FILE_SUFFIX));
+            vfs.file_create(&ok_uri)?;
+            return Ok(self.uri);
+        }
+
+        let commits_uri = self.array_uri.join(COMMITS_DIR);
+        if !vfs.dir_exists(&commits_uri)? {
+            vfs.dir_create(&commits_uri)?;
+        }
+        vfs.file_create(&commits_uri.join(&(self.name + WRITE_FILE_SUFFIX)))?;
+
+        Ok(self.uri)
+    }
+
+    // Versions 1 and 2 store all of the metadata in a single generic tile.
+    fn inline_metadata(
+        &self,
+        fields: &[FieldTiles],
+        tile_num: usize,
+    ) -> Result<Vec<u8>> {
+        let mbrs = if self.dense {
+            Vec::new()
+        } else {
+            self.mbrs.clone()
+        };
+        let inline = storage::InlineMetadata {
+            version: self.format_version,
+            non_empty_domain: self.non_empty_domain.clone(),
+            mbr_num: mbrs.len() as u64,
+            mbrs,
+            bounding_coords_num: if self.dense { 0 } else { tile_num as u64 },
+            bounding_coords: self.bounding_coords.clone(),
+            tile_offsets: fields
+                .iter()
+                .map(|f| storage::U64List::new(f.offsets.clone()))
+                .collect(),
+            tile_var_offsets: fields
+                .iter()
+                .map(|f| storage::U64List::new(f.var_offsets.clone()))
+                .collect(),
+            tile_var_sizes: fields
+                .iter()
+                .map(|f| storage::U64List::new(f.var_sizes.clone()))
+                .collect(),
+            last_tile_cell_num: self.last_tile_cell_num,
+            file_sizes: fields.iter().map(|f| f.file_size).collect(),
+            file_var_sizes: fields.iter().map(|f| f.var_file_size).collect(),
+        };
+        let mut data = Cursor::new(Vec::new());
+        inline
+            .write_args(&mut data, (storage::dimension_sizes(self.schema),))?;
+        storage::generic_tile_bytes(&data.into_inner())
+    }
+
+    // Generic tiles holding the R-tree, tile offsets and statistics
+    // followed by the footer locating them.
+    fn footer_metadata(
+        &self,
+        fields: &[FieldTiles],
+        tile_num: usize,
+    ) -> Result<Vec<u8>> {
+        let version = self.format_version;
+        let dims = storage::dimension_sizes(self.schema);
+        let dtypes: Vec<DataType> = self
+            .schema
+            .domain()
+            .dimensions()
+            .iter()
+            .map(|d| d.data_type())
+            .collect();
+        let rtree = storage::RTree::build(
+            &dtypes,
+            storage::RTREE_FANOUT,
+            self.mbrs.clone(),
+        );
+        let mut data = Cursor::new(Vec::new());
+        rtree.write_args(&mut data, (version, dims.clone()))?;
+
+        let mut meta = Vec::new();
+        let rtree_offset = append_generic_tile(&mut meta, &data.into_inner())?;
+
+        // Tiles are only written for the offsets that the version stores.
+        let mut offsets =
+            |since: u32, values: &dyn Fn(&FieldTiles) -> Vec<u8>| {
+                let mut ret = Vec::new();
+                for field in fields.iter().filter(|_| version >= since) {
+                    ret.push(append_generic_tile(&mut meta, &values(field))?);
+                }
+                Ok::<_, anyhow::Error>(ret)
+            };
+        let fixed_offsets = offsets(0, &|f| u64_tile(&f.offsets))?;
+        let var_offsets = offsets(0, &|f| u64_tile(&f.var_offsets))?;
+        let var_sizes = offsets(0, &|f| u64_tile(&f.var_sizes))?;
+        let validity_offsets = offsets(7, &|f| u64_tile(&f.validity_offsets))?;
+        let min_offsets = offsets(11, &|f| min_max_tile(f, |s| &s.min))?;
+        let max_offsets = offsets(11, &|f| min_max_tile(f, |s| &s.max))?;
+        let sum_offsets = offsets(11, &sum_tile)?;
+        let null_count_offsets = offsets(11, &|f| {
+            let counts: Vec<u64> = if f.validity_offsets.is_empty() {
+                Vec::new()
+            } else {
+                f.stats.iter().map(|s| s.null_count).collect()
+            };
+            u64_tile(&counts)
+        })?;
+
+        let frag_meta_offset = if version >= 11 {
+            let mut fragment_stats = Vec::new();
+            for field in fields.iter() {
+                fragment_stats.extend(fragment_stats_bytes(field));
+            }
+            append_generic_tile(&mut meta, &fragment_stats)?
+        } else {
+            0
+        };
+
+        let processed_conditions_offset = if version >= 16 {
+            let names = &self.processed_conditions;
+            let mut data = (names.len() as u64).to_le_bytes().to_vec();
+            for name in names {
+                data.extend((name.len() as u64).to_le_bytes());
+                data.extend(name.as_bytes());
+            }
+            append_generic_tile(&mut meta, &data)?
+        } else if !self.processed_conditions.is_empty() {
+            return Err(anyhow!(
+                "Processed conditions require format version 16, not {}",
+                version
+            ));
+        } else {
+            0
+        };
+
+        let footer = storage::FragmentFooter {
+            version,
+            array_schema_name_size: self.schema_name.len() as u64,
+            array_schema_name: self.schema_name.clone(),
+            dense: self.dense as u8,
+            non_empty_domain: self.non_empty_domain.clone(),
+            sparse_tile_num: if self.dense { 0 } else { tile_num as u64 },
+            last_tile_cell_num: self.last_tile_cell_num,
+            has_timestamps: self.has_timestamps as u8,
+            has_delete_meta: 0,
+            file_offsets: storage::FragmentFileOffsets {
+                fixed_sizes: fields.iter().map(|f| f.file_size).collect(),
+                var_sizes: fields.iter().map(|f| f.var_file_size).collect(),
+                validity_sizes: fields
+                    .iter()
+                    .map(|f| f.validity_file_size)
+                    .collect(),
+            },
+            tile_offsets: storage::FragmentTileOffsets {
+                rtree: rtree_offset,
+                fixed_offsets,
+                var_offsets,
+                var_sizes,
+                validity_offsets,
+                min_offsets,
+                max_offsets,
+                sum_offsets,
+                null_count_offsets,
+                frag_meta_offset,
+                processed_conditions_offset,
+            },
+        };
+        let mut data = Cursor::new(Vec::new());
+        footer.write_args(&mut data, (dims,))?;
+        let footer = data.into_inner();
+        meta.extend_from_slice(&footer);
+        // Before version 10 the size is implied by the schema when every
+        // dimension is fixed sized.
+        if version >= 10 || !self.schema.domain().all_dims_fixed() {
+            meta.extend_from_slice(&(footer.len() as u64).to_le_bytes());
+        }
+
+        Ok(meta)
+    }
+}
+
+// Filter a tile and append it to a field file, returning its persisted
+// size. Chunks hold whole cells.
+fn append_tile(
+    vfs: &dyn VFSService,
+    uri: &uri::URI,
+    offset: u64,
+    chain: &FilterChain,
+    data: &[u8],
+    cell_size: usize,
+) -> Result<u64> {
+    let cell_size = cell_size.max(1);
+    let chunk_size = (storage::DEFAULT_MAX_CHUNK_SIZE as usize / cell_size)
+        .max(1)
+        * cell_size;
+    let chunks = chain.filter_chunks(data, chunk_size)?.to_bytes()?;
+
+    if offset == 0 {
+        vfs.file_create(uri)?;
+    }
+    vfs.file_write(uri, offset, &chunks)?;
+    Ok(chunks.len() as u64)
+}
+
+fn append_generic_tile(meta: &mut Vec<u8>, data: &[u8]) -> Result<u64> {
+    let offset = meta.len() as u64;
+    meta.extend(storage::generic_tile_bytes(data)?);
+    Ok(offset)
+}
+
+// A count followed by that many u64 values.
+fn u64_tile(values: &[u64]) -> Vec<u8> {
+    let mut ret = (values.len() as u64).to_le_bytes().to_vec();
+    ret.extend(datatype::values_to_bytes(values));
+    ret
+}
+
+// Fixed size mins and maxs are stored back to back. Var sized ones are
+// stored as offsets into a separate buffer of values.
+fn min_max_tile(
+    field: &FieldTiles,
+    value: fn(&TileStats) -> &Option<Vec<u8>>,
+) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut var_buffer = Vec::new();
+    let has_values = field.stats.iter().any(|s| value(s).is_some());
+    if has_values && field.var_min_max {
+        for stats in field.stats.iter() {
+            buffer.extend((var_buffer.len() as u64).to_le_bytes());
+            var_buffer
+                .extend_from_slice(value(stats).as_deref().unwrap_or(&[]));
+        }
+    } else if has_values {
+        for stats in field.stats.iter() {
+            match value(stats) {
+                Some(v) => buffer.extend_from_slice(v),
+                None => buffer.extend(vec![0; field.cell_size]),
+            }
+        }
+    }
+
+    let mut ret = (buffer.len() as u64).to_le_bytes().to_vec();
+    ret.extend((var_buffer.len() as u64).to_le_bytes());
+    ret.extend(buffer);
+    ret.extend(var_buffer);
+    ret
+}
+
+fn sum_tile(field: &FieldTiles) -> Vec<u8> {
+    let mut ret = Vec::new();
+    let num = if field.has_sum { field.stats.len() } else { 0 };
+    ret.extend((num as u64).to_le_bytes());
+    for stats in field.stats.iter().take(num) {
+        ret.extend(stats.sum.to_bytes());
+    }
+    ret
+}
+
+// The statistics of the whole fragment for a field: the sized min and max
+// followed by the sum and null count.
+fn fragment_stats_bytes(field: &FieldTiles) -> Vec<u8> {
+    let mut min = None;
+    let mut max = None;
+    let mut sum = if field.has_sum {
+        Sum::zero(field.data_type)
+    } else {
+        Sum::None
+    };
+    let mut null_count = 0;
+    for stats in field.stats.iter() {
+        for value in stats.min.iter().chain(stats.max.iter()) {
+            update_min_max(field.data_type, &mut min, &mut max, value);
+        }
+        sum = sum.merge(stats.sum);
+        null_count += stats.null_count;
+    }
+
+    let mut ret = Vec::new();
+    for value in [min, max] {
+        let value = value.unwrap_or_default();
+        ret.extend((value.len() as u64).to_le_bytes());
+        ret.extend(value);
+    }
+    ret.extend(sum.to_bytes());
+    ret.extend(null_count.to_le_bytes());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_and_mins() {
+        let mut field = FieldTiles {
+            has_sum: true,
+            cell_size: 4,
+            data_type: DataType::Int32,
+            ..FieldTiles::default()
+        };
+        for values in [[3i32, -2], [7, 1]] {
+            let mut stats = TileStats {
+                sum: Sum::zero(DataType::Int32),
+                ..TileStats::default()
+            };
+            for v in values {
+                let v = v.to_le_bytes();
+                update_min_max(
+                    DataType::Int32,
+                    &mut stats.min,
+                    &mut stats.max,
+                    &v,
+                );
+                stats.sum = stats.sum.add_value(DataType::Int32, &v);
+            }
+            field.stats.push(stats);
+        }
+
+        assert_eq!(sum_tile(&field)[8..16], 1i64.to_le_bytes());
+        assert_eq!(sum_tile(&field)[16..], 8i64.to_le_bytes());
+        let mins = min_max_tile(&field, |s| &s.min);
+        assert_eq!(
+            mins[..16],
+            [8u64.to_le_bytes(), 0u64.to_le_bytes()].concat()
+        );
+        assert_eq!(
+            mins[16..],
+            [(-2i32).to_le_bytes(), 1i32.to_le_bytes()].concat()
+        );
+
+        let stats = fragment_stats_bytes(&field);
+        assert_eq!(stats[8..12], (-2i32).to_le_bytes());
+        assert_eq!(stats[20..24], 7i32.to_le_bytes());
+        assert_eq!(stats[24..32], 9i64.to_le_bytes());
+    }
+}
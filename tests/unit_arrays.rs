@@ -1,12 +1,16 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
-use tdbtk::array;
+use tdbtk::array::{self, ArrayType};
+use tdbtk::fixtures;
 use tdbtk::io::service::{VFSService, WalkOptions};
 use tdbtk::io::{uri, FSEntryType, PosixVFSService};
+use tdbtk::query::{DenseReader, SparseReader};
 use tdbtk::storage;
 
-const UNIT_ARRAY_DIR: &str = "/Users/davisp/github/tiledb/unit-test-arrays";
+// Generated by the generate_fixtures binary.
+const UNIT_ARRAY_DIR: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/resources/arrays");
 
 fn list_arrays(uri: &uri::URI, vfs: &dyn VFSService) -> Result<Vec<uri::URI>> {
     let mut ret: Vec<uri::URI> = Vec::new();
@@ -21,10 +25,6 @@ fn list_arrays(uri: &uri::URI, vfs: &dyn VFSService) -> Result<Vec<uri::URI>> {
             return Ok(true);
         }
 
-        if entry.uri().path().contains("encryption") {
-            return Ok(true);
-        }
-
         ret.push(entry.uri().clone());
 
         Ok(true)
@@ -33,33 +33,74 @@ fn list_arrays(uri: &uri::URI, vfs: &dyn VFSService) -> Result<Vec<uri::URI>> {
     Ok(ret)
 }
 
-#[test]
-fn parse_all_schema() -> Result<()> {
+fn unit_arrays(vfs: &dyn VFSService) -> Result<Vec<uri::URI>> {
     let uri_str = "file://".to_string() + UNIT_ARRAY_DIR;
     let uri = uri::URI::from_string(&uri_str)?;
-    let vfs = PosixVFSService::default();
+    let arrays = list_arrays(&uri, vfs)?;
+    let versions =
+        storage::CURRENT_FORMAT_VERSION - storage::MIN_FORMAT_VERSION + 1;
+    assert_eq!(arrays.len(), 2 * versions as usize);
+    Ok(arrays)
+}
 
-    let arrays = list_arrays(&uri, &vfs)?;
+#[test]
+fn parse_all_schema() -> Result<()> {
+    let vfs = PosixVFSService::default();
 
-    for uri in arrays.iter() {
+    for uri in unit_arrays(&vfs)?.iter() {
         let mut dir = array::Directory::new(uri);
         dir.load_all(&vfs)?;
-
-        if dir.schema_uris().is_empty() {
-            println!("Skipping non-array: {}", uri);
-            continue;
-        }
+        assert!(!dir.schema_uris().is_empty());
 
         let mut schemas: HashMap<String, array::Schema> = HashMap::new();
         for uri in dir.schema_uris() {
-            let storage_schema =
-                storage::ArraySchema::load(dir.schema_uris().first().unwrap())?;
+            let storage_schema = storage::ArraySchema::load(&vfs, &uri)?;
             let schema = array::Schema::try_from(storage_schema)?;
             schemas.insert(uri.last_path_part(), schema);
         }
 
-        for uri in dir.fragment_uris().iter() {
-            let fmd = storage::FragmentMetadata::load(uri, &schemas);
+        for uri in dir.fragment_uris()?.iter() {
+            storage::FragmentMetadata::load(&vfs, uri, &schemas)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn read_all_arrays() -> Result<()> {
+    let vfs = PosixVFSService::default();
+
+    for uri in unit_arrays(&vfs)?.iter() {
+        let array = array::Array::open(&vfs, uri)?;
+        let results = match array.schema().array_type() {
+            ArrayType::Dense => DenseReader::new(&array).read(&vfs)?,
+            _ => SparseReader::new(&array).read(&vfs)?,
+        };
+
+        for (dtype, var) in fixtures::attribute_types() {
+            let buffer = &results[&fixtures::attribute_name(dtype)];
+            assert_eq!(buffer.cell_num(), fixtures::FIXTURE_CELL_NUM);
+            for cell in 0..fixtures::FIXTURE_CELL_NUM {
+                let expected = fixtures::cell_value(dtype, var, cell);
+                assert_eq!(
+                    buffer.value(cell),
+                    Some(&expected[..]),
+                    "{} {:?} cell {}",
+                    uri,
+                    dtype,
+                    cell
+                );
+            }
+        }
+
+        if let Some(buffer) = results.get(fixtures::NULLABLE_ATTRIBUTE) {
+            for cell in 0..fixtures::FIXTURE_CELL_NUM {
+                assert_eq!(
+                    buffer.is_valid(cell),
+                    fixtures::cell_is_valid(cell)
+                );
+            }
         }
     }
 